@@ -10,10 +10,10 @@ rm -f rust/joinstr/include/c/joinstr.h
 rm -f rust/joinstr/include/cpp/joinstr.h
 
 # C headers
-cbindgen --lang c  --crate joinstr  -o rust/joinstr/include/c/joinstr.h 
+cbindgen --lang c  --crate joinstr_wallet  -o rust/joinstr/include/c/joinstr.h 
 
 # C++ headers
-cbindgen --crate joinstr  -o rust/joinstr/include/cpp/joinstr.h
+cbindgen --crate joinstr_wallet  -o rust/joinstr/include/cpp/joinstr.h
 
 # Then generate dart bindings
 
@@ -30,10 +30,10 @@ dart analyze
 
 # Generate rust librairies
 
-cd ../rust/joinstr
+cd ../rust/joinstr_wallet
 
 # Variables
-RUST_LIB_NAME="joinstr"
+RUST_LIB_NAME="joinstr_wallet"
 ANDROID_OUTPUT_DIR="../../dart/android"
 IOS_OUTPUT_DIR="../../dart/ios/Frameworks"
 
@@ -0,0 +1,50 @@
+#![no_main]
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use joinstr::{
+    miniscript::bitcoin::{hashes::Hash, Script, Txid},
+    simple_electrum_client::electrum::{request::Request, response::Response},
+};
+use libfuzzer_sys::fuzz_target;
+
+static INDEX: OnceLock<HashMap<usize, Request>> = OnceLock::new();
+
+// one pending request per method, w/ id = index in the list
+fn index() -> HashMap<usize, Request> {
+    let script = Script::from_bytes(&[0x00]);
+    let txid = Txid::all_zeros();
+    [
+        Request::ping(),
+        Request::version("fuzz".into(), "1.4".into()),
+        Request::banner(),
+        Request::donation(),
+        Request::features(),
+        Request::subscribe_peers(),
+        Request::header(0),
+        Request::headers(0, 10),
+        Request::estimate_fee(2),
+        Request::subscribe_headers(),
+        Request::relay_fee(),
+        Request::sh_get_balance(script),
+        Request::sh_get_history(script),
+        Request::sh_list_unspent(script),
+        Request::subscribe_sh(script),
+        Request::unsubscribe_sh(script),
+        Request::tx_broadcast("00".into()),
+        Request::tx_get(txid),
+        Request::tx_get_merkle(txid, 0),
+        Request::tx_from_pos(0, 0, true),
+        Request::get_fee_histogram(),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(id, request)| (id, request.id(id)))
+    .collect()
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = std::str::from_utf8(data) {
+        let _ = Response::try_parse(raw, INDEX.get_or_init(index));
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use joinstr::{nostr::InputDataSigned, serde_json::Value};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(value) = joinstr::serde_json::from_slice::<Value>(data) {
+        if let Ok(input) = InputDataSigned::from_value(value) {
+            let roundtrip = InputDataSigned::from_value(input.to_json()).expect("roundtrip");
+            assert_eq!(input, roundtrip);
+        }
+    }
+});
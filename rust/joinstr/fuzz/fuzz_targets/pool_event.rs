@@ -0,0 +1,32 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use joinstr::{
+    nostr::Pool,
+    simple_nostr_client::nostr::{EventBuilder, Keys, Kind, SecretKey},
+};
+use libfuzzer_sys::fuzz_target;
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+fuzz_target!(|data: (bool, &str)| {
+    let (pool_kind, content) = data;
+    let keys =
+        KEYS.get_or_init(|| Keys::new(SecretKey::from_slice(&[1; 32]).expect("valid secret key")));
+    let kind = if pool_kind {
+        Kind::Custom(2022)
+    } else {
+        Kind::EncryptedDirectMessage
+    };
+    let event = EventBuilder::new(kind, content, Vec::new())
+        .to_event(keys)
+        .expect("signing do not fail");
+    if let Ok(pool) = Pool::try_from(event) {
+        let builder: EventBuilder = pool.try_into().expect("serializable");
+        let event = builder.to_event(keys).expect("signing do not fail");
+        // NOTE: not compared as `versions: null` is parsed as `None` but
+        // an absent `versions` as the default version
+        Pool::try_from(event).expect("roundtrip");
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use joinstr::nostr::PoolMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = std::str::from_utf8(data) {
+        if let Ok(msg) = PoolMessage::from_str(raw) {
+            // a parsed message must serialize back
+            msg.to_string().expect("serializable");
+        }
+    }
+});
@@ -0,0 +1,187 @@
+use std::future::Future;
+
+use miniscript::bitcoin::{
+    consensus::encode::{deserialize_hex, serialize_hex},
+    FeeRate, OutPoint, Transaction, TxOut, Txid,
+};
+use simple_electrum_client::{
+    async_client::AsyncClient,
+    electrum::{
+        request::Request,
+        response::{
+            EstimateFeeResponse, FeeHistogramResponse, HistoryResult, OptionalFee, Response,
+            SHGetHistoryResponse, TxBroadcastResponse, TxGetResponse, TxGetResult,
+        },
+    },
+};
+
+use super::{memory::MemoryChain, ChainSource, Error};
+use crate::electrum;
+
+/// Send a request w/ an [`AsyncClient`], mapping the electrum errors.
+async fn request(client: &AsyncClient, request: Request) -> Result<Response, Error> {
+    match client.call(request).await {
+        Ok(Response::Error(e)) => Err(electrum::Error::Electrum(format!("{:?}", e.error)).into()),
+        Ok(response) => Ok(response),
+        Err(e) => Err(electrum::Error::Electrum(e.to_string()).into()),
+    }
+}
+
+/// Fetch a transaction w/ an [`AsyncClient`], None if the server does not
+///   know it.
+async fn get_tx(client: &AsyncClient, txid: Txid) -> Result<Option<Transaction>, Error> {
+    match client.call(Request::tx_get(txid)).await {
+        // NOTE: as for the blocking client, an error response is very likely
+        // because the txid does not match any transaction
+        Ok(Response::Error(_)) => Ok(None),
+        Ok(Response::TxGet(TxGetResponse {
+            result: TxGetResult::Raw(raw_tx),
+            ..
+        })) => {
+            let tx: Transaction =
+                deserialize_hex(&raw_tx).map_err(|_| electrum::Error::TxParsing)?;
+            if tx.compute_txid() != txid {
+                return Err(electrum::Error::TxidMismatch(txid).into());
+            }
+            Ok(Some(tx))
+        }
+        Ok(_) => Err(electrum::Error::WrongResponse.into()),
+        Err(e) => Err(electrum::Error::Electrum(e.to_string()).into()),
+    }
+}
+
+/// The chain data used by [`crate::joinstr::r#async::Joinstr`], the async
+///   counterpart of [`super::ChainSource`].
+pub trait AsyncChainSource: Send + Sync {
+    /// Broadcast a transaction.
+    fn broadcast(&self, tx: &Transaction) -> impl Future<Output = Result<Txid, Error>> + Send;
+
+    /// Estimate the fee rate needed for a transaction to confirm within
+    ///   `target` blocks, returns None if no estimation is available.
+    fn estimate_fee(
+        &self,
+        target: u16,
+    ) -> impl Future<Output = Result<Option<FeeRate>, Error>> + Send;
+
+    /// Returns the fee histogram of the mempool, see
+    ///   [`super::ChainSource::fee_histogram()`].
+    fn fee_histogram(&self) -> impl Future<Output = Result<Vec<(FeeRate, u64)>, Error>> + Send {
+        async { Err(Error::Unsupported("fee_histogram")) }
+    }
+
+    /// Returns the output at `outpoint` w/ the transaction spending it if
+    ///   any, see [`super::ChainSource::get_txout()`]. The coordinator relies
+    ///   on it to check the inputs of the peers.
+    #[allow(clippy::type_complexity)]
+    fn get_txout(
+        &self,
+        outpoint: OutPoint,
+    ) -> impl Future<Output = Result<Option<(TxOut, Option<Transaction>)>, Error>> + Send;
+}
+
+impl AsyncChainSource for AsyncClient {
+    fn broadcast(&self, tx: &Transaction) -> impl Future<Output = Result<Txid, Error>> + Send {
+        let txid = tx.compute_txid();
+        let req = Request::tx_broadcast(serialize_hex(tx));
+        async move {
+            match request(self, req).await? {
+                Response::TxBroadcast(TxBroadcastResponse { .. }) => Ok(txid),
+                _ => Err(electrum::Error::WrongResponse.into()),
+            }
+        }
+    }
+
+    fn estimate_fee(
+        &self,
+        target: u16,
+    ) -> impl Future<Output = Result<Option<FeeRate>, Error>> + Send {
+        async move {
+            match request(self, Request::estimate_fee(target)).await? {
+                Response::EstimateFee(EstimateFeeResponse {
+                    fee: OptionalFee::Fee(btc_kvb),
+                    ..
+                }) if btc_kvb >= 0.0 => {
+                    // BTC/kvB => sat/kwu
+                    let sat_kvb = (btc_kvb * 100_000_000.0).round() as u64;
+                    Ok(Some(FeeRate::from_sat_per_kwu(sat_kvb / 4)))
+                }
+                Response::EstimateFee(_) => Ok(None),
+                _ => Err(electrum::Error::WrongResponse.into()),
+            }
+        }
+    }
+
+    fn fee_histogram(&self) -> impl Future<Output = Result<Vec<(FeeRate, u64)>, Error>> + Send {
+        async move {
+            match request(self, Request::get_fee_histogram()).await? {
+                Response::FeeHistogram(FeeHistogramResponse { histogram, .. }) => Ok(histogram
+                    .into_iter()
+                    .map(|(sat_vb, vsize)| {
+                        (
+                            FeeRate::from_sat_per_vb_unchecked(sat_vb as u64),
+                            vsize as u64,
+                        )
+                    })
+                    .collect()),
+                _ => Err(electrum::Error::WrongResponse.into()),
+            }
+        }
+    }
+
+    fn get_txout(
+        &self,
+        outpoint: OutPoint,
+    ) -> impl Future<Output = Result<Option<(TxOut, Option<Transaction>)>, Error>> + Send {
+        async move {
+            let Some(txout) = get_tx(self, outpoint.txid)
+                .await?
+                .and_then(|tx| tx.output.get(outpoint.vout as usize).cloned())
+            else {
+                return Ok(None);
+            };
+            let history = match request(self, Request::sh_get_history(&txout.script_pubkey)).await?
+            {
+                Response::SHGetHistory(SHGetHistoryResponse { history, .. }) => history,
+                _ => return Err(electrum::Error::WrongResponse.into()),
+            };
+            for HistoryResult { txid, .. } in history {
+                if txid == outpoint.txid {
+                    continue;
+                }
+                let tx = get_tx(self, txid).await?.ok_or(Error::TxDoesNotExists)?;
+                if tx.input.iter().any(|i| i.previous_output == outpoint) {
+                    return Ok(Some((txout, Some(tx))));
+                }
+            }
+            Ok(Some((txout, None)))
+        }
+    }
+}
+
+impl AsyncChainSource for MemoryChain {
+    fn broadcast(&self, tx: &Transaction) -> impl Future<Output = Result<Txid, Error>> + Send {
+        let result = ChainSource::broadcast(&mut self.clone(), tx);
+        async move { result }
+    }
+
+    fn estimate_fee(
+        &self,
+        target: u16,
+    ) -> impl Future<Output = Result<Option<FeeRate>, Error>> + Send {
+        let result = ChainSource::estimate_fee(&mut self.clone(), target);
+        async move { result }
+    }
+
+    fn fee_histogram(&self) -> impl Future<Output = Result<Vec<(FeeRate, u64)>, Error>> + Send {
+        let result = ChainSource::fee_histogram(&mut self.clone());
+        async move { result }
+    }
+
+    fn get_txout(
+        &self,
+        outpoint: OutPoint,
+    ) -> impl Future<Output = Result<Option<(TxOut, Option<Transaction>)>, Error>> + Send {
+        let result = ChainSource::get_txout(&mut self.clone(), outpoint);
+        async move { result }
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+
+use crate::electrum;
+
+#[derive(Debug)]
+pub enum Error {
+    Electrum(electrum::Error),
+    Bitcoind(crate::bitcoind::Error),
+    Esplora(crate::esplora::Error),
+    /// The transaction is not known by the chain source
+    TxDoesNotExists,
+    /// The chain source does not support this call
+    Unsupported(&'static str),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Electrum(e) => write!(f, "{e}"),
+            Error::Bitcoind(e) => write!(f, "{e}"),
+            Error::Esplora(e) => write!(f, "{e}"),
+            Error::TxDoesNotExists => write!(f, "The transaction does not exists"),
+            Error::Unsupported(call) => write!(f, "{call} is not supported by this backend"),
+        }
+    }
+}
+
+impl From<electrum::Error> for Error {
+    fn from(value: electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
+
+impl From<crate::bitcoind::Error> for Error {
+    fn from(value: crate::bitcoind::Error) -> Self {
+        Error::Bitcoind(value)
+    }
+}
+
+impl From<crate::esplora::Error> for Error {
+    fn from(value: crate::esplora::Error) -> Self {
+        Error::Esplora(value)
+    }
+}
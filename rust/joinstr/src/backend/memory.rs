@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use miniscript::bitcoin::{
+    Address, Amount, FeeRate, OutPoint, Script, ScriptBuf, Transaction, Txid,
+};
+
+use super::{ChainSource, Error};
+use crate::coinjoin::BitcoinBackend;
+
+#[derive(Debug, Default)]
+struct State {
+    // txs by txid w/ their confirmation height
+    txs: HashMap<Txid, (Transaction, Option<u32>)>,
+    // order in which txs have been added, used to return histories in order
+    order: Vec<Txid>,
+    broadcasted: Vec<Transaction>,
+    fee_rate: Option<FeeRate>,
+    fee_histogram: Vec<(FeeRate, u64)>,
+    // history of the watched spks at the last poll
+    subscriptions: HashMap<ScriptBuf, Vec<(Txid, Option<u32>)>>,
+}
+
+impl State {
+    fn insert(&mut self, tx: Transaction, height: Option<u32>) {
+        let txid = tx.compute_txid();
+        if self.txs.insert(txid, (tx, height)).is_none() {
+            self.order.push(txid);
+        }
+    }
+
+    fn history(&self, script: &Script) -> Vec<(Txid, Option<u32>)> {
+        let pays_to = |outpoint: &OutPoint| {
+            self.txs
+                .get(&outpoint.txid)
+                .and_then(|(tx, _)| tx.output.get(outpoint.vout as usize))
+                .is_some_and(|txout| *txout.script_pubkey == *script)
+        };
+        self.order
+            .iter()
+            .filter_map(|txid| self.txs.get(txid).map(|(tx, height)| (txid, tx, height)))
+            .filter(|(_, tx, _)| {
+                tx.output.iter().any(|o| *o.script_pubkey == *script)
+                    || tx.input.iter().any(|i| pays_to(&i.previous_output))
+            })
+            .map(|(txid, _, height)| (*txid, *height))
+            .collect()
+    }
+}
+
+/// An in-memory [`ChainSource`] for tests: transactions are added by
+///   hand and broadcasted transactions are kept unconfirmed.
+///
+/// Clones share the same state, so a test can keep a handle on a chain
+///   given to a signer or a [`crate::joinstr::Joinstr`] instance.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryChain {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryChain {
+    /// Create an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a transaction to the chain.
+    ///
+    /// # Arguments
+    /// * `tx` - The transaction
+    /// * `height` - The height of the block including the transaction, None
+    ///   if unconfirmed
+    pub fn add_tx(&self, tx: Transaction, height: Option<u32>) {
+        self.state.lock().expect("poisoned").insert(tx, height);
+    }
+
+    /// Set the fee rate returned by [`ChainSource::estimate_fee()`].
+    pub fn set_fee_rate(&self, fee_rate: Option<FeeRate>) {
+        self.state.lock().expect("poisoned").fee_rate = fee_rate;
+    }
+
+    /// Set the histogram returned by [`ChainSource::fee_histogram()`].
+    pub fn set_fee_histogram(&self, histogram: Vec<(FeeRate, u64)>) {
+        self.state.lock().expect("poisoned").fee_histogram = histogram;
+    }
+
+    /// Returns the transactions broadcasted through this chain.
+    pub fn broadcasted(&self) -> Vec<Transaction> {
+        self.state.lock().expect("poisoned").broadcasted.clone()
+    }
+}
+
+impl ChainSource for MemoryChain {
+    fn get_history_at(&mut self, script: &Script) -> Result<Vec<(Txid, Option<u32>)>, Error> {
+        Ok(self.state.lock().expect("poisoned").history(script))
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        self.state
+            .lock()
+            .expect("poisoned")
+            .txs
+            .get(&txid)
+            .map(|(tx, _)| tx.clone())
+            .ok_or(Error::TxDoesNotExists)
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error> {
+        let mut state = self.state.lock().expect("poisoned");
+        state.broadcasted.push(tx.clone());
+        state.insert(tx.clone(), None);
+        Ok(tx.compute_txid())
+    }
+
+    fn estimate_fee(&mut self, _target: u16) -> Result<Option<FeeRate>, Error> {
+        Ok(self.state.lock().expect("poisoned").fee_rate)
+    }
+
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, Error> {
+        Ok(self.state.lock().expect("poisoned").fee_histogram.clone())
+    }
+
+    fn subscribe(&mut self, script: &Script) -> Result<(), Error> {
+        let mut state = self.state.lock().expect("poisoned");
+        let history = state.history(script);
+        state.subscriptions.insert(script.to_owned(), history);
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, Error> {
+        let mut state = self.state.lock().expect("poisoned");
+        let mut changed = Vec::new();
+        let spks: Vec<_> = state.subscriptions.keys().cloned().collect();
+        for spk in spks {
+            let history = state.history(&spk);
+            if state.subscriptions.get(&spk) != Some(&history) {
+                state.subscriptions.insert(spk.clone(), history);
+                changed.push(spk);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl BitcoinBackend for MemoryChain {
+    type Error = Error;
+    fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
+        Ok(!self.get_history_at(&addr.script_pubkey())?.is_empty())
+    }
+
+    fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Error> {
+        match self.get_tx(outpoint.txid) {
+            Ok(tx) => Ok(tx.output.get(outpoint.vout as usize).map(|o| o.value)),
+            Err(Error::TxDoesNotExists) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miniscript::bitcoin::{absolute::LockTime, transaction::Version, TxIn, TxOut};
+
+    use super::*;
+
+    fn tx(input: OutPoint, script: &Script) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: input,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: script.to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn history_and_subscriptions() {
+        let spk = ScriptBuf::from_bytes(vec![0x51]);
+        let other = ScriptBuf::from_bytes(vec![0x52]);
+        let mut chain = MemoryChain::new();
+        chain.subscribe(&spk).unwrap();
+
+        let receive = tx(OutPoint::null(), &spk);
+        let receive_txid = receive.compute_txid();
+        chain.add_tx(receive, Some(100));
+        assert_eq!(chain.poll().unwrap(), vec![spk.clone()]);
+        assert!(chain.poll().unwrap().is_empty());
+
+        // spending from spk is part of its history
+        let spend = tx(OutPoint::new(receive_txid, 0), &other);
+        let spend_txid = chain.clone().broadcast(&spend).unwrap();
+        assert_eq!(chain.broadcasted(), vec![spend]);
+        assert_eq!(
+            chain.get_history_at(&spk).unwrap(),
+            vec![(receive_txid, Some(100)), (spend_txid, None)]
+        );
+        assert_eq!(chain.poll().unwrap(), vec![spk.clone()]);
+
+        let (coins, txs) = chain.get_coins_at(&spk).unwrap();
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].1, OutPoint::new(receive_txid, 0));
+        assert_eq!(txs.len(), 2);
+        assert_eq!(
+            chain
+                .get_outpoint_value(OutPoint::new(spend_txid, 0))
+                .unwrap(),
+            Some(Amount::from_sat(1_000))
+        );
+        assert_eq!(chain.get_outpoint_value(OutPoint::null()).unwrap(), None);
+    }
+}
@@ -0,0 +1,285 @@
+mod error;
+pub mod memory;
+#[cfg(feature = "async")]
+pub mod r#async;
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+pub use error::Error;
+use miniscript::bitcoin::{
+    Address, Amount, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bitcoind::{BitcoindBackend, BitcoindConfig},
+    coinjoin::BitcoinBackend,
+    electrum,
+    electrum_pool::ElectrumPool,
+    esplora::EsploraClient,
+};
+
+/// A [`ChainSource`] that can be shared between a signer and a
+///   [`crate::joinstr::Joinstr`] instance.
+pub type SharedChainSource = Arc<Mutex<dyn ChainSource>>;
+
+/// Wrap a [`ChainSource`] into a [`SharedChainSource`].
+pub fn shared(source: impl ChainSource + 'static) -> SharedChainSource {
+    Arc::new(Mutex::new(source))
+}
+
+/// A source of chain data used by [`crate::signer::WpkhHotSigner`],
+///   [`crate::joinstr::Joinstr`] and the [`crate::interface`] functions.
+pub trait ChainSource: Debug + Send {
+    /// Returns the txid & confirmation height (None if unconfirmed) of the
+    ///   transactions paying to or spending from the given spk.
+    fn get_history_at(&mut self, script: &Script) -> Result<Vec<(Txid, Option<u32>)>, Error>;
+
+    /// Get a transaction by its txid.
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error>;
+
+    /// Broadcast a transaction.
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error>;
+
+    /// Estimate the fee rate needed for a transaction to confirm within
+    ///   `target` blocks, returns None if no estimation is available.
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, Error>;
+
+    /// Returns the fee histogram of the mempool: (fee rate, vsize) pairs
+    ///   sorted by decreasing fee rate, the vsize being the size of the
+    ///   transactions paying between this fee rate and the previous one.
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, Error> {
+        Err(Error::Unsupported("fee_histogram"))
+    }
+
+    /// Watch the given spk, see [`ChainSource::poll()`].
+    fn subscribe(&mut self, script: &Script) -> Result<(), Error>;
+
+    /// Returns the spks watched w/ [`ChainSource::subscribe()`] whose history
+    ///   changed since the last poll.
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, Error>;
+
+    /// Returns the config needed to connect again to this chain source, None
+    ///   if it cannot be persisted.
+    fn config(&self) -> Option<ChainConfig> {
+        None
+    }
+
+    /// Get a list of txid of all transactions paying to or spending from the
+    ///   given spk.
+    fn get_coins_tx_at(&mut self, script: &Script) -> Result<Vec<Txid>, Error> {
+        Ok(self
+            .get_history_at(script)?
+            .into_iter()
+            .map(|(txid, _)| txid)
+            .collect())
+    }
+
+    /// Returns the output at `outpoint` w/ the transaction spending it if
+    ///   any, None if the output does not exist.
+    #[allow(clippy::type_complexity)]
+    fn get_txout(
+        &mut self,
+        outpoint: OutPoint,
+    ) -> Result<Option<(TxOut, Option<Transaction>)>, Error> {
+        let txout = match self.get_tx(outpoint.txid) {
+            Ok(tx) => tx.output.get(outpoint.vout as usize).cloned(),
+            Err(Error::TxDoesNotExists) => None,
+            Err(e) => return Err(e),
+        };
+        let Some(txout) = txout else {
+            return Ok(None);
+        };
+        for txid in self.get_coins_tx_at(&txout.script_pubkey)? {
+            if txid == outpoint.txid {
+                continue;
+            }
+            let tx = self.get_tx(txid)?;
+            if tx.input.iter().any(|i| i.previous_output == outpoint) {
+                return Ok(Some((txout, Some(tx))));
+            }
+        }
+        Ok(Some((txout, None)))
+    }
+
+    /// Get the coins paying to the given spk and the transactions that
+    ///   created them.
+    #[allow(clippy::type_complexity)]
+    fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
+        let mut txouts = Vec::new();
+        let mut transactions = HashMap::new();
+        for txid in self.get_coins_tx_at(script)? {
+            let tx = self.get_tx(txid)?;
+            for (i, txout) in tx.output.iter().enumerate() {
+                if *txout.script_pubkey == *script {
+                    txouts.push((txout.clone(), OutPoint::new(txid, i as u32)));
+                }
+            }
+            transactions.insert(txid, tx);
+        }
+        Ok((txouts, transactions))
+    }
+}
+
+/// The config of a persistable [`ChainSource`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChainConfig {
+    /// (url, port) of an electrum server, url is prefixed w/ `ssl://` for
+    ///   SSL connections
+    Electrum(String, u16),
+    Bitcoind(BitcoindConfig),
+    /// Base url of an Esplora API
+    Esplora(String),
+    /// (url, port) of the servers of an [`ElectrumPool`] and its quorum
+    ElectrumPool(Vec<(String, u16)>, usize),
+}
+
+impl ChainConfig {
+    /// Connect to the chain source.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection to an electrum
+    ///   server fails.
+    pub fn connect(self) -> Result<Backend, Error> {
+        Ok(match self {
+            ChainConfig::Electrum(url, port) => electrum::Client::new(&url, port)?.into(),
+            ChainConfig::Bitcoind(config) => BitcoindBackend::from_config(config).into(),
+            ChainConfig::Esplora(url) => EsploraClient::new(&url).into(),
+            ChainConfig::ElectrumPool(servers, quorum) => {
+                ElectrumPool::new(servers).quorum(quorum).into()
+            }
+        })
+    }
+}
+
+/// One of the bitcoin backends implemented by this crate.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Electrum(electrum::Client),
+    Bitcoind(BitcoindBackend),
+    Esplora(EsploraClient),
+    ElectrumPool(ElectrumPool),
+}
+
+impl Backend {
+    fn source(&mut self) -> &mut dyn ChainSource {
+        match self {
+            Backend::Electrum(client) => client,
+            Backend::Bitcoind(client) => client,
+            Backend::Esplora(client) => client,
+            Backend::ElectrumPool(pool) => pool,
+        }
+    }
+}
+
+impl From<electrum::Client> for Backend {
+    fn from(value: electrum::Client) -> Self {
+        Backend::Electrum(value)
+    }
+}
+
+impl From<BitcoindBackend> for Backend {
+    fn from(value: BitcoindBackend) -> Self {
+        Backend::Bitcoind(value)
+    }
+}
+
+impl From<EsploraClient> for Backend {
+    fn from(value: EsploraClient) -> Self {
+        Backend::Esplora(value)
+    }
+}
+
+impl From<ElectrumPool> for Backend {
+    fn from(value: ElectrumPool) -> Self {
+        Backend::ElectrumPool(value)
+    }
+}
+
+impl ChainSource for Backend {
+    fn get_history_at(&mut self, script: &Script) -> Result<Vec<(Txid, Option<u32>)>, Error> {
+        self.source().get_history_at(script)
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        self.source().get_tx(txid)
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error> {
+        self.source().broadcast(tx)
+    }
+
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, Error> {
+        self.source().estimate_fee(target)
+    }
+
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, Error> {
+        self.source().fee_histogram()
+    }
+
+    fn subscribe(&mut self, script: &Script) -> Result<(), Error> {
+        self.source().subscribe(script)
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, Error> {
+        self.source().poll()
+    }
+
+    fn config(&self) -> Option<ChainConfig> {
+        match self {
+            Backend::Electrum(client) => ChainSource::config(client),
+            Backend::Bitcoind(client) => ChainSource::config(client),
+            Backend::Esplora(client) => ChainSource::config(client),
+            Backend::ElectrumPool(pool) => ChainSource::config(pool),
+        }
+    }
+
+    fn get_coins_tx_at(&mut self, script: &Script) -> Result<Vec<Txid>, Error> {
+        self.source().get_coins_tx_at(script)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_txout(
+        &mut self,
+        outpoint: OutPoint,
+    ) -> Result<Option<(TxOut, Option<Transaction>)>, Error> {
+        self.source().get_txout(outpoint)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
+        self.source().get_coins_at(script)
+    }
+}
+
+impl BitcoinBackend for Backend {
+    type Error = Error;
+    fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
+        Ok(match self {
+            Backend::Electrum(client) => client.address_already_used(addr)?,
+            Backend::Bitcoind(client) => client.address_already_used(addr)?,
+            Backend::Esplora(client) => client.address_already_used(addr)?,
+            Backend::ElectrumPool(pool) => pool.address_already_used(addr)?,
+        })
+    }
+
+    fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Error> {
+        Ok(match self {
+            Backend::Electrum(client) => client.get_outpoint_value(outpoint)?,
+            Backend::Bitcoind(client) => client.get_outpoint_value(outpoint)?,
+            Backend::Esplora(client) => client.get_outpoint_value(outpoint)?,
+            Backend::ElectrumPool(pool) => pool.get_outpoint_value(outpoint)?,
+        })
+    }
+}
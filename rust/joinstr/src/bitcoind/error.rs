@@ -0,0 +1,35 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(crate::http::Error),
+    /// Fail to read the cookie file
+    Cookie(String),
+    /// The node answered w/ an HTTP error status
+    Status(u16, String),
+    /// The node answered w/ a JSON-RPC error
+    Rpc(i64, String),
+    Json(String),
+    TxParsing,
+    WrongResponse,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "{}", e),
+            Error::Cookie(e) => write!(f, "Fail to read cookie file: {}", e),
+            Error::Status(code, body) => write!(f, "bitcoind HTTP error {}: {}", code, body),
+            Error::Rpc(code, msg) => write!(f, "bitcoind RPC error {}: {}", code, msg),
+            Error::Json(e) => write!(f, "Fail to parse bitcoind response: {}", e),
+            Error::TxParsing => write!(f, "Fail to parse the transaction"),
+            Error::WrongResponse => write!(f, "Wrong response from bitcoind"),
+        }
+    }
+}
+
+impl From<crate::http::Error> for Error {
+    fn from(value: crate::http::Error) -> Self {
+        Error::Http(value)
+    }
+}
@@ -0,0 +1,404 @@
+mod error;
+
+use std::{collections::HashMap, fs, path::PathBuf, str::FromStr};
+
+pub use error::Error;
+use miniscript::bitcoin::{
+    base64::{engine::general_purpose::STANDARD, Engine},
+    consensus::encode::{deserialize_hex, serialize_hex},
+    Address, Amount, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    backend::{self, ChainConfig, ChainSource},
+    coinjoin::BitcoinBackend,
+    http,
+};
+
+/// Authentication method for the bitcoind RPC interface.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Auth {
+    /// Path to the `.cookie` file, read on each request as bitcoind
+    ///   renew it on restart.
+    Cookie(PathBuf),
+    /// `rpcuser` / `rpcpassword`
+    UserPass(String, String),
+}
+
+impl Auth {
+    fn header(&self) -> Result<String, Error> {
+        let credentials = match self {
+            Auth::Cookie(path) => fs::read_to_string(path)
+                .map_err(|e| Error::Cookie(format!("{}: {e}", path.display())))?
+                .trim()
+                .to_string(),
+            Auth::UserPass(user, pass) => format!("{user}:{pass}"),
+        };
+        Ok(format!("Basic {}", STANDARD.encode(credentials)))
+    }
+}
+
+/// The config needed to (re)connect to a bitcoind node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitcoindConfig {
+    pub url: String,
+    pub auth: Auth,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxOutResult {
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: ScriptPubKeyResult,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptPubKeyResult {
+    hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanUnspent {
+    txid: Txid,
+    vout: u32,
+    amount: f64,
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanResult {
+    success: bool,
+    unspents: Vec<ScanUnspent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EstimateResult {
+    feerate: Option<f64>,
+}
+
+/// A [`BitcoinBackend`] talking to a Bitcoin Core node over JSON-RPC.
+///
+/// Lookups rely on `scantxoutset`, spent outputs are not visible, see
+///   [`BitcoindBackend::address_already_used()`].
+#[derive(Debug, Clone)]
+pub struct BitcoindBackend {
+    url: String,
+    auth: Auth,
+    last_id: u64,
+}
+
+impl BitcoindBackend {
+    /// Create a new backend, no request is sent to the node.
+    ///
+    /// # Arguments
+    /// * `url` - The url of the RPC interface (ex: `http://127.0.0.1:8332`),
+    ///   can contain a wallet path (ex: `http://127.0.0.1:8332/wallet/w1`)
+    /// * `auth` - The authentication method
+    pub fn new(url: &str, auth: Auth) -> Self {
+        BitcoindBackend {
+            url: url.into(),
+            auth,
+            last_id: 0,
+        }
+    }
+
+    /// Create a new backend from a [`BitcoindConfig`].
+    pub fn from_config(config: BitcoindConfig) -> Self {
+        Self::new(&config.url, config.auth)
+    }
+
+    /// Returns the config of this backend.
+    pub fn config(&self) -> BitcoindConfig {
+        BitcoindConfig {
+            url: self.url.clone(),
+            auth: self.auth.clone(),
+        }
+    }
+
+    /// Send a JSON-RPC request to the node.
+    ///
+    /// # Arguments
+    /// * `method` - The RPC method
+    /// * `params` - The params of the request, must be a JSON array
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the cookie file cannot be read
+    ///   - the HTTP request fails
+    ///   - the node returns an error
+    ///   - the result cannot be parsed as `T`
+    pub fn call<T: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T, Error> {
+        self.last_id += 1;
+        let request = json!({
+            "jsonrpc": "1.0",
+            "id": self.last_id,
+            "method": method,
+            "params": params,
+        });
+        log::debug!("BitcoindBackend::call() {request}");
+        let auth = self.auth.header()?;
+        let headers = [
+            ("Authorization", auth.as_str()),
+            ("Content-Type", "application/json"),
+        ];
+        let body = serde_json::to_vec(&request).map_err(|e| Error::Json(e.to_string()))?;
+        let response = http::post(&self.url, &headers, &body)?;
+
+        // NOTE: bitcoind returns RPC errors w/ a 500 status, so we try to
+        // parse the body before checking the status
+        match serde_json::from_slice::<RpcResponse>(&response.body) {
+            Ok(RpcResponse {
+                error: Some(RpcError { code, message }),
+                ..
+            }) => Err(Error::Rpc(code, message)),
+            Ok(RpcResponse { result, .. }) if response.is_success() => {
+                serde_json::from_value(result.unwrap_or(Value::Null))
+                    .map_err(|e| Error::Json(e.to_string()))
+            }
+            _ => Err(Error::Status(response.status, response.text())),
+        }
+    }
+
+    /// Check the node is reachable, returns the current block height.
+    pub fn ping(&mut self) -> Result<u64, Error> {
+        self.call("getblockcount", json!([]))
+    }
+
+    /// Get a transaction by its txid.
+    ///
+    /// Note: bitcoind must run w/ `txindex=1` for confirmed transactions
+    ///   not related to its wallet.
+    pub fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        let raw: String = self.call("getrawtransaction", json!([txid.to_string()]))?;
+        deserialize_hex(&raw).map_err(|_| Error::TxParsing)
+    }
+
+    /// Broadcast a transaction.
+    pub fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error> {
+        let txid: String = self.call("sendrawtransaction", json!([serialize_hex(tx)]))?;
+        Txid::from_str(&txid).map_err(|_| Error::WrongResponse)
+    }
+
+    /// Estimate the fee rate needed for a transaction to confirm within
+    ///   `target` blocks, returns None if the node has not enough data.
+    pub fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, Error> {
+        let estimate: EstimateResult = self.call("estimatesmartfee", json!([target]))?;
+        Ok(estimate.feerate.and_then(|btc_kvb| {
+            // BTC/kvB => sat/kwu
+            let sat_kvb = Amount::from_btc(btc_kvb).ok()?.to_sat();
+            Some(FeeRate::from_sat_per_kwu(sat_kvb / 4))
+        }))
+    }
+
+    /// Returns the value of an unspent output, None if the output does not
+    ///   exists or is spent.
+    ///
+    /// # Arguments
+    /// * `outpoint` - The outpoint to look up
+    /// * `include_mempool` - Whether to look at the mempool too
+    pub fn get_txout(
+        &mut self,
+        outpoint: OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<Amount>, Error> {
+        Ok(self
+            .get_unspent(outpoint, include_mempool)?
+            .map(|txout| txout.value))
+    }
+
+    // Returns the unspent output at `outpoint`, None if the output does not
+    // exists or is spent.
+    fn get_unspent(
+        &mut self,
+        outpoint: OutPoint,
+        include_mempool: bool,
+    ) -> Result<Option<TxOut>, Error> {
+        let txout: Option<TxOutResult> = self.call(
+            "gettxout",
+            json!([outpoint.txid.to_string(), outpoint.vout, include_mempool]),
+        )?;
+        let Some(txout) = txout else {
+            return Ok(None);
+        };
+        Ok(Some(TxOut {
+            value: Amount::from_btc(txout.value).map_err(|_| Error::WrongResponse)?,
+            script_pubkey: ScriptBuf::from_hex(&txout.script_pubkey.hex)
+                .map_err(|_| Error::WrongResponse)?,
+        }))
+    }
+
+    fn scan(&mut self, descriptor: String) -> Result<Vec<ScanUnspent>, Error> {
+        let scan: ScanResult = self.call("scantxoutset", json!(["start", [descriptor]]))?;
+        if !scan.success {
+            return Err(Error::WrongResponse);
+        }
+        Ok(scan.unspents)
+    }
+
+    /// Get the coins that pay to the given spk using `scantxoutset`.
+    ///
+    /// Note: only unspent coins are returned, transactions are returned
+    ///   only if bitcoind can fetch them (`txindex=1`).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a request fails.
+    #[allow(clippy::type_complexity)]
+    pub fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
+        let mut txouts = Vec::new();
+        let mut transactions = HashMap::new();
+        for unspent in self.scan(format!("raw({})", script.to_hex_string()))? {
+            let value = Amount::from_btc(unspent.amount).map_err(|_| Error::WrongResponse)?;
+            let txout = TxOut {
+                value,
+                script_pubkey: script.to_owned(),
+            };
+            txouts.push((txout, OutPoint::new(unspent.txid, unspent.vout)));
+            if let Ok(tx) = self.get_tx(unspent.txid) {
+                transactions.insert(unspent.txid, tx);
+            }
+        }
+        Ok((txouts, transactions))
+    }
+
+    /// Get a list of txid of the transactions that have an unspent output
+    ///   paying to the given spk.
+    ///
+    /// Note: `scantxoutset` only looks at the UTXO set, transactions whose
+    ///   outputs are all spent are not returned.
+    pub fn get_coins_tx_at(&mut self, script: &Script) -> Result<Vec<Txid>, Error> {
+        let mut txids: Vec<Txid> = self
+            .scan(format!("raw({})", script.to_hex_string()))?
+            .into_iter()
+            .map(|u| u.txid)
+            .collect();
+        txids.sort();
+        txids.dedup();
+        Ok(txids)
+    }
+}
+
+// RPC_INVALID_ADDRESS_OR_KEY, returned by getrawtransaction for unknown txs
+const RPC_NOT_FOUND: i64 = -5;
+
+/// Note: history is built from the UTXO set, transactions whose outputs are
+///   all spent are not returned and subscriptions are not supported.
+impl ChainSource for BitcoindBackend {
+    fn get_history_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<Vec<(Txid, Option<u32>)>, backend::Error> {
+        let mut history: Vec<_> = self
+            .scan(format!("raw({})", script.to_hex_string()))?
+            .into_iter()
+            .map(|u| (u.txid, Some(u.height)))
+            .collect();
+        history.sort();
+        history.dedup();
+        Ok(history)
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, backend::Error> {
+        match BitcoindBackend::get_tx(self, txid) {
+            Err(Error::Rpc(RPC_NOT_FOUND, _)) => Err(backend::Error::TxDoesNotExists),
+            r => Ok(r?),
+        }
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, backend::Error> {
+        Ok(BitcoindBackend::broadcast(self, tx)?)
+    }
+
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, backend::Error> {
+        Ok(BitcoindBackend::estimate_fee(self, target)?)
+    }
+
+    fn subscribe(&mut self, _script: &Script) -> Result<(), backend::Error> {
+        Err(backend::Error::Unsupported("subscribe"))
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, backend::Error> {
+        Err(backend::Error::Unsupported("poll"))
+    }
+
+    fn config(&self) -> Option<ChainConfig> {
+        Some(ChainConfig::Bitcoind(BitcoindBackend::config(self)))
+    }
+
+    /// Look up the output w/ `gettxout` (mempool included), a spent output
+    ///   is reported as missing as bitcoind cannot return the transaction
+    ///   spending it.
+    #[allow(clippy::type_complexity)]
+    fn get_txout(
+        &mut self,
+        outpoint: OutPoint,
+    ) -> Result<Option<(TxOut, Option<Transaction>)>, backend::Error> {
+        Ok(self.get_unspent(outpoint, true)?.map(|txout| (txout, None)))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), backend::Error> {
+        Ok(BitcoindBackend::get_coins_at(self, script)?)
+    }
+}
+
+impl BitcoinBackend for BitcoindBackend {
+    type Error = Error;
+
+    /// Check if `addr` holds unspent coins using `scantxoutset`.
+    ///
+    /// Note: bitcoind keeps no address index, so this check is best-effort:
+    ///   - only the UTXO set is looked at, an address that received coins
+    ///     that are all spent is not detected as used
+    ///   - the whole UTXO set is scanned on every call (~1 min on mainnet),
+    ///     and only one scan can run at a time on a node
+    ///
+    /// Use an electrum or esplora backend if address reuse must be detected
+    ///   reliably.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the scan fails or another
+    ///   scan is already running.
+    fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
+        Ok(!self.scan(format!("addr({addr})"))?.is_empty())
+    }
+
+    fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Error> {
+        self.get_txout(outpoint, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_header() {
+        let auth = Auth::UserPass("user".into(), "pass".into());
+        assert_eq!(auth.header().unwrap(), "Basic dXNlcjpwYXNz");
+        let auth = Auth::Cookie("/non/existing/.cookie".into());
+        assert!(matches!(auth.header(), Err(Error::Cookie(_))));
+    }
+}
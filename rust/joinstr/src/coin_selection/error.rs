@@ -0,0 +1,45 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    PoolPayloadMissing,
+    FeeProviderNotImplemented,
+    CoinTooSmall,
+    AddressesMissing,
+    ChangeAddressMissing,
+    WrongAddressNetwork,
+    NotYetBuilt,
+    Signer(crate::signer::Error),
+    Backend(crate::backend::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::PoolPayloadMissing => write!(f, "The pool payload is missing"),
+            Error::FeeProviderNotImplemented => write!(f, "Fee provider not yet implemented"),
+            Error::CoinTooSmall => write!(f, "Coin value too small to fund a pool input"),
+            Error::AddressesMissing => write!(f, "No output address provided"),
+            Error::ChangeAddressMissing => write!(
+                f,
+                "A change output is needed but no change address provided"
+            ),
+            Error::WrongAddressNetwork => write!(f, "Address is not valid for this network"),
+            Error::NotYetBuilt => write!(f, "The transaction have not been built yet"),
+            Error::Signer(e) => write!(f, "{}", e),
+            Error::Backend(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<crate::signer::Error> for Error {
+    fn from(value: crate::signer::Error) -> Self {
+        Error::Signer(value)
+    }
+}
+
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        Error::Backend(value)
+    }
+}
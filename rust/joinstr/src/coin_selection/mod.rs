@@ -0,0 +1,389 @@
+mod error;
+pub use error::Error;
+
+use miniscript::bitcoin::{
+    absolute, transaction::Version, Address, Amount, Network, Transaction, TxIn, TxOut, Witness,
+};
+
+use crate::{
+    backend::ChainSource,
+    nostr::{Fee, Pool},
+    signer::{Coin, WpkhHotSigner},
+};
+
+/// Weight of a P2WPKH input (outpoint, sequence, empty script_sig & witness)
+pub const P2WPKH_INPUT_WEIGHT: u64 = 272;
+/// Weight of a P2WPKH output
+pub const P2WPKH_OUTPUT_WEIGHT: u64 = 124;
+/// Weight of the transaction fields that do not depend on inputs/outputs
+///   (version, locktime, inputs/outputs count & segwit marker/flag)
+pub const TX_OVERHEAD_WEIGHT: u64 = 42;
+/// Dust limit of a P2WPKH output
+pub const P2WPKH_DUST: Amount = Amount::from_sat(294);
+
+/// Returns the (denomination, fee) of a pool.
+///
+/// # Errors
+///
+/// This function will return an error if:
+///   - the pool payload is missing
+///   - the pool use a fee provider
+fn pool_params(pool: &Pool) -> Result<(Amount, u32), Error> {
+    match &pool.payload {
+        Some(payload) => match payload.fee {
+            Fee::Fixed(fee) => Ok((payload.denomination, fee)),
+            Fee::Provider(_) => Err(Error::FeeProviderNotImplemented),
+        },
+        None => Err(Error::PoolPayloadMissing),
+    }
+}
+
+/// Returns the minimum value a coin must have in order to be registered
+///   as input of a coinjoin.
+///
+/// Note: the fee check of [`crate::coinjoin::CoinJoin::generate_tx()`] is done
+///   against the transaction vsize, every peer pay for its own input & output
+///   plus the whole transaction overhead, in order to stay on the safe side.
+///
+/// # Arguments
+/// * `denomination` - The denomination of the pool
+/// * `fee` - The fee rate of the pool in sats/vb
+pub fn min_input_value(denomination: Amount, fee: u32) -> Amount {
+    let weight = P2WPKH_INPUT_WEIGHT + P2WPKH_OUTPUT_WEIGHT + TX_OVERHEAD_WEIGHT;
+    denomination + fee_for(weight, fee as u64)
+}
+
+/// Select the best coin to register to a pool, the best coin is the smallest
+///   coin that can pay for the denomination and the fees, as any extra value
+///   is lost as mining fee. Returns None if no coin match.
+///
+/// # Arguments
+/// * `coins` - The coins to select from, usually [`WpkhHotSigner::list_coins()`]
+/// * `denomination` - The denomination of the pool
+/// * `fee` - The fee rate of the pool
+pub fn select_coin(coins: &[Coin], denomination: Amount, fee: u32) -> Option<Coin> {
+    let min = min_input_value(denomination, fee);
+    coins
+        .iter()
+        .filter(|c| c.txout.value >= min)
+        .min_by_key(|c| c.txout.value)
+        .cloned()
+}
+
+/// Select the best coin of the signer to register to the given pool.
+///   See [`select_coin()`].
+///
+/// Note: [`WpkhHotSigner::get_coins_at()`] should be call before in order to
+///   fill the signer coins.
+///
+/// # Errors
+///
+/// This function will return an error if:
+///   - the pool payload is missing
+///   - the pool use a fee provider
+pub fn select_coin_for_pool(signer: &WpkhHotSigner, pool: &Pool) -> Result<Option<Coin>, Error> {
+    let (denomination, fee) = pool_params(pool)?;
+    let coins: Vec<_> = signer.list_coins().into_iter().map(|(_, c)| c).collect();
+    Ok(select_coin(&coins, denomination, fee))
+}
+
+/// Returns the fee to pay for a transaction of `weight` at `fee_rate` (sats/vb).
+fn fee_for(weight: u64, fee_rate: u64) -> Amount {
+    Amount::from_sat(weight.div_ceil(4) * fee_rate)
+}
+
+/// Builder for a tx0, the transaction that split a single coin into several
+///   coins of value [`min_input_value()`] (ready to be registered in a pool)
+///   plus a change output.
+#[derive(Debug, Clone)]
+pub struct Tx0 {
+    /// The coin to split
+    input: Coin,
+    /// The value of every premix output
+    premix_value: Amount,
+    /// Fee rate of the tx0 in sats/vb
+    fee_rate: u64,
+    /// Addresses to receive premix coins, one output is created per address
+    ///   at most
+    addresses: Vec<Address>,
+    /// Address to receive the change
+    change: Option<Address>,
+    network: Network,
+    /// The unsigned transaction
+    tx: Option<Transaction>,
+}
+
+impl Tx0 {
+    /// Create a new [`Tx0`] builder.
+    ///
+    /// # Arguments
+    /// * `input` - The coin to split
+    /// * `denomination` - The denomination of the pool
+    /// * `pool_fee` - The fee rate of the pool
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    pub fn new(input: Coin, denomination: Amount, pool_fee: u32, network: Network) -> Self {
+        Tx0 {
+            input,
+            premix_value: min_input_value(denomination, pool_fee),
+            fee_rate: 1,
+            addresses: Vec::new(),
+            change: None,
+            network,
+            tx: None,
+        }
+    }
+
+    /// Create a new [`Tx0`] builder that produce coins for the given pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool payload is missing
+    ///   - the pool use a fee provider
+    pub fn for_pool(input: Coin, pool: &Pool) -> Result<Self, Error> {
+        let (denomination, fee) = pool_params(pool)?;
+        Ok(Self::new(input, denomination, fee, pool.network))
+    }
+
+    /// Set the fee rate of the tx0 in sats/vb
+    pub fn fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Add an address to receive a premix coin
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is for the wrong network
+    pub fn address(mut self, addr: Address) -> Result<Self, Error> {
+        self.check_network(&addr)?;
+        self.addresses.push(addr);
+        Ok(self)
+    }
+
+    /// Set the address to receive the change
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is for the wrong network
+    pub fn change(mut self, addr: Address) -> Result<Self, Error> {
+        self.check_network(&addr)?;
+        self.change = Some(addr);
+        Ok(self)
+    }
+
+    fn check_network(&self, addr: &Address) -> Result<(), Error> {
+        if addr.as_unchecked().is_valid_for_network(self.network) {
+            Ok(())
+        } else {
+            Err(Error::WrongAddressNetwork)
+        }
+    }
+
+    /// Returns the max number of premix coins the input can fund, without
+    ///   taking in account the number of addresses provided.
+    pub fn max_outputs(&self) -> usize {
+        if self.premix_value == Amount::ZERO {
+            return 0;
+        }
+        let value = self.input.txout.value;
+        let mut count = 0;
+        loop {
+            let next = count as u64 + 1;
+            // with a change output
+            let weight =
+                TX_OVERHEAD_WEIGHT + P2WPKH_INPUT_WEIGHT + P2WPKH_OUTPUT_WEIGHT * (next + 1);
+            let needed = self.premix_value * next + fee_for(weight, self.fee_rate);
+            // without change, if the change would be dust
+            let weight_no_change =
+                TX_OVERHEAD_WEIGHT + P2WPKH_INPUT_WEIGHT + P2WPKH_OUTPUT_WEIGHT * next;
+            let needed_no_change =
+                self.premix_value * next + fee_for(weight_no_change, self.fee_rate);
+            if needed <= value || needed_no_change <= value {
+                count += 1;
+            } else {
+                return count;
+            }
+        }
+    }
+
+    /// Build the unsigned tx0, a premix output is created for every address
+    ///   provided as long as the input can fund it, the remaining value is
+    ///   sent to the change address if it's not dust.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - no address provided
+    ///   - the input cannot fund a single premix output
+    ///   - the change output is not dust and no change address provided
+    pub fn build(&mut self) -> Result<Transaction, Error> {
+        if self.addresses.is_empty() {
+            return Err(Error::AddressesMissing);
+        }
+        let count = self.max_outputs().min(self.addresses.len());
+        if count == 0 {
+            return Err(Error::CoinTooSmall);
+        }
+        let value = self.input.txout.value;
+        let premix_amount = self.premix_value * count as u64;
+
+        let mut output: Vec<TxOut> = self.addresses[..count]
+            .iter()
+            .map(|addr| TxOut {
+                value: self.premix_value,
+                script_pubkey: addr.script_pubkey(),
+            })
+            .collect();
+
+        // premix outputs + change output
+        let outputs_weight = P2WPKH_OUTPUT_WEIGHT * (count as u64 + 1);
+        let weight = TX_OVERHEAD_WEIGHT + P2WPKH_INPUT_WEIGHT + outputs_weight;
+        let change_value = value
+            .checked_sub(premix_amount + fee_for(weight, self.fee_rate))
+            .unwrap_or(Amount::ZERO);
+        if change_value >= P2WPKH_DUST {
+            if let Some(change) = &self.change {
+                output.push(TxOut {
+                    value: change_value,
+                    script_pubkey: change.script_pubkey(),
+                });
+            } else {
+                return Err(Error::ChangeAddressMissing);
+            }
+        }
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: self.input.outpoint,
+                sequence: self.input.sequence,
+                witness: Witness::new(),
+                ..Default::default()
+            }],
+            output,
+        };
+        self.tx = Some(tx.clone());
+        Ok(tx)
+    }
+
+    /// Sign the built tx0 with the signer owning the input.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - [`Tx0::build()`] have not been called before
+    ///   - signing fails
+    pub fn sign(&self, signer: &WpkhHotSigner) -> Result<Transaction, Error> {
+        let tx = self.tx.clone().ok_or(Error::NotYetBuilt)?;
+        Ok(signer.sign_tx(tx, &[self.input.clone()])?)
+    }
+
+    /// Sign the built tx0 and broadcast it.
+    ///
+    /// # Arguments
+    /// * `signer` - The signer owning the input
+    /// * `source` - The chain source the tx0 is broadcast through
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - [`Tx0::build()`] have not been called before
+    ///   - signing fails
+    ///   - broadcast fails
+    pub fn sign_and_broadcast(
+        &self,
+        signer: &WpkhHotSigner,
+        source: &mut dyn ChainSource,
+    ) -> Result<Transaction, Error> {
+        let tx = self.sign(signer)?;
+        source.broadcast(&tx)?;
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use miniscript::bitcoin::{OutPoint, Sequence, Txid};
+
+    use super::*;
+    use crate::signer::CoinPath;
+
+    fn coin(signer: &WpkhHotSigner, index: u32, value: Amount) -> Coin {
+        let coin_path = CoinPath::new(0, index);
+        Coin {
+            txout: TxOut {
+                value,
+                script_pubkey: signer.spk_at(&coin_path).unwrap(),
+            },
+            outpoint: OutPoint {
+                txid: Txid::from_str(
+                    "000000000000000000032aea06ce8a8dd70127e86382b5ea68c7d810e8dbfc9b",
+                )
+                .unwrap(),
+                vout: index,
+            },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            coin_path,
+        }
+    }
+
+    #[test]
+    fn select_smallest_matching_coin() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let denomination = Amount::from_sat(100_000);
+        let coins = vec![
+            coin(&signer, 0, Amount::from_sat(99_000)),
+            coin(&signer, 1, Amount::from_sat(150_000)),
+            coin(&signer, 2, Amount::from_sat(110_000)),
+            coin(&signer, 3, Amount::from_sat(500_000)),
+        ];
+        // (272 + 124 + 42) / 4 vb at 10 sats/vb
+        assert_eq!(min_input_value(denomination, 10), Amount::from_sat(101_100));
+        let selected = select_coin(&coins, denomination, 10).unwrap();
+        assert_eq!(selected.outpoint.vout, 2);
+
+        // no coin can pay the fees
+        assert!(select_coin(&coins[..1], denomination, 10).is_none());
+    }
+
+    #[test]
+    fn split_coin() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let denomination = Amount::from_sat(100_000);
+        let premix_value = min_input_value(denomination, 10);
+        let input = coin(&signer, 0, Amount::from_sat(1_000_000));
+        let mut tx0 = Tx0::new(input, denomination, 10, Network::Regtest)
+            .fee_rate(2)
+            .change(signer.change_addr_at(0))
+            .unwrap();
+        assert_eq!(tx0.max_outputs(), 9);
+        for i in 1..4 {
+            tx0 = tx0.address(signer.recv_addr_at(i)).unwrap();
+        }
+        let tx = tx0.build().unwrap();
+        // 3 premix outputs + change
+        assert_eq!(tx.output.len(), 4);
+        assert!(tx.output[..3].iter().all(|o| o.value == premix_value));
+        let out_value: Amount = tx.output.iter().map(|o| o.value).sum();
+        assert!(out_value < Amount::from_sat(1_000_000));
+
+        let signed = tx0.sign(&signer).unwrap();
+        assert!(!signed.input[0].witness.is_empty());
+    }
+
+    #[test]
+    fn split_coin_too_small() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let denomination = Amount::from_sat(100_000);
+        let input = coin(&signer, 0, Amount::from_sat(100_500));
+        let mut tx0 = Tx0::new(input, denomination, 10, Network::Regtest)
+            .address(signer.recv_addr_at(1))
+            .unwrap();
+        assert!(matches!(tx0.build(), Err(Error::CoinTooSmall)));
+    }
+}
@@ -15,7 +15,11 @@ pub enum Error {
     InputValueNotMatch,
     InputDoesNotExists,
     FeeTooLow(u64, u64, u64),
+    FeeTooHigh(u64, u64, u64),
     Electrum(electrum::Error),
+    Bitcoind(crate::bitcoind::Error),
+    Esplora(crate::esplora::Error),
+    Backend(crate::backend::Error),
     FailVerifyAmount,
     AmountMissing,
     Unknown(String),
@@ -51,15 +55,26 @@ impl Display for Error {
                 f,
                 "The input outpoint supplied by peer did not exists in our chain"
             ),
-            Error::FeeTooLow(expected_sat_vb, weight, sats) => write!(
+            Error::FeeTooLow(expected_sat_vb, vsize, sats) => write!(
                 f,
                 "Fee are inferior than the expected minimal \
                 fee rate ({} sats/vb):\n \
-                tx_weight: {} \n \
+                tx_vsize: {} \n \
                 fee_amount: {}",
-                expected_sat_vb, weight, sats
+                expected_sat_vb, vsize, sats
+            ),
+            Error::FeeTooHigh(max_sat_vb, vsize, sats) => write!(
+                f,
+                "Fee are superior than the expected maximal \
+                fee rate ({} sats/vb):\n \
+                tx_vsize: {} \n \
+                fee_amount: {}",
+                max_sat_vb, vsize, sats
             ),
             Error::Electrum(e) => write!(f, "Electrum error: {}", e),
+            Error::Bitcoind(e) => write!(f, "Bitcoind error: {}", e),
+            Error::Esplora(e) => write!(f, "Esplora error: {}", e),
+            Error::Backend(e) => write!(f, "Backend error: {}", e),
             Error::FailVerifyAmount => write!(f, "Fail to verify the input amount"),
             Error::AmountMissing => write!(
                 f,
@@ -75,3 +90,26 @@ impl From<electrum::Error> for Error {
         Error::Electrum(value)
     }
 }
+
+impl From<crate::bitcoind::Error> for Error {
+    fn from(value: crate::bitcoind::Error) -> Self {
+        Error::Bitcoind(value)
+    }
+}
+
+impl From<crate::esplora::Error> for Error {
+    fn from(value: crate::esplora::Error) -> Self {
+        Error::Esplora(value)
+    }
+}
+
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        match value {
+            crate::backend::Error::Electrum(e) => Error::Electrum(e),
+            crate::backend::Error::Bitcoind(e) => Error::Bitcoind(e),
+            crate::backend::Error::Esplora(e) => Error::Esplora(e),
+            e => Error::Backend(e),
+        }
+    }
+}
@@ -14,6 +14,9 @@ const BACKEND_RETRY: usize = 3;
 
 pub trait BitcoinBackend {
     type Error: Into<Error>;
+    /// Returns true if `addr` already received coins, the accuracy depends
+    ///   on the backend (e.g. [`crate::bitcoind::BitcoindBackend`] only sees
+    ///   unspent coins).
     fn address_already_used(&mut self, addr: &Address) -> Result<bool, Self::Error>;
     fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Self::Error>;
 }
@@ -35,6 +38,9 @@ pub struct CoinJoin<'a, C: BitcoinBackend> {
     /// Min feerate for the coinjoin to be considered broadcastable
     /// in sats/vb
     fee: usize,
+    /// Max feerate for the coinjoin to be considered broadcastable
+    /// in sats/vb, if any
+    max_fee: Option<usize>,
     /// Electrum client, used to check input amount and addresses
     /// already used
     client: Option<&'a mut C>,
@@ -59,6 +65,7 @@ where
             min_peer: 5,
             denomination,
             fee: 2,
+            max_fee: None,
             client,
         }
     }
@@ -75,6 +82,12 @@ where
         self
     }
 
+    /// Set the max fee rate in sats/vb
+    pub fn max_fee(mut self, max_fee: usize) -> Self {
+        self.max_fee = Some(max_fee);
+        self
+    }
+
     /// Mapping to [`CoinJoin::generate_psbt()`] using builder pattern.
     pub fn generate(mut self) -> Result<Self, Error> {
         self.generate_psbt()?;
@@ -259,6 +272,7 @@ where
     /// - [`ConJoin::psbt`] have not yet been generated
     /// - [`ConJoin::tx`] have already been generated
     /// - total input amount < (total_output + fees)
+    /// - the fee rate is lower than [`CoinJoin::fee`] or higher than [`CoinJoin::max_fee`]
     pub fn generate_tx(&mut self, dry_run: bool) -> Result<Option<Transaction>, Error>
     where
         <C as crate::coinjoin::BitcoinBackend>::Error: Display,
@@ -337,9 +351,15 @@ where
 
         // if not dry_run
         if let Some(fee) = fee {
-            let tx_weight = tx.weight().to_wu();
-            if (((fee.to_sat() as f64) / (tx_weight as f64)) < (self.fee as f64)) && !dry_run {
-                return Err(Error::FeeTooLow(self.fee as u64, tx_weight, fee.to_sat()));
+            let vsize = tx.vsize() as u64;
+            let fee_rate = (fee.to_sat() as f64) / (vsize as f64);
+            if fee_rate < (self.fee as f64) {
+                return Err(Error::FeeTooLow(self.fee as u64, vsize, fee.to_sat()));
+            }
+            if let Some(max_fee) = self.max_fee {
+                if fee_rate > (max_fee as f64) {
+                    return Err(Error::FeeTooHigh(max_fee as u64, vsize, fee.to_sat()));
+                }
             }
             self.tx = Some(tx);
             Ok(None)
@@ -1,13 +1,18 @@
 use backoff::Backoff;
 use bitcoin::{consensus, Address, Amount, ScriptBuf};
 use hex_conservative::FromHex;
-use miniscript::bitcoin::{consensus::Decodable, OutPoint, Script, Transaction, TxOut, Txid};
+use miniscript::bitcoin::{
+    consensus::Decodable, FeeRate, OutPoint, Script, Transaction, TxOut, Txid,
+};
+use serde::{Deserialize, Serialize};
 use simple_electrum_client::{
     electrum::{
         request::Request,
         response::{
-            ErrorResponse, HistoryResult, Response, SHGetHistoryResponse, SHNotification,
-            SHSubscribeResponse, TxBroadcastResponse, TxGetResponse, TxGetResult,
+            ErrorResponse, EstimateFeeResponse, FeeHistogramResponse, HeadersResponse,
+            HistoryResult, ListPeersResponse, OptionalFee, Response, SHGetHistoryResponse,
+            SHNotification, SHSubscribeResponse, TxBroadcastResponse, TxGetMerkleResponse,
+            TxGetResponse, TxGetResult,
         },
         types::ScriptHash,
     },
@@ -17,12 +22,21 @@ use simple_nostr_client::nostr::bitcoin::consensus::encode::serialize_hex;
 use std::{
     collections::{BTreeMap, HashMap},
     fmt::{Debug, Display},
-    sync::mpsc,
+    sync::{mpsc, Arc, Mutex},
     thread::{self},
     time::Duration,
 };
 
-use crate::coinjoin::BitcoinBackend;
+use crate::{
+    backend::{self, ChainConfig, ChainSource},
+    coinjoin::BitcoinBackend,
+    spv::HeaderChain,
+};
+
+// max number of headers an electrum server returns in a single request
+const MAX_HEADERS: usize = 2016;
+// number of blocks we step back when the server chain forks from ours
+const REORG_STEP: u32 = 10;
 
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -31,6 +45,13 @@ pub enum Error {
     WrongResponse,
     WrongOutPoint,
     TxDoesNotExists,
+    HeaderParsing,
+    Spv(crate::spv::Error),
+    Unverifiable,
+    NoServerAvailable,
+    Disagreement(String),
+    /// The server returned a transaction that does not match the requested txid
+    TxidMismatch(Txid),
 }
 
 impl Display for Error {
@@ -41,17 +62,31 @@ impl Display for Error {
             Error::WrongResponse => write!(f, "Wrong response from electrum server"),
             Error::WrongOutPoint => write!(f, "Requested outpoint did not exists"),
             Error::TxDoesNotExists => write!(f, "Requested transaction did not exists"),
+            Error::HeaderParsing => write!(f, "Fail to parse block headers"),
+            Error::Spv(e) => write!(f, "SPV verification failed: {e}"),
+            Error::Unverifiable => write!(f, "Data from the server cannot be verified"),
+            Error::NoServerAvailable => write!(f, "No electrum server available"),
+            Error::Disagreement(e) => write!(f, "Electrum servers disagree: {e}"),
+            Error::TxidMismatch(txid) => {
+                write!(f, "The server returned a wrong transaction for {txid}")
+            }
         }
     }
 }
 
+impl From<crate::spv::Error> for Error {
+    fn from(value: crate::spv::Error) -> Self {
+        Error::Spv(value)
+    }
+}
+
 impl From<raw_client::Error> for Error {
     fn from(value: raw_client::Error) -> Self {
         Error::Electrum(format!("{value:?}"))
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CoinStatus {
     Unconfirmed,
     Confirmed,
@@ -156,11 +191,42 @@ pub struct Client {
     last_id: usize,
     url: String,
     port: u16,
+    ssl: bool,
+    verif_certificate: bool,
+    spv: Option<Arc<Mutex<HeaderChain>>>,
+    // spks watched w/ ChainSource::subscribe()
+    subscriptions: HashMap<ScriptHash, ScriptBuf>,
+    // watched spks notified since the last ChainSource::poll()
+    notified: Vec<ScriptBuf>,
 }
 
 impl Clone for Client {
+    /// Open a new connection to the same server.
+    ///
+    /// Note: if the connection fails, the error is logged and the returned
+    ///   client will return an error on every call.
     fn clone(&self) -> Self {
-        Client::new(&self.url, self.port).unwrap()
+        let mut inner = RawClient::new_ssl_maybe(&self.url, self.port, self.ssl)
+            .verif_certificate(self.verif_certificate);
+        if let Err(e) = inner.try_connect() {
+            log::error!(
+                "electrum::Client::clone() fail to connect {}:{}: {e:?}",
+                self.url,
+                self.port
+            );
+        }
+        Client {
+            inner,
+            index: HashMap::new(),
+            last_id: 0,
+            url: self.url.clone(),
+            port: self.port,
+            ssl: self.ssl,
+            verif_certificate: self.verif_certificate,
+            spv: self.spv.clone(),
+            subscriptions: HashMap::new(),
+            notified: Vec::new(),
+        }
     }
 }
 
@@ -171,17 +237,7 @@ impl Client {
     /// * `address` - url/ip of the electrum server as String
     /// * `port` - port of the electrum server
     pub fn new(address: &str, port: u16) -> Result<Self, Error> {
-        let ssl = address.starts_with("ssl://");
-        let address = address.to_string().replace("ssl://", "");
-        let mut inner = RawClient::new_ssl_maybe(&address, port, ssl);
-        inner.try_connect()?;
-        Ok(Client {
-            inner,
-            index: HashMap::new(),
-            last_id: 0,
-            url: address,
-            port,
-        })
+        Self::connect(address, port, true)
     }
 
     /// Create a new local electrum client: SSL certificate validation id disabled in
@@ -191,9 +247,14 @@ impl Client {
     /// * `address` - url/ip of the electrum server as String
     /// * `port` - port of the electrum server
     pub fn new_local(address: &str, port: u16) -> Result<Self, Error> {
+        Self::connect(address, port, false)
+    }
+
+    fn connect(address: &str, port: u16, verif_certificate: bool) -> Result<Self, Error> {
         let ssl = address.starts_with("ssl://");
         let address = address.to_string().replace("ssl://", "");
-        let mut inner = RawClient::new_ssl_maybe(&address, port, ssl).verif_certificate(false);
+        let mut inner =
+            RawClient::new_ssl_maybe(&address, port, ssl).verif_certificate(verif_certificate);
         inner.try_connect()?;
         Ok(Client {
             inner,
@@ -201,9 +262,31 @@ impl Client {
             last_id: 0,
             url: address,
             port,
+            ssl,
+            verif_certificate,
+            spv: None,
+            subscriptions: HashMap::new(),
+            notified: Vec::new(),
         })
     }
 
+    /// Enable SPV verification: block headers are synced & validated, and
+    ///   confirmed transactions returned by [`Client::get_coins_at()`] or
+    ///   used by [`BitcoinBackend`] lookups are checked against their merkle
+    ///   proof. The header chain is shared between clones of the client.
+    ///
+    /// # Arguments
+    /// * `chain` - The header chain to start from (genesis or checkpoint)
+    pub fn spv(mut self, chain: HeaderChain) -> Self {
+        self.spv = Some(Arc::new(Mutex::new(chain)));
+        self
+    }
+
+    /// Returns true if SPV verification is enabled.
+    pub fn is_spv(&self) -> bool {
+        self.spv.is_some()
+    }
+
     /// Generate a new request id
     fn id(&mut self) -> usize {
         self.last_id = self.last_id.wrapping_add(1);
@@ -479,6 +562,174 @@ impl Client {
         }
     }
 
+    /// Send a request and wait for its response.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - fail to send the request
+    ///   - fail to receive or parse the response
+    ///   - the server answer w/ an error
+    ///   - no response match the request
+    fn request(&mut self, request: Request) -> Result<Response, Error> {
+        let request = request.id(self.id());
+        self.inner.try_send(&request)?;
+        let req_id = request.id;
+        self.index.insert(request.id, request);
+        let resp = self.inner.recv(&self.index);
+        self.index.remove(&req_id);
+        let mut response = None;
+        for r in resp? {
+            match r {
+                Response::Error(e) if e.id == req_id => {
+                    return Err(Error::Electrum(e.to_string()));
+                }
+                r if r.id() == Some(req_id) => response = Some(r),
+                r => self.handle_notification(r),
+            }
+        }
+        response.ok_or(Error::WrongResponse)
+    }
+
+    /// Returns true if `script` is watched on this connection, see
+    ///   [`ChainSource::subscribe()`].
+    pub(crate) fn is_subscribed(&self, script: &Script) -> bool {
+        self.subscriptions.contains_key(&ScriptHash::new(script))
+    }
+
+    // Keep track of notifications of watched spks received while waiting
+    // for a response
+    fn handle_notification(&mut self, response: Response) {
+        if let Response::SHNotification(SHNotification {
+            status: (sh, _), ..
+        }) = response
+        {
+            if let Some(spk) = self.subscriptions.get(&sh) {
+                if !self.notified.contains(spk) {
+                    self.notified.push(spk.clone());
+                }
+            }
+        }
+    }
+
+    /// Ping the server.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server do not answer.
+    pub fn ping(&mut self) -> Result<(), Error> {
+        match self.request(Request::ping())? {
+            Response::Ping(_) => Ok(()),
+            _ => Err(Error::WrongResponse),
+        }
+    }
+
+    /// Returns the peers known by the server as (address, port) of servers
+    ///   accepting SSL connections, address is prefixed by `ssl://`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the call to the server fails.
+    pub fn get_peers(&mut self) -> Result<Vec<(String, u16)>, Error> {
+        match self.request(Request::subscribe_peers())? {
+            Response::ListPeers(ListPeersResponse { peers, .. }) => Ok(peers
+                .iter()
+                // NOTE: we do not support tor
+                .filter(|p| !p.host().ends_with(".onion"))
+                .filter_map(|p| {
+                    p.ssl_port()
+                        .map(|port| (format!("ssl://{}", p.host()), port))
+                })
+                .collect()),
+            _ => Err(Error::WrongResponse),
+        }
+    }
+
+    /// Sync & validate block headers up to the server tip, returns the
+    ///   height of the tip.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - SPV verification is not enabled
+    ///   - a call to the electrum server fail
+    ///   - the headers sent by the server are invalid
+    pub fn sync_headers(&mut self) -> Result<u32, Error> {
+        let chain = self.spv.clone().ok_or(Error::Unverifiable)?;
+        let (mut start, checkpoint) = {
+            let chain = chain.lock().expect("poisoned");
+            (chain.tip_height() + 1, chain.checkpoint_height())
+        };
+        loop {
+            let response = self.request(Request::headers(start as usize, MAX_HEADERS))?;
+            let raw = match response {
+                Response::Headers(HeadersResponse { headers, .. }) => headers.raw_headers,
+                _ => return Err(Error::WrongResponse),
+            };
+            let raw = Vec::<u8>::from_hex(&raw).map_err(|_| Error::HeaderParsing)?;
+            let headers = raw
+                .chunks(80)
+                .map(consensus::deserialize)
+                .collect::<Result<Vec<bitcoin::block::Header>, _>>()
+                .map_err(|_| Error::HeaderParsing)?;
+
+            let mut chain = chain.lock().expect("poisoned");
+            match chain.extend(start, &headers) {
+                Ok(()) => {}
+                // the server chain forks from ours, step back
+                Err(crate::spv::Error::BrokenChain(height))
+                    if height == start && start > checkpoint + 1 =>
+                {
+                    start = start.saturating_sub(REORG_STEP).max(checkpoint + 1);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if headers.len() < MAX_HEADERS {
+                return Ok(chain.tip_height());
+            }
+            start += headers.len() as u32;
+        }
+    }
+
+    /// Verify that a transaction is confirmed in the block at `height`
+    ///   using its merkle proof, headers are synced if needed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - SPV verification is not enabled
+    ///   - a call to the electrum server fail
+    ///   - the merkle proof is invalid
+    pub fn verify_tx(&mut self, txid: Txid, height: u32) -> Result<(), Error> {
+        let chain = self.spv.clone().ok_or(Error::Unverifiable)?;
+        let synced = chain.lock().expect("poisoned").header(height).is_some();
+        if !synced {
+            self.sync_headers()?;
+        }
+        let proof = match self.request(Request::tx_get_merkle(txid, height as usize))? {
+            Response::TxGetMerkle(TxGetMerkleResponse { result, .. }) => result,
+            _ => return Err(Error::WrongResponse),
+        };
+        if proof.block_height != height as usize {
+            return Err(Error::Unverifiable);
+        }
+        let chain = chain.lock().expect("poisoned");
+        chain.verify_tx(txid, height, proof.tx_pos, &proof.merkle)?;
+        Ok(())
+    }
+
+    /// Verify a transaction returned by the server in the history of a
+    ///   script: confirmed transactions are checked against their merkle
+    ///   proof, unconfirmed ones cannot be verified.
+    fn verify_history_entry(&mut self, entry: &HistoryResult) -> Result<bool, Error> {
+        if entry.height < 1 {
+            return Ok(false);
+        }
+        self.verify_tx(entry.txid, entry.height as u32)?;
+        Ok(true)
+    }
+
     /// Try to get a transaction by its txid
     ///
     /// # Errors
@@ -488,6 +739,7 @@ impl Client {
     ///   - parsing response fails
     ///   - the response is not of expected type
     ///   - the transaction does not exists
+    ///   - the transaction returned does not match `txid`
     pub fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
         let request = Request::tx_get(txid).id(self.id());
         self.inner.try_send(&request)?;
@@ -514,9 +766,13 @@ impl Client {
                             return Err(Error::TxParsing);
                         }
                     };
-                    let tx: Result<Transaction, _> =
-                        Decodable::consensus_decode(&mut raw_tx.as_slice());
-                    return tx.map_err(|_| Error::TxParsing);
+                    let tx: Transaction = Decodable::consensus_decode(&mut raw_tx.as_slice())
+                        .map_err(|_| Error::TxParsing)?;
+                    // NOTE: an SPV proof only proves the txid, the body must hash to it
+                    if tx.compute_txid() != txid {
+                        return Err(Error::TxidMismatch(txid));
+                    }
+                    return Ok(tx);
                 }
             } else if let Response::Error(ErrorResponse { id, .. }) = r {
                 if req_id == id {
@@ -541,10 +797,14 @@ impl Client {
     ///     that pay to the given spk.
     ///   - it will return a list of (TxOut, OutPoint) and a map of transactions.
     ///
+    /// Note: if SPV is enabled, confirmed transactions are verified against
+    ///   their merkle proof, unconfirmed transactions are returned unverified.
+    ///
     /// # Errors
     ///
     /// This function will return an error if:
     ///   - a call to the electrum server fail
+    ///   - SPV is enabled and a confirmed transaction cannot be verified
     #[allow(clippy::type_complexity)]
     pub fn get_coins_at(
         &mut self,
@@ -552,8 +812,12 @@ impl Client {
     ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
         let mut txouts = Vec::new();
         let mut transactions = HashMap::new();
-        let txs = self.get_coins_tx_at(script)?;
-        for txid in txs {
+        let history = self.get_history_at(script)?;
+        for entry in history {
+            if self.is_spv() {
+                self.verify_history_entry(&entry)?;
+            }
+            let txid = entry.txid;
             let tx = self.get_tx(txid)?;
             for (i, txout) in tx.output.iter().enumerate() {
                 if *txout.script_pubkey == *script {
@@ -578,6 +842,22 @@ impl Client {
     ///   - fail sending the request
     ///   - receive a wrong response
     pub fn get_coins_tx_at(&mut self, script: &Script) -> Result<Vec<Txid>, Error> {
+        Ok(self
+            .get_history_at(script)?
+            .into_iter()
+            .map(|r| r.txid)
+            .collect())
+    }
+
+    /// Get the history of the given spk: txid and height of all transactions
+    ///   that have an output paying to it or spending from it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - fail sending the request
+    ///   - receive a wrong response
+    pub fn get_history_at(&mut self, script: &Script) -> Result<Vec<HistoryResult>, Error> {
         let request = Request::sh_get_history(script).id(self.id());
         self.inner.try_send(&request)?;
         let req_id = request.id;
@@ -593,7 +873,6 @@ impl Client {
             if let Response::SHGetHistory(SHGetHistoryResponse { id, history }) = r {
                 if req_id == id {
                     self.index.remove(&req_id);
-                    let history: Vec<_> = history.into_iter().map(|r| r.txid).collect();
                     return Ok(history);
                 }
             }
@@ -647,6 +926,11 @@ impl Client {
         self.url.clone()
     }
 
+    /// Returns true if the client connects over SSL.
+    pub fn ssl(&self) -> bool {
+        self.ssl
+    }
+
     /// Returns the port of the electrum client.
     ///
     /// # Returns
@@ -656,6 +940,98 @@ impl Client {
     }
 }
 
+impl ChainSource for Client {
+    fn get_history_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<Vec<(Txid, Option<u32>)>, backend::Error> {
+        Ok(Client::get_history_at(self, script)?
+            .into_iter()
+            .map(|h| (h.txid, u32::try_from(h.height).ok().filter(|h| *h > 0)))
+            .collect())
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, backend::Error> {
+        match Client::get_tx(self, txid) {
+            Err(Error::TxDoesNotExists) => Err(backend::Error::TxDoesNotExists),
+            r => Ok(r?),
+        }
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, backend::Error> {
+        Client::broadcast(self, tx)?;
+        Ok(tx.compute_txid())
+    }
+
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, backend::Error> {
+        match self.request(Request::estimate_fee(target))? {
+            Response::EstimateFee(EstimateFeeResponse {
+                fee: OptionalFee::Fee(btc_kvb),
+                ..
+            }) if btc_kvb >= 0.0 => {
+                // BTC/kvB => sat/kwu
+                let sat_kvb = (btc_kvb * 100_000_000.0).round() as u64;
+                Ok(Some(FeeRate::from_sat_per_kwu(sat_kvb / 4)))
+            }
+            Response::EstimateFee(_) => Ok(None),
+            _ => Err(Error::WrongResponse.into()),
+        }
+    }
+
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, backend::Error> {
+        match self.request(Request::get_fee_histogram())? {
+            Response::FeeHistogram(FeeHistogramResponse { histogram, .. }) => Ok(histogram
+                .into_iter()
+                .map(|(sat_vb, vsize)| {
+                    (
+                        FeeRate::from_sat_per_vb_unchecked(sat_vb as u64),
+                        vsize as u64,
+                    )
+                })
+                .collect()),
+            _ => Err(Error::WrongResponse.into()),
+        }
+    }
+
+    fn subscribe(&mut self, script: &Script) -> Result<(), backend::Error> {
+        match self.request(Request::subscribe_sh(script))? {
+            Response::SHSubscribe(_) => {
+                self.subscriptions
+                    .insert(ScriptHash::new(script), script.to_owned());
+                Ok(())
+            }
+            _ => Err(Error::WrongResponse.into()),
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, backend::Error> {
+        while let Some(responses) = self.inner.try_recv(&self.index).map_err(Error::from)? {
+            for r in responses {
+                self.handle_notification(r);
+            }
+        }
+        Ok(std::mem::take(&mut self.notified))
+    }
+
+    fn config(&self) -> Option<ChainConfig> {
+        let url = if self.ssl {
+            format!("ssl://{}", self.url)
+        } else {
+            self.url.clone()
+        };
+        Some(ChainConfig::Electrum(url, self.port))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), backend::Error> {
+        // NOTE: keep the SPV verification of Client::get_coins_at()
+        Ok(Client::get_coins_at(self, script)?)
+    }
+}
+
 impl BitcoinBackend for Client {
     type Error = Error;
     fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
@@ -676,11 +1052,21 @@ impl BitcoinBackend for Client {
                 e => return Err(e),
             },
         };
-        Ok(Some(
-            tx.output
-                .get(outpoint.vout as usize)
-                .ok_or(Error::WrongOutPoint)?
-                .value,
-        ))
+        let txout = tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(Error::WrongOutPoint)?;
+        if self.is_spv() {
+            // NOTE: we refuse coins we cannot prove are confirmed
+            let history = self.get_history_at(&txout.script_pubkey)?;
+            let entry = history
+                .iter()
+                .find(|h| h.txid == outpoint.txid)
+                .ok_or(Error::Unverifiable)?;
+            if !self.verify_history_entry(entry)? {
+                return Err(Error::Unverifiable);
+            }
+        }
+        Ok(Some(txout.value))
     }
 }
@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{Address, Amount, OutPoint, Transaction};
+use miniscript::bitcoin::{FeeRate, Script, ScriptBuf, TxOut, Txid};
+
+use crate::{
+    backend::{self, ChainConfig, ChainSource},
+    coinjoin::BitcoinBackend,
+    electrum::{Client, Error},
+};
+
+// number of consecutive failures before a server is considered down
+const MAX_FAILURES: usize = 3;
+// min delay between two health checks triggered by all servers being down
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+struct Server {
+    url: String,
+    port: u16,
+    client: Option<Client>,
+    failures: usize,
+}
+
+impl Server {
+    fn new(url: String, port: u16) -> Self {
+        Server {
+            url,
+            port,
+            client: None,
+            failures: 0,
+        }
+    }
+
+    fn is_down(&self) -> bool {
+        self.failures >= MAX_FAILURES
+    }
+
+    fn client(&mut self) -> Result<&mut Client, Error> {
+        if self.client.is_none() {
+            self.client = Some(Client::new(&self.url, self.port)?);
+        }
+        Ok(self.client.as_mut().expect("just connected"))
+    }
+
+    fn fail(&mut self, e: &Error) {
+        log::warn!(
+            "ElectrumPool: server {}:{} failed: {e}",
+            self.url,
+            self.port
+        );
+        self.client = None;
+        self.failures += 1;
+    }
+}
+
+// The electrum ChainSource impl only returns electrum errors, unwrap them
+// so `ElectrumPool::call()` can tell server failures apart.
+fn unwrap_error(e: backend::Error) -> Error {
+    match e {
+        backend::Error::Electrum(e) => e,
+        backend::Error::TxDoesNotExists => Error::TxDoesNotExists,
+        e => Error::Electrum(e.to_string()),
+    }
+}
+
+fn wrap_error(e: Error) -> backend::Error {
+    match e {
+        Error::TxDoesNotExists => backend::Error::TxDoesNotExists,
+        e => e.into(),
+    }
+}
+
+/// Returns true if the error is caused by the server or the connection,
+///   other errors (tx does not exists, ...) are valid answers.
+fn is_server_failure(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Electrum(_) | Error::WrongResponse | Error::HeaderParsing | Error::TxParsing
+    )
+}
+
+/// A pool of electrum servers w/ automatic failover.
+///
+/// Calls are sent to the current server, if it fails the next available
+///   server is used. Servers that fail [`MAX_FAILURES`] times in a row are
+///   skipped until a [`ElectrumPool::health_check()`] succeed, a health check
+///   is run before a call if all the servers are down, at most every
+///   [`HEALTH_CHECK_INTERVAL`].
+///
+/// If a quorum greater than 1 is set, [`BitcoinBackend`] lookups are sent
+///   to several servers and an [`Error::Disagreement`] is returned if their
+///   answers differ.
+///
+/// As a [`ChainSource`], watched spks are subscribed again on the new server
+///   after a failover, and reported as notified by the next
+///   [`ChainSource::poll()`] as notifications may have been missed.
+#[derive(Debug, Clone)]
+pub struct ElectrumPool {
+    servers: Vec<Server>,
+    current: usize,
+    quorum: usize,
+    // spks watched w/ ChainSource::subscribe()
+    subscriptions: Vec<ScriptBuf>,
+    // last health check run because all the servers were down
+    last_revive: Option<Instant>,
+}
+
+impl ElectrumPool {
+    /// Create a new pool, servers are connected on first use.
+    ///
+    /// # Arguments
+    /// * `servers` - list of (url, port) of electrum servers, url must be
+    ///   prefixed w/ `ssl://` for SSL connections
+    pub fn new(servers: Vec<(String, u16)>) -> Self {
+        ElectrumPool {
+            servers: servers
+                .into_iter()
+                .map(|(url, port)| Server::new(url, port))
+                .collect(),
+            current: 0,
+            quorum: 1,
+            subscriptions: Vec::new(),
+            last_revive: None,
+        }
+    }
+
+    /// Set the number of servers that must agree on [`BitcoinBackend`]
+    ///   lookups, 1 (default) disable cross-checking.
+    pub fn quorum(mut self, quorum: usize) -> Self {
+        self.quorum = quorum.max(1);
+        self
+    }
+
+    /// Add a server to the pool, do nothing if the server is already known.
+    pub fn add_server(&mut self, url: String, port: u16) -> bool {
+        if self.servers.iter().any(|s| s.url == url && s.port == port) {
+            return false;
+        }
+        self.servers.push(Server::new(url, port));
+        true
+    }
+
+    /// Returns the list of (url, port) of the servers of the pool.
+    pub fn servers(&self) -> Vec<(String, u16)> {
+        self.servers
+            .iter()
+            .map(|s| (s.url.clone(), s.port))
+            .collect()
+    }
+
+    /// Returns the number of servers not considered down.
+    pub fn available(&self) -> usize {
+        self.servers.iter().filter(|s| !s.is_down()).count()
+    }
+
+    /// Discover new servers using `server.peers.subscribe` on the current
+    ///   server, returns the number of servers added to the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no server is available.
+    pub fn discover(&mut self) -> Result<usize, Error> {
+        let peers = self.call(|c| c.get_peers())?;
+        Ok(peers
+            .into_iter()
+            .filter(|(url, port)| self.add_server(url.clone(), *port))
+            .count())
+    }
+
+    /// Ping all the servers of the pool, returns the number of servers
+    ///   that answered.
+    pub fn health_check(&mut self) -> usize {
+        let mut healthy = 0;
+        for server in &mut self.servers {
+            match server.client().and_then(|c| c.ping()) {
+                Ok(()) => {
+                    server.failures = 0;
+                    healthy += 1;
+                }
+                Err(e) => server.fail(&e),
+            }
+        }
+        healthy
+    }
+
+    // Run a health check if all the servers are down, so a transient
+    // outage does not kill the pool for good.
+    fn revive(&mut self) {
+        if self.available() > 0
+            || self
+                .last_revive
+                .is_some_and(|last| last.elapsed() < HEALTH_CHECK_INTERVAL)
+        {
+            return;
+        }
+        self.last_revive = Some(Instant::now());
+        let healthy = self.health_check();
+        log::warn!("ElectrumPool: all servers down, {healthy} back after health check");
+    }
+
+    /// Run `f` against the current server, failover to the next available
+    ///   server if the server fails.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - `f` returns an error that is not caused by the server
+    ///   - all the servers fail
+    pub fn call<T, F>(&mut self, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut(&mut Client) -> Result<T, Error>,
+    {
+        self.revive();
+        let len = self.servers.len();
+        for i in 0..len {
+            let index = (self.current + i) % len;
+            let server = &mut self.servers[index];
+            if server.is_down() {
+                continue;
+            }
+            match server.client().and_then(&mut f) {
+                Ok(r) => {
+                    server.failures = 0;
+                    self.current = index;
+                    return Ok(r);
+                }
+                Err(e) if is_server_failure(&e) => server.fail(&e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::NoServerAvailable)
+    }
+
+    /// Run `f` against [`ElectrumPool::quorum()`] servers and check they
+    ///   all return the same result.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - less than quorum servers answered
+    ///   - servers disagree
+    pub fn cross_check<T, F>(&mut self, mut f: F) -> Result<T, Error>
+    where
+        T: PartialEq + Debug,
+        F: FnMut(&mut Client) -> Result<T, Error>,
+    {
+        if self.quorum == 1 {
+            return self.call(f);
+        }
+        self.revive();
+        let mut answers = Vec::new();
+        for server in &mut self.servers {
+            if answers.len() >= self.quorum {
+                break;
+            }
+            if server.is_down() {
+                continue;
+            }
+            let answer = server.client().and_then(&mut f);
+            match answer {
+                Err(e) if is_server_failure(&e) => server.fail(&e),
+                answer => {
+                    server.failures = 0;
+                    answers.push((format!("{}:{}", server.url, server.port), answer));
+                }
+            }
+        }
+        if answers.len() < self.quorum {
+            return Err(Error::NoServerAvailable);
+        }
+        let (first_server, first) = &answers[0];
+        for (server, answer) in &answers[1..] {
+            let agree = match (first, answer) {
+                (Ok(a), Ok(b)) => a == b,
+                (Err(a), Err(b)) => a.to_string() == b.to_string(),
+                _ => false,
+            };
+            if !agree {
+                let msg = format!("{first_server} => {first:?}, {server} => {answer:?}");
+                log::error!("ElectrumPool::cross_check() {msg}");
+                return Err(Error::Disagreement(msg));
+            }
+        }
+        answers.swap_remove(0).1
+    }
+
+    /// Get a transaction by its txid, see [`Client::get_tx()`].
+    pub fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        self.call(|c| c.get_tx(txid))
+    }
+
+    /// Get the coins paying to a spk, see [`Client::get_coins_at()`].
+    #[allow(clippy::type_complexity)]
+    pub fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
+        self.call(|c| c.get_coins_at(script))
+    }
+
+    /// Broadcast a transaction, see [`Client::broadcast()`].
+    pub fn broadcast(&mut self, tx: &Transaction) -> Result<(), Error> {
+        self.call(|c| c.broadcast(tx))
+    }
+}
+
+impl BitcoinBackend for ElectrumPool {
+    type Error = Error;
+    fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
+        self.cross_check(|c| c.address_already_used(addr))
+    }
+
+    fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Error> {
+        self.cross_check(|c| c.get_outpoint_value(outpoint))
+    }
+}
+
+impl ChainSource for ElectrumPool {
+    fn get_history_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<Vec<(Txid, Option<u32>)>, backend::Error> {
+        self.call(|c| ChainSource::get_history_at(c, script).map_err(unwrap_error))
+            .map_err(wrap_error)
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, backend::Error> {
+        self.call(|c| ChainSource::get_tx(c, txid).map_err(unwrap_error))
+            .map_err(wrap_error)
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, backend::Error> {
+        self.call(|c| ChainSource::broadcast(c, tx).map_err(unwrap_error))
+            .map_err(wrap_error)
+    }
+
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, backend::Error> {
+        self.call(|c| ChainSource::estimate_fee(c, target).map_err(unwrap_error))
+            .map_err(wrap_error)
+    }
+
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, backend::Error> {
+        self.call(|c| ChainSource::fee_histogram(c).map_err(unwrap_error))
+            .map_err(wrap_error)
+    }
+
+    fn subscribe(&mut self, script: &Script) -> Result<(), backend::Error> {
+        self.call(|c| ChainSource::subscribe(c, script).map_err(unwrap_error))
+            .map_err(wrap_error)?;
+        if !self.subscriptions.iter().any(|s| s.as_script() == script) {
+            self.subscriptions.push(script.to_owned());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, backend::Error> {
+        if self.subscriptions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let subscriptions = self.subscriptions.clone();
+        self.call(|c| {
+            let mut notified = Vec::new();
+            for script in &subscriptions {
+                if !c.is_subscribed(script) {
+                    // NOTE: this server did not watch the spk before the
+                    // failover, we may have missed a notification
+                    ChainSource::subscribe(c, script).map_err(unwrap_error)?;
+                    notified.push(script.clone());
+                }
+            }
+            for script in ChainSource::poll(c).map_err(unwrap_error)? {
+                if !notified.contains(&script) {
+                    notified.push(script);
+                }
+            }
+            Ok(notified)
+        })
+        .map_err(wrap_error)
+    }
+
+    fn config(&self) -> Option<ChainConfig> {
+        Some(ChainConfig::ElectrumPool(self.servers(), self.quorum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miniscript::bitcoin::hashes::Hash;
+
+    use super::*;
+
+    #[test]
+    fn no_server_available() {
+        // nothing listen on port 1
+        let mut pool =
+            ElectrumPool::new(vec![("127.0.0.1".into(), 1), ("127.0.0.1".into(), 1)]).quorum(2);
+        assert!(!pool.add_server("127.0.0.1".into(), 1));
+        assert_eq!(pool.health_check(), 0);
+        assert!(matches!(
+            pool.get_outpoint_value(OutPoint::null()),
+            Err(Error::NoServerAvailable)
+        ));
+        assert!(matches!(
+            pool.get_tx(Txid::all_zeros()),
+            Err(Error::NoServerAvailable)
+        ));
+        // all the servers are down, the next call run a health check
+        assert_eq!(pool.available(), 0);
+        assert!(pool.last_revive.is_none());
+        assert!(matches!(
+            pool.get_tx(Txid::all_zeros()),
+            Err(Error::NoServerAvailable)
+        ));
+        assert!(pool.last_revive.is_some());
+    }
+
+    #[test]
+    fn chain_source() {
+        let servers = vec![("127.0.0.1".into(), 1), ("ssl://127.0.0.1".into(), 2)];
+        let mut pool = ElectrumPool::new(servers.clone()).quorum(2);
+        assert_eq!(
+            ChainSource::config(&pool),
+            Some(ChainConfig::ElectrumPool(servers, 2))
+        );
+        // nothing to poll before a subscription
+        assert!(ChainSource::poll(&mut pool).unwrap().is_empty());
+        assert!(matches!(
+            ChainSource::get_tx(&mut pool, Txid::all_zeros()),
+            Err(backend::Error::Electrum(Error::NoServerAvailable))
+        ));
+    }
+}
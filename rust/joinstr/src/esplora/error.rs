@@ -0,0 +1,38 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(crate::http::Error),
+    /// The server answered w/ an HTTP error status
+    Status(u16, String),
+    Json(String),
+    TxParsing,
+    TxDoesNotExists,
+    WrongOutPoint,
+    WrongResponse,
+    /// The server returned a transaction that does not match the requested txid
+    TxidMismatch(miniscript::bitcoin::Txid),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "{}", e),
+            Error::Status(code, body) => write!(f, "Esplora HTTP error {}: {}", code, body),
+            Error::Json(e) => write!(f, "Fail to parse esplora response: {}", e),
+            Error::TxParsing => write!(f, "Fail to parse the transaction"),
+            Error::TxDoesNotExists => write!(f, "The transaction does not exists"),
+            Error::WrongOutPoint => write!(f, "The outpoint does not exists"),
+            Error::WrongResponse => write!(f, "Wrong response from esplora"),
+            Error::TxidMismatch(txid) => {
+                write!(f, "Esplora returned a wrong transaction for {}", txid)
+            }
+        }
+    }
+}
+
+impl From<crate::http::Error> for Error {
+    fn from(value: crate::http::Error) -> Self {
+        Error::Http(value)
+    }
+}
@@ -0,0 +1,284 @@
+mod error;
+
+use std::{collections::HashMap, str::FromStr};
+
+pub use error::Error;
+use miniscript::bitcoin::{
+    consensus::encode::{deserialize_hex, serialize_hex},
+    Address, Amount, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use simple_electrum_client::electrum::types::ScriptHash;
+
+use crate::{
+    backend::{self, ChainConfig, ChainSource},
+    coinjoin::BitcoinBackend,
+    http,
+};
+
+// number of confirmed txs returned per page by `/scripthash/:hash/txs/chain`
+const CHAIN_PAGE: usize = 25;
+
+#[derive(Debug, Deserialize)]
+struct TxStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolInfo {
+    fee_histogram: Vec<(f64 /* sat/vb */, u64 /* vsize */)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTx {
+    txid: Txid,
+    status: TxStatus,
+}
+
+/// A [`BitcoinBackend`] talking to an Esplora REST API (Blockstream or
+///   mempool.space API shape).
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    url: String,
+    // history of the spks watched w/ ChainSource::subscribe()
+    subscriptions: HashMap<ScriptBuf, Vec<(Txid, Option<u32>)>>,
+}
+
+impl EsploraClient {
+    /// Create a new client, no request is sent to the server.
+    ///
+    /// # Arguments
+    /// * `url` - The base url of the API (ex: `https://mempool.space/api`)
+    pub fn new(url: &str) -> Self {
+        EsploraClient {
+            url: url.trim_end_matches('/').into(),
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    /// Returns the base url of the API.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get(&self, path: &str) -> Result<http::Response, Error> {
+        let url = format!("{}{path}", self.url);
+        log::debug!("EsploraClient::get({url})");
+        let response = http::get(&url)?;
+        if response.is_success() {
+            Ok(response)
+        } else {
+            Err(Error::Status(response.status, response.text()))
+        }
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let response = self.get(path)?;
+        serde_json::from_slice(&response.body).map_err(|e| Error::Json(e.to_string()))
+    }
+
+    /// Get a transaction by its txid.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the request fails
+    ///   - the transaction does not exists
+    ///   - the transaction cannot be parsed
+    ///   - the transaction returned does not match `txid`
+    pub fn get_tx(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        let response = match self.get(&format!("/tx/{txid}/hex")) {
+            Err(Error::Status(404, _)) => return Err(Error::TxDoesNotExists),
+            r => r?,
+        };
+        let tx: Transaction =
+            deserialize_hex(response.text().trim()).map_err(|_| Error::TxParsing)?;
+        if tx.compute_txid() != txid {
+            return Err(Error::TxidMismatch(txid));
+        }
+        Ok(tx)
+    }
+
+    /// Get the txid & confirmation height (None if unconfirmed) of all
+    ///   transactions that have an output paying to or spending from the
+    ///   given spk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a request fails.
+    pub fn get_history_at(&mut self, script: &Script) -> Result<Vec<(Txid, Option<u32>)>, Error> {
+        let sh = ScriptHash::new(script);
+        let entry = |tx: &EsploraTx| (tx.txid, tx.status.block_height);
+        // the first page contains mempool txs and the first confirmed txs
+        let mut page: Vec<EsploraTx> = self.get_json(&format!("/scripthash/{sh}/txs"))?;
+        let mut history: Vec<_> = page.iter().map(entry).collect();
+        let mut confirmed = page.iter().filter(|tx| tx.status.confirmed).count();
+        while confirmed == CHAIN_PAGE {
+            let last = page.last().expect("not empty").txid;
+            page = self.get_json(&format!("/scripthash/{sh}/txs/chain/{last}"))?;
+            history.extend(page.iter().map(entry));
+            confirmed = page.len();
+        }
+        Ok(history)
+    }
+
+    /// Get a list of txid of all transactions that have an output paying to
+    ///   or spending from the given spk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a request fails.
+    pub fn get_coins_tx_at(&mut self, script: &Script) -> Result<Vec<Txid>, Error> {
+        Ok(self
+            .get_history_at(script)?
+            .into_iter()
+            .map(|(txid, _)| txid)
+            .collect())
+    }
+
+    /// Get the coins that pay to the given spk, see
+    ///   [`crate::electrum::Client::get_coins_at()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a request fails.
+    #[allow(clippy::type_complexity)]
+    pub fn get_coins_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Vec<(TxOut, OutPoint)>, HashMap<Txid, Transaction>), Error> {
+        let mut txouts = Vec::new();
+        let mut transactions = HashMap::new();
+        for txid in self.get_coins_tx_at(script)? {
+            let tx = self.get_tx(txid)?;
+            for (i, txout) in tx.output.iter().enumerate() {
+                if *txout.script_pubkey == *script {
+                    let outpoint = OutPoint {
+                        txid,
+                        vout: i as u32,
+                    };
+                    txouts.push((txout.clone(), outpoint));
+                }
+            }
+            transactions.insert(txid, tx);
+        }
+        Ok((txouts, transactions))
+    }
+
+    /// Broadcast a transaction.
+    pub fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error> {
+        let url = format!("{}/tx", self.url);
+        let response = http::post(&url, &[], serialize_hex(tx).as_bytes())?;
+        if !response.is_success() {
+            return Err(Error::Status(response.status, response.text()));
+        }
+        Txid::from_str(response.text().trim()).map_err(|_| Error::WrongResponse)
+    }
+
+    /// Estimate the fee rate needed for a transaction to confirm within
+    ///   `target` blocks, returns None if the server has no estimate for
+    ///   this target.
+    pub fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, Error> {
+        let estimates: HashMap<String, f64> = self.get_json("/fee-estimates")?;
+        // use the closest estimate at or below the target
+        let estimate = estimates
+            .into_iter()
+            .filter_map(|(t, rate)| t.parse::<u16>().ok().map(|t| (t, rate)))
+            .filter(|(t, _)| *t <= target)
+            .max_by_key(|(t, _)| *t);
+        // sat/vB => sat/kwu
+        Ok(estimate.map(|(_, rate)| FeeRate::from_sat_per_kwu((rate * 250.0).ceil() as u64)))
+    }
+
+    /// Returns the fee histogram of the mempool, see
+    ///   [`ChainSource::fee_histogram()`].
+    pub fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, Error> {
+        let mempool: MempoolInfo = self.get_json("/mempool")?;
+        Ok(mempool
+            .fee_histogram
+            .into_iter()
+            // sat/vB => sat/kwu
+            .map(|(rate, vsize)| {
+                (
+                    FeeRate::from_sat_per_kwu((rate * 250.0).ceil() as u64),
+                    vsize,
+                )
+            })
+            .collect())
+    }
+}
+
+/// Note: Esplora has no push notifications, [`ChainSource::poll()`] fetch
+///   the history of every watched spk.
+impl ChainSource for EsploraClient {
+    fn get_history_at(
+        &mut self,
+        script: &Script,
+    ) -> Result<Vec<(Txid, Option<u32>)>, backend::Error> {
+        Ok(EsploraClient::get_history_at(self, script)?)
+    }
+
+    fn get_tx(&mut self, txid: Txid) -> Result<Transaction, backend::Error> {
+        match EsploraClient::get_tx(self, txid) {
+            Err(Error::TxDoesNotExists) => Err(backend::Error::TxDoesNotExists),
+            r => Ok(r?),
+        }
+    }
+
+    fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, backend::Error> {
+        Ok(EsploraClient::broadcast(self, tx)?)
+    }
+
+    fn estimate_fee(&mut self, target: u16) -> Result<Option<FeeRate>, backend::Error> {
+        Ok(EsploraClient::estimate_fee(self, target)?)
+    }
+
+    fn fee_histogram(&mut self) -> Result<Vec<(FeeRate, u64)>, backend::Error> {
+        Ok(EsploraClient::fee_histogram(self)?)
+    }
+
+    fn subscribe(&mut self, script: &Script) -> Result<(), backend::Error> {
+        let history = EsploraClient::get_history_at(self, script)?;
+        self.subscriptions.insert(script.to_owned(), history);
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<ScriptBuf>, backend::Error> {
+        let mut changed = Vec::new();
+        let spks: Vec<_> = self.subscriptions.keys().cloned().collect();
+        for spk in spks {
+            let history = EsploraClient::get_history_at(self, &spk)?;
+            if self.subscriptions.get(&spk) != Some(&history) {
+                self.subscriptions.insert(spk.clone(), history);
+                changed.push(spk);
+            }
+        }
+        Ok(changed)
+    }
+
+    fn config(&self) -> Option<ChainConfig> {
+        Some(ChainConfig::Esplora(self.url.clone()))
+    }
+}
+
+impl BitcoinBackend for EsploraClient {
+    type Error = Error;
+    fn address_already_used(&mut self, addr: &Address) -> Result<bool, Error> {
+        let txs = self.get_coins_tx_at(&addr.script_pubkey())?;
+        Ok(!txs.is_empty())
+    }
+
+    fn get_outpoint_value(&mut self, outpoint: OutPoint) -> Result<Option<Amount>, Error> {
+        let tx = match self.get_tx(outpoint.txid) {
+            Ok(tx) => tx,
+            Err(Error::TxDoesNotExists) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let txout = tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(Error::WrongOutPoint)?;
+        Ok(Some(txout.value))
+    }
+}
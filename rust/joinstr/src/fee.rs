@@ -0,0 +1,373 @@
+use std::fmt::Display;
+
+use miniscript::bitcoin::{Amount, FeeRate, Transaction};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+use crate::backend::r#async::AsyncChainSource;
+use crate::backend::{self, ChainSource};
+
+/// Block target used to compute the lower bound of an acceptable fee rate.
+pub const SLOW_TARGET: u16 = 144;
+/// Block target used to compute the upper bound of an acceptable fee rate.
+pub const FAST_TARGET: u16 = 1;
+/// A pool fee rate is refused if it is lower than the slow estimate divided
+///   by this factor or higher than the fast estimate multiplied by it.
+pub const TOLERANCE: u64 = 2;
+/// Virtual size of a signed p2wpkh input.
+pub const P2WPKH_INPUT_VSIZE: u64 = 68;
+
+#[derive(Debug)]
+pub enum Error {
+    Backend(backend::Error),
+    NoEstimate,
+    InvalidPercentile(u8),
+    FeeTooLow(u32 /* fee */, u32 /* min */),
+    FeeTooHigh(u32 /* fee */, u32 /* max */),
+    InputFeeTooLow(Amount /* fee */, Amount /* min */),
+    InputFeeTooHigh(Amount /* fee */, Amount /* max */),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Backend(e) => write!(f, "Backend error: {}", e),
+            Error::NoEstimate => write!(f, "The bitcoin backend has no fee estimation"),
+            Error::InvalidPercentile(p) => {
+                write!(f, "Mempool percentile must be in 1..=100, got {}", p)
+            }
+            Error::FeeTooLow(fee, min) => write!(
+                f,
+                "Pool fee rate ({} sats/vb) is lower than current estimates (min {} sats/vb)",
+                fee, min
+            ),
+            Error::FeeTooHigh(fee, max) => write!(
+                f,
+                "Pool fee rate ({} sats/vb) is higher than current estimates (max {} sats/vb)",
+                fee, max
+            ),
+            Error::InputFeeTooLow(fee, min) => write!(
+                f,
+                "Our input pays {} of fee, it should pay at least {}",
+                fee, min
+            ),
+            Error::InputFeeTooHigh(fee, max) => write!(
+                f,
+                "Our input pays {} of fee, it should pay at most {}",
+                fee, max
+            ),
+        }
+    }
+}
+
+impl From<backend::Error> for Error {
+    fn from(value: backend::Error) -> Self {
+        Error::Backend(value)
+    }
+}
+
+/// How an initiator choose the fee rate of a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeePolicy {
+    /// A fixed fee rate in sats/vb
+    Fixed(u32),
+    /// The fee rate estimated to confirm within this number of blocks
+    TargetBlocks(u16),
+    /// The fee rate paying more than this percentage (by vsize) of the
+    ///   mempool transactions
+    MempoolPercentile(u8),
+}
+
+impl FeePolicy {
+    /// Resolve the policy into a fee rate in sats/vb.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - a bitcoin backend request fails
+    ///   - the bitcoin backend has no estimation for this target
+    ///   - the percentile is not in 1..=100
+    pub fn resolve(&self, source: &mut dyn ChainSource) -> Result<u32, Error> {
+        match *self {
+            FeePolicy::Fixed(fee) => Ok(fee),
+            FeePolicy::TargetBlocks(target) => source
+                .estimate_fee(target)?
+                .map(sat_per_vb)
+                .ok_or(Error::NoEstimate),
+            FeePolicy::MempoolPercentile(percentile) => {
+                if !(1..=100).contains(&percentile) {
+                    return Err(Error::InvalidPercentile(percentile));
+                }
+                let histogram = source.fee_histogram()?;
+                Ok(histogram_percentile(&histogram, percentile)
+                    .map(sat_per_vb)
+                    // an empty mempool, the min relay fee is enough
+                    .unwrap_or(1))
+            }
+        }
+    }
+
+    /// Async version of [`FeePolicy::resolve()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - a bitcoin backend request fails
+    ///   - the bitcoin backend has no estimation for this target
+    ///   - the percentile is not in 1..=100
+    #[cfg(feature = "async")]
+    pub async fn resolve_async(&self, source: &impl AsyncChainSource) -> Result<u32, Error> {
+        match *self {
+            FeePolicy::Fixed(fee) => Ok(fee),
+            FeePolicy::TargetBlocks(target) => source
+                .estimate_fee(target)
+                .await?
+                .map(sat_per_vb)
+                .ok_or(Error::NoEstimate),
+            FeePolicy::MempoolPercentile(percentile) => {
+                if !(1..=100).contains(&percentile) {
+                    return Err(Error::InvalidPercentile(percentile));
+                }
+                let histogram = source.fee_histogram().await?;
+                Ok(histogram_percentile(&histogram, percentile)
+                    .map(sat_per_vb)
+                    // an empty mempool, the min relay fee is enough
+                    .unwrap_or(1))
+            }
+        }
+    }
+}
+
+// Round a fee rate up to the next sat/vb
+fn sat_per_vb(fee_rate: FeeRate) -> u32 {
+    fee_rate.to_sat_per_vb_ceil().max(1) as u32
+}
+
+/// Returns the lowest fee rate paying more than `percentile`% (by vsize) of
+///   the mempool, None if the histogram is empty.
+///
+/// # Arguments
+/// * `histogram` - (fee rate, vsize) pairs sorted by decreasing fee rate,
+///   as returned by [`ChainSource::fee_histogram()`]
+/// * `percentile` - The percentage of the mempool to outbid
+pub fn histogram_percentile(histogram: &[(FeeRate, u64)], percentile: u8) -> Option<FeeRate> {
+    let total: u64 = histogram.iter().map(|(_, vsize)| vsize).sum();
+    if total == 0 {
+        return None;
+    }
+    // vsize of the transactions paying more than the returned fee rate
+    let above = total - total * percentile.min(100) as u64 / 100;
+    let mut cumulated = 0;
+    for (fee_rate, vsize) in histogram {
+        cumulated += vsize;
+        if cumulated >= above {
+            return Some(*fee_rate);
+        }
+    }
+    histogram.last().map(|(fee_rate, _)| *fee_rate)
+}
+
+/// The range of pool fee rates (sats/vb) a peer accept to join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeBounds {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl FeeBounds {
+    /// Compute the bounds from the current estimates of the bitcoin backend:
+    ///   a fee rate lower than the [`SLOW_TARGET`] estimate or higher than the
+    ///   [`FAST_TARGET`] estimate by more than a [`TOLERANCE`] factor is refused.
+    ///   Returns None if the backend has no estimation.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a bitcoin backend request fails.
+    pub fn from_estimates(source: &mut dyn ChainSource) -> Result<Option<Self>, Error> {
+        let slow = source.estimate_fee(SLOW_TARGET)?;
+        let fast = source.estimate_fee(FAST_TARGET)?;
+        Ok(Self::from_rates(slow, fast))
+    }
+
+    /// Async version of [`FeeBounds::from_estimates()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a bitcoin backend request fails.
+    #[cfg(feature = "async")]
+    pub async fn from_async_estimates(
+        source: &impl AsyncChainSource,
+    ) -> Result<Option<Self>, Error> {
+        let slow = source.estimate_fee(SLOW_TARGET).await?;
+        let fast = source.estimate_fee(FAST_TARGET).await?;
+        Ok(Self::from_rates(slow, fast))
+    }
+
+    // Compute the bounds from the [`SLOW_TARGET`] & [`FAST_TARGET`] estimates
+    fn from_rates(slow: Option<FeeRate>, fast: Option<FeeRate>) -> Option<Self> {
+        match (slow, fast) {
+            (Some(slow), Some(fast)) => Some(FeeBounds {
+                min: (slow.to_sat_per_vb_floor() / TOLERANCE) as u32,
+                max: sat_per_vb(fast) * TOLERANCE as u32,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Check a pool fee rate is inside the bounds.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the fee rate is out of bounds.
+    pub fn check(&self, fee: u32) -> Result<(), Error> {
+        if fee < self.min {
+            Err(Error::FeeTooLow(fee, self.min))
+        } else if fee > self.max {
+            Err(Error::FeeTooHigh(fee, self.max))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the vsize each peer should pay for: one signed p2wpkh input, one
+///   output and its share of the transaction overhead.
+///
+/// # Arguments
+/// * `unsigned` - The unsigned coinjoin transaction (outputs only)
+pub fn peer_vsize(unsigned: &Transaction) -> u64 {
+    let peers = unsigned.output.len().max(1) as u64;
+    let vsize = unsigned.vsize() as u64 + peers * P2WPKH_INPUT_VSIZE;
+    vsize.div_ceil(peers)
+}
+
+/// Check the fee paid by one of our inputs before signing it.
+///
+/// # Arguments
+/// * `unsigned` - The unsigned coinjoin transaction (outputs only)
+/// * `input` - The value of our input
+/// * `denomination` - The value of our output
+/// * `fee` - The pool fee rate in sats/vb
+/// * `max_fee` - The max fee rate we accept to pay in sats/vb, if any
+///
+/// # Errors
+///
+/// This function will return an error if:
+///   - the input pays less than the pool fee rate for its share
+///   - the input pays more than `max_fee` for its share
+pub fn check_input_fee(
+    unsigned: &Transaction,
+    input: Amount,
+    denomination: Amount,
+    fee: u32,
+    max_fee: Option<u32>,
+) -> Result<(), Error> {
+    let paid = input.checked_sub(denomination).unwrap_or(Amount::ZERO);
+    let vsize = peer_vsize(unsigned);
+    let min = Amount::from_sat(vsize * fee as u64);
+    if paid < min {
+        return Err(Error::InputFeeTooLow(paid, min));
+    }
+    if let Some(max_fee) = max_fee {
+        let max = Amount::from_sat(vsize * max_fee as u64);
+        if paid > max {
+            return Err(Error::InputFeeTooHigh(paid, max));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use miniscript::bitcoin::{absolute::LockTime, transaction::Version, Network, TxOut};
+
+    use super::*;
+    use crate::{backend::memory::MemoryChain, signer::WpkhHotSigner};
+
+    fn vb(sat_vb: u64) -> FeeRate {
+        FeeRate::from_sat_per_vb_unchecked(sat_vb)
+    }
+
+    #[test]
+    fn percentile() {
+        let histogram = [(vb(50), 100), (vb(20), 300), (vb(10), 400), (vb(1), 200)];
+        assert_eq!(histogram_percentile(&histogram, 100), Some(vb(50)));
+        assert_eq!(histogram_percentile(&histogram, 90), Some(vb(50)));
+        assert_eq!(histogram_percentile(&histogram, 70), Some(vb(20)));
+        assert_eq!(histogram_percentile(&histogram, 50), Some(vb(10)));
+        assert_eq!(histogram_percentile(&histogram, 1), Some(vb(1)));
+        assert_eq!(histogram_percentile(&[], 50), None);
+    }
+
+    #[test]
+    fn policy_and_bounds() {
+        let mut chain = MemoryChain::new();
+        assert_eq!(FeePolicy::Fixed(3).resolve(&mut chain).unwrap(), 3);
+        assert!(matches!(
+            FeePolicy::TargetBlocks(6).resolve(&mut chain),
+            Err(Error::NoEstimate)
+        ));
+        assert_eq!(FeeBounds::from_estimates(&mut chain).unwrap(), None);
+        assert_eq!(
+            FeePolicy::MempoolPercentile(50)
+                .resolve(&mut chain)
+                .unwrap(),
+            1
+        );
+        assert!(matches!(
+            FeePolicy::MempoolPercentile(101).resolve(&mut chain),
+            Err(Error::InvalidPercentile(101))
+        ));
+
+        chain.set_fee_rate(Some(vb(10)));
+        chain.set_fee_histogram(vec![(vb(30), 500), (vb(5), 500)]);
+        assert_eq!(FeePolicy::TargetBlocks(6).resolve(&mut chain).unwrap(), 10);
+        assert_eq!(
+            FeePolicy::MempoolPercentile(80)
+                .resolve(&mut chain)
+                .unwrap(),
+            30
+        );
+
+        let bounds = FeeBounds::from_estimates(&mut chain).unwrap().unwrap();
+        assert_eq!(bounds, FeeBounds { min: 5, max: 20 });
+        assert!(bounds.check(10).is_ok());
+        assert!(matches!(bounds.check(4), Err(Error::FeeTooLow(4, 5))));
+        assert!(matches!(bounds.check(21), Err(Error::FeeTooHigh(21, 20))));
+    }
+
+    #[test]
+    fn input_fee() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let denomination = Amount::from_sat(100_000);
+        let output = |i| TxOut {
+            value: denomination,
+            script_pubkey: signer.recv_addr_at(i).script_pubkey(),
+        };
+        let unsigned = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: (0..5).map(output).collect(),
+        };
+        // 5 p2wpkh outputs (31 vb) + overhead + 5 inputs
+        let vsize = peer_vsize(&unsigned);
+        assert!((99..=101).contains(&vsize));
+
+        let input = denomination + Amount::from_sat(vsize * 10);
+        assert!(check_input_fee(&unsigned, input, denomination, 10, None).is_ok());
+        assert!(check_input_fee(&unsigned, input, denomination, 10, Some(10)).is_ok());
+        assert!(matches!(
+            check_input_fee(&unsigned, input, denomination, 11, None),
+            Err(Error::InputFeeTooLow(..))
+        ));
+        assert!(matches!(
+            check_input_fee(&unsigned, input, denomination, 5, Some(9)),
+            Err(Error::InputFeeTooHigh(..))
+        ));
+        assert!(matches!(
+            check_input_fee(&unsigned, denomination, denomination, 1, None),
+            Err(Error::InputFeeTooLow(..))
+        ));
+    }
+}
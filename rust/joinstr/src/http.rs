@@ -0,0 +1,257 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use native_tls::TlsConnector;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum Error {
+    Url(String),
+    Io(std::io::Error),
+    Tls(String),
+    Response(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Url(u) => write!(f, "Invalid url: {u}"),
+            Error::Io(e) => write!(f, "HTTP connection error: {e}"),
+            Error::Tls(e) => write!(f, "TLS error: {e}"),
+            Error::Response(e) => write!(f, "Invalid HTTP response: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+/// An HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns true if the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns the body as an UTF-8 string.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Url {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+fn parse_url(url: &str) -> Result<Url, Error> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(Error::Url(url.into()));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| Error::Url(url.into()))?),
+        None => (authority, if tls { 443 } else { 80 }),
+    };
+    if host.is_empty() {
+        return Err(Error::Url(url.into()));
+    }
+    Ok(Url {
+        tls,
+        host: host.into(),
+        port,
+        path: path.into(),
+    })
+}
+
+/// Send a blocking HTTP/1.1 request, the connection is closed after the
+///   response is received.
+///
+/// # Arguments
+/// * `method` - The HTTP method (GET, POST, ...)
+/// * `url` - The url, must start w/ `http://` or `https://`
+/// * `headers` - Additional headers
+/// * `body` - The body of the request, can be empty
+///
+/// # Errors
+///
+/// This function will return an error if:
+///   - the url is invalid
+///   - the connection fails
+///   - the response is malformed
+pub fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<Response, Error> {
+    let url = parse_url(url)?;
+    let mut raw = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        url.path,
+        url.host,
+        body.len()
+    );
+    for (name, value) in headers {
+        raw.push_str(&format!("{name}: {value}\r\n"));
+    }
+    raw.push_str("\r\n");
+    let mut raw = raw.into_bytes();
+    raw.extend_from_slice(body);
+
+    let stream = TcpStream::connect((url.host.as_str(), url.port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut response = Vec::new();
+    if url.tls {
+        let connector = TlsConnector::new().map_err(|e| Error::Tls(e.to_string()))?;
+        let mut stream = connector
+            .connect(&url.host, stream)
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        stream.write_all(&raw)?;
+        stream.read_to_end(&mut response)?;
+    } else {
+        let mut stream = stream;
+        stream.write_all(&raw)?;
+        stream.read_to_end(&mut response)?;
+    }
+    parse_response(&response)
+}
+
+/// Send a GET request, see [`request()`].
+pub fn get(url: &str) -> Result<Response, Error> {
+    request("GET", url, &[], &[])
+}
+
+/// Send a POST request, see [`request()`].
+pub fn post(url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<Response, Error> {
+    request("POST", url, headers, body)
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, Error> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(Error::Response("headers not terminated".into()))?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::Response("invalid status line".into()))?;
+
+    let mut chunked = false;
+    let mut length = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_lowercase().as_str() {
+                "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                "content-length" => length = value.parse::<usize>().ok(),
+                _ => {}
+            }
+        }
+    }
+
+    let body = if chunked {
+        dechunk(body)?
+    } else if let Some(length) = length {
+        body.get(..length)
+            .ok_or(Error::Response("body truncated".into()))?
+            .to_vec()
+    } else {
+        body.to_vec()
+    };
+    Ok(Response { status, body })
+}
+
+fn dechunk(mut raw: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        let end = raw
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(Error::Response("invalid chunk".into()))?;
+        let size = String::from_utf8_lossy(&raw[..end]);
+        // NOTE: chunk extensions are ignored
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| Error::Response("invalid chunk size".into()))?;
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = raw
+            .get(end + 2..end + 2 + size)
+            .ok_or(Error::Response("chunk truncated".into()))?;
+        body.extend_from_slice(chunk);
+        raw = raw.get(end + 4 + size..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            parse_url("https://mempool.space/api/tx").unwrap(),
+            Url {
+                tls: true,
+                host: "mempool.space".into(),
+                port: 443,
+                path: "/api/tx".into()
+            }
+        );
+        assert_eq!(
+            parse_url("http://127.0.0.1:18443").unwrap(),
+            Url {
+                tls: false,
+                host: "127.0.0.1".into(),
+                port: 18443,
+                path: "/".into()
+            }
+        );
+        assert!(parse_url("127.0.0.1:18443").is_err());
+    }
+
+    #[test]
+    fn response() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let response = parse_response(raw).unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.text(), "hello");
+
+        let raw = b"HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.text(), "Wikipedia");
+    }
+}
@@ -1,14 +1,23 @@
-use std::{fmt::Display, thread::sleep, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+    thread::sleep,
+    time::Duration,
+};
 
 use bip39::Mnemonic;
-use bitcoin::{address::NetworkUnchecked, Address, Network, Txid};
+use bitcoin::{address::NetworkUnchecked, bip32::Fingerprint, Address, Network, Txid};
 use simple_nostr_client::nostr::Keys;
 
 use crate::{
+    backend::{Backend, ChainSource},
     electrum::Client,
+    esplora::EsploraClient,
     joinstr::Joinstr,
     nostr::{sync::NostrClient, Pool},
-    signer::{Coin, CoinPath, WpkhHotSigner},
+    privacy::ScoredCoin,
+    signer::{sync::WalletSync, Account, Coin, CoinPath, WpkhHotSigner},
     utils::now,
 };
 
@@ -77,70 +86,214 @@ pub struct PeerConfig {
     pub mnemonics: Mnemonic,
     pub electrum_address: String,
     pub electrum_port: u16,
+    /// If Some, the Esplora API at this url is used as bitcoin backend
+    ///   instead of the electrum server
+    pub esplora_url: Option<String>,
     pub input: Coin,
-    pub output: Address<NetworkUnchecked>,
+    /// If None, the output address is drawn from the postmix account
+    pub output: Option<Address<NetworkUnchecked>>,
     pub relay: String,
 }
 
 /// List available coins
-// FIXME: this function is a ugly+ineficient hack, we should use
-// the electrum notification mechanism and let consumer poll our
-// static/cached state
+///
+/// # Arguments
+/// * `gap_limit` - addresses are derived until `gap_limit` consecutive
+///   addresses are unused on every account
 pub fn list_coins(
     mnemonics: String,
     electrum_address: String,
     electrum_port: u16,
+    gap_limit: u32,
+    network: Network,
+) -> Result<Vec<Coin>, Error> {
+    let signer = sync_wallet(
+        mnemonics,
+        electrum_address,
+        electrum_port,
+        gap_limit,
+        network,
+    )?;
+    let coins = signer.list_coins().into_iter().map(|c| c.1).collect();
+
+    Ok(coins)
+}
+
+/// List available coins using an Esplora API as bitcoin backend
+pub fn list_coins_esplora(
+    mnemonics: String,
+    esplora_url: String,
     range: (u32, u32),
     network: Network,
 ) -> Result<Vec<Coin>, Error> {
-    let mut signer = WpkhHotSigner::new_from_mnemonics(network, &mnemonics)?;
-    let client = Client::new(&electrum_address, electrum_port)?;
-    signer.set_client(client);
-
-    for i in range.0..range.1 {
-        let recv = CoinPath::new(0, i);
-        let change = CoinPath::new(1, i);
-        let _ = signer.get_coins_at(recv);
-        let _ = signer.get_coins_at(change);
-    }
+    let client = EsploraClient::new(&esplora_url);
+    list_coins_from(client, mnemonics, range, network)
+}
 
+/// List available coins using the given chain source
+pub fn list_coins_from(
+    source: impl ChainSource + 'static,
+    mnemonics: String,
+    range: (u32, u32),
+    network: Network,
+) -> Result<Vec<Coin>, Error> {
+    let signer = scan_coins(mnemonics, source, range, network)?;
     let coins = signer.list_coins().into_iter().map(|c| c.1).collect();
 
     Ok(coins)
 }
 
-/// Initiate and participate to a coinjoin
+/// List available coins along with their privacy score
+///
+/// # Arguments
+/// * `gap_limit` - addresses are derived until `gap_limit` consecutive
+///   addresses are unused on every account
+pub fn list_scored_coins(
+    mnemonics: String,
+    electrum_address: String,
+    electrum_port: u16,
+    gap_limit: u32,
+    network: Network,
+) -> Result<Vec<ScoredCoin>, Error> {
+    let signer = sync_wallet(
+        mnemonics,
+        electrum_address,
+        electrum_port,
+        gap_limit,
+        network,
+    )?;
+    Ok(signer.list_scored_coins())
+}
+
+type WalletKey = (Fingerprint, String, u16, Network);
+
+// the synced wallets by (fingerprint, electrum address, electrum port,
+// network), kept across calls so only what changed is fetched again
+static WALLETS: OnceLock<Mutex<HashMap<WalletKey, (WpkhHotSigner, WalletSync)>>> = OnceLock::new();
+
+fn wallets() -> &'static Mutex<HashMap<WalletKey, (WpkhHotSigner, WalletSync)>> {
+    WALLETS.get_or_init(Default::default)
+}
+
+// Sync all the accounts of the wallet w/ the electrum server
+fn sync_wallet(
+    mnemonics: String,
+    electrum_address: String,
+    electrum_port: u16,
+    gap_limit: u32,
+    network: Network,
+) -> Result<WpkhHotSigner, Error> {
+    let signer = WpkhHotSigner::new_from_mnemonics(network, &mnemonics)?;
+    let key = (
+        signer.fingerprint(),
+        electrum_address.clone(),
+        electrum_port,
+        network,
+    );
+    // NOTE: the wallet is taken out of the map while syncing in order to not
+    // block the sync of other wallets, it's dropped on error so the next call
+    // starts over w/ a new connection
+    let known = wallets().lock().expect("poisoned").remove(&key);
+    let (mut signer, mut sync) = match known {
+        Some(wallet) => wallet,
+        None => {
+            let client = Client::new(&electrum_address, electrum_port)?;
+            (signer, WalletSync::new(client))
+        }
+    };
+    for account in Account::all() {
+        signer.set_gap_limit(account, gap_limit);
+    }
+    sync.sync(&mut signer)?;
+    wallets()
+        .lock()
+        .expect("poisoned")
+        .insert(key, (signer.clone(), sync));
+
+    Ok(signer)
+}
+
+// FIXME: this function is a ugly+ineficient hack, spent coins are not
+// detected, electrum users should rely on `WalletSync`
+fn scan_coins(
+    mnemonics: String,
+    source: impl ChainSource + 'static,
+    range: (u32, u32),
+    network: Network,
+) -> Result<WpkhHotSigner, Error> {
+    let mut signer = WpkhHotSigner::new_from_mnemonics(network, &mnemonics)?;
+    signer.set_client(source);
+
+    for account in Account::all() {
+        for i in range.0..range.1 {
+            let recv = CoinPath::with_account(account, 0, i);
+            let change = CoinPath::with_account(account, 1, i);
+            let _ = signer.get_coins_at(recv);
+            let _ = signer.get_coins_at(change);
+        }
+    }
+
+    Ok(signer)
+}
+
+/// Prepare a [`Joinstr`] instance initiating a pool and the signer of its
+///   input, the coinjoin is not started, see [`initiate_coinjoin()`].
 ///
 /// # Arguments
 /// * `config` - configuration of the pool to initiate
 /// * `peer` - information about the peer
-///
-pub fn initiate_coinjoin(config: PoolConfig, peer: PeerConfig) -> Result<Txid, Error> {
+pub fn initiator(
+    config: PoolConfig,
+    peer: PeerConfig,
+) -> Result<(Joinstr<'static>, WpkhHotSigner), Error> {
     let (url, port) = (peer.electrum_address, peer.electrum_port);
-    let mut initiator = Joinstr::new_initiator(
-        Keys::generate(),
-        peer.relay.clone(),
-        (&url, port),
-        config.network,
-        "initiator",
-    )?
-    .denomination(config.denomination)?
-    .fee(config.fee)?
-    .simple_timeout(now() + config.max_duration)?
-    .min_peers(config.peers)?;
+    let initiator = match &peer.esplora_url {
+        Some(esplora_url) => Joinstr::new_initiator_with_esplora(
+            Keys::generate(),
+            peer.relay.clone(),
+            esplora_url,
+            config.network,
+            "initiator",
+        )?,
+        None => Joinstr::new_initiator(
+            Keys::generate(),
+            peer.relay.clone(),
+            (&url, port),
+            config.network,
+            "initiator",
+        )?,
+    };
+    let mut initiator = initiator
+        .denomination(config.denomination)?
+        .fee(config.fee)?
+        .simple_timeout(now() + config.max_duration)?
+        .min_peers(config.peers)?;
 
     let mut signer =
         WpkhHotSigner::new_from_mnemonics(config.network, &peer.mnemonics.to_string())?;
-    let client = Client::new(&url, port)?;
-    signer.set_client(client);
+    signer.set_client(peer_backend(&peer.esplora_url, &url, port)?);
 
-    let addr = peer.output;
     let coin = peer.input;
 
     initiator.set_coin(coin)?;
-    initiator.set_address(addr)?;
+    // if no address provided, it will be drawn from the signer postmix account
+    if let Some(addr) = peer.output {
+        initiator.set_address(addr)?;
+    }
+
+    Ok((initiator, signer))
+}
+
+/// Initiate and participate to a coinjoin
+///
+/// # Arguments
+/// * `config` - configuration of the pool to initiate
+/// * `peer` - information about the peer
+///
+pub fn initiate_coinjoin(config: PoolConfig, peer: PeerConfig) -> Result<Txid, Error> {
+    let (mut initiator, signer) = initiator(config, peer)?;
 
-    initiator.start_coinjoin_blocking(None, Some(signer.clone()), || {})?;
+    initiator.start_coinjoin_blocking(None, Some(signer), || {})?;
 
     let txid = initiator
         .final_tx()
@@ -176,31 +329,58 @@ pub fn list_pools(back: u64, timeout: u64, relay: String) -> Result<Vec<Pool>, E
     Ok(pools)
 }
 
-/// Try to join an already initiated coinjoin
+/// Prepare a [`Joinstr`] instance joining an already initiated pool and
+///   the signer of its input, the coinjoin is not started, see
+///   [`join_coinjoin()`].
 ///
 /// # Arguments
 /// * `pool` - information about the pool
 /// * `peer` - information about the peer
-///
-pub fn join_coinjoin(pool: Pool, peer: PeerConfig) -> Result<String /* Txid */, Error> {
+pub fn peer(pool: &Pool, peer: PeerConfig) -> Result<(Joinstr<'static>, WpkhHotSigner), Error> {
     let (url, port) = (peer.electrum_address, peer.electrum_port);
-    let addr = peer.output;
-    let coin = peer.input;
-    let mut joinstr_peer = Joinstr::new_peer_with_electrum(
-        peer.relay.clone(),
-        &pool,
-        (&url, port),
-        coin,
-        addr,
-        pool.network,
-        "peer",
-    )?;
 
     let mut signer = WpkhHotSigner::new_from_mnemonics(pool.network, &peer.mnemonics.to_string())?;
-    let client = Client::new(&url, port)?;
-    signer.set_client(client);
+    signer.set_client(peer_backend(&peer.esplora_url, &url, port)?);
 
-    joinstr_peer.start_coinjoin_blocking(None, Some(signer.clone()), || {})?;
+    let addr = match peer.output {
+        Some(addr) => addr,
+        None => signer.next_postmix_address()?.as_unchecked().clone(),
+    };
+    let coin = peer.input;
+    let joinstr_peer = match &peer.esplora_url {
+        Some(esplora_url) => Joinstr::new_peer_with_esplora(
+            peer.relay.clone(),
+            pool,
+            esplora_url,
+            coin,
+            addr,
+            pool.network,
+            "peer",
+        )?,
+        None => Joinstr::new_peer_with_electrum(
+            peer.relay.clone(),
+            pool,
+            (&url, port),
+            coin,
+            addr,
+            pool.network,
+            "peer",
+        )?,
+    };
+
+    Ok((joinstr_peer, signer))
+}
+
+/// Try to join an already initiated coinjoin
+///
+/// # Arguments
+/// * `pool` - information about the pool
+/// * `peer` - information about the peer
+///
+pub fn join_coinjoin(pool: Pool, peer: PeerConfig) -> Result<String /* Txid */, Error> {
+    let (mut joinstr_peer, signer) = self::peer(&pool, peer)?;
+
+    joinstr_peer.start_coinjoin_blocking(Some(pool), Some(signer), || {})?;
 
     let txid = joinstr_peer
         .final_tx()
@@ -210,3 +390,11 @@ pub fn join_coinjoin(pool: Pool, peer: PeerConfig) -> Result<String /* Txid */,
 
     Ok(txid)
 }
+
+// Returns the bitcoin backend selected in the [`PeerConfig`]
+fn peer_backend(esplora_url: &Option<String>, url: &str, port: u16) -> Result<Backend, Error> {
+    Ok(match esplora_url {
+        Some(esplora_url) => EsploraClient::new(esplora_url).into(),
+        None => Client::new(url, port)?.into(),
+    })
+}
@@ -0,0 +1,675 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use miniscript::bitcoin::Network;
+use simple_nostr_client::nostr::{
+    bitcoin::{address::NetworkUnchecked, Address},
+    EventBuilder, Keys, PublicKey,
+};
+
+use super::{pool_params, Error, JoinstrInner, Outgoing, Role, Status, Step};
+use crate::{
+    backend::r#async::AsyncChainSource,
+    fee::{FeeBounds, FeePolicy},
+    nostr::{self, Pool, PoolMessage},
+    signer::{Coin, JoinstrSigner},
+    utils::{Clock, SystemClock},
+};
+
+/// The nostr transport used by an async [`Joinstr`] instance to talk to the
+///   pool.
+pub trait NostrTransport: Sized + Send {
+    /// Open a connection to the relay, subscribed to the NIP04 DMs sent to
+    ///   `keys`.
+    fn connect(
+        relay: &str,
+        keys: Keys,
+        name: &str,
+    ) -> impl Future<Output = Result<Self, nostr::error::Error>> + Send;
+
+    /// Post a nostr event.
+    fn post_event(
+        &mut self,
+        event: EventBuilder,
+    ) -> impl Future<Output = Result<(), nostr::error::Error>> + Send;
+
+    /// Send a [`PoolMessage`] wrapped into a NIP04 encrypted DM, w/ a NIP-13
+    ///   proof of work of `pow` leading zero bits if any.
+    fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> impl Future<Output = Result<(), nostr::error::Error>> + Send;
+
+    /// Wait up to `timeout` for a [`PoolMessage`], returns it w/ its NIP-13
+    ///   proof of work, or None if the timeout elapsed.
+    fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Option<(PoolMessage, u8)>, nostr::error::Error>> + Send;
+}
+
+impl NostrTransport for nostr::r#async::NostrClient {
+    async fn connect(relay: &str, keys: Keys, name: &str) -> Result<Self, nostr::error::Error> {
+        let mut client = Self::new(name).relay(relay.into())?.keys(keys)?;
+        client.connect_nostr().await?;
+        Ok(client)
+    }
+
+    async fn post_event(&mut self, event: EventBuilder) -> Result<(), nostr::error::Error> {
+        nostr::r#async::NostrClient::post_event(self, event).await
+    }
+
+    async fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> Result<(), nostr::error::Error> {
+        match pow {
+            Some(difficulty) => self.send_pool_message_with_pow(npub, msg, difficulty).await,
+            None => nostr::r#async::NostrClient::send_pool_message(self, npub, msg).await,
+        }
+    }
+
+    async fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, nostr::error::Error> {
+        self.wait_pool_msg_with_pow(timeout).await
+    }
+}
+
+/// An async version of [`super::Joinstr`], generic over the nostr transport
+///   & the bitcoin backend.
+///
+/// The coinjoin runs in the future returned by [`Joinstr::start_coinjoin()`],
+///   dropping this future cancels the coinjoin.
+pub struct Joinstr<T: NostrTransport, B: AsyncChainSource> {
+    inner: JoinstrInner<'static>,
+    client: T,
+    backend: Option<B>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<T: NostrTransport, B: AsyncChainSource> Joinstr<T, B> {
+    /// Create a new [`Joinstr`] instance
+    ///
+    /// # Arguments
+    /// * `keys` - Nostr keys that will be used for auth to the nostr relay
+    /// * `relay` - The relay url
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    async fn new(keys: Keys, relay: &str, name: &str) -> Result<Self, Error> {
+        let client = T::connect(relay, keys.clone(), name).await?;
+        let inner = JoinstrInner::new(keys, relay.into(), name);
+        Ok(Joinstr {
+            inner,
+            client,
+            backend: None,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Create a new [`Joinstr`] instance that have a `Peer` role, this role means
+    ///   the pool have already been initited by another peer.
+    ///
+    /// # Arguments
+    /// * `relay` - The relay url
+    /// * `pool` - The [`Pool`] struct representing the pool we want to join
+    /// * `input` - The transaction input to include in the coinjoin
+    /// * `output` - The address we want to receive the coin to
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    ///
+    /// Note: this instance do not have a bitcoin backend, it can be set w/
+    ///   [`Joinstr::backend()`].
+    pub async fn new_peer(
+        relay: &str,
+        pool: &Pool,
+        input: Coin,
+        output: Address<NetworkUnchecked>,
+        network: Network,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let (denomination, fee, timeout, peers) = pool_params(pool)?;
+        let address = match output.is_valid_for_network(network) {
+            true => output.assume_checked(),
+            false => return Err(Error::WrongAddressNetwork),
+        };
+        // NOTE: we create a randow key to process pool auth
+        let mut peer = Self::new(Keys::generate(), relay, name)
+            .await?
+            .network(network)
+            .denomination(denomination)?
+            .fee(fee)?
+            .simple_timeout(timeout)?
+            .min_peers(peers)?;
+        peer.inner.my_inputs.push(input);
+        peer.inner.my_outputs.push(address);
+        peer.inner.role = Role::Peer;
+        Ok(peer)
+    }
+
+    /// Create a new [`Joinstr`] instance that have a `Coordinator` role, this role means
+    ///   this instance will only initiate & monitor the coinjoin but will not add input
+    ///   nor output.
+    ///
+    /// # Arguments
+    /// * `keys` - Nostr keys that will be used for auth to the nostr relay
+    /// * `relay` - The relay url
+    /// * `backend` - The bitcoin backend
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    ///
+    /// Note: the parameters of the pool should be passed with builder pattern
+    pub async fn new_initiator(
+        keys: Keys,
+        relay: &str,
+        backend: B,
+        network: Network,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let mut j = Self::new(keys, relay, name)
+            .await?
+            .network(network)
+            .backend(backend);
+        j.inner.role = Role::Initiator;
+        Ok(j)
+    }
+
+    /// Set the bitcoin network to network
+    pub fn network(mut self, network: Network) -> Self {
+        self.inner.network = network;
+        self
+    }
+
+    /// Set the bitcoin backend used to check the pool fee & broadcast the
+    ///   coinjoin transaction, replace the current one if any.
+    pub fn backend(mut self, backend: B) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the clock used for the pool timelines & the random delays,
+    ///   default to [`SystemClock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the denomination of the pool in Bitcoin.
+    pub fn denomination(mut self, denomination: f64) -> Result<Self, Error> {
+        self.inner.set_denomination(denomination)?;
+        Ok(self)
+    }
+
+    /// Set the min number of peers of the pool
+    pub fn min_peers(mut self, peers: usize) -> Result<Self, Error> {
+        self.inner.set_min_peers(peers)?;
+        Ok(self)
+    }
+
+    /// Set the timestamp at which the pool will be considered canceled if
+    ///   not enough peer have join.
+    pub fn simple_timeout(mut self, timestamp: u64) -> Result<Self, Error> {
+        self.inner.set_simple_timeout(timestamp)?;
+        Ok(self)
+    }
+
+    /// Set the minimum fee rate that the final transaction should spend to
+    /// be considered valid (sats/vb)
+    pub fn fee(mut self, fee: u32) -> Result<Self, Error> {
+        self.inner.set_fee(fee)?;
+        Ok(self)
+    }
+
+    /// Set the minimum fee rate that the final transaction should spend
+    ///   from a [`FeePolicy`], the policy is resolved using the bitcoin
+    ///   backend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool already exists
+    ///   - the fee rate is already set
+    ///   - there is no bitcoin backend
+    ///   - the policy cannot be resolved
+    pub async fn fee_policy(self, policy: FeePolicy) -> Result<Self, Error> {
+        self.inner.pool_not_exists()?;
+        let fee = match (policy, self.backend.as_ref()) {
+            (FeePolicy::Fixed(fee), _) => fee,
+            (policy, Some(backend)) => policy.resolve_async(backend).await?,
+            (_, None) => return Err(Error::BackendMissing),
+        };
+        log::debug!("Joinstr::fee_policy() {policy:?} resolved to {fee} sats/vb");
+        self.fee(fee)
+    }
+
+    /// Set the max fee rate (sats/vb) our inputs accept to pay, an input
+    ///   paying more for its share of the transaction will not be signed.
+    pub fn max_fee(mut self, max_fee: u32) -> Self {
+        self.inner.max_fee = Some(max_fee);
+        self
+    }
+
+    /// Set the min NIP-13 proof of work (leading zero bits of the event id)
+    ///   of the join requests of the pool, announced in the pool payload.
+    pub fn pow(mut self, difficulty: u8) -> Result<Self, Error> {
+        self.inner.pool_not_exists()?;
+        self.inner.pow = Some(difficulty);
+        Ok(self)
+    }
+
+    /// Ignore the join requests of these nostr keys, the list is announced
+    ///   in the pool payload so every peer ignores them too.
+    pub fn ban_peers(mut self, peers: impl IntoIterator<Item = PublicKey>) -> Result<Self, Error> {
+        self.inner.pool_not_exists()?;
+        self.inner.banned.extend(peers);
+        Ok(self)
+    }
+
+    /// Add a coin to coinjoin, a peer can register several coins to the
+    ///   same pool as long as it register as many output addresses.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coin is already registered
+    pub fn add_coin(&mut self, coin: Coin) -> Result<(), Error> {
+        self.inner.add_coin(coin)
+    }
+
+    /// Add an address the coins must be sent to, a peer can register
+    ///   several addresses to the same pool as long as it register as many
+    ///   coins.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is already registered
+    /// or if address is for wrong network
+    pub fn add_address(&mut self, addr: Address<NetworkUnchecked>) -> Result<(), Error> {
+        self.inner.add_address(addr)
+    }
+
+    /// Returns the pool of this instance, if posted or joined.
+    pub fn pool(&self) -> Option<&Pool> {
+        self.inner.pool.as_ref()
+    }
+
+    /// Returns the finalized transaction
+    pub fn final_tx(&self) -> Option<&miniscript::bitcoin::Transaction> {
+        self.inner.final_tx()
+    }
+
+    /// Returns the current status of the [`Joinstr`] instance.
+    ///
+    /// # Returns
+    /// A [`Status`] struct containing the current state information.
+    pub fn status(&self) -> Status {
+        self.inner.status()
+    }
+
+    /// Run a coinjoin process, see [`super::Joinstr::start_coinjoin_blocking()`]
+    ///   for the steps processed.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool we want join (optional)
+    /// * `signer` - The signer to sign our input with (optional)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any step return an error.
+    pub async fn start_coinjoin<S>(
+        &mut self,
+        pool: Option<Pool>,
+        signer: Option<S>,
+    ) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+    {
+        self.start_coinjoin_with_notif(pool, signer, || {}).await
+    }
+
+    /// Same as [`Joinstr::start_coinjoin()`] w/ a callback function called every
+    ///   time the pool state is updated.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool we want join (optional)
+    /// * `signer` - The signer to sign our input with (optional)
+    /// * `notif` - A callback function called every time the pool state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any step return an error.
+    pub async fn start_coinjoin_with_notif<S, N>(
+        &mut self,
+        pool: Option<Pool>,
+        signer: Option<S>,
+        notif: N,
+    ) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+        N: Fn(),
+    {
+        let result = self.run_coinjoin(pool, signer, &notif).await;
+        if let Err(e) = &result {
+            log::error!("Joinstr::start_coinjoin() failed: {e:?}");
+            self.inner.error = Some(format!("{:?}", e));
+            self.inner.step = Step::Failed;
+            notif();
+        }
+        result
+    }
+
+    async fn run_coinjoin<S, N>(
+        &mut self,
+        pool: Option<Pool>,
+        mut signer: Option<S>,
+        notif: &N,
+    ) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+        N: Fn(),
+    {
+        let name = self.inner.name.clone();
+        log::debug!("Joinstr::start_coinjoin({name})");
+        let join = pool.is_some();
+        self.inner.prepare(pool, signer.as_mut())?;
+
+        if join {
+            log::debug!("Joinstr::start_coinjoin({name}) try to join pool...");
+            self.join_pool().await?;
+            log::debug!("Joinstr::start_coinjoin({name}) pool joined");
+        } else {
+            log::debug!("Joinstr::start_coinjoin({name}) try to broadcast pool...");
+            self.post().await?;
+            log::debug!("Joinstr::start_coinjoin({name}) pool broadcast!");
+        }
+        notif();
+
+        // register peers & outputs
+        self.register_outputs(notif).await?;
+        log::debug!("Joinstr::start_coinjoin({name}) outputs registered!");
+
+        self.inner.generate_unsigned_tx()?;
+        notif();
+
+        tokio::time::sleep(self.clock.rand_delay()).await;
+
+        if !self.inner.my_inputs.is_empty() {
+            match signer {
+                Some(s) => self.register_my_inputs(&s, notif).await?,
+                None => return Err(Error::SignerMissing),
+            }
+        }
+
+        self.register_inputs(notif).await?;
+        log::debug!("Joinstr::start_coinjoin({name}) inputs registerd!");
+
+        self.broadcast_tx().await?;
+        log::debug!("Joinstr::start_coinjoin({name}) tx broadcast!");
+        notif();
+
+        Ok(())
+    }
+
+    /// Send a pool message of the state machine, see [`JoinstrInner`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if connecting to the relay or
+    ///   sending the message fails.
+    async fn send(&mut self, out: Outgoing) -> Result<(), Error> {
+        let Outgoing {
+            npub,
+            msg,
+            pow,
+            detached,
+        } = out;
+        match detached {
+            Some((keys, name)) => {
+                let relay = self.inner.relay.clone().ok_or(Error::RelaysMissing)?;
+                let mut client = T::connect(&relay, keys, &name).await?;
+                client.send_pool_message(&npub, msg, pow).await?;
+            }
+            None => self.client.send_pool_message(&npub, msg, pow).await?,
+        }
+        Ok(())
+    }
+
+    /// Initiate a new pool by sending a pool creation event (Kind 2022)
+    ///   to nostr relays.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a pool already exists, if
+    ///   some fields of the pool are missing or if posting the event fail.
+    async fn post(&mut self) -> Result<(), Error> {
+        let pool = self.inner.pool_to_post()?;
+        self.client.post_event(pool.clone().try_into()?).await?;
+        self.inner.posted(pool);
+        Ok(())
+    }
+
+    /// Try to join the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool does not exists
+    ///   - the fee rate is far outside the current estimates
+    ///   - the nostr client fail to connect relays
+    ///   - sending a message to the pool fails
+    ///   - receiving credentials fails
+    ///   - pool connexion timed out
+    async fn join_pool(&mut self) -> Result<(), Error> {
+        self.inner.pool_exists()?;
+        // check the fee rate of the pool against the current estimates of the
+        // bitcoin backend, if any.
+        let fee = self.inner.pool_fee()?;
+        if let Some(backend) = self.backend.as_ref() {
+            if let Some(bounds) = FeeBounds::from_async_estimates(backend).await? {
+                bounds.check(fee)?;
+            }
+        }
+        let join = self.inner.join_request()?;
+        let (timeout, _) = self.inner.start_timeline()?;
+        self.send(join).await?;
+
+        while let Some(left) = remaining(&*self.clock, timeout) {
+            let Some((msg, _)) = self.client.receive_pool_message(left).await? else {
+                continue;
+            };
+            if let Some(keys) = self.inner.receive_credentials(msg)? {
+                // we connect a new nostr client using pool keys and replace the actual one
+                let relay = self.inner.relay.clone().ok_or(Error::RelaysMissing)?;
+                self.client = T::connect(&relay, keys, &self.inner.name).await?;
+                return self.join_extra_peers().await;
+            }
+        }
+        Err(Error::PoolConnectionTimeout)
+    }
+
+    /// Send a join request for every additional input/output pair we want to
+    ///   register, see [`super::Joinstr`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool does not exists
+    ///   - connecting to the relay fails
+    ///   - sending a join request fails
+    async fn join_extra_peers(&mut self) -> Result<(), Error> {
+        for join in self.inner.extra_joins()? {
+            tokio::time::sleep(self.clock.rand_delay()).await;
+            self.send(join).await?;
+        }
+        Ok(())
+    }
+
+    /// Run the round of output registration, until enough output registered
+    ///   or if some error occur.
+    ///
+    /// # Arguments
+    /// * `notif` - A callback function called every time the pool state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the inner pool not exists
+    ///   - the payload of the pool is missing
+    ///   - the fee are not of type [`nostr::Fee::Fixed`]
+    ///   - sending a message to the pool fails
+    ///   - timeout elapsed
+    ///   - peer count do not match
+    async fn register_outputs<N>(&mut self, notif: &N) -> Result<(), Error>
+    where
+        N: Fn(),
+    {
+        let mut round = self.inner.output_round()?;
+        for join in self.inner.dummy_joins()? {
+            self.send(join).await?;
+        }
+
+        // register peers
+        while !round.peers_joined() {
+            let Some(timeout) = remaining(&*self.clock, round.expired) else {
+                break;
+            };
+            let Some((msg, pow)) = self.client.receive_pool_message(timeout).await? else {
+                continue;
+            };
+            if let Some(response) = self.inner.receive_join(&mut round, msg, pow, notif)? {
+                self.send(response).await?;
+            }
+        }
+
+        tokio::time::sleep(self.clock.rand_delay()).await;
+
+        for i in 0..self.inner.my_outputs.len() {
+            if i > 0 {
+                tokio::time::sleep(self.clock.rand_delay()).await;
+            }
+            let msg = self.inner.register_output(&mut round, i)?;
+            self.send(msg).await?;
+            notif();
+        }
+
+        // register ouputs
+        let expired = self.inner.end_timeline()?;
+        while !round.outputs_registered() {
+            let Some(timeout) = remaining(&*self.clock, expired) else {
+                break;
+            };
+            let Some((msg, _)) = self.client.receive_pool_message(timeout).await? else {
+                continue;
+            };
+            self.inner.receive_output(&mut round, msg, notif)?;
+        }
+
+        self.inner.end_output_round(round, self.clock.now())?;
+        notif();
+        Ok(())
+    }
+
+    /// Sign & send all the inputs we own, a random delay is awaited between
+    ///   each input registration.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing or sending one of the
+    ///   inputs fails.
+    async fn register_my_inputs<S, N>(&mut self, signer: &S, notif: &N) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+        N: Fn(),
+    {
+        let inputs = std::mem::take(&mut self.inner.my_inputs);
+        for (i, input) in inputs.into_iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(self.clock.rand_delay()).await;
+            }
+            let msg = self.inner.register_input(signer, input, i)?;
+            self.send(msg).await?;
+            notif();
+        }
+        Ok(())
+    }
+
+    /// Run the round of input registration, until enough input registered
+    ///   or if some error occur.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the inner pool does not exists
+    ///   - the pool payload is missing
+    ///   - the inner coinjoin is None
+    ///   - timeout expired
+    ///   - trying register an input error
+    ///   - trying finalize coinjoin error
+    async fn register_inputs<N>(&mut self, notif: &N) -> Result<(), Error>
+    where
+        N: Fn(),
+    {
+        let expired = self.inner.input_deadline(self.clock.now())?;
+
+        while !self.inner.inputs_registered()? {
+            let Some(timeout) = remaining(&*self.clock, expired) else {
+                break;
+            };
+            let Some((msg, _)) = self.client.receive_pool_message(timeout).await? else {
+                continue;
+            };
+            let Some(input) = self.inner.input_of(msg)? else {
+                continue;
+            };
+            let checked = match self.backend.as_ref() {
+                // NOTE: an input that cannot be checked is dropped
+                Some(backend) => match backend.get_txout(input.txin.previous_output).await {
+                    Ok(prevout) => self.inner.check_input(&input, prevout),
+                    Err(e) => Err(e.into()),
+                },
+                None => Ok(()),
+            };
+            if self.inner.receive_input(input, checked, notif)? {
+                break;
+            }
+        }
+        if self.clock.now() > expired {
+            Err(Error::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Broadcast the signed + finalized transaction.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - The inner pool does not exists
+    ///   - the inner coinjoin is None
+    ///   - The transaction has not been finalized
+    ///   - brodcasting transaction to the backend fails
+    ///
+    /// Note: if no backend, the transaction will not been broadcasted
+    ///   but no error will be emited.
+    async fn broadcast_tx(&mut self) -> Result<(), Error> {
+        let tx = self.inner.tx_to_broadcast()?;
+        if let Some(backend) = self.backend.as_ref() {
+            backend.broadcast(&tx).await?;
+        }
+        self.inner.broadcasted(tx);
+        Ok(())
+    }
+}
+
+// Returns the time left until the `deadline` timestamp, None if expired.
+fn remaining(clock: &dyn Clock, deadline: u64) -> Option<Duration> {
+    Duration::from_secs(deadline)
+        .checked_sub(clock.unix_time())
+        .filter(|d| !d.is_zero())
+}
@@ -4,6 +4,11 @@ pub enum Error {
     Event(crate::nostr::EventError),
     Coinjoin(crate::coinjoin::Error),
     Electrum(crate::electrum::Error),
+    Bitcoind(crate::bitcoind::Error),
+    Esplora(crate::esplora::Error),
+    Backend(crate::backend::Error),
+    Fee(crate::fee::Error),
+    BackendMissing,
     PoolAlreadyCreated,
     PoolAlreadyExists,
     PoolNotExists,
@@ -19,6 +24,7 @@ pub enum Error {
     NotYetImplemented,
     PeerCountNotMatch(usize, usize),
     Timeout,
+    Canceled,
     CoinjoinMissing,
     MissingFinalTx,
     PoolConnectionTimeout,
@@ -37,15 +43,18 @@ pub enum Error {
     PeerMissing,
     TimeoutMissing,
     RelaysMissing,
+    RelayNotMatch,
     FeeMissing,
     TimelineDuration,
     AlreadyHaveInput,
     AlreadyHaveOutput,
+    InputsOutputsNotMatch(usize, usize),
     WrongRole,
     WrongConfig,
     PoolKey,
     InputParsing,
     PoolCorrupted,
+    OwnCoins,
 }
 
 impl From<crate::coinjoin::Error> for Error {
@@ -71,3 +80,32 @@ impl From<crate::electrum::Error> for Error {
         Self::Electrum(value)
     }
 }
+
+impl From<crate::bitcoind::Error> for Error {
+    fn from(value: crate::bitcoind::Error) -> Self {
+        Self::Bitcoind(value)
+    }
+}
+
+impl From<crate::esplora::Error> for Error {
+    fn from(value: crate::esplora::Error) -> Self {
+        Self::Esplora(value)
+    }
+}
+
+impl From<crate::fee::Error> for Error {
+    fn from(value: crate::fee::Error) -> Self {
+        Self::Fee(value)
+    }
+}
+
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        match value {
+            crate::backend::Error::Electrum(e) => Self::Electrum(e),
+            crate::backend::Error::Bitcoind(e) => Self::Bitcoind(e),
+            crate::backend::Error::Esplora(e) => Self::Esplora(e),
+            e => Self::Backend(e),
+        }
+    }
+}
@@ -1,39 +1,208 @@
+#[cfg(feature = "async")]
+pub mod r#async;
 mod error;
-use backoff::Backoff;
 pub use error::Error;
 use serde::{Deserialize, Serialize};
 
 use std::{
     collections::HashSet,
+    fmt::Debug,
     sync::{Arc, Mutex},
     thread,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use miniscript::bitcoin::{Amount, Network};
+use miniscript::bitcoin::{Amount, Network, Transaction, TxOut};
 use simple_nostr_client::nostr::{
     self,
     bitcoin::{address::NetworkUnchecked, Address},
     hashes::{sha256, Hash, HashEngine},
-    Keys, PublicKey,
+    EventBuilder, Keys, PublicKey,
 };
+use simple_nostr_client::SharedConnection;
 
 use crate::{
-    coinjoin::CoinJoin,
+    backend::{self, ChainConfig, ChainSource, SharedChainSource},
+    bitcoind::{BitcoindBackend, BitcoindConfig},
+    coinjoin::{self, CoinJoin},
+    esplora::EsploraClient,
+    fee::{self, FeeBounds, FeePolicy},
     nostr::{
-        default_version, sync::NostrClient, Credentials, Fee, InputDataSigned, Pool, PoolMessage,
-        PoolPayload, PoolType, Timeline, Tor, Vpn,
+        default_version,
+        sync::{Connection, NostrClient},
+        Credentials, Fee, InputDataSigned, Pool, PoolMessage, PoolPayload, PoolType, Timeline, Tor,
+        Vpn,
     },
-    signer::{Coin, JoinstrSigner},
-    utils::{now, rand_delay},
+    signer::{Coin, JoinstrSigner, WpkhHotSigner},
+    utils::{Clock, SystemClock},
 };
 
 // delay we wait between (non-blocking) polls of a channel
 pub const WAIT: u64 = 50;
+// max delay we block waiting for a pool message, the timeouts & cancellation
+// are checked at least at this interval
+const WAIT_MSG: Duration = Duration::from_millis(100);
+// max number of pool messages a call to `Joinstr::poll()` handles, in order a
+// flood of messages to one pool cannot starve the other ones
+const MAX_POLL_MSG: usize = 100;
+
+/// The nostr transport used by a [`Joinstr`] instance to talk to the pool,
+///   see [`Connector`].
+pub trait NostrTransport: Debug + Send {
+    /// Post a nostr event.
+    fn post_event(&mut self, event: EventBuilder) -> Result<(), crate::nostr::error::Error>;
+
+    /// Send a [`PoolMessage`] wrapped into a NIP04 encrypted DM, w/ a NIP-13
+    ///   proof of work of `pow` leading zero bits if any.
+    fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> Result<(), crate::nostr::error::Error>;
+
+    /// Wait up to `timeout` for a [`PoolMessage`], returns it w/ its NIP-13
+    ///   proof of work, or None if the timeout elapsed.
+    fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, crate::nostr::error::Error>;
+}
+
+/// Open the [`NostrTransport`]s of a [`Joinstr`] instance.
+pub trait Connector: Debug + Send + Sync {
+    /// Open a connection to the relay, subscribed to the NIP04 DMs sent to
+    ///   `keys`.
+    fn connect(
+        &self,
+        connection: Connection,
+        keys: Keys,
+        name: &str,
+    ) -> Result<Box<dyn NostrTransport>, crate::nostr::error::Error>;
+}
+
+/// The default [`Connector`], open [`NostrClient`]s.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NostrConnector;
+
+impl Connector for NostrConnector {
+    fn connect(
+        &self,
+        connection: Connection,
+        keys: Keys,
+        name: &str,
+    ) -> Result<Box<dyn NostrTransport>, crate::nostr::error::Error> {
+        let mut client = NostrClient::new(name).connection(connection)?.keys(keys)?;
+        client.connect_nostr()?;
+        Ok(Box::new(client))
+    }
+}
+
+impl NostrTransport for NostrClient {
+    fn post_event(&mut self, event: EventBuilder) -> Result<(), crate::nostr::error::Error> {
+        NostrClient::post_event(self, event)
+    }
+
+    fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> Result<(), crate::nostr::error::Error> {
+        match pow {
+            Some(difficulty) => self.send_pool_message_with_pow(npub, msg, difficulty),
+            None => NostrClient::send_pool_message(self, npub, msg),
+        }
+    }
+
+    fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, crate::nostr::error::Error> {
+        // NOTE: the waiter is created first in order to not miss a message
+        // received between the poll & the wait
+        let mut waiter = self.waiter()?;
+        if let Some(msg) = self.try_receive_pool_msg_with_pow()? {
+            return Ok(Some(msg));
+        }
+        if !timeout.is_zero() && waiter.wait(timeout) {
+            return self.try_receive_pool_msg_with_pow();
+        }
+        Ok(None)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Joinstr<'a> {
     pub inner: Arc<Mutex<JoinstrInner<'a>>>,
+    // the main nostr client, connected on first use.
+    // NOTE: when both are needed, this lock must be taken before the inner one.
+    client: Arc<Mutex<Option<Box<dyn NostrTransport>>>>,
+    connection: Connection,
+    connector: Arc<dyn Connector>,
+    clock: Arc<dyn Clock>,
+}
+
+// A pool message the driver of a [`JoinstrInner`] must send.
+struct Outgoing {
+    npub: PublicKey,
+    msg: PoolMessage,
+    pow: Option<u8>,
+    // the keys & name of the fresh connection the message must be sent from,
+    // in order it cannot be linked to the main one at network level, None to
+    // send it from the main nostr client
+    detached: Option<(Keys, String)>,
+}
+
+// The state of the output registration round of a [`JoinstrInner`].
+struct OutputRound<'a> {
+    payload: PoolPayload,
+    // end of the peer registration
+    expired: u64,
+    start_early: bool,
+    peers: HashSet<PublicKey>,
+    coinjoin: CoinJoin<'a, crate::electrum::Client>,
+}
+
+impl OutputRound<'_> {
+    // Whether enough peers have joined to start the output registration
+    // before the end of the peer registration.
+    fn peers_joined(&self) -> bool {
+        self.start_early && self.peers.len() >= self.payload.peers
+    }
+
+    // Whether every peer registered its output.
+    fn outputs_registered(&self) -> bool {
+        self.coinjoin.outputs_len() >= self.peers.len()
+    }
+}
+
+// The step a coinjoin driven by `Joinstr::poll()` is at.
+enum Phase<'a> {
+    Peers(OutputRound<'a>),
+    // the output registration round & its deadline
+    Outputs(OutputRound<'a>, u64),
+    // the deadline of the input registration
+    Inputs(u64),
+    Ended,
+}
+
+/// A coinjoin driven by the caller w/ [`Joinstr::poll()`] instead of a
+///   dedicated thread, see [`Joinstr::drive()`].
+pub struct Driver<'a> {
+    phase: Phase<'a>,
+}
+
+impl Debug for Driver<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phase = match self.phase {
+            Phase::Peers(_) => "peers",
+            Phase::Outputs(..) => "outputs",
+            Phase::Inputs(_) => "inputs",
+            Phase::Ended => "ended",
+        };
+        f.debug_struct("Driver").field("phase", &phase).finish()
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -58,6 +227,7 @@ pub enum Step {
     Failed,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Status {
     role: Role,
     step: Step,
@@ -68,24 +238,70 @@ pub struct Status {
     error: Option<String>,
 }
 
+impl Status {
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn registered_peers(&self) -> usize {
+        self.registered_peers
+    }
+
+    pub fn registered_outputs(&self) -> usize {
+        self.registered_outputs
+    }
+
+    pub fn registered_inputs(&self) -> usize {
+        self.registered_inputs
+    }
+
+    pub fn confirmations(&self) -> usize {
+        self.confirmations
+    }
+
+    /// The error that made the coinjoin fail, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
 #[derive(Debug)]
 pub struct JoinstrInner<'a> {
     role: Role,
     step: Step,
     confirmations: usize,
     error: Option<String>,
-    pub client: NostrClient,
+    // set by [`Joinstr::cancel()`], checked at each round of the blocking loops
+    canceled: bool,
+    // nostr keys of the main client: our keys until we receive the pool
+    // credentials, then the pool keys
+    keys: Keys,
+    // name of the instance, used for debug logs
+    name: String,
     pub pool: Option<Pool>,
     pub denomination: Option<Amount>,
     pub peers_count: Option<usize>,
     pub timeout: Option<Timeline>,
     pub relay: Option<String>,
     pub fee: Option<Fee>,
+    /// The min NIP-13 proof of work of the join requests of the pool
+    pub pow: Option<u8>,
+    /// The join requests of these keys are ignored
+    pub banned: Vec<PublicKey>,
+    /// The max fee rate (sats/vb) our inputs accept to pay
+    pub max_fee: Option<u32>,
     pub network: Network,
     pub coinjoin: Option<CoinJoin<'a, crate::electrum::Client>>,
-    pub electrum_client: Option<crate::electrum::Client>,
-    input: Option<Coin>,
-    output: Option<Address>,
+    pub backend: Option<SharedChainSource>,
+    // coins we own and want to add to the coinjoin
+    my_inputs: Vec<Coin>,
+    // addresses we own and want to receive coins to, every output is
+    // registered using its own nostr identity
+    my_outputs: Vec<Address>,
     final_tx: Option<miniscript::bitcoin::Transaction>,
     // requests history
     peers: Vec<nostr::PublicKey>,
@@ -100,9 +316,18 @@ pub struct State {
     pub pool_secret_key: String, /* nostr::Keys*/
     pub relay: String,
     pub electrum: Option<(String, u16)>,
+    #[serde(default)]
+    pub bitcoind: Option<BitcoindConfig>,
+    #[serde(default)]
+    pub esplora: Option<String>,
+    /// (servers, quorum) of an [`crate::electrum_pool::ElectrumPool`]
+    #[serde(default)]
+    pub electrum_pool: Option<(Vec<(String, u16)>, usize)>,
+    #[serde(default)]
+    pub max_fee: Option<u32>,
     pub pool: Pool,
-    pub input: Option<Coin>,
-    pub output: Option<Address<NetworkUnchecked>>,
+    pub my_inputs: Vec<Coin>,
+    pub my_outputs: Vec<Address<NetworkUnchecked>>,
     pub network: bitcoin::Network,
     pub final_tx: Option<bitcoin::Transaction>,
     // requests history
@@ -111,25 +336,36 @@ pub struct State {
     pub inputs: Vec<serde_json::Value /* InputDataSigned*/>,
 }
 
-impl Default for JoinstrInner<'_> {
-    fn default() -> Self {
+impl JoinstrInner<'_> {
+    /// Create a new [`JoinstrInner`].
+    ///
+    /// # Arguments
+    /// * `keys` - Nostr keys of the main nostr client
+    /// * `relay` - The relay url
+    /// * `name` - Name of the instance (use for debug logs), can be an empty &str.
+    fn new(keys: Keys, relay: String, name: &str) -> Self {
         Self {
             role: Default::default(),
             step: Default::default(),
             confirmations: 0,
             error: None,
-            client: Default::default(),
+            canceled: false,
+            keys,
+            name: name.into(),
             pool: Default::default(),
             denomination: Default::default(),
             peers_count: Default::default(),
             timeout: Default::default(),
-            relay: Default::default(),
+            relay: Some(relay),
             fee: Default::default(),
+            pow: None,
+            banned: Vec::new(),
+            max_fee: None,
             network: Network::Bitcoin,
             coinjoin: None,
-            electrum_client: None,
-            input: None,
-            output: None,
+            backend: None,
+            my_inputs: Vec::new(),
+            my_outputs: Vec::new(),
             final_tx: None,
             peers: Default::default(),
             outputs: Default::default(),
@@ -143,42 +379,49 @@ impl Joinstr<'_> {
     ///
     /// # Arguments
     /// * `keys` - Nostr keys that will be used for auth to the nostr relay
-    /// * `relays` - A list of relays address to connect to
+    /// * `relay` - The relay url, or a connection shared w/ other instances
     /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
     ///   be an empty &str.
     ///
     /// Note: this instance do not have a bitcoin backend, it then cannot verify
     ///   that coins registered by other peers exists, and that an output is willing to
     ///   do address reuse.
-    fn new(keys: Keys, relay: String, name: &str) -> Result<Self, Error> {
-        let mut client = NostrClient::new(name).relay(relay.clone())?.keys(keys)?;
-        client.connect_nostr()?;
-        let relay = Some(relay);
-        let inner = Arc::new(Mutex::new(JoinstrInner {
-            client,
-            relay,
-            ..Default::default()
-        }));
-        Ok(Joinstr { inner })
+    ///
+    /// Note: only the main nostr client use a shared connection, the messages that
+    ///   must not be linked to it (extra joins, dummy joins, additional outputs &
+    ///   inputs) are still sent over dedicated connections.
+    ///
+    /// Note: the main nostr client connects to the relay on first use, w/ the
+    ///   [`Connector`] of the instance, see [`Joinstr::connector()`].
+    fn new(keys: Keys, relay: impl Into<Connection>, name: &str) -> Result<Self, Error> {
+        let connection = relay.into();
+        let inner = JoinstrInner::new(keys, connection.relay(), name);
+        Ok(Joinstr {
+            inner: Arc::new(Mutex::new(inner)),
+            client: Default::default(),
+            connection,
+            connector: Arc::new(NostrConnector),
+            clock: Arc::new(SystemClock),
+        })
     }
 
     /// Create a new [`Joinstr`] instance with a bitcoin backend
     ///
     /// # Arguments
     /// * `keys` - Nostr keys that will be used for auth to the nostr relay
-    /// * `relays` - A list of relays address to connect to
+    /// * `relay` - The relay url, or a connection shared w/ other instances
     /// * `electrum_server` - A tuple (<address>, <port>)
     /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
     ///   be an empty &str.
     fn new_with_electrum(
         keys: Keys,
-        relay: String,
+        relay: impl Into<Connection>,
         electrum_server: (&str, u16),
         name: &str,
     ) -> Result<Self, Error> {
         let electrum = crate::electrum::Client::new(electrum_server.0, electrum_server.1)?;
         let j = Self::new(keys, relay, name)?;
-        j.inner.lock().expect("poisoned").electrum_client = Some(electrum);
+        j.inner.lock().expect("poisoned").backend = Some(backend::shared(electrum));
         Ok(j)
     }
 
@@ -186,7 +429,7 @@ impl Joinstr<'_> {
     ///   the pool have already been initited by another peer.
     ///
     /// # Arguments
-    /// * `relays` - A list of relays address to connect to
+    /// * `relay` - The relay url, or a connection shared w/ other instances
     /// * `pool` - The [`Pool`] struct representing the pool we want to join
     /// * `input` - The transaction input to include in the coinjoin
     /// * `output` - The address we want to receive the coin to
@@ -198,33 +441,14 @@ impl Joinstr<'_> {
     ///   that coins registered by other peers exists, and that an output is willing to
     ///   do address reuse.
     pub fn new_peer(
-        relay: String,
+        relay: impl Into<Connection>,
         pool: &Pool,
         input: Coin,
         output: Address<NetworkUnchecked>,
         network: Network,
         name: &str,
     ) -> Result<Self, Error> {
-        let (denomination, fee, timeout, peers) = match &pool.payload {
-            None => return Err(Error::PoolPayloadMissing),
-            Some(PoolPayload {
-                denomination,
-                peers,
-                timeout,
-                fee,
-                ..
-            }) => {
-                let fee = match &fee {
-                    Fee::Fixed(f) => *f,
-                    Fee::Provider(_) => return Err(Error::FeeProviderNotImplemented),
-                };
-                let timeout = match timeout {
-                    Timeline::Simple(t) => *t,
-                    _ => return Err(Error::TimelineNotImplemented),
-                };
-                (denomination.to_btc(), fee, timeout, *peers)
-            }
-        };
+        let (denomination, fee, timeout, peers) = pool_params(pool)?;
         let address = match output.is_valid_for_network(network) {
             true => output.assume_checked(),
             false => return Err(Error::WrongAddressNetwork),
@@ -238,8 +462,8 @@ impl Joinstr<'_> {
             .simple_timeout(timeout)?
             .min_peers(peers)?;
         let mut inner = peer.inner.lock().expect("poisoned");
-        inner.input = Some(input);
-        inner.output = Some(address);
+        inner.my_inputs.push(input);
+        inner.my_outputs.push(address);
         inner.role = Role::Peer;
         drop(inner);
         Ok(peer)
@@ -249,7 +473,7 @@ impl Joinstr<'_> {
     ///   the pool have already been initited by another peer.
     ///
     /// # Arguments
-    /// * `relays` - A list of relays address to connect to
+    /// * `relay` - The relay url, or a connection shared w/ other instances
     /// * `pool` - The [`Pool`] struct representing the pool we want to join
     /// * `electrum_server` - A tuple (<address>, <port>)
     /// * `input` - The transaction input to include in the coinjoin
@@ -259,7 +483,7 @@ impl Joinstr<'_> {
     ///   be an empty &str.
     #[allow(clippy::too_many_arguments)]
     pub fn new_peer_with_electrum(
-        relay: String,
+        relay: impl Into<Connection>,
         pool: &Pool,
         electrum_server: (&str, u16),
         input: Coin,
@@ -271,18 +495,46 @@ impl Joinstr<'_> {
         let peer = Self::new_peer(relay, pool, input, output, network, name)?;
         let mut inner = peer.inner.lock().expect("poisoned");
         inner.role = Role::Peer;
-        inner.electrum_client = Some(electrum);
+        inner.backend = Some(backend::shared(electrum));
         drop(inner);
         Ok(peer)
     }
 
+    /// Create a new [`Joinstr`] instance that have a `Peer` role and use an
+    ///   Esplora server as bitcoin backend, see [`Joinstr::new_peer_with_electrum()`].
+    ///
+    /// # Arguments
+    /// * `relay` - The relay url, or a connection shared w/ other instances
+    /// * `pool` - The [`Pool`] struct representing the pool we want to join
+    /// * `esplora_url` - The base url of the Esplora API
+    /// * `input` - The transaction input to include in the coinjoin
+    /// * `output` - The address we want to receive the coin to
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_peer_with_esplora(
+        relay: impl Into<Connection>,
+        pool: &Pool,
+        esplora_url: &str,
+        input: Coin,
+        output: Address<NetworkUnchecked>,
+        network: Network,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let peer = Self::new_peer(relay, pool, input, output, network, name)?;
+        peer.inner.lock().expect("poisoned").backend =
+            Some(backend::shared(EsploraClient::new(esplora_url)));
+        Ok(peer)
+    }
+
     /// Create a new [`Joinstr`] instance that have a `Coordinator` role, this role means
     ///   this instance will only initiate & monitor the coinjoin but will not add input
     ///   nor output.
     ///
     /// # Arguments
     /// * `keys` - Nostr keys that will be used for auth to the nostr relay
-    /// * `relays` - A list of relays address to connect to
+    /// * `relay` - The relay url, or a connection shared w/ other instances
     /// * `electrum_server` - A tuple (<address>, <port>)
     /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
     /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
@@ -291,7 +543,7 @@ impl Joinstr<'_> {
     /// Note: the parameters of the pool should be passed with builder pattern
     pub fn new_initiator(
         keys: Keys,
-        relay: String,
+        relay: impl Into<Connection>,
         electrum_server: (&str, u16),
         network: Network,
         name: &str,
@@ -301,6 +553,55 @@ impl Joinstr<'_> {
         Ok(j)
     }
 
+    /// Create a new [`Joinstr`] instance that have a `Coordinator` role and
+    ///   use an Esplora server as bitcoin backend, see [`Joinstr::new_initiator()`].
+    ///
+    /// # Arguments
+    /// * `keys` - Nostr keys that will be used for auth to the nostr relay
+    /// * `relay` - The relay url, or a connection shared w/ other instances
+    /// * `esplora_url` - The base url of the Esplora API
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    pub fn new_initiator_with_esplora(
+        keys: Keys,
+        relay: impl Into<Connection>,
+        esplora_url: &str,
+        network: Network,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let j = Self::new(keys, relay, name)?.network(network);
+        let mut inner = j.inner.lock().expect("poisoned");
+        inner.role = Role::Initiator;
+        inner.backend = Some(backend::shared(EsploraClient::new(esplora_url)));
+        drop(inner);
+        Ok(j)
+    }
+
+    /// Create a new [`Joinstr`] instance that have a `Coordinator` role and
+    ///   use `source` as bitcoin backend, see [`Joinstr::new_initiator()`].
+    ///
+    /// # Arguments
+    /// * `keys` - Nostr keys that will be used for auth to the nostr relay
+    /// * `relay` - The relay url, or a connection shared w/ other instances
+    /// * `source` - The chain source
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    /// * `name` - Name of the [`Joinstr`] instance (use for debug logs), can
+    ///   be an empty &str.
+    pub fn new_initiator_with_chain_source(
+        keys: Keys,
+        relay: impl Into<Connection>,
+        source: SharedChainSource,
+        network: Network,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let j = Self::new(keys, relay, name)?
+            .network(network)
+            .chain_source(source);
+        j.inner.lock().expect("poisoned").role = Role::Initiator;
+        Ok(j)
+    }
+
     /// Set the bitcoin network to mainnet
     pub fn mainnet(self) -> Self {
         self.inner.lock().expect("poisoned").network = Network::Bitcoin;
@@ -331,48 +632,60 @@ impl Joinstr<'_> {
         self
     }
 
+    /// Use a bitcoind node as bitcoin backend, replace the electrum client
+    ///   if any.
+    pub fn bitcoind(self, bitcoind: BitcoindBackend) -> Self {
+        self.chain_source(backend::shared(bitcoind))
+    }
+
+    /// Set the chain source used to broadcast the coinjoin transaction,
+    ///   replace the current one if any.
+    ///
+    /// Note: the chain source can be shared w/ the signer, see
+    ///   [`crate::signer::WpkhHotSigner::shared_client()`].
+    pub fn chain_source(self, source: SharedChainSource) -> Self {
+        self.inner.lock().expect("poisoned").backend = Some(source);
+        self
+    }
+
+    /// Set the [`Connector`] used to open the nostr connections of this
+    ///   instance, default to [`NostrConnector`]. Must be set before the
+    ///   coinjoin starts.
+    pub fn connector(mut self, connector: Arc<dyn Connector>) -> Self {
+        self.connector = connector;
+        self
+    }
+
+    /// Set the clock used for the pool timelines & the random delays,
+    ///   default to [`SystemClock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Set the denomination of the pool in Bitcoin.
     pub fn denomination(self, denomination: f64) -> Result<Self, Error> {
-        let mut inner = self.inner.lock().expect("poisoned");
-        inner.pool_not_exists()?;
-        if inner.denomination.is_none() {
-            inner.denomination =
-                Some(Amount::from_btc(denomination).map_err(|_| Error::WrongDenomination)?);
-            drop(inner);
-            Ok(self)
-        } else {
-            Err(Error::DenominationAlreadySet)
-        }
+        self.inner
+            .lock()
+            .expect("poisoned")
+            .set_denomination(denomination)?;
+        Ok(self)
     }
 
     /// Set the min number of peers of the pool
     pub fn min_peers(self, peers: usize) -> Result<Self, Error> {
-        if peers < 2 {
-            return Err(Error::Min2Peers);
-        }
-        let mut inner = self.inner.lock().expect("poisoned");
-        inner.pool_not_exists()?;
-        if inner.peers_count.is_none() {
-            inner.peers_count = Some(peers);
-            drop(inner);
-            Ok(self)
-        } else {
-            Err(Error::PeersAlreadySet)
-        }
+        self.inner.lock().expect("poisoned").set_min_peers(peers)?;
+        Ok(self)
     }
 
     /// Set the timestamp at which the pool will be considered canceled if
     ///   not enough peer have join.
     pub fn simple_timeout(self, timestamp: u64) -> Result<Self, Error> {
-        let mut inner = self.inner.lock().expect("poisoned");
-        inner.pool_not_exists()?;
-        if inner.timeout.is_none() {
-            inner.timeout = Some(Timeline::Simple(timestamp));
-            drop(inner);
-            Ok(self)
-        } else {
-            Err(Error::TimeoutAlreadySet)
-        }
+        self.inner
+            .lock()
+            .expect("poisoned")
+            .set_simple_timeout(timestamp)?;
+        Ok(self)
     }
 
     /// Add a relay address to [`Joinstr::relays`]
@@ -389,15 +702,63 @@ impl Joinstr<'_> {
     /// Set the minimum fee rate that the final transaction should spend to
     /// be considered valid (sats/vb)
     pub fn fee(self, fee: u32) -> Result<Self, Error> {
+        self.inner.lock().expect("poisoned").set_fee(fee)?;
+        Ok(self)
+    }
+
+    /// Set the minimum fee rate that the final transaction should spend
+    ///   from a [`FeePolicy`], the policy is resolved using the bitcoin
+    ///   backend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool already exists
+    ///   - the fee rate is already set
+    ///   - there is no bitcoin backend
+    ///   - the policy cannot be resolved
+    pub fn fee_policy(self, policy: FeePolicy) -> Result<Self, Error> {
+        let inner = self.inner.lock().expect("poisoned");
+        inner.pool_not_exists()?;
+        let backend = inner.backend.clone();
+        drop(inner);
+        let fee = match (policy, backend) {
+            (FeePolicy::Fixed(fee), _) => fee,
+            (policy, Some(backend)) => policy.resolve(&mut *backend.lock().expect("poisoned"))?,
+            (_, None) => return Err(Error::BackendMissing),
+        };
+        log::debug!("Joinstr::fee_policy() {policy:?} resolved to {fee} sats/vb");
+        self.fee(fee)
+    }
+
+    /// Set the max fee rate (sats/vb) our inputs accept to pay, an input
+    ///   paying more for its share of the transaction will not be signed.
+    pub fn max_fee(self, max_fee: u32) -> Self {
+        self.inner.lock().expect("poisoned").max_fee = Some(max_fee);
+        self
+    }
+
+    /// Set the min NIP-13 proof of work (leading zero bits of the event id)
+    ///   of the join requests of the pool, announced in the pool payload.
+    pub fn pow(self, difficulty: u8) -> Result<Self, Error> {
         let mut inner = self.inner.lock().expect("poisoned");
         inner.pool_not_exists()?;
-        if inner.fee.is_none() {
-            inner.fee = Some(Fee::Fixed(fee));
-            drop(inner);
-            Ok(self)
-        } else {
-            Err(Error::FeeAlreadySet)
-        }
+        inner.pow = Some(difficulty);
+        drop(inner);
+        Ok(self)
+    }
+
+    /// Ignore the join requests of these nostr keys, the list is announced
+    ///   in the pool payload so every peer ignores them too.
+    ///
+    /// Note: a ban is best-effort, a peer can join again from a fresh nostr
+    ///   key, use [`Joinstr::pow()`] to make join requests costly.
+    pub fn ban_peers(self, peers: impl IntoIterator<Item = PublicKey>) -> Result<Self, Error> {
+        let mut inner = self.inner.lock().expect("poisoned");
+        inner.pool_not_exists()?;
+        inner.banned.extend(peers);
+        drop(inner);
+        Ok(self)
     }
 
     /// Set the coin to coinjoin
@@ -419,6 +780,28 @@ impl Joinstr<'_> {
         self.inner.lock().expect("poisoned").set_address(addr)
     }
 
+    /// Add a coin to coinjoin, a peer can register several coins to the
+    ///   same pool as long as it register as many output addresses.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coin is already registered
+    pub fn add_coin(&mut self, coin: Coin) -> Result<(), Error> {
+        self.inner.lock().expect("poisoned").add_coin(coin)
+    }
+
+    /// Add an address the coins must be sent to, a peer can register
+    ///   several addresses to the same pool as long as it register as many
+    ///   coins.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is already registered
+    /// or if address is for wrong network
+    pub fn add_address(&mut self, addr: Address<NetworkUnchecked>) -> Result<(), Error> {
+        self.inner.lock().expect("poisoned").add_address(addr)
+    }
+
     /// Returns the finalized transaction
     pub fn final_tx(&self) -> Option<miniscript::bitcoin::Transaction> {
         self.inner
@@ -429,13 +812,109 @@ impl Joinstr<'_> {
             .cloned()
     }
 
+    /// Run `f` on the main nostr client, connect it first if needed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if connecting the client fails.
+    fn with_client<R>(&self, f: impl FnOnce(&mut dyn NostrTransport) -> R) -> Result<R, Error> {
+        let mut client = self.client.lock().expect("poisoned");
+        if client.is_none() {
+            let inner = self.inner.lock().expect("poisoned");
+            let (keys, name) = (inner.keys.clone(), inner.name.clone());
+            drop(inner);
+            let connection = self.connection.clone();
+            *client = Some(self.connector.connect(connection, keys, &name)?);
+        }
+        let client = client.as_mut().expect("just connected");
+        Ok(f(&mut **client))
+    }
+
+    /// Replace the main nostr client by a new one using `keys`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the relay is missing or
+    ///   connecting the client fails.
+    fn reconnect(&self, keys: Keys, name: &str) -> Result<(), Error> {
+        let relay = self.inner.lock().expect("poisoned").relay.clone();
+        let relay = relay.ok_or(Error::RelaysMissing)?;
+        let client = self.connector.connect(relay.into(), keys, name)?;
+        *self.client.lock().expect("poisoned") = Some(client);
+        Ok(())
+    }
+
+    /// Send a pool message of the state machine, see [`JoinstrInner`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if connecting to the relay or
+    ///   sending the message fails.
+    fn send(&self, out: Outgoing) -> Result<(), Error> {
+        let Outgoing {
+            npub,
+            msg,
+            pow,
+            detached,
+        } = out;
+        match detached {
+            Some((keys, name)) => {
+                let relay = self.inner.lock().expect("poisoned").relay.clone();
+                let relay = relay.ok_or(Error::RelaysMissing)?;
+                let mut client = self.connector.connect(relay.into(), keys, &name)?;
+                client.send_pool_message(&npub, msg, pow)?;
+            }
+            None => self.with_client(|client| client.send_pool_message(&npub, msg, pow))??,
+        }
+        Ok(())
+    }
+
+    /// Wait for a random delay of the clock of the instance.
+    fn rand_delay(&self) {
+        self.clock.sleep(self.clock.rand_delay());
+    }
+
+    /// Wait up to [`WAIT_MSG`] for a pool message, a message that cannot be
+    ///   received (e.g. it cannot be decrypted) is dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the process have been canceled
+    ///   or connecting the main nostr client fails.
+    fn receive(&self) -> Result<Option<(PoolMessage, u8)>, Error> {
+        self.inner.lock().expect("poisoned").check_canceled()?;
+        match self.with_client(|client| client.receive_pool_message(WAIT_MSG))? {
+            Ok(msg) => Ok(msg),
+            Err(e) => {
+                log::debug!("Joinstr::receive(): drop message: {e:?}");
+                thread::sleep(WAIT_MSG);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Initiate a new pool by sending a pool creation event (Kind 2022)
+    ///   to nostr relays.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a pool already exists, if
+    ///   some fields of the pool are missing or if posting the event fail.
+    fn post(&mut self) -> Result<(), Error> {
+        let pool = self.inner.lock().expect("poisoned").pool_to_post()?;
+        let event: EventBuilder = pool.clone().try_into()?;
+        self.with_client(|client| client.post_event(event))??;
+        self.inner.lock().expect("poisoned").posted(pool);
+        Ok(())
+    }
+
     /// Try to join the pool.
     ///
     /// # Errors
     ///
     /// This function will return an error if:
     ///   - the pool does not exists
-    ///   - the nostr client does not have keys
+    ///   - the fee rate is far outside the current estimates
     ///   - the nostr client fail to connect relays
     ///   - sending a message to the pool fails
     ///   - receiving credentials fails
@@ -443,54 +922,44 @@ impl Joinstr<'_> {
     fn join_pool(&mut self) -> Result<(), Error> {
         let mut inner = self.inner.lock().expect("poisoned");
         inner.pool_exists()?;
-        inner.step = Step::Connecting;
-        let pool_npub = inner.pool_as_ref()?.public_key;
-        // TODO: receive the response on a derived npub;
-        let my_npub = inner.client.get_keys()?.public_key();
-
-        inner
-            .client
-            .send_pool_message(&pool_npub, PoolMessage::Join(Some(my_npub)))?;
+        inner.check_pool_fee()?;
+        let join = inner.join_request()?;
         let (timeout, _) = inner.start_timeline()?;
         drop(inner);
+        self.send(join)?;
 
-        let mut backoff = Backoff::new_us(WAIT);
-
-        let mut connected = false;
-        while now() < timeout {
+        while self.clock.now() < timeout {
+            let Some((msg, _)) = self.receive()? else {
+                continue;
+            };
             let mut inner = self.inner.lock().expect("poisoned");
-            if let Some(PoolMessage::Credentials(Credentials { id, key })) =
-                inner.client.try_receive_pool_msg()?
-            {
-                log::debug!(
-                    "Coordinator({}).connect_to_pool(): receive credentials.",
-                    inner.client.name
-                );
-                if id == inner.pool_as_ref()?.id {
-                    // we create a new nostr client using pool keys and replace the actual one
-                    let keys = Keys::new(key);
-                    let fg = &inner.client.name;
-                    let name = format!("prev_{fg}");
-                    let mut new_client = NostrClient::new(&name)
-                        .relay(inner.client.get_relay().unwrap())?
-                        .keys(keys)?;
-                    new_client.connect_nostr()?;
-                    inner.client = new_client;
-                    connected = true;
-                    inner.step = Step::OutputRegistration;
-                    break;
-                } else {
-                    log::error!(
-                        "Coordinator({}).connect_to_pool(): pool id not match!",
-                        inner.client.name
-                    );
-                }
+            if let Some(keys) = inner.receive_credentials(msg)? {
+                // we connect a new nostr client using pool keys and replace the actual one
+                let name = inner.name.clone();
+                drop(inner);
+                self.reconnect(keys, &name)?;
+                return self.join_extra_peers();
             }
-            drop(inner);
-            backoff.snooze();
         }
-        if !connected {
-            return Err(Error::PoolConnectionTimeout);
+        Err(Error::PoolConnectionTimeout)
+    }
+
+    /// Send a join request for every additional input/output pair we want to
+    ///   register, each request is sent by a fresh nostr identity over its own
+    ///   relay connection and after a random delay, in order the pool cannot
+    ///   link them together.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool does not exists
+    ///   - connecting to the relay fails
+    ///   - sending a join request fails
+    fn join_extra_peers(&mut self) -> Result<(), Error> {
+        let joins = self.inner.lock().expect("poisoned").extra_joins()?;
+        for join in joins {
+            self.rand_delay();
+            self.send(join)?;
         }
         Ok(())
     }
@@ -507,7 +976,7 @@ impl Joinstr<'_> {
     ///   - the inner pool not exists
     ///   - the payload of the pool is missing
     ///   - the fee are not of type [`Fee::Fixed`]
-    ///   - the nostr client do not have private keys
+    ///   - sending a message to the pool fails
     ///   - timeout elapsed
     ///   - peer count do not match
     fn register_outputs<N>(&mut self, notif: N) -> Result<(), Error>
@@ -515,161 +984,61 @@ impl Joinstr<'_> {
         N: Fn(),
     {
         let inner = self.inner.lock().expect("poisoned");
-        inner.pool_exists()?;
-        let (expired, start_early) = inner.start_timeline()?;
-        let payload = inner.payload_as_ref()?.clone();
-        let fee = if let Fee::Fixed(fee) = payload.fee {
-            fee
-        } else {
-            return Err(Error::NotYetImplemented);
-        };
-        let pool_pubkey = inner.pool_as_ref()?.public_key;
-        let role = inner.role;
-        let relay = inner.client.get_relay().ok_or(Error::RelaysMissing)?;
+        let mut round = inner.output_round()?;
+        let dummy_joins = inner.dummy_joins()?;
         drop(inner);
-
-        let mut peers = HashSet::<PublicKey>::new();
-        let mut coinjoin = CoinJoin::<crate::electrum::Client>::new(payload.denomination, None)
-            .min_peer(payload.peers)
-            .fee(fee as usize);
-
-        if role == Role::Initiator {
-            // send a dummy join request
-            let mut dummy_client = NostrClient::new("dummy")
-                .keys(Keys::generate())?
-                .relay(relay)?;
-            dummy_client.connect_nostr()?;
-
-            let dummy_response_key = Keys::generate().public_key();
-            dummy_client
-                .send_pool_message(&pool_pubkey, PoolMessage::Join(Some(dummy_response_key)))?;
+        for join in dummy_joins {
+            self.send(join)?;
         }
 
-        let mut backoff = Backoff::new_us(WAIT);
         // register peers
-        while (now() < expired) && !(start_early && peers.len() >= payload.peers) {
+        while self.clock.now() < round.expired && !round.peers_joined() {
+            let Some((msg, pow)) = self.receive()? else {
+                continue;
+            };
             let mut inner = self.inner.lock().expect("poisoned");
-            if let Ok(Some(msg)) = inner.client.try_receive_pool_msg() {
-                match (msg, matches!(inner.role, Role::Initiator)) {
-                    (PoolMessage::Join(Some(npub)), send_response) => {
-                        if !peers.contains(&npub) {
-                            if send_response {
-                                let response = PoolMessage::Credentials(Credentials {
-                                    id: inner.pool_as_ref()?.id.clone(),
-                                    key: inner.client.get_keys()?.secret_key().clone(),
-                                });
-                                inner.client.send_pool_message(&npub, response)?;
-                            }
-                            peers.insert(npub);
-                            inner.peers.push(npub);
-                            notif();
-                            log::debug!(
-                                "Coordinator({}).register_outputs(): receive Join({}) request. \n      peers: {}",
-                                inner.client.name,
-                                npub,
-                                peers.len()
-                            );
-                        }
-                    }
-                    // TODO: do not panic here
-                    (PoolMessage::Join(None), _) => panic!("cannot answer if npub is None!"),
-                    (PoolMessage::Output(o), _) => {
-                        log::error!(
-                            "Coordinator({}).register_outputs(): receive Output({:?}) request before output registartion step!",
-                            inner.client.name,
-                            o
-                        );
-                        // NOTE: should we accept output registration at this step?
-                        // Should we store the output and reuse at next step?
-                    }
-                    r => {
-                        // NOTE: simply drop other kind of messages
-                        log::debug!(
-                            "Coordinator({}).register_outputs(): request not handled at peer registration step: {:?}!",
-                            inner.client.name,
-                            r
-                        );
-                    }
-                }
-            } else {
-                drop(inner);
-                backoff.snooze();
+            let response = inner.receive_join(&mut round, msg, pow, &notif)?;
+            drop(inner);
+            if let Some(response) = response {
+                self.send(response)?;
             }
         }
 
         // NOTE: at this point should we wait for every peer ACK the output template prior to
         // signing inputs?
 
-        rand_delay();
+        self.rand_delay();
 
-        let mut inner = self.inner.lock().expect("poisoned");
-        if let Some(output) = inner.output.as_ref() {
-            coinjoin.add_output(output.clone());
-            inner.register_output(&notif)?;
+        let count = self.inner.lock().expect("poisoned").my_outputs.len();
+        for i in 0..count {
+            if i > 0 {
+                self.rand_delay();
+            }
+            let msg = self
+                .inner
+                .lock()
+                .expect("poisoned")
+                .register_output(&mut round, i)?;
+            self.send(msg)?;
+            notif();
         }
-        drop(inner);
-
-        let mut backoff = Backoff::new_us(WAIT);
 
         // register ouputs
         let expired = self.inner.lock().expect("poisoned").end_timeline()?;
-        while (now() < expired) && (coinjoin.outputs_len() < peers.len()) {
-            let mut inner = self.inner.lock().expect("poisoned");
-            if let Ok(Some(msg)) = inner.client.try_receive_pool_msg() {
-                match msg {
-                    PoolMessage::Join(_) => {
-                        // FIXME: we should not log an error here
-                        log::error!(
-                            "Coordinator({}).register_outputs(): receive Join request at output registration step!",
-                            inner.client.name,
-                        );
-                    }
-                    PoolMessage::Output(o) => {
-                        log::debug!(
-                            "Coordinator({}).register_outputs(): receive Output({:?}) request.",
-                            inner.client.name,
-                            o
-                        );
-                        let outputs = vec![o.clone()];
-                        inner.receive_outputs(outputs, &mut coinjoin)?;
-                        // TODO: we must error if outputs > peers
-                        // TODO: check address network
-                        inner.outputs.push(o.assume_checked());
-                        notif();
-                    }
-                    // FIXME: here it can be some cases where, because network timing, we can
-                    // receive a signed input before the output registration round ended, we should
-                    // store those inputs in order to use them later.
-                    PoolMessage::Input(_) => todo!("store input"),
-                    r => {
-                        // NOTE: simply drop other kind of messages
-                        log::debug!(
-                            "Coordinator({}).register_outputs(): request not handled at output registration step: {:?}!",
-                            inner.client.name,
-                            r
-                        );
-                    }
-                }
-            } else {
-                drop(inner);
-                backoff.snooze();
-            }
+        while self.clock.now() < expired && !round.outputs_registered() {
+            let Some((msg, _)) = self.receive()? else {
+                continue;
+            };
+            self.inner
+                .lock()
+                .expect("poisoned")
+                .receive_output(&mut round, msg, &notif)?;
         }
 
-        if now() > expired {
-            return Err(Error::Timeout);
-        } else if peers.len() < payload.peers {
-            return Err(Error::NotEnoughPeers(peers.len(), payload.peers));
-        } else if coinjoin.outputs_len() != peers.len() {
-            // NOTE: do not allow registered peer that not commit an output as it can be some
-            // lurkers trying deanonimyze peers
-
-            return Err(Error::PeerCountNotMatch(
-                coinjoin.outputs_len(),
-                peers.len(),
-            ));
-        }
-        self.inner.lock().expect("poisoined").coinjoin = Some(coinjoin);
+        self.inner
+            .lock()
+            .expect("poisoned")
+            .end_output_round(round, self.clock.now())?;
         notif();
         Ok(())
     }
@@ -693,73 +1062,65 @@ impl Joinstr<'_> {
     where
         N: Fn(),
     {
-        let inner = self.inner.lock().expect("poisoned");
-        inner.pool_exists()?;
-        inner.coinjoin_exists()?;
-        let payload = inner.payload_as_ref()?;
-        let expired = match payload.timeout {
-            Timeline::Simple(timestamp) => timestamp,
-            Timeline::Fixed {
-                start,
-                max_duration,
-            } => start + max_duration,
-            Timeline::Timeout { max_duration, .. } => now() + max_duration,
-        };
-        drop(inner);
-        if now() > expired {
-            return Err(Error::Timeout);
-        }
-
-        let mut backoff = Backoff::new_us(WAIT);
+        let expired = self
+            .inner
+            .lock()
+            .expect("poisoned")
+            .input_deadline(self.clock.now())?;
 
-        while now() < expired
-            && self
-                .inner
-                .lock()
-                .expect("poisoned")
-                .coinjoin_as_ref()?
-                .tx
-                .is_none()
+        while self.clock.now() < expired
+            && !self.inner.lock().expect("poisoned").inputs_registered()?
         {
+            let Some((msg, _)) = self.receive()? else {
+                continue;
+            };
             let mut inner = self.inner.lock().expect("poisoned");
-            let msg = inner.client.try_receive_pool_msg();
-            if let Ok(Some(msg)) = msg {
-                match msg {
-                    PoolMessage::Psbt(psbt) => {
-                        let input: InputDataSigned =
-                            psbt.try_into().map_err(|_| Error::PsbtToInput)?;
-                        inner.try_register_input(input, &notif)?;
-                        if inner.try_finalize_coinjoin()? {
-                            break;
-                        }
-                    }
-                    PoolMessage::Input(input) => {
-                        inner.try_register_input(input, &notif)?;
-                        if inner.try_finalize_coinjoin()? {
-                            break;
-                        }
-                    }
-                    m => {
-                        // NOTE: simply drop other kind of messages
-                        log::error!(
-                            "Coordinator({}).register_input(): drop message {:?}",
-                            inner.client.name,
-                            m
-                        );
-                    }
-                }
-            } else {
-                drop(inner);
-                backoff.snooze();
+            let Some(input) = inner.input_of(msg)? else {
+                continue;
+            };
+            let checked = inner.verify_input(&input);
+            if inner.receive_input(input, checked, &notif)? {
+                break;
             }
         }
-        if now() > expired {
+        if self.clock.now() > expired {
             Err(Error::Timeout)
         } else {
             Ok(())
         }
     }
 
+    /// Sign & send all the inputs we own, a random delay is awaited between
+    ///   each input registration.
+    ///
+    /// # Arguments
+    /// * `signer` - The signer to sign our inputs with
+    /// * `notif` - A callback function called every time the pool state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if registering one of the inputs fails.
+    fn register_my_inputs<S, N>(&mut self, signer: &S, notif: N) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+        N: Fn(),
+    {
+        let inputs = std::mem::take(&mut self.inner.lock().expect("poisoned").my_inputs);
+        for (i, input) in inputs.into_iter().enumerate() {
+            if i > 0 {
+                self.rand_delay();
+            }
+            let msg = self
+                .inner
+                .lock()
+                .expect("poisoned")
+                .register_input(signer, input, i)?;
+            self.send(msg)?;
+            notif();
+        }
+        Ok(())
+    }
+
     /// Returns the current status of the [`Joinstr`] instance.
     ///
     /// # Returns
@@ -776,9 +1137,27 @@ impl Joinstr<'_> {
         self.inner.lock().expect("poisoned").state()
     }
 
+    /// Cancel the coinjoin process: a coinjoin running on a clone of this
+    ///   instance will fail with [`Error::Canceled`] at its next round, the
+    ///   transaction is never broadcasted once canceled.
+    pub fn cancel(&self) {
+        self.inner.lock().expect("poisoned").canceled = true;
+    }
+
+    /// Mark the coinjoin as failed w/ `error`, as done when a coinjoin run
+    ///   in the background fails, e.g. after
+    ///   [`Joinstr::start_coinjoin_blocking()`] returned an error.
+    pub fn fail(&self, error: &Error) {
+        let mut inner = self.inner.lock().expect("poisoned");
+        inner.error = Some(format!("{:?}", error));
+        inner.step = Step::Failed;
+    }
+
     /// Start a coinjoin process, followings steps will be processed:
     ///   - if no `pool` arg is passed, a new pool will be initiated.
     ///   - if a `pool` arg is passed, it will join the pool
+    ///   - if a `signer` arg is passed and there is less outputs than inputs, the
+    ///     missing outputs are drawn from the signer postmix account.
     ///   - run the outputs registration round
     ///   - if a `signer` arg is passed, it will signed the input it owns.
     ///   - run the inputs registration round
@@ -812,6 +1191,8 @@ impl Joinstr<'_> {
     /// Start a coinjoin process, followings steps will be processed:
     ///   - if no `pool` arg is passed, a new pool will be initiated.
     ///   - if a `pool` arg is passed, it will join the pool
+    ///   - if a `signer` arg is passed and there is less outputs than inputs, the
+    ///     missing outputs are drawn from the signer postmix account.
     ///   - run the outputs registration round
     ///   - if a `signer` arg is passed, it will signed the input it owns.
     ///   - run the inputs registration round
@@ -839,10 +1220,9 @@ impl Joinstr<'_> {
         let mut cloned = self.clone();
         let signer = signer.clone();
         thread::spawn(move || {
-            if let Err(e) = cloned.start_coinjoin_blocking(pool, signer, notif) {
-                let mut inner = cloned.inner.lock().expect("poisoned");
-                inner.error = Some(format!("{:?}", e));
-                inner.step = Step::Failed;
+            if let Err(e) = cloned.start_coinjoin_blocking(pool, signer, &notif) {
+                cloned.fail(&e);
+                notif();
             }
         });
     }
@@ -850,36 +1230,31 @@ impl Joinstr<'_> {
     pub fn start_coinjoin_blocking<S, N>(
         &mut self,
         pool: Option<Pool>,
-        signer: Option<S>,
+        mut signer: Option<S>,
         notif: N,
     ) -> Result<(), Error>
     where
         S: JoinstrSigner + Sync + Clone + Send + 'static,
         N: Fn(),
     {
-        let name = self.inner.lock().expect("poisoned").client.name.clone();
+        let name = self.inner.lock().expect("poisoned").name.clone();
 
         log::debug!("Joinstr::Start_coinjoin_blocking({name})");
-        let mut inner = self.inner.lock().expect("poisoned");
-
-        if matches!(inner.role, Role::Unknown) {
-            log::error!("Joinstr::start_coinjoin_blocking({name}): wrong role!");
-            return Err(Error::WrongRole);
-        }
+        let join = pool.is_some();
+        self.inner
+            .lock()
+            .expect("poisoned")
+            .prepare(pool, signer.as_mut())?;
 
-        if let Some(pool) = pool {
+        if join {
             log::debug!("Joinstr::start_coinjoin_blocking({name}) try to join pool...");
-            inner.pool_not_exists()?;
-            inner.pool = Some(pool);
-            drop(inner);
             self.join_pool()?;
             log::debug!("Joinstr::start_coinjoin_blocking({name}) pool joined");
         } else {
             // broadcast the pool event
             log::debug!("Joinstr::start_coinjoin_blocking({name}) try to broadcast pool...");
-            inner.post()?;
+            self.post()?;
             log::debug!("Joinstr::start_coinjoin_blocking({name}) pool broadcast!");
-            drop(inner);
         }
         notif();
 
@@ -896,20 +1271,19 @@ impl Joinstr<'_> {
         log::debug!("Joinstr::start_coinjoin_blocking({name}) unsigned tx generated!");
         notif();
 
-        rand_delay();
+        self.rand_delay();
 
-        let mut inner = self.inner.lock().expect("poisoned");
-        if inner.input.is_some() {
+        let have_input = !self.inner.lock().expect("poisoned").my_inputs.is_empty();
+        if have_input {
             if let Some(s) = signer {
                 log::debug!("Joinstr::start_coinjoin_blocking({name}) try register input....");
-                inner.register_input(&s, &notif)?;
+                self.register_my_inputs(&s, &notif)?;
                 log::debug!("Joinstr::start_coinjoin_blocking({name}) input registered!");
             } else {
                 log::debug!("Joinstr::start_coinjoin_blocking({name}) no input to register!");
                 return Err(Error::SignerMissing);
             }
         }
-        drop(inner);
 
         log::debug!(
             "Joinstr::start_coinjoin_blocking({name}) start registering external inputs..."
@@ -932,15 +1306,128 @@ impl Joinstr<'_> {
         N: Fn() + Send + 'static,
         Self: Sized + Send + 'static,
     {
+        Self::restart_on(state, None, name, signer, notif)
+    }
+
+    /// Same as [`Joinstr::restart()`] but the instance use a relay connection
+    ///   shared w/ other instances, the connection must be opened to the relay
+    ///   of the state.
+    pub fn restart_shared<S, N>(
+        state: State,
+        connection: &SharedConnection,
+        name: &str,
+        signer: S,
+        notif: N,
+    ) -> Result<Self, Error>
+    where
+        S: JoinstrSigner + Sized + Sync + Clone + Send + 'static,
+        N: Fn() + Send + 'static,
+        Self: Sized + Send + 'static,
+    {
+        if connection.get_relay() != state.relay {
+            return Err(Error::RelayNotMatch);
+        }
+        Self::restart_on(state, Some(connection.clone()), name, signer, notif)
+    }
+
+    fn restart_on<S, N>(
+        state: State,
+        connection: Option<SharedConnection>,
+        name: &str,
+        signer: S,
+        notif: N,
+    ) -> Result<Self, Error>
+    where
+        S: JoinstrSigner + Sized + Sync + Clone + Send + 'static,
+        N: Fn() + Send + 'static,
+        Self: Sized + Send + 'static,
+    {
+        let (j, expected_peers) = Self::restore(state, connection, name)?;
+        // pool state is already finalized
+        let Some(expected_peers) = expected_peers else {
+            return Ok(j);
+        };
+
+        fn restart_blocking<S, N>(
+            mut j: Joinstr,
+            expected_peers: usize,
+            signer: S,
+            notif: N,
+        ) -> Result<(), Error>
+        where
+            S: JoinstrSigner + Sync + Clone + Send + 'static,
+            N: Fn(),
+        {
+            let inner = j.inner.lock().expect("poisoned");
+            let joined = inner.peers.len() >= expected_peers;
+            let output_registered = inner.outputs.len() >= expected_peers;
+            let inputs_registered = inner.inputs.len() >= expected_peers;
+
+            drop(inner);
+
+            if !joined || !output_registered {
+                j.register_outputs(&notif)?;
+            }
+
+            if !inputs_registered {
+                j.inner.lock().expect("poisoned").generate_unsigned_tx()?;
+                notif();
+
+                j.rand_delay();
+
+                let have_input = !j.inner.lock().expect("poisoned").my_inputs.is_empty();
+                if have_input {
+                    j.register_my_inputs(&signer, &notif)?;
+                }
+
+                j.register_inputs(&notif)?;
+
+                j.inner.lock().expect("poisoned").broadcast_tx()?;
+            }
+            Ok(())
+        }
+
+        let j2 = j.clone();
+        let j3 = j.clone();
+
+        std::thread::spawn(move || {
+            if let Err(e) = restart_blocking(j2, expected_peers, signer, &notif) {
+                let mut inner = j3.inner.lock().expect("poisoned");
+                inner.error = Some(format!("{:?}", e));
+                inner.step = Step::Failed;
+            }
+        });
+
+        Ok(j)
+    }
+
+    /// Restore an instance from its state, the pool messages received while
+    ///   we were away are applied. Returns the number of peers the pool
+    ///   expects, None if the transaction is already finalized.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the state cannot be restored
+    ///   - the received messages do not match the pool
+    fn restore(
+        state: State,
+        connection: Option<SharedConnection>,
+        name: &str,
+    ) -> Result<(Self, Option<usize>), Error> {
         let State {
             role,
             step: _,
             pool_secret_key,
             relay,
             electrum,
+            bitcoind,
+            esplora,
+            electrum_pool,
+            max_fee,
             pool,
-            input,
-            output,
+            my_inputs,
+            my_outputs,
             network,
             peers,
             outputs,
@@ -949,17 +1436,29 @@ impl Joinstr<'_> {
         } = state;
         let secret_key = nostr::SecretKey::from_hex(pool_secret_key).map_err(|_| Error::PoolKey)?;
         let keys = Keys::new(secret_key);
-        let j = Joinstr::new(keys, relay, name)?.network(network);
+        let connection = match connection {
+            Some(shared) => Connection::Shared(shared),
+            None => Connection::Dedicated(relay),
+        };
+        let j = Joinstr::new(keys, connection, name)?.network(network);
         let mut inner = j.inner.lock().expect("poisoned");
         inner.role = role;
         inner.pool = Some(pool);
-        if let Some((url, port)) = electrum {
-            inner.electrum_client = Some(crate::electrum::Client::new(&url, port)?)
+        inner.max_fee = max_fee;
+        let config = match (electrum, bitcoind, esplora, electrum_pool) {
+            (Some((url, port)), _, _, _) => Some(ChainConfig::Electrum(url, port)),
+            (_, Some(config), _, _) => Some(ChainConfig::Bitcoind(config)),
+            (_, _, Some(url), _) => Some(ChainConfig::Esplora(url)),
+            (_, _, _, Some((servers, quorum))) => Some(ChainConfig::ElectrumPool(servers, quorum)),
+            _ => None,
+        };
+        if let Some(config) = config {
+            inner.backend = Some(backend::shared(config.connect()?));
         }
-        inner.input = input;
-        if let Some(addr) = output {
+        inner.my_inputs = my_inputs;
+        for addr in my_outputs {
             if addr.is_valid_for_network(network) {
-                inner.output = Some(addr.assume_checked().clone());
+                inner.my_outputs.push(addr.assume_checked().clone());
             } else {
                 return Err(Error::WrongAddressNetwork);
             }
@@ -984,7 +1483,7 @@ impl Joinstr<'_> {
         if let Some(tx) = final_tx {
             inner.final_tx = Some(tx);
             drop(inner);
-            return Ok(j);
+            return Ok((j, None));
         }
 
         let expected_peers = inner
@@ -1011,7 +1510,11 @@ impl Joinstr<'_> {
         // with the pool key & post a backup state
 
         // get all already received pool messages
-        while let Ok(Some(msg)) = inner.client.try_receive_pool_msg() {
+        let payload = inner.payload_as_ref()?.clone();
+        drop(inner);
+        while let Ok(Some((msg, pow))) =
+            j.with_client(|client| client.receive_pool_message(Duration::ZERO))?
+        {
             match msg {
                 PoolMessage::Input(input) => {
                     recv_inputs.push(input);
@@ -1026,13 +1529,14 @@ impl Joinstr<'_> {
                         recv_inputs.push(input);
                     }
                 }
-                PoolMessage::Join(Some(public_key)) => {
+                PoolMessage::Join(Some(public_key)) if accept_join(&payload, &public_key, pow) => {
                     recv_peers.push(public_key);
                 }
                 _ => {}
             }
         }
 
+        let mut inner = j.inner.lock().expect("poisoned");
         let total_peers = inner.peers.len() + recv_peers.len();
         let total_outputs = inner.outputs.len() + recv_outputs.len();
         let total_inputs = inner.inputs.len() + recv_inputs.len();
@@ -1056,68 +1560,324 @@ impl Joinstr<'_> {
 
         drop(inner);
 
-        fn restart_blocking<S, N>(
-            mut j: Joinstr,
-            expected_peers: usize,
-            signer: S,
-            notif: N,
-        ) -> Result<(), Error>
-        where
-            S: JoinstrSigner + Sync + Clone + Send + 'static,
-            N: Fn(),
-        {
-            let inner = j.inner.lock().expect("poisoned");
-            let joined = inner.peers.len() >= expected_peers;
-            let output_registered = inner.outputs.len() >= expected_peers;
-            let inputs_registered = inner.inputs.len() >= expected_peers;
-
-            drop(inner);
-
-            if !joined || !output_registered {
-                j.register_outputs(&notif)?;
-            }
-
-            if !inputs_registered {
-                j.inner.lock().expect("poisoned").generate_unsigned_tx()?;
-                notif();
-
-                rand_delay();
-
-                let mut inner = j.inner.lock().expect("poisoned");
-                if inner.input.is_some() {
-                    inner.register_input(&signer, &notif)?;
-                }
-                drop(inner);
-
-                j.register_inputs(&notif)?;
-
-                j.inner.lock().expect("poisoned").broadcast_tx()?;
-            }
-            Ok(())
-        }
-
-        let j2 = j.clone();
-        let j3 = j.clone();
-
-        std::thread::spawn(move || {
-            if let Err(e) = restart_blocking(j2, expected_peers, signer, &notif) {
-                let mut inner = j3.inner.lock().expect("poisoned");
-                inner.error = Some(format!("{:?}", e));
-                inner.step = Step::Failed;
-            }
-        });
-
-        Ok(j)
+        Ok((j, Some(expected_peers)))
     }
 }
 
-impl<'a> JoinstrInner<'a> {
-    /// Utility function that will error if [`Joinstr::pool`] is Some()
-    fn pool_not_exists(&self) -> Result<(), Error> {
-        if self.pool.is_some() {
-            Err(Error::PoolAlreadyExists)
-        } else {
-            Ok(())
+impl<'a> Joinstr<'a> {
+    /// Start a coinjoin as the initiator of a pool we do not take part in
+    ///   (e.g. as a coordinator): the pool is posted but the coinjoin is not
+    ///   run on a dedicated thread, the caller drives it w/
+    ///   [`Joinstr::poll()`]. A single thread can then drive many rounds.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - this instance is not the initiator of the pool
+    ///   - we own inputs or outputs
+    ///   - posting the pool fails
+    pub fn drive(&mut self) -> Result<Driver<'a>, Error> {
+        let mut inner = self.inner.lock().expect("poisoned");
+        if inner.role != Role::Initiator {
+            return Err(Error::WrongRole);
+        }
+        inner.not_own_coins()?;
+        inner.prepare::<WpkhHotSigner>(None, None)?;
+        drop(inner);
+        self.post()?;
+        Ok(Driver {
+            phase: self.output_phase()?,
+        })
+    }
+
+    /// Same as [`Joinstr::restart_shared()`] but the coinjoin is not run on a
+    ///   dedicated thread, the caller drives it w/ [`Joinstr::poll()`], see
+    ///   [`Joinstr::drive()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the connection is not opened to the relay of the state
+    ///   - the state cannot be restored
+    ///   - we own inputs or outputs
+    pub fn restart_driven(
+        state: State,
+        connection: &SharedConnection,
+        name: &str,
+    ) -> Result<(Self, Driver<'a>), Error> {
+        if connection.get_relay() != state.relay {
+            return Err(Error::RelayNotMatch);
+        }
+        let (j, expected_peers) = Self::restore(state, Some(connection.clone()), name)?;
+        let inner = j.inner.lock().expect("poisoned");
+        inner.not_own_coins()?;
+        let (peers, outputs, inputs) = (inner.peers.len(), inner.outputs.len(), inner.inputs.len());
+        drop(inner);
+        let phase = match expected_peers {
+            // pool state is already finalized
+            None => Phase::Ended,
+            Some(expected) if peers < expected || outputs < expected => j.output_phase()?,
+            Some(expected) if inputs < expected => {
+                let mut inner = j.inner.lock().expect("poisoned");
+                inner.generate_unsigned_tx()?;
+                Phase::Inputs(inner.input_deadline(j.clock.now())?)
+            }
+            Some(_) => Phase::Ended,
+        };
+        Ok((j, Driver { phase }))
+    }
+
+    /// Handle the pool messages received since the last call & move the
+    ///   coinjoin to its next step if the current one is over, never blocks.
+    ///   It should be called each time the relay connection receives a
+    ///   message (see [`SharedConnection::waiter()`]) & periodically, for
+    ///   the timeouts to be handled.
+    ///
+    /// Returns true once the coinjoin ended, the round is marked failed if
+    ///   an error is returned.
+    ///
+    /// # Arguments
+    /// * `driver` - The driver returned when the coinjoin started
+    /// * `notif` - A callback function called every time the pool state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any step return an error.
+    pub fn poll<N>(&mut self, driver: &mut Driver<'a>, notif: N) -> Result<bool, Error>
+    where
+        N: Fn(),
+    {
+        let phase = std::mem::replace(&mut driver.phase, Phase::Ended);
+        match self.advance(phase, &notif) {
+            Ok(phase) => {
+                driver.phase = phase;
+                Ok(matches!(driver.phase, Phase::Ended))
+            }
+            Err(e) => {
+                let mut inner = self.inner.lock().expect("poisoned");
+                inner.error = Some(format!("{:?}", e));
+                inner.step = Step::Failed;
+                drop(inner);
+                notif();
+                Err(e)
+            }
+        }
+    }
+
+    /// Start the output registration round of a driven coinjoin.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists or
+    ///   sending a dummy join request fails.
+    fn output_phase(&self) -> Result<Phase<'a>, Error> {
+        let inner = self.inner.lock().expect("poisoned");
+        let round = inner.output_round()?;
+        let dummy_joins = inner.dummy_joins()?;
+        drop(inner);
+        for join in dummy_joins {
+            self.send(join)?;
+        }
+        Ok(Phase::Peers(round))
+    }
+
+    /// Move a driven coinjoin forward until it waits for a pool message, see
+    ///   [`Joinstr::poll()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the process have been canceled
+    ///   or any step return an error.
+    fn advance<N>(&self, mut phase: Phase<'a>, notif: &N) -> Result<Phase<'a>, Error>
+    where
+        N: Fn(),
+    {
+        self.inner.lock().expect("poisoned").check_canceled()?;
+        let mut received = 0;
+        loop {
+            let now = self.clock.now();
+            phase = match phase {
+                Phase::Peers(round) if now >= round.expired || round.peers_joined() => {
+                    let expired = self.inner.lock().expect("poisoned").end_timeline()?;
+                    Phase::Outputs(round, expired)
+                }
+                Phase::Outputs(round, expired) if now >= expired || round.outputs_registered() => {
+                    let mut inner = self.inner.lock().expect("poisoned");
+                    inner.end_output_round(round, now)?;
+                    inner.generate_unsigned_tx()?;
+                    let expired = inner.input_deadline(now)?;
+                    drop(inner);
+                    notif();
+                    Phase::Inputs(expired)
+                }
+                Phase::Inputs(_) if self.inner.lock().expect("poisoned").inputs_registered()? => {
+                    self.inner.lock().expect("poisoned").broadcast_tx()?;
+                    notif();
+                    return Ok(Phase::Ended);
+                }
+                Phase::Inputs(expired) if now > expired => return Err(Error::Timeout),
+                Phase::Ended => return Ok(Phase::Ended),
+                mut phase => {
+                    if received == MAX_POLL_MSG {
+                        return Ok(phase);
+                    }
+                    received += 1;
+                    match self.with_client(|client| client.receive_pool_message(Duration::ZERO))? {
+                        Ok(Some((msg, pow))) => self.handle(&mut phase, msg, pow, notif)?,
+                        Ok(None) => return Ok(phase),
+                        Err(e) => log::debug!("Joinstr::poll(): drop message: {e:?}"),
+                    }
+                    phase
+                }
+            };
+        }
+    }
+
+    /// Process a pool message received by a driven coinjoin.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if processing the message or
+    ///   sending the response fails.
+    fn handle<N>(
+        &self,
+        phase: &mut Phase<'a>,
+        msg: PoolMessage,
+        pow: u8,
+        notif: &N,
+    ) -> Result<(), Error>
+    where
+        N: Fn(),
+    {
+        let mut inner = self.inner.lock().expect("poisoned");
+        match phase {
+            Phase::Peers(round) => {
+                let response = inner.receive_join(round, msg, pow, notif)?;
+                drop(inner);
+                if let Some(response) = response {
+                    self.send(response)?;
+                }
+            }
+            Phase::Outputs(round, _) => inner.receive_output(round, msg, notif)?,
+            Phase::Inputs(_) => {
+                if let Some(input) = inner.input_of(msg)? {
+                    let checked = inner.verify_input(&input);
+                    inner.receive_input(input, checked, notif)?;
+                }
+            }
+            Phase::Ended => {}
+        }
+        Ok(())
+    }
+}
+
+// Returns the (denomination, fee, timeout, peers) a peer joining the pool
+// should use.
+fn pool_params(pool: &Pool) -> Result<(f64, u32, u64, usize), Error> {
+    match &pool.payload {
+        None => Err(Error::PoolPayloadMissing),
+        Some(PoolPayload {
+            denomination,
+            peers,
+            timeout,
+            fee,
+            ..
+        }) => {
+            let fee = match &fee {
+                Fee::Fixed(f) => *f,
+                Fee::Provider(_) => return Err(Error::FeeProviderNotImplemented),
+            };
+            let timeout = match timeout {
+                Timeline::Simple(t) => *t,
+                _ => return Err(Error::TimelineNotImplemented),
+            };
+            Ok((denomination.to_btc(), fee, timeout, *peers))
+        }
+    }
+}
+
+// Whether a join request is counted, every peer applies the rules announced in
+// the pool payload so they agree on the peers count.
+// NOTE: the ban list is best-effort: it matches the npub the join request asks
+// to be answered on, that a peer can rotate freely (the protocol has no stable
+// peer identity), the proof of work is what makes flooding a pool w/ join
+// requests costly.
+fn accept_join(payload: &PoolPayload, npub: &PublicKey, pow: u8) -> bool {
+    payload.pow.is_none_or(|difficulty| pow >= difficulty) && !payload.banned.contains(npub)
+}
+
+impl<'a> JoinstrInner<'a> {
+    /// Set the denomination of the pool in Bitcoin.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool already exists, the
+    ///   denomination is invalid or already set.
+    fn set_denomination(&mut self, denomination: f64) -> Result<(), Error> {
+        self.pool_not_exists()?;
+        if self.denomination.is_some() {
+            return Err(Error::DenominationAlreadySet);
+        }
+        self.denomination =
+            Some(Amount::from_btc(denomination).map_err(|_| Error::WrongDenomination)?);
+        Ok(())
+    }
+
+    /// Set the min number of peers of the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool already exists, there
+    ///   is less than 2 peers or the number of peers is already set.
+    fn set_min_peers(&mut self, peers: usize) -> Result<(), Error> {
+        if peers < 2 {
+            return Err(Error::Min2Peers);
+        }
+        self.pool_not_exists()?;
+        if self.peers_count.is_some() {
+            return Err(Error::PeersAlreadySet);
+        }
+        self.peers_count = Some(peers);
+        Ok(())
+    }
+
+    /// Set the timestamp at which the pool will be considered canceled if
+    ///   not enough peer have join.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool already exists or the
+    ///   timeout is already set.
+    fn set_simple_timeout(&mut self, timestamp: u64) -> Result<(), Error> {
+        self.pool_not_exists()?;
+        if self.timeout.is_some() {
+            return Err(Error::TimeoutAlreadySet);
+        }
+        self.timeout = Some(Timeline::Simple(timestamp));
+        Ok(())
+    }
+
+    /// Set the fee rate (sats/vb) of the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool already exists or the
+    ///   fee rate is already set.
+    fn set_fee(&mut self, fee: u32) -> Result<(), Error> {
+        self.pool_not_exists()?;
+        if self.fee.is_some() {
+            return Err(Error::FeeAlreadySet);
+        }
+        self.fee = Some(Fee::Fixed(fee));
+        Ok(())
+    }
+
+    /// Utility function that will error if [`Joinstr::pool`] is Some()
+    fn pool_not_exists(&self) -> Result<(), Error> {
+        if self.pool.is_some() {
+            Err(Error::PoolAlreadyExists)
+        } else {
+            Ok(())
         }
     }
 
@@ -1155,6 +1915,39 @@ impl<'a> JoinstrInner<'a> {
             .and_then(|p| p.payload.as_ref().ok_or(Error::PoolPayloadMissing))
     }
 
+    /// Returns the fee rate (sats/vb) of the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool payload is missing
+    ///   or the fee is not of type [`Fee::Fixed`].
+    fn pool_fee(&self) -> Result<u32, Error> {
+        match self.payload_as_ref()?.fee {
+            Fee::Fixed(fee) => Ok(fee),
+            Fee::Provider(_) => Err(Error::FeeProviderNotImplemented),
+        }
+    }
+
+    /// Check the fee rate of the pool against the current estimates of the
+    ///   bitcoin backend, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool payload is missing
+    ///   - a bitcoin backend request fails
+    ///   - the fee rate is far outside the current estimates
+    fn check_pool_fee(&self) -> Result<(), Error> {
+        let fee = self.pool_fee()?;
+        if let Some(backend) = self.backend.as_ref() {
+            let mut backend = backend.lock().expect("poisoned");
+            if let Some(bounds) = FeeBounds::from_estimates(&mut *backend)? {
+                bounds.check(fee)?;
+            }
+        }
+        Ok(())
+    }
+
     /// utility funtion, will error if the inner [`CoinJoin`] is None
     fn coinjoin_exists(&self) -> Result<(), Error> {
         self.coinjoin
@@ -1214,19 +2007,38 @@ impl<'a> JoinstrInner<'a> {
         }
     }
 
-    /// Initiate a new pool by sending a pool creation event (Kind 2022)
-    ///   to nostr relays.
+    /// Returns the pool to post as a pool creation event (Kind 2022), see
+    ///   [`JoinstrInner::posted()`].
     ///
     /// # Errors
     ///
-    /// This function will return an error if a pool already exists, if
-    ///   some fields of the pool are missing or if posting the event fail.
-    fn post(&mut self) -> Result<(), Error> {
+    /// This function will return an error if a pool already exists or if
+    ///   some fields of the pool are missing.
+    fn pool_to_post(&mut self) -> Result<Pool, Error> {
+        let pool = self.new_pool(self.keys.public_key())?;
+        self.step = Step::Posting;
+        Ok(pool)
+    }
+
+    /// Record the pool once its creation event have been posted.
+    fn posted(&mut self, pool: Pool) {
+        self.pool = Some(pool);
+        self.step = Step::OutputRegistration;
+    }
+
+    /// Returns the pool to post, from the parameters of this instance.
+    ///
+    /// # Arguments
+    /// * `public_key` - The nostr key of the pool
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a pool already exists or if
+    ///   some fields of the pool are missing.
+    fn new_pool(&self, public_key: PublicKey) -> Result<Pool, Error> {
         self.is_ready()?;
         self.pool_not_exists()?;
 
-        self.step = Step::Posting;
-        let public_key = self.client.get_keys()?.public_key();
         let transport = crate::nostr::Transport {
             vpn: Some(Vpn {
                 enable: false,
@@ -1244,6 +2056,8 @@ impl<'a> JoinstrInner<'a> {
             relays: self.relay.clone().map(|r| vec![r]).unwrap_or_default(),
             fee: self.fee.clone().ok_or(Error::FeeMissing)?,
             transport,
+            pow: self.pow,
+            banned: self.banned.clone(),
         };
         let mut engine = sha256::Hash::engine();
         engine.input(&public_key.clone().to_bytes());
@@ -1256,23 +2070,19 @@ impl<'a> JoinstrInner<'a> {
         );
         let id = sha256::Hash::from_engine(engine).to_string();
 
-        let pool = Pool {
+        Ok(Pool {
             versions: default_version(),
             id,
             pool_type: PoolType::Create,
             public_key,
             payload: Some(payload),
             network: self.network,
-        };
-        self.client.post_event(pool.clone().try_into()?)?;
-        self.pool = Some(pool);
-        self.step = Step::OutputRegistration;
-        Ok(())
+        })
     }
 
     /// Returns informations about the start timeline of this pool:
     ///   - expiration timestamp
-    ///   - wether if the coinjoin can start early if enough peer join
+    ///   - whether the coinjoin can start early if enough peer join
     ///
     /// # Errors
     ///
@@ -1315,34 +2125,530 @@ impl<'a> JoinstrInner<'a> {
         })
     }
 
-    /// Register [`Joinstr::output`] address to the pool
+    /// Returns a [`PoolMessage`] to send to the pool, the first input/output
+    ///   pair use the main nostr client, each other pair use a fresh
+    ///   connection to the relay in order messages cannot be linked together
+    ///   at network level.
+    ///
+    /// # Arguments
+    /// * `msg` - The message to send
+    /// * `index` - The index of the input/output pair this message is related to
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool not exists.
+    fn pair_message(&self, msg: PoolMessage, index: usize) -> Result<Outgoing, Error> {
+        self.pool_exists()?;
+        let detached = (index > 0).then(|| (self.keys.clone(), format!("{}_{}", self.name, index)));
+        Ok(Outgoing {
+            npub: self.pool_as_ref()?.public_key,
+            msg,
+            pow: None,
+            detached,
+        })
+    }
+
+    /// Check the role of this instance and that we register as many inputs as
+    ///   outputs, the missing outputs are drawn from the signer postmix
+    ///   account.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool we want join (optional)
+    /// * `signer` - The signer to draw the missing outputs from (optional)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the role of this instance is unknown
+    ///   - a postmix address is not valid
+    ///   - we do not register as many inputs as outputs
+    ///   - a pool is passed and the pool already exists
+    fn prepare<S>(&mut self, pool: Option<Pool>, signer: Option<&mut S>) -> Result<(), Error>
+    where
+        S: JoinstrSigner,
+    {
+        if matches!(self.role, Role::Unknown) {
+            log::error!("Joinstr::start_coinjoin({}): wrong role!", self.name);
+            return Err(Error::WrongRole);
+        }
+
+        // draw missing outputs from the signer postmix account
+        if let Some(s) = signer {
+            while self.my_outputs.len() < self.my_inputs.len() {
+                match s.postmix_address() {
+                    Some(addr) => self.add_address(addr.as_unchecked().clone())?,
+                    None => break,
+                }
+            }
+        }
+
+        self.inputs_match_outputs()?;
+
+        if let Some(pool) = pool {
+            self.pool_not_exists()?;
+            self.pool = Some(pool);
+        }
+        Ok(())
+    }
+
+    /// Returns the join request to send to the pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists.
+    fn join_request(&mut self) -> Result<Outgoing, Error> {
+        self.pool_exists()?;
+        self.step = Step::Connecting;
+        // TODO: receive the response on a derived npub;
+        Ok(Outgoing {
+            npub: self.pool_as_ref()?.public_key,
+            msg: PoolMessage::Join(Some(self.keys.public_key())),
+            pow: self.payload_as_ref()?.pow,
+            detached: None,
+        })
+    }
+
+    /// Process a message received while waiting for the credentials of the
+    ///   pool, returns the pool keys the main nostr client must now use if
+    ///   it's the credentials of our pool.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists.
+    fn receive_credentials(&mut self, msg: PoolMessage) -> Result<Option<Keys>, Error> {
+        let PoolMessage::Credentials(Credentials { id, key }) = msg else {
+            return Ok(None);
+        };
+        log::debug!(
+            "Coordinator({}).connect_to_pool(): receive credentials.",
+            self.name
+        );
+        if id != self.pool_as_ref()?.id {
+            log::error!(
+                "Coordinator({}).connect_to_pool(): pool id not match!",
+                self.name
+            );
+            return Ok(None);
+        }
+        let keys = Keys::new(key);
+        self.keys = keys.clone();
+        self.name = format!("prev_{}", self.name);
+        self.step = Step::OutputRegistration;
+        Ok(Some(keys))
+    }
+
+    /// Returns a join request for every additional input/output pair we want
+    ///   to register, each one sent by a fresh nostr identity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists.
+    fn extra_joins(&self) -> Result<Vec<Outgoing>, Error> {
+        let npub = self.pool_as_ref()?.public_key;
+        let pow = self.payload_as_ref()?.pow;
+        let extra = self.my_outputs.len().saturating_sub(1);
+        Ok((0..extra)
+            .map(|i| {
+                let keys = Keys::generate();
+                Outgoing {
+                    npub,
+                    msg: PoolMessage::Join(Some(keys.public_key())),
+                    pow,
+                    detached: Some((keys, format!("{}_join_{i}", self.name))),
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the dummy join requests the initiator sends for each output it
+    ///   want to register, a coordinator that does not take part in the
+    ///   coinjoin sends none.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists.
+    fn dummy_joins(&self) -> Result<Vec<Outgoing>, Error> {
+        if self.role != Role::Initiator {
+            return Ok(Vec::new());
+        }
+        let npub = self.pool_as_ref()?.public_key;
+        let pow = self.payload_as_ref()?.pow;
+        Ok((0..self.my_outputs.len())
+            .map(|_| Outgoing {
+                npub,
+                msg: PoolMessage::Join(Some(Keys::generate().public_key())),
+                pow,
+                detached: Some((Keys::generate(), "dummy".into())),
+            })
+            .collect())
+    }
+
+    /// Start the output registration round.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the inner pool not exists
+    ///   - the payload of the pool is missing
+    ///   - the fee are not of type [`Fee::Fixed`]
+    fn output_round(&self) -> Result<OutputRound<'a>, Error> {
+        let (expired, start_early) = self.start_timeline()?;
+        let payload = self.payload_as_ref()?.clone();
+        let mut coinjoin = CoinJoin::<crate::electrum::Client>::new(payload.denomination, None)
+            .min_peer(payload.peers)
+            .fee(self.pool_fee()? as usize);
+        if let Some(max_fee) = self.max_fee {
+            coinjoin = coinjoin.max_fee(max_fee as usize);
+        }
+        Ok(OutputRound {
+            payload,
+            expired,
+            start_early,
+            peers: HashSet::new(),
+            coinjoin,
+        })
+    }
+
+    /// Process a message received at the peer registration step, returns the
+    ///   credentials to send to the peer if we are the initiator of the pool.
     ///
     /// # Arguments
+    /// * `round` - The output registration round
+    /// * `msg` - The received message
+    /// * `pow` - The NIP-13 proof of work of the message
     /// * `notif` - A callback function called every time the pool state is updated.
     ///
     /// # Errors
     ///
+    /// This function will return an error if the pool does not exists.
+    fn receive_join<N>(
+        &mut self,
+        round: &mut OutputRound<'a>,
+        msg: PoolMessage,
+        pow: u8,
+        notif: N,
+    ) -> Result<Option<Outgoing>, Error>
+    where
+        N: Fn(),
+    {
+        match msg {
+            PoolMessage::Join(Some(npub)) if !accept_join(&round.payload, &npub, pow) => {
+                log::debug!(
+                    "Coordinator({}).register_outputs(): ignore Join({}) request: banned or not enough PoW.",
+                    self.name,
+                    npub,
+                );
+            }
+            PoolMessage::Join(Some(npub)) => {
+                if round.peers.insert(npub) {
+                    self.peers.push(npub);
+                    notif();
+                    log::debug!(
+                        "Coordinator({}).register_outputs(): receive Join({}) request. \n      peers: {}",
+                        self.name,
+                        npub,
+                        round.peers.len()
+                    );
+                    if self.role == Role::Initiator {
+                        let response = PoolMessage::Credentials(Credentials {
+                            id: self.pool_as_ref()?.id.clone(),
+                            key: self.keys.secret_key().clone(),
+                        });
+                        return Ok(Some(Outgoing {
+                            npub,
+                            msg: response,
+                            pow: None,
+                            detached: None,
+                        }));
+                    }
+                }
+            }
+            PoolMessage::Join(None) => {
+                log::error!(
+                    "Coordinator({}).register_outputs(): cannot answer if npub is None!",
+                    self.name,
+                );
+            }
+            PoolMessage::Output(o) => {
+                log::error!(
+                    "Coordinator({}).register_outputs(): receive Output({:?}) request before output registartion step!",
+                    self.name,
+                    o
+                );
+                // NOTE: should we accept output registration at this step?
+                // Should we store the output and reuse at next step?
+            }
+            r => {
+                // NOTE: simply drop other kind of messages
+                log::debug!(
+                    "Coordinator({}).register_outputs(): request not handled at peer registration step: {:?}!",
+                    self.name,
+                    r
+                );
+            }
+        }
+        Ok(None)
+    }
+
+    /// Register one of [`JoinstrInner::my_outputs`] address to the round,
+    ///   returns the message to send to the pool.
+    ///
+    /// # Arguments
+    /// * `round` - The output registration round
+    /// * `index` - The index of the address in [`JoinstrInner::my_outputs`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool not exists or there
+    ///   is no address at `index`.
+    fn register_output(
+        &mut self,
+        round: &mut OutputRound<'a>,
+        index: usize,
+    ) -> Result<Outgoing, Error> {
+        let address = self
+            .my_outputs
+            .get(index)
+            .cloned()
+            .ok_or(Error::OutputMissing)?;
+        round.coinjoin.add_output(address.clone());
+        let msg = PoolMessage::Output(address.as_unchecked().clone());
+        self.outputs.push(address);
+        // TODO: handle re-send if fails
+        self.pair_message(msg, index)
+    }
+
+    /// Process a message received at the output registration step.
+    ///
+    /// # Arguments
+    /// * `round` - The output registration round
+    /// * `msg` - The received message
+    /// * `notif` - A callback function called every time the pool state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pool does not exists.
+    fn receive_output<N>(
+        &mut self,
+        round: &mut OutputRound<'a>,
+        msg: PoolMessage,
+        notif: N,
+    ) -> Result<(), Error>
+    where
+        N: Fn(),
+    {
+        match msg {
+            PoolMessage::Join(_) => {
+                log::debug!(
+                    "Coordinator({}).register_outputs(): receive Join request at output registration step!",
+                    self.name,
+                );
+            }
+            PoolMessage::Output(o) => {
+                log::debug!(
+                    "Coordinator({}).register_outputs(): receive Output({:?}) request.",
+                    self.name,
+                    o
+                );
+                self.receive_outputs(vec![o.clone()], &mut round.coinjoin)?;
+                // TODO: we must error if outputs > peers
+                // TODO: check address network
+                self.outputs.push(o.assume_checked());
+                notif();
+            }
+            // FIXME: here it can be some cases where, because network timing, we can
+            // receive a signed input before the output registration round ended, we should
+            // store those inputs in order to use them later.
+            PoolMessage::Input(_) => {
+                log::error!(
+                    "Coordinator({}).register_outputs(): drop Input received at output registration step!",
+                    self.name,
+                );
+            }
+            r => {
+                // NOTE: simply drop other kind of messages
+                log::debug!(
+                    "Coordinator({}).register_outputs(): request not handled at output registration step: {:?}!",
+                    self.name,
+                    r
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// End the output registration round, the coinjoin of the round become
+    ///   the inner [`CoinJoin`].
+    ///
+    /// # Arguments
+    /// * `round` - The output registration round
+    /// * `now` - The current timestamp
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the pool does not exists
+    ///   - timeout elapsed
+    ///   - not enough peers joined
+    ///   - peer count do not match
+    fn end_output_round(&mut self, round: OutputRound<'a>, now: u64) -> Result<(), Error> {
+        let expired = self.end_timeline()?;
+        let peers = round.peers.len();
+        if now > expired {
+            return Err(Error::Timeout);
+        } else if peers < round.payload.peers {
+            return Err(Error::NotEnoughPeers(peers, round.payload.peers));
+        } else if round.coinjoin.outputs_len() != peers {
+            // NOTE: do not allow registered peer that not commit an output as it can be some
+            // lurkers trying deanonimyze peers
+            return Err(Error::PeerCountNotMatch(
+                round.coinjoin.outputs_len(),
+                peers,
+            ));
+        }
+        self.coinjoin = Some(round.coinjoin);
+        Ok(())
+    }
+
+    /// Returns the timestamp at which the input registration round expires.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the inner pool does not exists
+    ///   - the inner coinjoin is None
+    ///   - the round already expired
+    fn input_deadline(&self, now: u64) -> Result<u64, Error> {
+        self.pool_exists()?;
+        self.coinjoin_exists()?;
+        let expired = match self.payload_as_ref()?.timeout {
+            Timeline::Simple(timestamp) => timestamp,
+            Timeline::Fixed {
+                start,
+                max_duration,
+            } => start + max_duration,
+            Timeline::Timeout { max_duration, .. } => now + max_duration,
+        };
+        if now > expired {
+            return Err(Error::Timeout);
+        }
+        Ok(expired)
+    }
+
+    /// Returns whether the coinjoin transaction is finalized.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner coinjoin is None.
+    fn inputs_registered(&self) -> Result<bool, Error> {
+        Ok(self.coinjoin_as_ref()?.tx.is_some())
+    }
+
+    /// Returns the input of a message received at the input registration
+    ///   step, None if the message is not an input.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a received PSBT cannot be
+    ///   parsed as an input.
+    fn input_of(&self, msg: PoolMessage) -> Result<Option<InputDataSigned>, Error> {
+        match msg {
+            PoolMessage::Psbt(psbt) => Ok(Some(psbt.try_into().map_err(|_| Error::PsbtToInput)?)),
+            PoolMessage::Input(input) => Ok(Some(input)),
+            m => {
+                // NOTE: simply drop other kind of messages
+                log::error!(
+                    "Coordinator({}).register_input(): drop message {:?}",
+                    self.name,
+                    m
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Check a received input against its previous output, as returned by
+    ///   [`ChainSource::get_txout()`].
+    ///
+    /// # Errors
+    ///
     /// This function will return an error if:
-    ///   - the pool not exists
-    ///   - [`Joinstr::output`] is missing
-    ///   - fails to send the nostr message
-    fn register_output<N>(&mut self, notif: N) -> Result<(), Error>
+    ///   - the previous output does not exist
+    ///   - the amount announced by the peer does not match the previous output
+    ///   - the previous output is spent by another transaction than the coinjoin
+    #[allow(clippy::type_complexity)]
+    fn check_input(
+        &self,
+        input: &InputDataSigned,
+        prevout: Option<(TxOut, Option<Transaction>)>,
+    ) -> Result<(), Error> {
+        let (txout, spent_by) = prevout.ok_or(coinjoin::Error::InputDoesNotExists)?;
+        if input.amount.is_some_and(|amount| amount != txout.value) {
+            return Err(coinjoin::Error::InputValueNotMatch.into());
+        }
+        if let Some(tx) = spent_by {
+            // NOTE: the coinjoin can have already been broadcast by another peer
+            let outputs = self
+                .coinjoin
+                .as_ref()
+                .and_then(|c| c.unsigned_tx())
+                .map(|tx| tx.output);
+            if outputs != Some(tx.output) {
+                return Err(coinjoin::Error::DoubleSpend.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a received input against the bitcoin backend, if any, see
+    ///   [`JoinstrInner::check_input()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the backend request fails or
+    ///   the input is not valid.
+    fn verify_input(&self, input: &InputDataSigned) -> Result<(), Error> {
+        let Some(backend) = self.backend.as_ref() else {
+            return Ok(());
+        };
+        let prevout = backend
+            .lock()
+            .expect("poisoned")
+            .get_txout(input.txin.previous_output)?;
+        self.check_input(input, prevout)
+    }
+
+    /// Register a received input, returns whether the coinjoin is finalized.
+    ///
+    /// # Arguments
+    /// * `input` - The received input
+    /// * `checked` - The result of the checks of the input against the
+    ///   bitcoin backend, the input is dropped if it failed
+    /// * `notif` - A callback function called if the input is registered
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner coinjoin is None.
+    fn receive_input<N>(
+        &mut self,
+        input: InputDataSigned,
+        checked: Result<(), Error>,
+        notif: N,
+    ) -> Result<bool, Error>
     where
         N: Fn(),
     {
-        if let Some(address) = &self.output {
-            // let msg = PoolMessage::Outputs(Outputs::single(address.as_unchecked().clone()));
-            let msg = PoolMessage::Output(address.as_unchecked().clone());
-            self.pool_exists()?;
-            let npub = self.pool_as_ref()?.public_key;
-            self.client.send_pool_message(&npub, msg)?;
-            self.outputs.push(address.clone());
-            notif();
-            // TODO: handle re-send if fails
-            Ok(())
-        } else {
-            Err(Error::OutputMissing)
+        if let Err(e) = checked {
+            log::error!(
+                "Coordinator({}).register_input(): drop input {:?}: {:?}",
+                self.name,
+                input.txin.previous_output,
+                e
+            );
+            return Ok(false);
         }
+        self.try_register_input(input, notif)?;
+        self.try_finalize_coinjoin()
     }
 
     /// Try to register a received output address to the inner [`CoinJoin`]
@@ -1371,7 +2677,7 @@ impl<'a> JoinstrInner<'a> {
             } else {
                 log::debug!(
                     "Coordinator({}).register_outputs(): address {:?} is not valid for network {}.",
-                    self.client.name,
+                    self.name,
                     addr,
                     self.network
                 );
@@ -1380,50 +2686,73 @@ impl<'a> JoinstrInner<'a> {
         Ok(())
     }
 
-    /// Try to sign / register / send our input.
+    /// Sign & register one of our inputs, returns the message to send to the
+    ///   pool.
     ///
     /// # Arguments
-    /// * `notif` - A callback function called every time the pool state is updated.
+    /// * `signer` - The signer to sign the input with
+    /// * `input` - The coin to sign
+    /// * `index` - The index of the coin in [`JoinstrInner::my_inputs`]
     ///
     /// # Errors
     ///
     /// This function will return an error if:
     ///   - the inner coinjoin is missing
     ///   - the unsigned transaction has not been processed
+    ///   - the input pays less than the pool fee rate or more than
+    ///     [`JoinstrInner::max_fee`] for its share of the transaction
     ///   - signing the input fails
     ///   - the inner pool dont exists
-    ///   - [`Joinstr::input`] is None
-    ///   - sending the input fails
-    fn register_input<S, N>(&mut self, signer: &S, notif: N) -> Result<(), Error>
+    fn register_input<S>(
+        &mut self,
+        signer: &S,
+        input: Coin,
+        index: usize,
+    ) -> Result<Outgoing, Error>
     where
         S: JoinstrSigner,
-        N: Fn(),
     {
-        let name = self.client.name.clone();
-        log::debug!("Joinstr::register_input({name})");
+        log::debug!("Joinstr::register_input({})", self.name);
+        let signed_input = self.sign_input(signer, input)?;
+        let msg = PoolMessage::Input(signed_input.clone());
+        self.inputs.push(signed_input);
+        // TODO: handle re-send if fails
+        self.pair_message(msg, index)
+    }
+
+    /// Sign one of our inputs, after checking the fee it pays for its share
+    ///   of the unsigned transaction.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the inner coinjoin is missing
+    ///   - the unsigned transaction has not been processed
+    ///   - the input pays less than the pool fee rate or more than
+    ///     [`JoinstrInner::max_fee`] for its share of the transaction
+    ///   - signing the input fails
+    fn sign_input<S>(&self, signer: &S, input: Coin) -> Result<InputDataSigned, Error>
+    where
+        S: JoinstrSigner,
+    {
+        let name = &self.name;
         let unsigned = match self.coinjoin_as_ref()?.unsigned_tx() {
             Some(u) => u,
             None => return Err(Error::UnsignedTxNotExists),
         };
-        if let Some(input) = self.input.take() {
-            log::debug!("Joinstr::register_input({name}) signing input ...");
-            let signed_input = signer
-                .sign_input(&unsigned, input)
-                .map_err(Error::SigningFail)?;
-            log::debug!("Joinstr::register_input({name}) input signed!");
-            let msg = PoolMessage::Input(signed_input.clone());
-            self.pool_exists()?;
-            let npub = self.pool_as_ref()?.public_key;
-            log::debug!("Joinstr::register_input({name}) sending signed input to pool..");
-            self.client.send_pool_message(&npub, msg)?;
-            self.inputs.push(signed_input);
-            notif();
-            log::debug!("Joinstr::register_input({name}) input sent & locally registered!");
-            // TODO: handle re-send if fails
-            Ok(())
-        } else {
-            Err(Error::InputMissing)
-        }
+        fee::check_input_fee(
+            &unsigned,
+            input.txout.value,
+            self.payload_as_ref()?.denomination,
+            self.pool_fee()?,
+            self.max_fee,
+        )?;
+        log::debug!("Joinstr::register_input({name}) signing input ...");
+        let signed_input = signer
+            .sign_input(&unsigned, input)
+            .map_err(Error::SigningFail)?;
+        log::debug!("Joinstr::register_input({name}) input signed!");
+        Ok(signed_input)
     }
 
     /// Try to register a received signed input to the inner [`CoinJoin`]
@@ -1441,7 +2770,7 @@ impl<'a> JoinstrInner<'a> {
         self.coinjoin_exists()?;
         log::debug!(
             "Coordinator({}).register_input(): receive Inputs({:?}) request.",
-            self.client.name,
+            self.name,
             input
         );
         // Register inputs
@@ -1449,7 +2778,7 @@ impl<'a> JoinstrInner<'a> {
             if let Err(e) = coinjoin.add_input(input.clone()) {
                 log::error!(
                     "Coordinator({}).register_input(): fail to add input: {:?}",
-                    self.client.name,
+                    self.name,
                     e
                 );
             } else {
@@ -1460,7 +2789,7 @@ impl<'a> JoinstrInner<'a> {
         Ok(())
     }
 
-    /// Return wether the coinjoin can be finalyzed.
+    /// Return whether the coinjoin can be finalyzed.
     ///
     /// # Errors
     ///
@@ -1470,7 +2799,7 @@ impl<'a> JoinstrInner<'a> {
         if coinjoin.inputs_len() >= coinjoin.outputs_len() && coinjoin.generate_tx(false).is_ok() {
             log::info!(
                 "Coordinator({}).register_input(): coinjoin finalyzed!",
-                self.client.name,
+                self.name,
             );
             Ok(true)
         } else {
@@ -1505,14 +2834,34 @@ impl<'a> JoinstrInner<'a> {
     /// Note: if no backend, the transaction will not been broadcasted
     ///   but no error will be emited.
     fn broadcast_tx(&mut self) -> Result<(), Error> {
-        self.pool_exists()?;
-        let tx = self.coinjoin_as_ref()?.tx().ok_or(Error::MissingFinalTx)?;
-        if let Some(client) = self.electrum_client.as_mut() {
-            client.broadcast(&tx)?;
+        let tx = self.tx_to_broadcast()?;
+        if let Some(backend) = self.backend.as_ref() {
+            backend.lock().expect("poisoned").broadcast(&tx)?;
         }
+        self.broadcasted(tx);
+        Ok(())
+    }
+
+    /// Returns the signed + finalized transaction to broadcast, see
+    ///   [`JoinstrInner::broadcasted()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the coinjoin process has been canceled
+    ///   - The inner pool does not exists
+    ///   - [`Joinstr::coinjoin`] is None
+    ///   - The transaction has not been finalized
+    fn tx_to_broadcast(&self) -> Result<Transaction, Error> {
+        self.check_canceled()?;
+        self.pool_exists()?;
+        self.coinjoin_as_ref()?.tx().ok_or(Error::MissingFinalTx)
+    }
+
+    /// Record the transaction once broadcasted.
+    fn broadcasted(&mut self, tx: Transaction) {
         self.final_tx = Some(tx);
         self.step = Step::Broadcast;
-        Ok(())
     }
 
     /// Returns the finalized transaction
@@ -1526,14 +2875,28 @@ impl<'a> JoinstrInner<'a> {
     ///
     /// This function will return an error if the coin is already set
     pub fn set_coin(&mut self, coin: Coin) -> Result<(), Error> {
-        if self.input.is_none() {
-            self.input = Some(coin);
+        if self.my_inputs.is_empty() {
+            self.my_inputs.push(coin);
             Ok(())
         } else {
             Err(Error::AlreadyHaveInput)
         }
     }
 
+    /// Add a coin to coinjoin
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coin is already registered
+    pub fn add_coin(&mut self, coin: Coin) -> Result<(), Error> {
+        if self.my_inputs.iter().any(|c| c.outpoint == coin.outpoint) {
+            Err(Error::AlreadyHaveInput)
+        } else {
+            self.my_inputs.push(coin);
+            Ok(())
+        }
+    }
+
     /// Set the address the coin must be sent to
     ///
     /// # Errors
@@ -1546,14 +2909,70 @@ impl<'a> JoinstrInner<'a> {
         } else {
             return Err(Error::WrongAddressNetwork);
         };
-        if self.output.is_none() {
-            self.output = Some(addr);
+        if self.my_outputs.is_empty() {
+            self.my_outputs.push(addr);
             Ok(())
         } else {
             Err(Error::AlreadyHaveOutput)
         }
     }
 
+    /// Add an address the coins must be sent to
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is already registered
+    /// or if address is for wrong network
+    pub fn add_address(&mut self, addr: Address<NetworkUnchecked>) -> Result<(), Error> {
+        let addr = if addr.is_valid_for_network(self.network) {
+            addr.assume_checked()
+        } else {
+            return Err(Error::WrongAddressNetwork);
+        };
+        if self.my_outputs.contains(&addr) {
+            Err(Error::AlreadyHaveOutput)
+        } else {
+            self.my_outputs.push(addr);
+            Ok(())
+        }
+    }
+
+    /// Utility function, will error if we do not register as many inputs
+    ///   as outputs.
+    /// Check we do not own any coin in the pool, a coinjoin driven w/
+    ///   [`Joinstr::poll()`] cannot sign inputs nor wait the random delays
+    ///   between our registrations.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we own inputs or outputs.
+    fn not_own_coins(&self) -> Result<(), Error> {
+        if !self.my_inputs.is_empty() || !self.my_outputs.is_empty() {
+            return Err(Error::OwnCoins);
+        }
+        Ok(())
+    }
+
+    fn inputs_match_outputs(&self) -> Result<(), Error> {
+        let should_match = self.role == Role::Peer || !self.my_inputs.is_empty();
+        if should_match && self.my_inputs.len() != self.my_outputs.len() {
+            return Err(Error::InputsOutputsNotMatch(
+                self.my_inputs.len(),
+                self.my_outputs.len(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the coinjoin process has been canceled.
+    fn check_canceled(&self) -> Result<(), Error> {
+        if self.canceled {
+            Err(Error::Canceled)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Returns the current status of the [`JoinstrInner`] instance.
     ///
     /// # Returns
@@ -1575,17 +2994,24 @@ impl<'a> JoinstrInner<'a> {
     /// # Returns
     /// A [`State`] struct containing the current state information.
     pub fn state(&self) -> Option<State> {
-        let keys = if let Ok(keys) = self.client.get_keys() {
-            keys
-        } else {
-            return None;
-        };
         let relay = if let Some(relay) = &self.relay {
             relay.clone()
         } else {
             return None;
         };
-        let electrum = self.electrum_client.as_ref().map(|c| (c.url(), c.port()));
+        let config = self
+            .backend
+            .as_ref()
+            .and_then(|b| b.lock().expect("poisoned").config());
+        let (electrum, bitcoind, esplora, electrum_pool) = match config {
+            Some(ChainConfig::Electrum(url, port)) => (Some((url, port)), None, None, None),
+            Some(ChainConfig::Bitcoind(config)) => (None, Some(config), None, None),
+            Some(ChainConfig::Esplora(url)) => (None, None, Some(url), None),
+            Some(ChainConfig::ElectrumPool(servers, quorum)) => {
+                (None, None, None, Some((servers, quorum)))
+            }
+            None => (None, None, None, None),
+        };
         let pool = if let Some(pool) = &self.pool {
             pool.clone()
         } else {
@@ -1594,12 +3020,20 @@ impl<'a> JoinstrInner<'a> {
         Some(State {
             role: self.role,
             step: self.step,
-            pool_secret_key: keys.secret_key().to_secret_hex(),
+            pool_secret_key: self.keys.secret_key().to_secret_hex(),
             relay,
             electrum,
+            bitcoind,
+            esplora,
+            electrum_pool,
+            max_fee: self.max_fee,
             pool,
-            input: self.input.clone(),
-            output: self.output.clone().map(|a| a.as_unchecked().clone()),
+            my_inputs: self.my_inputs.clone(),
+            my_outputs: self
+                .my_outputs
+                .iter()
+                .map(|a| a.as_unchecked().clone())
+                .collect(),
             network: self.network,
             final_tx: self.final_tx.clone(),
             peers: self.peers.clone(),
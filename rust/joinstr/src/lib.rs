@@ -1,10 +1,22 @@
 #![allow(dead_code)]
+pub mod backend;
+pub mod bitcoind;
+pub mod coin_selection;
 pub mod coinjoin;
 pub mod electrum;
+pub mod electrum_pool;
+pub mod esplora;
+pub mod fee;
+pub mod http;
 pub mod interface;
 pub mod joinstr;
 pub mod nostr;
+pub mod privacy;
+pub mod remixer;
 pub mod signer;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod spv;
 pub mod utils;
 pub use bip39;
 pub use log;
@@ -1,8 +1,9 @@
 use std::{str::FromStr, time::Duration};
 
 use nostr_sdk::{
-    nips::nip04, Client, Event, EventBuilder, Filter, Keys, Kind, Options, PublicKey,
-    RelayPoolNotification, Tag, Timestamp,
+    nips::{nip04, nip13},
+    Client, Event, EventBuilder, Filter, Keys, Kind, Options, PublicKey, RelayPoolNotification,
+    Tag, Timestamp,
 };
 
 use tokio::sync::broadcast;
@@ -215,6 +216,41 @@ impl NostrClient {
         self.send_dm(npub, clear_content).await
     }
 
+    /// Send a [`PoolMessage`] wrapped into a NIP04 encrypted DM w/ a NIP-13
+    ///   proof of work, see [`NostrClient::send_pool_message()`].
+    ///
+    /// # Arguments
+    /// * `npub` - nostr pubkey of the pool
+    /// * `msg` - the PoolMessage to send
+    /// * `difficulty` - the min number of leading zero bits of the event id
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the message cannot be serialized into String json payload
+    ///   - sending the DM fails
+    pub async fn send_pool_message_with_pow(
+        &self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        difficulty: u8,
+    ) -> Result<(), Error> {
+        let clear_content = msg.to_string()?;
+        log::debug!(
+            "NostrClient.send_pool_message_with_pow({difficulty}): {:#?}",
+            clear_content
+        );
+        let signer = self.client()?.signer().await?;
+        let content = signer.nip04_encrypt(npub, clear_content).await?;
+        let dm = EventBuilder::new(
+            Kind::EncryptedDirectMessage,
+            content,
+            vec![Tag::public_key(*npub)],
+        )
+        .pow(difficulty);
+        self.post_event(dm).await
+    }
+
     /// Subscribe to notifications of NIP04 DMs thatare send tu the client pubkey
     ///
     /// # Errors
@@ -362,6 +398,69 @@ impl NostrClient {
         })
     }
 
+    /// Wait up to `timeout` for a [`PoolMessage`], returns it w/ its NIP-13
+    ///   proof of work (leading zero bits of the event id), or None if no
+    ///   message have been received before the timeout.
+    ///
+    /// Events that are not NIP04 DMs or cannot be parsed as a PoolMessage are
+    ///   skipped, see [`NostrClient::receive_pool_msg()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the client is not connected
+    ///   - the channel is closed
+    pub async fn wait_pool_msg_with_pow(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, Error> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let receiver = self.nostr_receiver.as_mut().ok_or(Error::NotConnected)?;
+            let notif = match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Err(_) => return Ok(None),
+                Ok(Ok(notif)) => notif,
+                Ok(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                    log::warn!(
+                        "NostrClient({}).wait_pool_msg_with_pow(): {missed} notifications missed",
+                        self.name
+                    );
+                    continue;
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) => return Err(Error::Disconnected),
+            };
+            let event = match notif {
+                RelayPoolNotification::Event { event, .. }
+                    if event.kind == Kind::EncryptedDirectMessage =>
+                {
+                    *event
+                }
+                _ => continue,
+            };
+            let pow = nip13::get_leading_zero_bits(event.id.as_bytes());
+            let event = match self.decrypt_dm(event) {
+                Ok(e) => e,
+                Err(Error::DmEncryption) => {
+                    log::error!(
+                        "NostrClient({}).wait_pool_msg_with_pow(): cannot decrypt DM!",
+                        self.name
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Ok(msg) = PoolMessage::from_str(&event.content) {
+                // if the join request does not contain a pubkey to respond to, we respond to
+                // sender
+                let msg = match msg {
+                    PoolMessage::Join(None) => PoolMessage::Join(Some(event.pubkey)),
+                    m => m,
+                };
+                return Ok(Some((msg, pow)));
+            }
+        }
+    }
+
     /// Try to poll notifications/events received by the client and parse it as
     ///    a Pool, it will return:
     ///    - Some(Pool) if there is a message in the channel
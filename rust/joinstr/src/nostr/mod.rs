@@ -104,6 +104,8 @@ impl Pool {
             relays: vec![relay],
             fee: Fee::Fixed(fee),
             transport: default_transport(),
+            pow: None,
+            banned: Vec::new(),
         };
 
         let id = pool_id(&key);
@@ -173,6 +175,14 @@ pub struct PoolPayload {
     #[serde(rename = "fee_rate")]
     pub fee: Fee,
     pub transport: Transport,
+    /// The min NIP-13 proof of work (leading zero bits of the event id) of
+    ///   the join requests, join requests w/ less work are ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pow: Option<u8>,
+    /// The join requests of these nostr keys are ignored, best-effort as a
+    ///   peer can join again from a fresh key, see [`PoolPayload::pow`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub banned: Vec<PublicKey>,
 }
 
 pub fn default_version() -> Option<Vec<String>> {
@@ -319,7 +329,7 @@ impl FromStr for PoolMessage {
                 return match t.as_str() {
                     "psbt" => {
                         if let Some(Value::String(psbt)) = map.get("psbt") {
-                            let psbt: Psbt = serde_json::from_str(psbt)?;
+                            let psbt = Psbt::from_str(psbt).map_err(|_| ParsingError::Psbt)?;
                             Ok(Self::Psbt(psbt))
                         } else {
                             Err(ParsingError::Psbt)
@@ -445,10 +455,11 @@ impl InputDataSigned {
             };
             txin.witness = witness;
 
-            let amount = map
-                .get("amount")
-                .ok_or(ParsingError::MissingKey("amount".into()))?;
-            let amount: Option<Amount> = Some(serde_json::from_value(amount.clone())?);
+            // `amount` is not serialized if None
+            let amount: Option<Amount> = match map.get("amount") {
+                None | Some(Value::Null) => None,
+                Some(amount) => Some(serde_json::from_value(amount.clone())?),
+            };
             Ok(Self { txin, amount })
         } else {
             Err(ParsingError::NotAnObject)
@@ -471,7 +482,8 @@ impl PoolMessage {
         map.insert("type".into(), msg_type.into());
         match self {
             PoolMessage::Psbt(psbt) => {
-                map.insert(msg_type.into(), serde_json::to_value(psbt)?);
+                // base64 encoded
+                map.insert(msg_type.into(), Value::String(psbt.to_string()));
             }
             PoolMessage::Transaction(tx) => {
                 let raw = serialize_hex(tx);
@@ -579,6 +591,8 @@ pub mod tests {
                     }),
                     tor: None,
                 },
+                pow: None,
+                banned: Vec::new(),
             }),
         };
 
@@ -588,6 +602,20 @@ pub mod tests {
         assert_eq!(pool, parsed);
     }
 
+    #[test]
+    fn pool_join_rules() {
+        let mut pool: Pool = serde_json::from_str(RAW_POOL).unwrap();
+        let payload = pool.payload.as_mut().unwrap();
+        assert_eq!(payload.pow, None);
+        assert!(payload.banned.is_empty());
+
+        payload.pow = Some(16);
+        payload.banned.push(Keys::generate().public_key());
+        let serialized = serde_json::to_string(&pool).unwrap();
+        let parsed: Pool = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(pool, parsed);
+    }
+
     #[test]
     fn input_data_signed() {
         let raw = r#"
@@ -716,4 +744,166 @@ pub mod tests {
         let roundtrip: Pool = event.try_into().unwrap();
         assert_eq!(pool, roundtrip);
     }
+
+    #[test]
+    fn input_data_signed_no_amount() {
+        let raw = r#"
+            {
+              "txin": "4f8176ffbca02baba974a4458eae799a87afa8a00317565827f035a8d45556ba0000000000fdffffff",
+              "witness": "00"
+            }
+        "#;
+        let ids = InputDataSigned::from_str(raw).unwrap();
+        assert_eq!(ids.amount, None);
+        let serialized = ids.to_string().unwrap();
+        let roundtrip = InputDataSigned::from_str(&serialized).unwrap();
+        assert_eq!(ids, roundtrip);
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let raws = [
+            "",
+            "[]",
+            "null",
+            r#"{"type": "input"}"#,
+            r#"{"version": 1, "type": "input"}"#,
+            r#"{"version": "1", "type": 1}"#,
+            r#"{"version": "1", "type": "psbt", "psbt": "cHNidP8="}"#,
+            r#"{"version": "1", "type": "psbt", "psbt": {}}"#,
+            r#"{"version": "1", "type": "input", "input": []}"#,
+            r#"{"version": "1", "type": "input", "input": {"txin": "00", "witness": "00"}}"#,
+            r#"{"version": "1", "type": "output", "address": "bc1q"}"#,
+            r#"{"version": "1", "type": "transaction", "transaction": "zz"}"#,
+            r#"{"version": "1", "type": "join_pool", "npub": "00"}"#,
+            r#"{"version": "1", "type": "credentials", "credentials": {"id": "1"}}"#,
+            r#"{"version": "1", "type": "unknown"}"#,
+        ];
+        for raw in raws {
+            assert!(PoolMessage::from_str(raw).is_err(), "{raw}");
+        }
+    }
+
+    mod roundtrip {
+        use miniscript::bitcoin::{
+            absolute::LockTime, hashes::Hash as _, transaction::Version, OutPoint, PubkeyHash,
+            Script, ScriptBuf, Sequence, TxOut, Txid, Witness,
+        };
+        use proptest::prelude::*;
+
+        use super::*;
+
+        fn bytes(len: std::ops::Range<usize>) -> impl Strategy<Value = Vec<u8>> {
+            prop::collection::vec(any::<u8>(), len)
+        }
+
+        fn network() -> impl Strategy<Value = Network> {
+            prop_oneof![
+                Just(Network::Bitcoin),
+                Just(Network::Testnet),
+                Just(Network::Signet),
+                Just(Network::Regtest),
+            ]
+        }
+
+        fn secret_key() -> impl Strategy<Value = nostr::SecretKey> {
+            any::<[u8; 32]>().prop_filter_map("invalid secret key", |bytes| {
+                nostr::SecretKey::from_slice(&bytes).ok()
+            })
+        }
+
+        fn txin() -> impl Strategy<Value = TxIn> {
+            (
+                any::<[u8; 32]>(),
+                any::<u32>(),
+                bytes(0..64),
+                any::<u32>(),
+                prop::collection::vec(bytes(0..80), 0..4),
+            )
+                .prop_map(|(txid, vout, script_sig, sequence, witness)| TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array(txid), vout),
+                    script_sig: ScriptBuf::from_bytes(script_sig),
+                    sequence: Sequence(sequence),
+                    witness: Witness::from_slice(&witness),
+                })
+        }
+
+        fn txout() -> impl Strategy<Value = TxOut> {
+            (any::<u64>(), bytes(0..64)).prop_map(|(value, script)| TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::from_bytes(script),
+            })
+        }
+
+        // NOTE: a tx w/o inputs cannot be deserialized unambiguously
+        fn transaction() -> impl Strategy<Value = Transaction> {
+            (
+                any::<i32>(),
+                any::<u32>(),
+                prop::collection::vec(txin(), 1..4),
+                prop::collection::vec(txout(), 0..4),
+            )
+                .prop_map(|(version, lock_time, input, output)| Transaction {
+                    version: Version(version),
+                    lock_time: LockTime::from_consensus(lock_time),
+                    input,
+                    output,
+                })
+        }
+
+        fn psbt() -> impl Strategy<Value = Psbt> {
+            transaction().prop_map(|mut tx| {
+                for txin in &mut tx.input {
+                    txin.script_sig = ScriptBuf::new();
+                    txin.witness = Witness::new();
+                }
+                Psbt::from_unsigned_tx(tx).expect("empty script_sig & witness")
+            })
+        }
+
+        fn address() -> impl Strategy<Value = Address<NetworkUnchecked>> {
+            (network(), any::<[u8; 20]>(), bytes(0..64), any::<bool>()).prop_map(
+                |(network, hash, script, p2pkh)| {
+                    let address = if p2pkh {
+                        Address::p2pkh(PubkeyHash::from_byte_array(hash), network)
+                    } else {
+                        Address::p2wsh(Script::from_bytes(&script), network)
+                    };
+                    address.as_unchecked().clone()
+                },
+            )
+        }
+
+        fn pool_message() -> impl Strategy<Value = PoolMessage> {
+            prop_oneof![
+                (txin(), any::<Option<u64>>()).prop_map(|(txin, amount)| {
+                    PoolMessage::Input(InputDataSigned {
+                        txin,
+                        amount: amount.map(Amount::from_sat),
+                    })
+                }),
+                address().prop_map(PoolMessage::Output),
+                psbt().prop_map(PoolMessage::Psbt),
+                transaction().prop_map(PoolMessage::Transaction),
+                prop::option::of(secret_key())
+                    .prop_map(|key| PoolMessage::Join(key.map(|k| Keys::new(k).public_key()))),
+                (any::<String>(), secret_key())
+                    .prop_map(|(id, key)| PoolMessage::Credentials(Credentials { id, key })),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn pool_message_roundtrip(msg in pool_message()) {
+                let serialized = msg.to_string().unwrap();
+                let roundtrip = PoolMessage::from_str(&serialized).unwrap();
+                prop_assert_eq!(msg, roundtrip);
+            }
+
+            #[test]
+            fn pool_message_never_panic(raw in any::<String>()) {
+                let _ = PoolMessage::from_str(&raw);
+            }
+        }
+    }
 }
@@ -2,15 +2,60 @@ use std::{fmt::Debug, str::FromStr};
 
 use simple_nostr_client::nostr::event::{Event, EventBuilder};
 use simple_nostr_client::nostr::key::PublicKey;
+use simple_nostr_client::nostr::nips::nip13;
 use simple_nostr_client::nostr::Keys;
-use simple_nostr_client::{WsClient, WsClientBuilder};
+use simple_nostr_client::{SharedConnection, Waiter, WsClient, WsClientBuilder};
 
 use crate::nostr::{error::Error, Pool, PoolMessage};
 
+/// How a [`NostrClient`] reach its relay.
+#[derive(Debug, Clone)]
+pub enum Connection {
+    /// Open a dedicated connection to the relay url
+    Dedicated(String),
+    /// Use a connection shared w/ other clients
+    Shared(SharedConnection),
+}
+
+impl Connection {
+    /// Returns the relay url.
+    pub fn relay(&self) -> String {
+        match self {
+            Connection::Dedicated(url) => url.clone(),
+            Connection::Shared(connection) => connection.get_relay(),
+        }
+    }
+}
+
+impl From<String> for Connection {
+    fn from(value: String) -> Self {
+        Connection::Dedicated(value)
+    }
+}
+
+impl From<&str> for Connection {
+    fn from(value: &str) -> Self {
+        Connection::Dedicated(value.into())
+    }
+}
+
+impl From<SharedConnection> for Connection {
+    fn from(value: SharedConnection) -> Self {
+        Connection::Shared(value)
+    }
+}
+
+impl From<&SharedConnection> for Connection {
+    fn from(value: &SharedConnection) -> Self {
+        Connection::Shared(value.clone())
+    }
+}
+
 #[derive(Default)]
 pub struct NostrClient {
     client: Option<WsClient>,
     builder: Option<WsClientBuilder>,
+    shared: Option<SharedConnection>,
     pub name: String,
 }
 
@@ -51,6 +96,37 @@ impl NostrClient {
         }
     }
 
+    /// Use a connection shared w/ other clients instead of opening a
+    ///   dedicated one, replace the relay url if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client is already connected
+    ///   to some relays.
+    pub fn shared(mut self, connection: SharedConnection) -> Result<Self, Error> {
+        if let Some(builder) = self.builder.as_mut() {
+            builder.set_relay(connection.get_relay());
+            self.shared = Some(connection);
+            Ok(self)
+        } else {
+            Err(Error::AlreadyConnected)
+        }
+    }
+
+    /// Set the relay url or the shared connection of this client, see
+    ///   [`NostrClient::relay()`] & [`NostrClient::shared()`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client is already connected
+    ///   to some relays.
+    pub fn connection(self, connection: Connection) -> Result<Self, Error> {
+        match connection {
+            Connection::Dedicated(url) => self.relay(url),
+            Connection::Shared(connection) => self.shared(connection),
+        }
+    }
+
     /// Set the nostr key pair of this client.
     ///
     /// # Errors
@@ -81,7 +157,12 @@ impl NostrClient {
     ///   - suscribing to NIP04 Dms fails
     pub fn connect_nostr(&mut self) -> Result<(), Error> {
         if let Some(builder) = self.builder.take() {
-            let mut client = builder.connect()?;
+            let mut client = match self.shared.take() {
+                Some(shared) => {
+                    shared.client(builder.get_keys().ok_or(Error::KeysMissing)?.clone())?
+                }
+                None => builder.connect()?,
+            };
             client.subscribe_dm()?;
             self.client = Some(client);
             Ok(())
@@ -99,6 +180,16 @@ impl NostrClient {
         }
     }
 
+    /// Returns a [`Waiter`] that allow to block until the client receive an
+    ///   event, instead of busy polling it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if not connected.
+    pub fn waiter(&mut self) -> Result<Waiter, Error> {
+        Ok(self.client()?.waiter())
+    }
+
     /// Returns a ref to [`NostrClient::client`]
     ///
     /// # Errors
@@ -171,6 +262,35 @@ impl NostrClient {
         self.send_dm(npub, clear_content)
     }
 
+    /// Send a [`PoolMessage`] wrapped into a NIP04 encrypted DM w/ a NIP-13
+    ///   proof of work, see [`NostrClient::send_pool_message()`].
+    ///
+    /// # Arguments
+    /// * `npub` - nostr pubkey of the pool
+    /// * `msg` - the PoolMessage to send
+    /// * `difficulty` - the min number of leading zero bits of the event id
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the message cannot be serialized into String json payload
+    ///   - sending the DM fails
+    pub fn send_pool_message_with_pow(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        difficulty: u8,
+    ) -> Result<(), Error> {
+        let clear_content = msg.to_string()?;
+        log::debug!(
+            "NostrClient.send_pool_message_with_pow({difficulty}): {:#?}",
+            clear_content
+        );
+        self.client()?
+            .send_dm_with_pow(clear_content, npub, difficulty)?;
+        Ok(())
+    }
+
     /// Subscribe to notifications of NIP04 DMs thatare send tu the client pubkey
     ///
     /// # Errors
@@ -231,14 +351,29 @@ impl NostrClient {
     ///   - the the received event is not a NIP04
     ///   - the event cannot be parsed as a PoolMessage
     pub fn try_receive_pool_msg(&mut self) -> Result<Option<PoolMessage>, Error> {
+        Ok(self.try_receive_pool_msg_with_pow()?.map(|(msg, _)| msg))
+    }
+
+    /// Same as [`NostrClient::try_receive_pool_msg()`], but also returns the
+    ///   NIP-13 proof of work (leading zero bits of the event id) of the
+    ///   message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the client is not connected
+    ///   - the channel is closed
+    ///   - the received event is not a NIP04
+    pub fn try_receive_pool_msg_with_pow(&mut self) -> Result<Option<(PoolMessage, u8)>, Error> {
         Ok(if let Some(event) = self.client()?.try_receive()? {
+            let pow = nip13::get_leading_zero_bits(event.id.as_bytes());
             PoolMessage::from_str(&event.content).ok().map(|m| {
                 // if the join request does not contain a pubkey to respond to, we respond to
                 // sender
                 if let PoolMessage::Join(None) = m {
-                    PoolMessage::Join(Some(event.pubkey))
+                    (PoolMessage::Join(Some(event.pubkey)), pow)
                 } else {
-                    m
+                    (m, pow)
                 }
             })
         } else {
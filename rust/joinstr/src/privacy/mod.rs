@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+
+use miniscript::bitcoin::{Amount, OutPoint, ScriptBuf, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::signer::Coin;
+
+/// Privacy analysis of a single coin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyScore {
+    /// Number of outputs of the transaction that created the coin having the
+    ///   same value as the coin (1 if the coin value is unique), capped by
+    ///   the number of participants, see [`PrivacyAnalyzer::add_spks()`]
+    pub anonset: usize,
+    /// The coin is the change of a coinjoin/tx0: its value is unique in a
+    ///   transaction having several equal outputs, it's linkable to its inputs
+    pub toxic_change: bool,
+    /// Another output of the wallet history pay to the same script
+    pub address_reuse: bool,
+    /// The transaction that created the coin spend several mixed coins
+    ///   together, linking them
+    pub mixed_consolidation: bool,
+    /// The transaction that created the coin is unknown, the score cannot
+    ///   be processed
+    pub unknown: bool,
+}
+
+impl PrivacyScore {
+    /// Returns true if the coin have been mixed and nothing breaks its
+    ///   anonymity set.
+    pub fn is_mixed(&self) -> bool {
+        !self.unknown
+            && self.anonset > 1
+            && !self.toxic_change
+            && !self.address_reuse
+            && !self.mixed_consolidation
+    }
+}
+
+/// A coin along with its privacy analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredCoin {
+    pub coin: Coin,
+    pub score: PrivacyScore,
+}
+
+/// Returns the number of outputs of `tx` that have the same value as the
+///   output at `vout`, returns 0 if `vout` is out of bounds.
+pub fn anonset(tx: &Transaction, vout: u32) -> usize {
+    match tx.output.get(vout as usize) {
+        Some(out) => tx.output.iter().filter(|o| o.value == out.value).count(),
+        None => 0,
+    }
+}
+
+/// Returns the value of the larger set of equal outputs of the transaction
+///   if there is at least 2 equal outputs.
+pub fn denomination(tx: &Transaction) -> Option<Amount> {
+    let mut counts = HashMap::<Amount, usize>::new();
+    for o in &tx.output {
+        *counts.entry(o.value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .max_by_key(|(value, count)| (*count, *value))
+        .map(|(value, _)| value)
+}
+
+/// Returns true if the output at `vout` is the change of a transaction
+///   that have equal outputs (coinjoin or tx0).
+pub fn is_toxic_change(tx: &Transaction, vout: u32) -> bool {
+    match (tx.output.get(vout as usize), denomination(tx)) {
+        (Some(_), Some(_)) => anonset(tx, vout) == 1,
+        _ => false,
+    }
+}
+
+/// Analyze the privacy of wallet coins from the transactions that created
+///   them.
+#[derive(Debug, Clone, Default)]
+pub struct PrivacyAnalyzer {
+    txs: HashMap<Txid, Transaction>,
+    // spks of the wallet, used to tell our inputs apart
+    spks: HashSet<ScriptBuf>,
+}
+
+impl PrivacyAnalyzer {
+    /// Create a new [`PrivacyAnalyzer`] instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a transaction to the analyzer, the transactions of the history
+    ///   of the wallet spks should be added, including the ones that created
+    ///   spent coins.
+    pub fn add_tx(&mut self, tx: Transaction) {
+        self.txs.insert(tx.compute_txid(), tx);
+    }
+
+    /// Add several transactions to the analyzer.
+    pub fn add_txs<I: IntoIterator<Item = Transaction>>(&mut self, txs: I) {
+        for tx in txs {
+            self.add_tx(tx);
+        }
+    }
+
+    /// Add the spks of the wallet to the analyzer, the inputs spending them
+    ///   are not counted as mix participants: the equal outputs of a tx0
+    ///   spending only wallet coins are not mixed.
+    pub fn add_spks<I: IntoIterator<Item = ScriptBuf>>(&mut self, spks: I) {
+        self.spks.extend(spks);
+    }
+
+    /// Returns true if the output pays to a spk of the wallet.
+    fn is_owned(&self, outpoint: &OutPoint) -> bool {
+        self.txs
+            .get(&outpoint.txid)
+            .and_then(|tx| tx.output.get(outpoint.vout as usize))
+            .is_some_and(|o| self.spks.contains(&o.script_pubkey))
+    }
+
+    /// Returns the anonset of the output at `vout`, see [`anonset()`], capped
+    ///   by the number of participants: the distinct foreign inputs, plus one
+    ///   if the wallet spends some inputs. A transaction spending only
+    ///   wallet coins is not a mix, its outputs have an anonset of 1.
+    fn mix_anonset(&self, tx: &Transaction, vout: u32) -> usize {
+        let equal = anonset(tx, vout);
+        let (owned, foreign): (Vec<_>, Vec<_>) = tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .partition(|o| self.is_owned(o));
+        let foreign = foreign.into_iter().collect::<HashSet<_>>().len();
+        if foreign == 0 {
+            return equal.min(1);
+        }
+        equal.min(foreign + usize::from(!owned.is_empty()))
+    }
+
+    /// Returns true if the output have been mixed.
+    fn is_mixed_output(&self, outpoint: &OutPoint) -> bool {
+        match self.txs.get(&outpoint.txid) {
+            Some(tx) => self.mix_anonset(tx, outpoint.vout) > 1,
+            None => false,
+        }
+    }
+
+    /// Returns true if another output of the known transactions, or another
+    ///   coin, pays to the spk of `coin`.
+    fn is_reused(&self, coin: &Coin, coins: &[Coin]) -> bool {
+        let spk = &coin.txout.script_pubkey;
+        coins
+            .iter()
+            .any(|c| c.outpoint != coin.outpoint && c.txout.script_pubkey == *spk)
+            || self.txs.iter().any(|(txid, tx)| {
+                tx.output.iter().enumerate().any(|(vout, o)| {
+                    o.script_pubkey == *spk && OutPoint::new(*txid, vout as u32) != coin.outpoint
+                })
+            })
+    }
+
+    /// Process the privacy score of a coin.
+    ///
+    /// # Arguments
+    /// * `coin` - The coin to analyze
+    /// * `coins` - All the coins of the wallet, used w/ the known transactions
+    ///   to detect address reuse
+    pub fn score(&self, coin: &Coin, coins: &[Coin]) -> PrivacyScore {
+        let address_reuse = self.is_reused(coin, coins);
+        let tx = match self.txs.get(&coin.outpoint.txid) {
+            Some(tx) => tx,
+            None => {
+                return PrivacyScore {
+                    anonset: 1,
+                    address_reuse,
+                    unknown: true,
+                    ..Default::default()
+                }
+            }
+        };
+        let mixed_inputs = tx
+            .input
+            .iter()
+            .filter(|i| self.is_mixed_output(&i.previous_output))
+            .count();
+        // a coinjoin spend several mixed coins, but it's not a consolidation
+        let mixed_consolidation = mixed_inputs > 1 && denomination(tx).is_none();
+
+        PrivacyScore {
+            anonset: self.mix_anonset(tx, coin.outpoint.vout),
+            toxic_change: is_toxic_change(tx, coin.outpoint.vout),
+            address_reuse,
+            mixed_consolidation,
+            unknown: false,
+        }
+    }
+
+    /// Process the privacy score of all the coins.
+    pub fn score_coins(&self, coins: &[Coin]) -> Vec<ScoredCoin> {
+        coins
+            .iter()
+            .map(|coin| ScoredCoin {
+                coin: coin.clone(),
+                score: self.score(coin, coins),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miniscript::bitcoin::{
+        absolute, hashes::Hash, transaction::Version, Network, Sequence, TxIn, TxOut,
+    };
+
+    use super::*;
+    use crate::signer::{CoinPath, WpkhHotSigner};
+
+    fn tx(inputs: Vec<OutPoint>, outputs: Vec<(u64, ScriptBuf)>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(value, script_pubkey)| TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    // an outpoint that does not belong to the wallet
+    fn foreign(i: u8) -> OutPoint {
+        OutPoint::new(Txid::from_raw_hash(Hash::from_byte_array([i; 32])), 0)
+    }
+
+    fn coin(tx: &Transaction, vout: u32, coin_path: CoinPath) -> Coin {
+        Coin {
+            txout: tx.output[vout as usize].clone(),
+            outpoint: OutPoint {
+                txid: tx.compute_txid(),
+                vout,
+            },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            coin_path,
+        }
+    }
+
+    #[test]
+    fn score_coinjoin_outputs() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let spk = |i| signer.spk_at(&CoinPath::new(0, i)).unwrap();
+
+        // coinjoin w/ 3 equal outputs & a change
+        let coinjoin = tx(
+            vec![foreign(1), foreign(2), foreign(3)],
+            vec![
+                (100_000, spk(0)),
+                (100_000, spk(1)),
+                (100_000, spk(2)),
+                (50_000, spk(3)),
+            ],
+        );
+        let mixed_a = coin(&coinjoin, 0, CoinPath::new(0, 0));
+        let mixed_b = coin(&coinjoin, 1, CoinPath::new(0, 1));
+        let change = coin(&coinjoin, 3, CoinPath::new(0, 3));
+
+        // consolidate the 2 mixed coins, reusing an address
+        let consolidation = tx(
+            vec![mixed_a.outpoint, mixed_b.outpoint],
+            vec![(199_000, spk(0))],
+        );
+        let consolidated = coin(&consolidation, 0, CoinPath::new(0, 0));
+
+        let mut analyzer = PrivacyAnalyzer::new();
+        analyzer.add_txs([coinjoin, consolidation]);
+
+        let coins = vec![mixed_b.clone(), change.clone(), consolidated.clone()];
+        let scored = analyzer.score_coins(&coins);
+
+        assert_eq!(scored[0].score.anonset, 3);
+        assert!(scored[0].score.is_mixed());
+
+        assert_eq!(scored[1].score.anonset, 1);
+        assert!(scored[1].score.toxic_change);
+        assert!(!scored[1].score.is_mixed());
+
+        assert!(scored[2].score.mixed_consolidation);
+        assert!(!scored[2].score.toxic_change);
+
+        // mixed_a & consolidated pay to the same spk
+        let score = analyzer.score(&mixed_a, &[mixed_a.clone(), consolidated]);
+        assert!(score.address_reuse);
+        assert!(!score.is_mixed());
+        // the reuse is detected from the history even if mixed_a is spent
+        assert!(analyzer.score(&consolidated, &[]).address_reuse);
+    }
+
+    #[test]
+    fn score_tx0_outputs() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let spk = |i| signer.spk_at(&CoinPath::new(0, i)).unwrap();
+
+        let deposit = tx(vec![foreign(1)], vec![(300_000, spk(0))]);
+        let deposit_coin = coin(&deposit, 0, CoinPath::new(0, 0));
+        // tx0 split the deposit coin in equal premix outputs & a change
+        let tx0 = tx(
+            vec![deposit_coin.outpoint],
+            vec![(100_000, spk(1)), (100_000, spk(2)), (90_000, spk(3))],
+        );
+        let premix = coin(&tx0, 0, CoinPath::new(0, 1));
+        // a coinjoin between the premix coin & 2 foreign coins
+        let coinjoin = tx(
+            vec![premix.outpoint, foreign(2), foreign(3)],
+            vec![
+                (99_000, spk(4)),
+                (99_000, ScriptBuf::new()),
+                (99_000, ScriptBuf::new()),
+                (99_000, ScriptBuf::new()),
+            ],
+        );
+        let postmix = coin(&coinjoin, 0, CoinPath::new(0, 4));
+
+        let mut analyzer = PrivacyAnalyzer::new();
+        analyzer.add_txs([deposit, tx0, coinjoin]);
+        analyzer.add_spks((0..5).map(spk));
+
+        let score = analyzer.score(&premix, &[]);
+        assert_eq!(score.anonset, 1);
+        assert!(!score.is_mixed());
+
+        // 4 equal outputs but only 3 participants
+        let score = analyzer.score(&postmix, &[]);
+        assert_eq!(score.anonset, 3);
+        assert!(score.is_mixed());
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    Joinstr(crate::joinstr::Error),
+    Interface(crate::interface::Error),
+    Signer(crate::signer::Error),
+    Electrum(crate::electrum::Error),
+    MissingFinalTx,
+    OutputNotFound,
+    CoinNotTracked,
+    AlreadyRunning,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Joinstr(e) => write!(f, "Joinstr error: {:?}", e),
+            Error::Interface(e) => write!(f, "{}", e),
+            Error::Signer(e) => write!(f, "{}", e),
+            Error::Electrum(e) => write!(f, "{}", e),
+            Error::MissingFinalTx => write!(f, "The coinjoin have not been finalized"),
+            Error::OutputNotFound => write!(f, "Our output is missing in the coinjoin tx"),
+            Error::CoinNotTracked => write!(f, "The coin is not tracked by the remixer"),
+            Error::AlreadyRunning => write!(f, "A remix round is already running"),
+        }
+    }
+}
+
+impl From<crate::joinstr::Error> for Error {
+    fn from(value: crate::joinstr::Error) -> Self {
+        Error::Joinstr(value)
+    }
+}
+
+impl From<crate::interface::Error> for Error {
+    fn from(value: crate::interface::Error) -> Self {
+        Error::Interface(value)
+    }
+}
+
+impl From<crate::signer::Error> for Error {
+    fn from(value: crate::signer::Error) -> Self {
+        Error::Signer(value)
+    }
+}
+
+impl From<crate::electrum::Error> for Error {
+    fn from(value: crate::electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
@@ -0,0 +1,583 @@
+mod error;
+pub use error::Error;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use miniscript::bitcoin::{Amount, Network, OutPoint, Sequence, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coin_selection::min_input_value,
+    electrum::Client,
+    interface::list_pools,
+    joinstr::{Joinstr, State},
+    nostr::{Fee, Pool, Timeline},
+    signer::{Account, Coin, CoinPath, WpkhHotSigner},
+    utils::now,
+};
+
+/// Default max value a coin can lose as mining fee, beyond its fee share,
+///   when remixed in a pool of lower denomination, see [`find_pool()`].
+pub const DEFAULT_MAX_LOSS: Amount = Amount::from_sat(10_000);
+
+/// A coin tracked by the [`Remixer`] and its remix counters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemixCoin {
+    pub coin: Coin,
+    /// How many coinjoins this coin went through since tracked
+    pub remixes: usize,
+    /// Cumulative anonymity set: the sum of the peers count of every
+    ///   coinjoin this coin went through
+    pub anonset: usize,
+}
+
+/// The remix round currently running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRemix {
+    /// The coin registered as input
+    pub input: OutPoint,
+    /// The derivation path of the address registered as output
+    pub output: CoinPath,
+    /// The peers count of the pool
+    pub peers: usize,
+    /// The state of the [`Joinstr`] instance
+    pub round: Option<State>,
+}
+
+/// Serializable state of a [`Remixer`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemixerState {
+    pub coins: Vec<RemixCoin>,
+    pub next_index: u32,
+    pub pending: Option<PendingRemix>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Remixer {
+    pub inner: Arc<Mutex<RemixerInner>>,
+}
+
+#[derive(Debug)]
+pub struct RemixerInner {
+    signer: WpkhHotSigner,
+    relay: String,
+    electrum: (String, u16),
+    network: Network,
+    // the derivation branch of the postmix account post-coinjoin outputs
+    // are sent to
+    depth: u32,
+    // next derivation index used for a coinjoin output
+    next_index: u32,
+    // target anonymity set per coin
+    target: usize,
+    // max value a coin can lose as mining fee, see [`find_pool()`]
+    max_loss: Amount,
+    // how many seconds back we look for pools
+    back: u64,
+    // how long we wait for pools notifications, see [`list_pools()`]
+    timeout: u64,
+    coins: Vec<RemixCoin>,
+    pending: Option<(PendingRemix, Joinstr<'static>)>,
+}
+
+impl Remixer {
+    /// Create a new [`Remixer`].
+    ///
+    /// # Arguments
+    /// * `signer` - The signer owning the coins to remix
+    /// * `relay` - The relay used to discover & join pools
+    /// * `electrum_server` - A tuple (<address>, <port>)
+    /// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+    pub fn new(
+        signer: WpkhHotSigner,
+        relay: String,
+        electrum_server: (&str, u16),
+        network: Network,
+    ) -> Self {
+        let inner = RemixerInner {
+            signer,
+            relay,
+            electrum: (electrum_server.0.to_string(), electrum_server.1),
+            network,
+            depth: 0,
+            next_index: 0,
+            target: 5,
+            max_loss: DEFAULT_MAX_LOSS,
+            back: 60 * 60,
+            timeout: 2_000_000,
+            coins: Vec::new(),
+            pending: None,
+        };
+        Remixer {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Restart a [`Remixer`] from a previously saved [`RemixerState`].
+    ///
+    /// Note: if the pending round have been finalized, its output is tracked
+    ///   in place of its input, otherwise the round is dropped and the input
+    ///   will be registered again later.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pending round has been
+    ///   finalized but our output is missing from the transaction.
+    pub fn restore(
+        state: RemixerState,
+        signer: WpkhHotSigner,
+        relay: String,
+        electrum_server: (&str, u16),
+        network: Network,
+    ) -> Result<Self, Error> {
+        let remixer = Self::new(signer, relay, electrum_server, network);
+        let mut inner = remixer.inner.lock().expect("poisoned");
+        inner.coins = state.coins;
+        inner.next_index = state.next_index;
+        if let Some(pending) = state.pending {
+            if let Some(tx) = pending.round.as_ref().and_then(|r| r.final_tx.clone()) {
+                inner.apply_remix(&pending, &tx)?;
+            }
+        }
+        drop(inner);
+        Ok(remixer)
+    }
+
+    /// Set the anonymity set each coin should reach before the remixer stops
+    ///   remixing it.
+    pub fn target(self, target: usize) -> Self {
+        self.inner.lock().expect("poisoned").target = target;
+        self
+    }
+
+    /// Set the max value a coin can lose as mining fee, beyond its fee share,
+    ///   when remixed, default to [`DEFAULT_MAX_LOSS`], see [`find_pool()`].
+    pub fn max_loss(self, max_loss: Amount) -> Self {
+        self.inner.lock().expect("poisoned").max_loss = max_loss;
+        self
+    }
+
+    /// Set the derivation branch of the postmix account & the first index
+    ///   the coinjoin outputs are sent to.
+    pub fn outputs_at(self, depth: u32, next_index: u32) -> Self {
+        let mut inner = self.inner.lock().expect("poisoned");
+        inner.depth = depth;
+        inner.next_index = next_index;
+        drop(inner);
+        self
+    }
+
+    /// Set how many seconds back in time we look for pools
+    pub fn pools_lookback(self, back: u64) -> Self {
+        self.inner.lock().expect("poisoned").back = back;
+        self
+    }
+
+    /// Track a post-coinjoin coin.
+    pub fn add_coin(&mut self, coin: Coin) {
+        let mut inner = self.inner.lock().expect("poisoned");
+        if !inner.coins.iter().any(|c| c.coin.outpoint == coin.outpoint) {
+            inner.coins.push(RemixCoin {
+                coin,
+                remixes: 0,
+                anonset: 0,
+            });
+        }
+    }
+
+    /// Returns a copy of the tracked coins.
+    pub fn coins(&self) -> Vec<RemixCoin> {
+        self.inner.lock().expect("poisoned").coins.clone()
+    }
+
+    /// Returns the tracked coins that did not reach the target anonymity set.
+    pub fn pending_coins(&self) -> Vec<RemixCoin> {
+        let inner = self.inner.lock().expect("poisoned");
+        inner
+            .coins
+            .iter()
+            .filter(|c| c.anonset < inner.target)
+            .cloned()
+            .collect()
+    }
+
+    /// Scan the postmix outputs derivation branch for unspent coins and track them,
+    ///   returns the number of coins added.
+    ///
+    /// # Arguments
+    /// * `range` - The range of derivation indexes to scan
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an electrum request fails.
+    pub fn scan(&mut self, range: (u32, u32)) -> Result<usize, Error> {
+        let inner = self.inner.lock().expect("poisoned");
+        let (url, port) = inner.electrum.clone();
+        let depth = inner.depth;
+        let signer = inner.signer.clone();
+        drop(inner);
+
+        let mut client = Client::new(&url, port)?;
+        let mut found = Vec::new();
+        for index in range.0..range.1 {
+            let coin_path = CoinPath::with_account(Account::Postmix, depth, index);
+            let spk = signer.spk_at(&coin_path)?;
+            let (coins, txs) = client.get_coins_at(&spk)?;
+            for (txout, outpoint) in coins {
+                let spent = txs
+                    .values()
+                    .any(|tx| tx.input.iter().any(|i| i.previous_output == outpoint));
+                if !spent {
+                    found.push((
+                        index,
+                        Coin {
+                            txout,
+                            outpoint,
+                            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                            coin_path,
+                        },
+                    ));
+                }
+            }
+        }
+
+        let mut inner = self.inner.lock().expect("poisoned");
+        let mut count = 0;
+        for (index, coin) in found {
+            if index >= inner.next_index {
+                inner.next_index = index + 1;
+            }
+            if !inner.coins.iter().any(|c| c.coin.outpoint == coin.outpoint) {
+                inner.coins.push(RemixCoin {
+                    coin,
+                    remixes: 0,
+                    anonset: 0,
+                });
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the current state of the remixer, including the state of the
+    ///   running round if any.
+    pub fn state(&self) -> RemixerState {
+        let inner = self.inner.lock().expect("poisoned");
+        let pending = inner.pending.as_ref().map(|(pending, joinstr)| {
+            let mut pending = pending.clone();
+            pending.round = joinstr.state();
+            pending
+        });
+        RemixerState {
+            coins: inner.coins.clone(),
+            next_index: inner.next_index,
+            pending,
+        }
+    }
+
+    /// Try to remix one of the coins that did not reach the target anonymity
+    ///   set: look for a matching pool, join it and wait the coinjoin to be
+    ///   finalized. Returns None if there is no coin to remix or no matching
+    ///   pool.
+    ///
+    /// # Arguments
+    /// * `notif` - A callback function called every time the round state is updated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - a round is already running
+    ///   - fails to list pools
+    ///   - the coinjoin fails
+    pub fn remix_once<N>(&mut self, notif: N) -> Result<Option<Txid>, Error>
+    where
+        N: Fn(),
+    {
+        let inner = self.inner.lock().expect("poisoned");
+        if inner.pending.is_some() {
+            return Err(Error::AlreadyRunning);
+        }
+        let candidates: Vec<_> = inner
+            .coins
+            .iter()
+            .filter(|c| c.anonset < inner.target)
+            .map(|c| c.coin.clone())
+            .collect();
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        let (relay, back, timeout) = (inner.relay.clone(), inner.back, inner.timeout);
+        drop(inner);
+
+        let pools = list_pools(back, timeout, relay)?;
+
+        let inner = self.inner.lock().expect("poisoned");
+        let (network, max_loss) = (inner.network, inner.max_loss);
+        let (coin, pool) = match candidates
+            .into_iter()
+            .find_map(|c| find_pool(&c, &pools, network, max_loss).map(|p| (c, p)))
+        {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let peers = pool.payload.as_ref().map(|p| p.peers).unwrap_or_default();
+
+        // NOTE: next_index is only advanced once the round is broadcast (see
+        // RemixerInner::apply_remix()), the output of a failed round is unused
+        // and reused by the next round, so we never go beyond the gap limit.
+        let output = CoinPath::with_account(Account::Postmix, inner.depth, inner.next_index);
+        let address = inner.signer.address_at(&output)?;
+        let signer = inner.signer.clone();
+        let (url, port) = inner.electrum.clone();
+        let relay = inner.relay.clone();
+        drop(inner);
+
+        let mut joinstr = Joinstr::new_peer_with_electrum(
+            relay,
+            &pool,
+            (&url, port),
+            coin.clone(),
+            address.as_unchecked().clone(),
+            network,
+            "remixer",
+        )?;
+        let pending = PendingRemix {
+            input: coin.outpoint,
+            output,
+            peers,
+            round: None,
+        };
+        let mut inner = self.inner.lock().expect("poisoned");
+        // another round may have been started while we were connecting
+        if inner.pending.is_some() {
+            return Err(Error::AlreadyRunning);
+        }
+        inner.pending = Some((pending.clone(), joinstr.clone()));
+        drop(inner);
+
+        log::debug!(
+            "Remixer::remix_once() join pool {} w/ coin {}",
+            pool.id,
+            coin.outpoint
+        );
+        let result = joinstr.start_coinjoin_blocking(Some(pool), Some(signer), notif);
+
+        let mut inner = self.inner.lock().expect("poisoned");
+        inner.pending = None;
+        result?;
+        let tx = joinstr.final_tx().ok_or(Error::MissingFinalTx)?;
+        inner.apply_remix(&pending, &tx)?;
+        Ok(Some(tx.compute_txid()))
+    }
+
+    /// Remix coins until all of them reach the target anonymity set or
+    ///   `stop` is set.
+    ///
+    /// # Arguments
+    /// * `interval` - How long we wait before looking for pools again if no
+    ///   matching pool found or if a round fails.
+    /// * `stop` - Flag to stop the remixer, checked between rounds.
+    /// * `notif` - A callback function called every time the round state is updated.
+    pub fn run_blocking<N>(&mut self, interval: Duration, stop: Arc<AtomicBool>, notif: N)
+    where
+        N: Fn(),
+    {
+        while !stop.load(Ordering::Relaxed) && !self.pending_coins().is_empty() {
+            match self.remix_once(&notif) {
+                Ok(Some(txid)) => log::info!("Remixer::run_blocking() coinjoin {txid} done!"),
+                Ok(None) => thread::sleep(interval),
+                Err(e) => {
+                    log::error!("Remixer::run_blocking() remix fails: {}", e);
+                    thread::sleep(interval);
+                }
+            }
+        }
+    }
+}
+
+impl RemixerInner {
+    /// Replace the remixed coin by the coinjoin output and update its counters.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the input coin is not tracked
+    ///   - our output is missing from the transaction
+    fn apply_remix(&mut self, pending: &PendingRemix, tx: &Transaction) -> Result<(), Error> {
+        let spk = self.signer.spk_at(&pending.output)?;
+        let (vout, txout) = tx
+            .output
+            .iter()
+            .enumerate()
+            .find(|(_, o)| o.script_pubkey == spk)
+            .ok_or(Error::OutputNotFound)?;
+        let remixed = self
+            .coins
+            .iter_mut()
+            .find(|c| c.coin.outpoint == pending.input)
+            .ok_or(Error::CoinNotTracked)?;
+        remixed.coin = Coin {
+            txout: txout.clone(),
+            outpoint: OutPoint {
+                txid: tx.compute_txid(),
+                vout: vout as u32,
+            },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            coin_path: pending.output,
+        };
+        remixed.remixes += 1;
+        remixed.anonset += pending.peers;
+        if pending.output.index.unwrap_or_default() >= self.next_index {
+            self.next_index = pending.output.index.unwrap_or_default() + 1;
+        }
+        Ok(())
+    }
+}
+
+/// Find the best pool to remix the coin to: among the pools the coin can
+///   pay for, the one with the highest denomination.
+///
+/// Note: a post-coinjoin coin is worth exactly the denomination of its pool,
+///   it cannot pay its fee share in a pool of the same denomination and can
+///   only be remixed in a pool of lower denomination, any value above
+///   [`min_input_value()`] being lost as mining fee. The pools where the coin
+///   would lose more than `max_loss` are skipped, a coin that cannot join any
+///   pool stays pending until a matching pool is announced.
+///
+/// # Arguments
+/// * `coin` - The coin to remix
+/// * `pools` - The pools to choose from
+/// * `network` - The bitcoin network (bitcoin/testnet/signet/regtest)
+/// * `max_loss` - The max value the coin can lose as mining fee, beyond its
+///   fee share
+pub fn find_pool(coin: &Coin, pools: &[Pool], network: Network, max_loss: Amount) -> Option<Pool> {
+    let now = now();
+    pools
+        .iter()
+        .filter(|p| p.network == network)
+        .filter_map(|p| {
+            let payload = p.payload.as_ref()?;
+            let fee = match payload.fee {
+                Fee::Fixed(fee) => fee,
+                Fee::Provider(_) => return None,
+            };
+            match payload.timeout {
+                Timeline::Simple(t) if t > now => {}
+                _ => return None,
+            }
+            let loss = coin
+                .txout
+                .value
+                .checked_sub(min_input_value(payload.denomination, fee))?;
+            (loss <= max_loss).then_some((payload.denomination, p))
+        })
+        .max_by_key(|(denomination, _)| *denomination)
+        .map(|(_, p)| p.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use miniscript::bitcoin::{Amount, TxOut};
+    use simple_nostr_client::nostr::Keys;
+
+    use super::*;
+
+    fn pool(denomination: u64, fee: u32, network: Network) -> Pool {
+        Pool::create(
+            "ws://127.0.0.1".into(),
+            denomination,
+            5,
+            60,
+            fee,
+            network,
+            Keys::generate().public_key(),
+        )
+    }
+
+    #[test]
+    fn find_matching_pool() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let coin_path = CoinPath::new(0, 0);
+        let coin = Coin {
+            txout: TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: signer.spk_at(&coin_path).unwrap(),
+            },
+            outpoint: OutPoint {
+                txid: Txid::from_str(
+                    "000000000000000000032aea06ce8a8dd70127e86382b5ea68c7d810e8dbfc9b",
+                )
+                .unwrap(),
+                vout: 0,
+            },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            coin_path,
+        };
+
+        let pools = vec![
+            // denomination too high
+            pool(100_000, 1, Network::Regtest),
+            // wrong network
+            pool(90_000, 1, Network::Signet),
+            pool(50_000, 1, Network::Regtest),
+            pool(90_000, 1, Network::Regtest),
+        ];
+        let selected = find_pool(&coin, &pools, Network::Regtest, DEFAULT_MAX_LOSS).unwrap();
+        assert_eq!(selected, pools[3]);
+
+        assert!(find_pool(&coin, &pools[..2], Network::Regtest, DEFAULT_MAX_LOSS).is_none());
+
+        // the coin would lose ~50k sats in the 50k pool
+        assert!(find_pool(&coin, &pools[2..3], Network::Regtest, DEFAULT_MAX_LOSS).is_none());
+        let selected = find_pool(
+            &coin,
+            &pools[2..3],
+            Network::Regtest,
+            Amount::from_sat(50_000),
+        )
+        .unwrap();
+        assert_eq!(selected, pools[2]);
+    }
+
+    #[test]
+    fn postmix_coin_skips_its_own_pool() {
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let coin_path = CoinPath::with_account(Account::Postmix, 0, 0);
+        let coin = Coin {
+            txout: TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: signer.spk_at(&coin_path).unwrap(),
+            },
+            outpoint: OutPoint {
+                txid: Txid::from_str(
+                    "000000000000000000032aea06ce8a8dd70127e86382b5ea68c7d810e8dbfc9b",
+                )
+                .unwrap(),
+                vout: 1,
+            },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            coin_path,
+        };
+
+        // the coin cannot pay its fee share in the pool it comes from
+        let pools = vec![pool(100_000, 1, Network::Regtest)];
+        assert!(find_pool(&coin, &pools, Network::Regtest, DEFAULT_MAX_LOSS).is_none());
+
+        // but can join a pool whose denomination leaves room for it
+        let pools = vec![
+            pool(100_000, 1, Network::Regtest),
+            pool(99_000, 1, Network::Regtest),
+        ];
+        let selected = find_pool(&coin, &pools, Network::Regtest, DEFAULT_MAX_LOSS).unwrap();
+        assert_eq!(selected, pools[1]);
+    }
+}
@@ -11,8 +11,13 @@ pub enum Error {
     CoinPath,
     XPrivFromSeed,
     Derivation,
+    CoinMissing,
     Bip39(bip39::Error),
     Electrum(crate::electrum::Error),
+    Backend(crate::backend::Error),
+    Sync(String),
+    SyncTimeout,
+    SyncStopped,
 }
 
 impl Display for Error {
@@ -22,13 +27,18 @@ impl Display for Error {
             Error::SighashFail => write!(f, "Sighash id not SIGHASH_ALL | SIGHASH_ANYONE_CAN_PAY"),
             Error::InvalidSignature => write!(f, "Signature processed is invalid"),
             Error::InvalidTransaction => write!(f, "Fail to create PSBT from unsigned transaction"),
-            Error::NoElectrumClient => write!(f, "There is no electrum client provided"),
+            Error::NoElectrumClient => write!(f, "There is no bitcoin backend provided"),
             Error::CoinPathWithoutIndex => write!(f, "Invalid CoinPath provided: index is missing"),
             Error::CoinPath => write!(f, "Wrong CoinPath"),
             Error::Electrum(e) => write!(f, "{}", e),
+            Error::Backend(e) => write!(f, "{}", e),
             Error::Bip39(e) => write!(f, "{}", e),
             Error::XPrivFromSeed => write!(f, "Fail to generate XPriv from seed"),
             Error::Derivation => write!(f, "Derivation fails"),
+            Error::CoinMissing => write!(f, "No coin provided for a transaction input"),
+            Error::Sync(e) => write!(f, "Wallet sync fails: {}", e),
+            Error::SyncTimeout => write!(f, "Wallet sync timed out"),
+            Error::SyncStopped => write!(f, "Wallet sync thread has stopped"),
         }
     }
 }
@@ -39,6 +49,15 @@ impl From<crate::electrum::Error> for Error {
     }
 }
 
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        match value {
+            crate::backend::Error::Electrum(e) => Error::Electrum(e),
+            e => Error::Backend(e),
+        }
+    }
+}
+
 impl From<bip39::Error> for Error {
     fn from(value: bip39::Error) -> Self {
         Error::Bip39(value)
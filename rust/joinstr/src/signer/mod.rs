@@ -1,17 +1,23 @@
 mod error;
+pub mod sync;
 pub use error::Error;
 use serde::{Deserialize, Serialize};
 
-use crate::{electrum::Client, nostr::InputDataSigned};
+use crate::{
+    backend::{self, ChainSource, SharedChainSource},
+    nostr::InputDataSigned,
+    privacy::{PrivacyAnalyzer, ScoredCoin},
+};
 use bip39::Mnemonic;
 use miniscript::{
     bitcoin::{
         bip32::{self, ChildNumber, DerivationPath, Fingerprint, Xpriv, Xpub},
         ecdsa,
+        hashes::Hash,
         psbt::{self, PsbtSighashType},
         secp256k1::{self, All},
         sighash, Address, CompressedPublicKey, EcdsaSighashType, Network, OutPoint, PrivateKey,
-        Psbt, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+        Psbt, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
     },
     descriptor::{DerivPaths, DescriptorMultiXKey, Wildcard},
     Descriptor, DescriptorPublicKey,
@@ -19,9 +25,54 @@ use miniscript::{
 use std::{collections::HashMap, fmt::Debug, str::FromStr};
 
 const MAX_DERIV: u32 = 2u32.pow(31) - 1;
+const DEFAULT_GAP_LIMIT: u32 = 20;
 
 pub trait JoinstrSigner {
     fn sign_input(&self, tx: &Transaction, input_data: Coin) -> Result<InputDataSigned, String>;
+
+    /// Returns a fresh address from the postmix account, used as coinjoin
+    ///   output if none have been provided.
+    fn postmix_address(&mut self) -> Option<Address> {
+        None
+    }
+}
+
+/// The accounts managed by the signer, every account have its own BIP84
+///   derivation path in order mixed & unmixed coins never land on the same
+///   account.
+#[derive(Debug, Default, Eq, Hash, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Account {
+    /// Coins received from outside
+    #[default]
+    Deposit,
+    /// Coins ready to be registered in a pool (tx0 outputs)
+    Premix,
+    /// Coinjoin outputs
+    Postmix,
+    /// Change of the tx0, linked to the deposit coins
+    Badbank,
+}
+
+impl Account {
+    /// Returns the (hardened) BIP84 account index
+    pub fn index(&self) -> u32 {
+        match self {
+            Account::Deposit => 0,
+            Account::Premix => MAX_DERIV - 2,
+            Account::Postmix => MAX_DERIV - 1,
+            Account::Badbank => MAX_DERIV - 3,
+        }
+    }
+
+    /// Returns all the accounts
+    pub fn all() -> [Account; 4] {
+        [
+            Account::Deposit,
+            Account::Premix,
+            Account::Postmix,
+            Account::Badbank,
+        ]
+    }
 }
 
 // S: JoinstrSigner + Sync + Clone + Send + 'static,
@@ -29,6 +80,8 @@ pub trait JoinstrSigner {
 pub struct WpkhHotSigner {
     #[allow(unused)]
     key: PrivateKey,
+    root_xpriv: Xpriv,
+    // xpriv of the deposit account
     master_xpriv: Xpriv,
     fingerprint: bip32::Fingerprint,
     secp: secp256k1::Secp256k1<All>,
@@ -36,7 +89,12 @@ pub struct WpkhHotSigner {
     secret_key: DescriptorMultiXKey<Xpriv>,
     network: Network,
     coins: HashMap<CoinPath, Vec<Coin>>,
-    client: Option<Client>,
+    // transactions that created our coins
+    txs: HashMap<Txid, Transaction>,
+    gap_limits: HashMap<Account, u32>,
+    // next unused index by (account, depth)
+    next_index: HashMap<(Account, u32), u32>,
+    client: Option<SharedChainSource>,
 }
 
 impl Debug for WpkhHotSigner {
@@ -55,13 +113,22 @@ pub struct Coin {
 
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct CoinPath {
+    #[serde(default)]
+    pub account: Account,
     pub depth: u32,
     pub index: Option<u32>,
 }
 
 impl CoinPath {
+    /// Create a new [`CoinPath`] on the deposit account.
     pub fn new(depth: u32, index: u32) -> Self {
+        Self::with_account(Account::Deposit, depth, index)
+    }
+
+    /// Create a new [`CoinPath`] on the given account.
+    pub fn with_account(account: Account, depth: u32, index: u32) -> Self {
         CoinPath {
+            account,
             depth,
             index: Some(index),
         }
@@ -73,11 +140,23 @@ pub fn descriptor(
     fg: &Fingerprint,
     multipath: u32,
     network: Network,
+) -> Descriptor<DescriptorPublicKey> {
+    account_descriptor(xpub, fg, Account::Deposit, multipath, network)
+}
+
+/// Returns the descriptor of the given account, `xpub` must be the
+///   account xpub.
+pub fn account_descriptor(
+    xpub: &Xpub,
+    fg: &Fingerprint,
+    account: Account,
+    multipath: u32,
+    network: Network,
 ) -> Descriptor<DescriptorPublicKey> {
     let descr_str = format!(
         "wpkh([{}{}]{}/{}/*)",
         fg,
-        deriv_path(network),
+        account_deriv_path(network, account),
         xpub,
         multipath
     );
@@ -92,6 +171,15 @@ pub fn deriv_path(network: Network) -> &'static str {
     }
 }
 
+/// Returns the derivation path of the given account.
+pub fn account_deriv_path(network: Network, account: Account) -> String {
+    let coin_type = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+    format!("/84'/{}'/{}'", coin_type, account.index())
+}
+
 impl WpkhHotSigner {
     /// Create a new [`WpkhHotSigner`] instance from the Xpriv key.
     ///
@@ -118,6 +206,7 @@ impl WpkhHotSigner {
 
         WpkhHotSigner {
             key: xprv.to_priv(),
+            root_xpriv: xpriv,
             master_xpriv: xprv,
             fingerprint,
             secp,
@@ -125,6 +214,9 @@ impl WpkhHotSigner {
             network,
             secret_key,
             coins: HashMap::new(),
+            txs: HashMap::new(),
+            gap_limits: HashMap::new(),
+            next_index: HashMap::new(),
             client: None,
         }
     }
@@ -157,24 +249,37 @@ impl WpkhHotSigner {
         Ok(signer)
     }
 
-    /// Set the electrum client to be used by the signer.
+    /// Set the chain source to be used by the signer.
     /// Note: the signer need a bitcoin backend client in coinjoin context
     ///   in order to verify amounts of the inputs of a transaction.
-    pub fn client(mut self, client: Client) -> Self {
+    pub fn client(mut self, client: impl ChainSource + 'static) -> Self {
         self.set_client(client);
         self
     }
 
-    /// Set the electrum client to be used by the signer.
+    /// Set the chain source to be used by the signer.
     /// Note: the signer need a bitcoin backend client in coinjoin context
     ///   in order to verify amounts of the inputs of a transaction.
-    pub fn set_client(&mut self, client: Client) {
+    pub fn set_client(&mut self, client: impl ChainSource + 'static) {
+        self.set_shared_client(backend::shared(client));
+    }
+
+    /// Set a chain source shared w/ other consumers (ex: a
+    ///   [`crate::joinstr::Joinstr`] instance).
+    ///
+    /// Note: clones of the signer share the same chain source.
+    pub fn set_shared_client(&mut self, client: SharedChainSource) {
         if self.client.is_none() {
             self.client = Some(client);
         }
     }
 
-    /// Remove the inner electrum client.
+    /// Returns the chain source of the signer, if any.
+    pub fn shared_client(&self) -> Option<SharedChainSource> {
+        self.client.clone()
+    }
+
+    /// Remove the inner chain source.
     pub fn drop_client(&mut self) {
         self.client = None;
     }
@@ -187,8 +292,14 @@ impl WpkhHotSigner {
     pub fn address_at(&self, coin_path: &CoinPath) -> Result<Address, Error> {
         if let Some(index) = coin_path.index {
             let fingerprint = self.fingerprint();
-            let xpub = Xpub::from_priv(self.secp(), &self.master_xpriv);
-            let descriptor = descriptor(&xpub, &fingerprint, coin_path.depth, self.network);
+            let xpub = Xpub::from_priv(self.secp(), &self.account_xpriv(coin_path.account)?);
+            let descriptor = account_descriptor(
+                &xpub,
+                &fingerprint,
+                coin_path.account,
+                coin_path.depth,
+                self.network,
+            );
             let definite = descriptor.at_derivation_index(index).expect("wildcard");
             Ok(definite.address(self.network).expect("wpkh"))
         } else {
@@ -205,7 +316,7 @@ impl WpkhHotSigner {
         Ok(self.address_at(coin_path)?.script_pubkey())
     }
 
-    /// Use the inner electrum client to get coins that have been paid
+    /// Use the inner bitcoin backend to get coins that have been paid
     ///   to the given [`CoinPath`]. coins are automatically added to
     ///   [`WpkhHotSigner::coins`] and the functions return the number
     ///   of coins added.
@@ -214,12 +325,13 @@ impl WpkhHotSigner {
     /// # Errors
     ///
     /// This function will return an error if:
-    ///   - an electrum request fails.
-    ///   - there is not electrum client
+    ///   - a bitcoin backend request fails.
+    ///   - there is not bitcoin backend
     pub fn get_coins_at(&mut self, coin_path: CoinPath) -> Result<usize, Error> {
         let spk = self.spk_at(&coin_path)?;
-        if let Some(client) = self.client.as_mut() {
-            let (coins, _txs) = client.get_coins_at(&spk)?;
+        if let Some(client) = self.client.as_ref() {
+            let (coins, txs) = client.lock().expect("poisoned").get_coins_at(&spk)?;
+            self.txs.extend(txs);
             let mut count = 0;
             for (txout, outpoint) in coins {
                 // TODO: should we enable RBF?
@@ -231,6 +343,9 @@ impl WpkhHotSigner {
                     coin_path,
                 };
                 if let Some(coins) = self.coins.get_mut(&coin_path) {
+                    if coins.iter().any(|c| c.outpoint == outpoint) {
+                        continue;
+                    }
                     coins.push(input_data);
                 } else {
                     self.coins.insert(coin_path, vec![input_data]);
@@ -244,6 +359,105 @@ impl WpkhHotSigner {
         }
     }
 
+    /// Set the gap limit used to scan the given account.
+    pub fn set_gap_limit(&mut self, account: Account, gap_limit: u32) {
+        self.gap_limits.insert(account, gap_limit);
+    }
+
+    /// Returns the gap limit used to scan the given account.
+    pub fn gap_limit(&self, account: Account) -> u32 {
+        self.gap_limits
+            .get(&account)
+            .copied()
+            .unwrap_or(DEFAULT_GAP_LIMIT)
+    }
+
+    /// Scan the receive & change branches of the given account until
+    ///   [`WpkhHotSigner::gap_limit()`] consecutive addresses are unused, coins
+    ///   are added to [`WpkhHotSigner::coins`] and the function returns the
+    ///   number of coins added.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - a bitcoin backend request fails.
+    ///   - there is not bitcoin backend
+    pub fn scan_account(&mut self, account: Account) -> Result<usize, Error> {
+        let gap_limit = self.gap_limit(account);
+        let mut count = 0;
+        for depth in [0, 1] {
+            let mut index = 0;
+            let mut unused = 0;
+            let mut next = 0;
+            while unused < gap_limit {
+                let coin_path = CoinPath::with_account(account, depth, index);
+                let spk = self.spk_at(&coin_path)?;
+                let client = self.client.as_ref().ok_or(Error::NoElectrumClient)?;
+                let used = !client
+                    .lock()
+                    .expect("poisoned")
+                    .get_coins_tx_at(&spk)?
+                    .is_empty();
+                if !used {
+                    unused += 1;
+                } else {
+                    count += self.get_coins_at(coin_path)?;
+                    unused = 0;
+                    next = index + 1;
+                }
+                index += 1;
+            }
+            let entry = self.next_index.entry((account, depth)).or_insert(0);
+            *entry = (*entry).max(next);
+        }
+        Ok(count)
+    }
+
+    /// Returns the next unused receive address of the given account.
+    ///
+    /// Note: [`WpkhHotSigner::scan_account()`] should be call before in order
+    ///   to not reuse an address.
+    pub fn next_address(&mut self, account: Account) -> Address {
+        self.next_address_at(account, 0)
+    }
+
+    /// Returns the next unused change address of the given account.
+    ///
+    /// Note: [`WpkhHotSigner::scan_account()`] should be call before in order
+    ///   to not reuse an address.
+    pub fn next_change_address(&mut self, account: Account) -> Address {
+        self.next_address_at(account, 1)
+    }
+
+    fn next_address_at(&mut self, account: Account, depth: u32) -> Address {
+        let index = self.next_index.entry((account, depth)).or_insert(0);
+        let coin_path = CoinPath::with_account(account, depth, *index);
+        *index += 1;
+        self.address_at(&coin_path).expect("index is not none")
+    }
+
+    /// Returns the next unused address of the postmix account, if the
+    ///   postmix account have not been scanned yet, it will be scanned first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the scan fails.
+    pub fn next_postmix_address(&mut self) -> Result<Address, Error> {
+        let scanned = self.next_index.contains_key(&(Account::Postmix, 0));
+        if !scanned && self.client.is_some() {
+            self.scan_account(Account::Postmix)?;
+        }
+        Ok(self.next_address(Account::Postmix))
+    }
+
+    /// Returns a list of coins of the given account.
+    pub fn list_account_coins(&self, account: Account) -> Vec<(CoinPath, Coin)> {
+        self.list_coins()
+            .into_iter()
+            .filter(|(path, _)| path.account == account)
+            .collect()
+    }
+
     /// Returns a list of coins copied from [`WpkhHotSigner::coins`]
     ///
     /// Note: [`WpkhHotSigner::get_coins_at()`] should be call before in order to
@@ -263,6 +477,37 @@ impl WpkhHotSigner {
         out
     }
 
+    // Returns the spks of the wallet up to the next unused index of each
+    // branch, and the spks of the known coins.
+    fn used_spks(&self) -> Vec<ScriptBuf> {
+        let mut spks: Vec<_> = self
+            .next_index
+            .iter()
+            .flat_map(|((account, depth), next)| {
+                (0..*next).filter_map(|i| {
+                    self.spk_at(&CoinPath::with_account(*account, *depth, i))
+                        .ok()
+                })
+            })
+            .collect();
+        for coins in self.coins.values() {
+            spks.extend(coins.iter().map(|c| c.txout.script_pubkey.clone()));
+        }
+        spks
+    }
+
+    /// Returns a list of coins along with their privacy score.
+    ///
+    /// Note: [`WpkhHotSigner::get_coins_at()`] should be call before in order to
+    ///   fill [`WpkhHotSigner::coins`].
+    pub fn list_scored_coins(&self) -> Vec<ScoredCoin> {
+        let coins: Vec<_> = self.list_coins().into_iter().map(|(_, c)| c).collect();
+        let mut analyzer = PrivacyAnalyzer::new();
+        analyzer.add_txs(self.txs.values().cloned());
+        analyzer.add_spks(self.used_spks());
+        analyzer.score_coins(&coins)
+    }
+
     /// Sign the transaction w/ the given [`Coin`] as input. Returns the signed input
     ///   only as a [`InputDataSigned`].
     ///
@@ -334,8 +579,7 @@ impl WpkhHotSigner {
         .expect("hardcoded");
 
         let signing_key = self
-            .secret_key
-            .xkey
+            .account_xpriv(input_data.coin_path.account)?
             .derive_priv(self.secp(), &deriv)
             .expect("deriveable")
             .private_key;
@@ -366,8 +610,81 @@ impl WpkhHotSigner {
         })
     }
 
+    /// Sign all the inputs of the transaction w/ SIGHASH_ALL, every input must
+    ///   spend one of the given coins.
+    ///
+    /// # Arguments
+    /// * `tx` - the [`Transaction`] to be signed.
+    /// * `coins` - the [`Coin`]s spent by the transaction inputs.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - an input does not spend one of the coins
+    ///   - fail to process the spk for a coin
+    ///   - fail to hash the transaction
+    ///   - the signature generated is invalid
+    pub fn sign_tx(&self, mut tx: Transaction, coins: &[Coin]) -> Result<Transaction, Error> {
+        let mut witnesses = Vec::new();
+        for (index, txin) in tx.input.iter().enumerate() {
+            let coin = coins
+                .iter()
+                .find(|c| c.outpoint == txin.previous_output)
+                .ok_or(Error::CoinMissing)?;
+
+            let spk = self.spk_at(&coin.coin_path).map_err(|_| Error::CoinPath)?;
+            if coin.txout.script_pubkey != spk {
+                log::error!("WpkhHotSigner::sign_tx(): derived and provided spk do no match!");
+                return Err(Error::CoinPath);
+            }
+            witnesses.push((index, coin.clone()));
+        }
+
+        let mut signed = Vec::new();
+        let mut cache = sighash::SighashCache::new(&tx);
+        for (index, coin) in witnesses {
+            let sighash = cache
+                .p2wpkh_signature_hash(
+                    index,
+                    &coin.txout.script_pubkey,
+                    coin.txout.value,
+                    EcdsaSighashType::All,
+                )
+                .map_err(|_| Error::SighashFail)?;
+            let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+
+            let deriv = DerivationPath::from_str(&format!(
+                "m/{}/{}",
+                coin.coin_path.depth,
+                coin.coin_path.index.expect("coinpath already checked")
+            ))
+            .expect("hardcoded");
+            let signing_key = self
+                .account_xpriv(coin.coin_path.account)?
+                .derive_priv(self.secp(), &deriv)
+                .map_err(|_| Error::Derivation)?
+                .private_key;
+            let pubkey = signing_key.public_key(self.secp());
+
+            let signature = self.secp.sign_ecdsa_low_r(&msg, &signing_key);
+            if self.secp().verify_ecdsa(&msg, &signature, &pubkey).is_err() {
+                return Err(Error::InvalidSignature);
+            }
+            let signature = ecdsa::Signature {
+                signature,
+                sighash_type: EcdsaSighashType::All,
+            };
+            signed.push((index, Witness::p2wpkh(&signature, &pubkey)));
+        }
+
+        for (index, witness) in signed {
+            tx.input[index].witness = witness;
+        }
+        Ok(tx)
+    }
+
     /// Returns the [`Fingerprint`] of this [`WpkhHotSigner`].
-    fn fingerprint(&self) -> Fingerprint {
+    pub(crate) fn fingerprint(&self) -> Fingerprint {
         self.fingerprint
     }
 
@@ -376,6 +693,23 @@ impl WpkhHotSigner {
         &self.secp
     }
 
+    /// Returns the [`Xpriv`] of the given account.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the derivation fails.
+    fn account_xpriv(&self, account: Account) -> Result<Xpriv, Error> {
+        if account == Account::Deposit {
+            return Ok(self.master_xpriv);
+        }
+        let path =
+            DerivationPath::from_str(&format!("m{}", account_deriv_path(self.network, account)))
+                .expect("hardcoded");
+        self.root_xpriv
+            .derive_priv(self.secp(), &path)
+            .map_err(|_| Error::Derivation)
+    }
+
     /// Returns the derived [`Xpriv`].
     ///
     /// # Arguments
@@ -412,20 +746,14 @@ impl WpkhHotSigner {
 
     /// Returns the receive address at the given `index`.
     pub fn recv_addr_at(&self, index: u32) -> Address {
-        self.address_at(&CoinPath {
-            depth: 0,
-            index: Some(index),
-        })
-        .expect("index is not none")
+        self.address_at(&CoinPath::new(0, index))
+            .expect("index is not none")
     }
 
     /// Returns the change address at the given `index`.
     pub fn change_addr_at(&self, index: u32) -> Address {
-        self.address_at(&CoinPath {
-            depth: 1,
-            index: Some(index),
-        })
-        .expect("index is not none")
+        self.address_at(&CoinPath::new(1, index))
+            .expect("index is not none")
     }
 }
 
@@ -433,6 +761,10 @@ impl JoinstrSigner for WpkhHotSigner {
     fn sign_input(&self, tx: &Transaction, input_data: Coin) -> Result<InputDataSigned, String> {
         self.sign(tx, input_data).map_err(|e| e.to_string())
     }
+
+    fn postmix_address(&mut self) -> Option<Address> {
+        self.next_postmix_address().ok()
+    }
 }
 
 #[cfg(test)]
@@ -441,17 +773,13 @@ mod tests {
     use miniscript::bitcoin::{absolute, transaction::Version, Amount, Txid};
 
     use super::*;
+    use crate::backend::memory::MemoryChain;
 
     #[test]
     fn create_and_sign() {
         let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
 
-        let recv_script = signer
-            .spk_at(&CoinPath {
-                depth: 0,
-                index: Some(11),
-            })
-            .unwrap();
+        let recv_script = signer.spk_at(&CoinPath::new(0, 11)).unwrap();
 
         let input_data = Coin {
             txout: TxOut {
@@ -466,24 +794,11 @@ mod tests {
                 vout: 0,
             },
             sequence: Sequence::MAX,
-            coin_path: CoinPath {
-                depth: 0,
-                index: Some(11),
-            },
+            coin_path: CoinPath::new(0, 11),
         };
 
-        let out1 = signer
-            .spk_at(&CoinPath {
-                depth: 0,
-                index: Some(12),
-            })
-            .unwrap();
-        let out2 = signer
-            .spk_at(&CoinPath {
-                depth: 0,
-                index: Some(13),
-            })
-            .unwrap();
+        let out1 = signer.spk_at(&CoinPath::new(0, 12)).unwrap();
+        let out2 = signer.spk_at(&CoinPath::new(0, 13)).unwrap();
 
         let tx = Transaction {
             version: Version::ONE,
@@ -503,4 +818,70 @@ mod tests {
 
         let _out_data = signer.sign(&tx, input_data).unwrap();
     }
+
+    #[test]
+    fn accounts_addresses() {
+        let mut signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let deposit = signer.address_at(&CoinPath::new(0, 0)).unwrap();
+        assert_eq!(deposit, signer.recv_addr_at(0));
+
+        let mut addresses = vec![deposit];
+        for account in [Account::Premix, Account::Postmix, Account::Badbank] {
+            let addr = signer
+                .address_at(&CoinPath::with_account(account, 0, 0))
+                .unwrap();
+            assert!(!addresses.contains(&addr));
+            addresses.push(addr);
+        }
+
+        // no electrum client, addresses are drawn from index 0
+        let postmix = signer.next_postmix_address().unwrap();
+        assert_eq!(postmix, addresses[2]);
+        let next = signer.next_address(Account::Postmix);
+        assert_eq!(
+            next,
+            signer
+                .address_at(&CoinPath::with_account(Account::Postmix, 0, 1))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn scan_with_memory_chain() {
+        let chain = MemoryChain::new();
+        let mut signer = WpkhHotSigner::new(Network::Regtest)
+            .unwrap()
+            .client(chain.clone());
+        signer.set_gap_limit(Account::Postmix, 5);
+
+        // fund postmix receive addresses at index 0 & 3, the gap between
+        // them is smaller than the gap limit
+        for index in [0, 3] {
+            let spk = signer
+                .spk_at(&CoinPath::with_account(Account::Postmix, 0, index))
+                .unwrap();
+            let tx = Transaction {
+                version: Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value: Amount::from_sat(50_000 + index as u64),
+                    script_pubkey: spk,
+                }],
+            };
+            chain.add_tx(tx, Some(100));
+        }
+
+        assert_eq!(signer.scan_account(Account::Postmix).unwrap(), 2);
+        assert_eq!(signer.list_account_coins(Account::Postmix).len(), 2);
+        assert!(signer.list_account_coins(Account::Deposit).is_empty());
+        assert_eq!(
+            signer.next_postmix_address().unwrap(),
+            signer
+                .address_at(&CoinPath::with_account(Account::Postmix, 0, 4))
+                .unwrap()
+        );
+        // a clone of the signer share the chain source
+        assert!(signer.clone().shared_client().is_some());
+    }
 }
@@ -0,0 +1,477 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use miniscript::bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, Txid};
+use simple_electrum_client::electrum::types::StatusHash;
+
+use super::{Account, Coin, CoinPath, Error, WpkhHotSigner};
+use crate::electrum::{self, CoinRequest, CoinResponse, CoinStatus};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct SyncState {
+    // derived spks & their path
+    spks: HashMap<ScriptBuf, CoinPath>,
+    // number of derived spks by (account, depth)
+    derived: HashMap<(Account, u32), u32>,
+    // last status received by spk, None if the spk has no history
+    statuses: HashMap<ScriptBuf, Option<StatusHash>>,
+    histories: HashMap<ScriptBuf, Vec<(Txid, Option<u64> /* height */)>>,
+    // transactions of the histories, kept across syncs
+    txs: HashMap<Txid, Transaction>,
+    // requests sent but not yet answered
+    pending_status: HashSet<ScriptBuf>,
+    pending_history: HashSet<ScriptBuf>,
+    pending_txs: HashSet<Txid>,
+}
+
+impl SyncState {
+    /// Derive spks until `gap_limit` consecutive spks are unused on every
+    ///   branch of every account, returns the spks to subscribe to.
+    fn derive(&mut self, signer: &WpkhHotSigner) -> Result<Vec<ScriptBuf>, Error> {
+        let mut new = Vec::new();
+        for account in Account::all() {
+            let gap_limit = signer.gap_limit(account);
+            for depth in [0, 1] {
+                let target = self.next_index(account, depth) + gap_limit;
+                let derived = self.derived.entry((account, depth)).or_insert(0);
+                while *derived < target {
+                    let coin_path = CoinPath::with_account(account, depth, *derived);
+                    let spk = signer.spk_at(&coin_path)?;
+                    self.spks.insert(spk.clone(), coin_path);
+                    self.pending_status.insert(spk.clone());
+                    new.push(spk);
+                    *derived += 1;
+                }
+            }
+        }
+        Ok(new)
+    }
+
+    /// Returns the index following the last used spk of the given branch.
+    fn next_index(&self, account: Account, depth: u32) -> u32 {
+        self.histories
+            .iter()
+            .filter(|(_, history)| !history.is_empty())
+            .filter_map(|(spk, _)| self.spks.get(spk))
+            .filter(|path| path.account == account && path.depth == depth)
+            .filter_map(|path| path.index)
+            .map(|index| index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Process a status update, returns the spks whose history must be
+    ///   fetched.
+    fn on_status(&mut self, statuses: BTreeMap<ScriptBuf, Option<String>>) -> Vec<ScriptBuf> {
+        let mut changed = Vec::new();
+        for (spk, status) in statuses {
+            self.pending_status.remove(&spk);
+            let status = match status.map(|s| StatusHash::from_str(&s)) {
+                Some(Ok(status)) => Some(status),
+                None => None,
+                Some(Err(e)) => {
+                    // NOTE: we cannot compare it, so we always refetch
+                    log::error!("SyncState::on_status() invalid status: {e:?}");
+                    self.statuses.remove(&spk);
+                    self.pending_history.insert(spk.clone());
+                    changed.push(spk);
+                    continue;
+                }
+            };
+            if self.statuses.insert(spk.clone(), status) == Some(status) {
+                continue;
+            }
+            if status.is_none() {
+                // no need to ask the server for an empty history
+                self.histories.insert(spk, Vec::new());
+            } else {
+                self.pending_history.insert(spk.clone());
+                changed.push(spk);
+            }
+        }
+        changed
+    }
+
+    /// Process history updates, returns the txids of the transactions that
+    ///   are not yet cached.
+    fn on_history(
+        &mut self,
+        histories: BTreeMap<ScriptBuf, Vec<(Txid, Option<u64>)>>,
+    ) -> Vec<Txid> {
+        let mut missing = Vec::new();
+        for (spk, history) in histories {
+            self.pending_history.remove(&spk);
+            for (txid, _) in &history {
+                if !self.txs.contains_key(txid) && self.pending_txs.insert(*txid) {
+                    missing.push(*txid);
+                }
+            }
+            self.histories.insert(spk, history);
+        }
+        missing
+    }
+
+    fn on_txs(&mut self, txs: Vec<Transaction>) {
+        for tx in txs {
+            let txid = tx.compute_txid();
+            self.pending_txs.remove(&txid);
+            self.txs.insert(txid, tx);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pending_status.is_empty()
+            && self.pending_history.is_empty()
+            && self.pending_txs.is_empty()
+    }
+
+    /// Returns the coins paying to our spks along w/ their status.
+    ///
+    /// Note: only the transactions of the current histories are considered,
+    ///   a cached transaction that has been replaced or evicted from the
+    ///   mempool neither creates nor spends coins.
+    fn coins(&self) -> Vec<(Coin, CoinStatus)> {
+        let spent: HashSet<OutPoint> = self
+            .histories
+            .values()
+            .flatten()
+            .filter_map(|(txid, _)| self.txs.get(txid))
+            .flat_map(|tx| tx.input.iter().map(|i| i.previous_output))
+            .collect();
+        let mut coins = Vec::new();
+        for (spk, history) in &self.histories {
+            let Some(coin_path) = self.spks.get(spk) else {
+                continue;
+            };
+            for (txid, height) in history {
+                let Some(tx) = self.txs.get(txid) else {
+                    continue;
+                };
+                for (vout, txout) in tx.output.iter().enumerate() {
+                    if txout.script_pubkey != *spk {
+                        continue;
+                    }
+                    let outpoint = OutPoint::new(*txid, vout as u32);
+                    let status = match (spent.contains(&outpoint), height) {
+                        (true, _) => CoinStatus::Spend,
+                        (false, Some(_)) => CoinStatus::Confirmed,
+                        (false, None) => CoinStatus::Unconfirmed,
+                    };
+                    let coin = Coin {
+                        txout: txout.clone(),
+                        outpoint,
+                        // TODO: should we enable RBF?
+                        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                        coin_path: *coin_path,
+                    };
+                    coins.push((coin, status));
+                }
+            }
+        }
+        coins
+    }
+
+    // Report the synced state into the signer
+    fn update(&self, signer: &mut WpkhHotSigner) {
+        let unspent: Vec<Coin> = self
+            .coins()
+            .into_iter()
+            .filter(|(_, status)| *status != CoinStatus::Spend)
+            .map(|(coin, _)| coin)
+            .collect();
+        let outpoints: HashSet<OutPoint> = unspent.iter().map(|c| c.outpoint).collect();
+        // drop the coins of synced spks that are spent or whose funding tx
+        // left the history (replaced or evicted from the mempool)
+        for coins in signer.coins.values_mut() {
+            coins.retain(|c| {
+                !self.histories.contains_key(&c.txout.script_pubkey)
+                    || outpoints.contains(&c.outpoint)
+            });
+        }
+        for coin in unspent {
+            let coins = signer.coins.entry(coin.coin_path).or_default();
+            if !coins.iter().any(|c| c.outpoint == coin.outpoint) {
+                coins.push(coin);
+            }
+        }
+        signer.coins.retain(|_, coins| !coins.is_empty());
+        signer.txs.extend(self.txs.clone());
+        for account in Account::all() {
+            for depth in [0, 1] {
+                let next = self.next_index(account, depth);
+                let entry = signer.next_index.entry((account, depth)).or_insert(0);
+                *entry = (*entry).max(next);
+            }
+        }
+    }
+}
+
+/// Incremental wallet sync over the electrum notification mechanism.
+///
+/// Every spk of the wallet is subscribed once, then on each
+///   [`WalletSync::sync()`] call only the spks whose status changed are
+///   fetched again, the derivation is extended in order to always watch
+///   [`WpkhHotSigner::gap_limit()`] unused spks after the last used one, and
+///   transactions are cached across calls.
+#[derive(Debug)]
+pub struct WalletSync {
+    sender: mpsc::Sender<CoinRequest>,
+    receiver: mpsc::Receiver<CoinResponse>,
+    timeout: Duration,
+    state: SyncState,
+}
+
+impl WalletSync {
+    /// Create a new [`WalletSync`], the client is moved to a thread that
+    ///   handles the requests to the electrum server.
+    ///
+    /// # Arguments
+    /// * `client` - The electrum client
+    pub fn new(client: electrum::Client) -> Self {
+        let (sender, receiver) = client.listen();
+        WalletSync {
+            sender,
+            receiver,
+            timeout: DEFAULT_TIMEOUT,
+            state: SyncState::default(),
+        }
+    }
+
+    /// Set the maximum time [`WalletSync::sync()`] waits for a response
+    ///   from the electrum server (default 30s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Seed the transaction cache, e.g. w/ the transactions persisted after
+    ///   a previous sync, those transactions are not fetched again.
+    pub fn with_txs(mut self, txs: impl IntoIterator<Item = Transaction>) -> Self {
+        self.state.on_txs(txs.into_iter().collect());
+        self
+    }
+
+    /// Sync the wallet of the given signer: coins are added to
+    ///   [`WpkhHotSigner::coins`], spent coins are removed from it and the
+    ///   next unused index of each branch is updated.
+    ///
+    /// Note: the wallet of a single signer should be synced w/ a given
+    ///   [`WalletSync`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the electrum server returns an error
+    ///   - the electrum server does not answer before the timeout
+    ///   - the thread handling the requests has stopped
+    pub fn sync(&mut self, signer: &mut WpkhHotSigner) -> Result<(), Error> {
+        // requests that have not been answered during a previous sync
+        let status: Vec<_> = self.state.pending_status.iter().cloned().collect();
+        let history: Vec<_> = self.state.pending_history.iter().cloned().collect();
+        let txs: Vec<_> = self.state.pending_txs.iter().cloned().collect();
+        self.send(CoinRequest::Subscribe(status))?;
+        self.send(CoinRequest::History(history))?;
+        self.send(CoinRequest::Txs(txs))?;
+
+        let new = self.state.derive(signer)?;
+        self.send(CoinRequest::Subscribe(new))?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let response = match self.receiver.try_recv() {
+                Ok(response) => response,
+                Err(TryRecvError::Empty) if self.state.is_done() => break,
+                Err(TryRecvError::Empty) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match self.receiver.recv_timeout(remaining) {
+                        Ok(response) => response,
+                        Err(RecvTimeoutError::Timeout) => return Err(Error::SyncTimeout),
+                        Err(RecvTimeoutError::Disconnected) => return Err(Error::SyncStopped),
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return Err(Error::SyncStopped),
+            };
+            self.handle(response, signer)?;
+        }
+
+        self.state.update(signer);
+        Ok(())
+    }
+
+    fn send(&self, request: CoinRequest) -> Result<(), Error> {
+        let empty = match &request {
+            CoinRequest::Subscribe(spks) | CoinRequest::History(spks) => spks.is_empty(),
+            CoinRequest::Txs(txids) => txids.is_empty(),
+            CoinRequest::Stop => false,
+        };
+        if !empty {
+            self.sender.send(request).map_err(|_| Error::SyncStopped)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, response: CoinResponse, signer: &WpkhHotSigner) -> Result<(), Error> {
+        log::debug!("WalletSync::handle() {response:?}");
+        match response {
+            CoinResponse::Status(statuses) => {
+                let changed = self.state.on_status(statuses);
+                self.send(CoinRequest::History(changed))?;
+            }
+            CoinResponse::History(histories) => {
+                let missing = self.state.on_history(histories);
+                self.send(CoinRequest::Txs(missing))?;
+                // a newly used spk can move the gap limit forward
+                let new = self.state.derive(signer)?;
+                self.send(CoinRequest::Subscribe(new))?;
+            }
+            CoinResponse::Txs(txs) => self.state.on_txs(txs),
+            CoinResponse::Error(e) => return Err(Error::Sync(e)),
+            CoinResponse::Stopped => return Err(Error::SyncStopped),
+        }
+        Ok(())
+    }
+
+    /// Returns the coins of the wallet along w/ their status, as of the last
+    ///   [`WalletSync::sync()`], spent coins included.
+    pub fn coins(&self) -> Vec<(Coin, CoinStatus)> {
+        self.state.coins()
+    }
+
+    /// Returns the cached transactions of the wallet.
+    pub fn txs(&self) -> impl Iterator<Item = &Transaction> {
+        self.state.txs.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miniscript::bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Amount, Network, TxIn, TxOut,
+    };
+
+    use super::*;
+
+    fn tx(input: OutPoint, spk: &ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: input,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: spk.clone(),
+            }],
+        }
+    }
+
+    fn status(s: &str) -> Option<String> {
+        Some(StatusHash::hash(s.as_bytes()).to_string())
+    }
+
+    #[test]
+    fn incremental_sync() {
+        let mut signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        signer.set_gap_limit(Account::Deposit, 5);
+        let mut state = SyncState::default();
+
+        // every branch is derived up to the gap limit
+        let new = state.derive(&signer).unwrap();
+        assert_eq!(new.len(), 2 * 5 + 6 * 20);
+        assert!(state.derive(&signer).unwrap().is_empty());
+
+        // empty statuses do not trigger history requests
+        let statuses: BTreeMap<_, _> = new.iter().map(|spk| (spk.clone(), None)).collect();
+        assert!(state.on_status(statuses).is_empty());
+        assert!(state.is_done());
+
+        // a payment to index 3 extends the deposit receive branch
+        let spk = signer.spk_at(&CoinPath::new(0, 3)).unwrap();
+        let receive = tx(OutPoint::null(), &spk);
+        let receive_txid = receive.compute_txid();
+        let changed = state.on_status(BTreeMap::from([(spk.clone(), status("1"))]));
+        assert_eq!(changed, vec![spk.clone()]);
+        let missing = state.on_history(BTreeMap::from([(spk.clone(), vec![(receive_txid, None)])]));
+        assert_eq!(missing, vec![receive_txid]);
+        assert!(!state.is_done());
+        assert_eq!(state.derive(&signer).unwrap().len(), 4);
+        assert_eq!(state.next_index(Account::Deposit, 0), 4);
+        state.on_txs(vec![receive.clone()]);
+        let coins = state.coins();
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].1, CoinStatus::Unconfirmed);
+
+        // same status: nothing to refetch
+        assert!(state
+            .on_status(BTreeMap::from([(spk.clone(), status("1"))]))
+            .is_empty());
+
+        // confirmation then spend, cached txs are not fetched again
+        state.on_status(BTreeMap::from([(spk.clone(), status("2"))]));
+        let missing = state.on_history(BTreeMap::from([(
+            spk.clone(),
+            vec![(receive_txid, Some(101))],
+        )]));
+        assert!(missing.is_empty());
+        assert_eq!(state.coins()[0].1, CoinStatus::Confirmed);
+
+        let other = signer.spk_at(&CoinPath::new(1, 0)).unwrap();
+        let spend = tx(OutPoint::new(receive_txid, 0), &other);
+        let spend_txid = spend.compute_txid();
+        state.on_status(BTreeMap::from([(spk.clone(), status("3"))]));
+        let missing = state.on_history(BTreeMap::from([(
+            spk.clone(),
+            vec![(receive_txid, Some(101)), (spend_txid, None)],
+        )]));
+        assert_eq!(missing, vec![spend_txid]);
+        state.on_txs(vec![spend]);
+        assert!(state.is_done());
+        let coins = state.coins();
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].0.outpoint, OutPoint::new(receive_txid, 0));
+        assert_eq!(coins[0].1, CoinStatus::Spend);
+    }
+
+    #[test]
+    fn evicted_txs() {
+        let mut signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let mut state = SyncState::default();
+        state.derive(&signer).unwrap();
+
+        let spk = signer.spk_at(&CoinPath::new(0, 0)).unwrap();
+        let other = signer.spk_at(&CoinPath::new(0, 1)).unwrap();
+        let receive = tx(OutPoint::null(), &spk);
+        let receive_txid = receive.compute_txid();
+        let spend = tx(OutPoint::new(receive_txid, 0), &other);
+        let spend_txid = spend.compute_txid();
+        state.on_history(BTreeMap::from([(
+            spk.clone(),
+            vec![(receive_txid, Some(101)), (spend_txid, None)],
+        )]));
+        state.on_txs(vec![receive.clone(), spend]);
+        state.update(&mut signer);
+        assert!(signer.list_coins().is_empty());
+
+        // the spending tx is evicted from the mempool: the coin is unspent
+        state.on_history(BTreeMap::from([(
+            spk.clone(),
+            vec![(receive_txid, Some(101))],
+        )]));
+        assert_eq!(state.coins()[0].1, CoinStatus::Confirmed);
+        state.update(&mut signer);
+        assert_eq!(signer.list_coins().len(), 1);
+
+        // the funding tx is reorged out: the coin is dropped
+        state.on_history(BTreeMap::from([(spk.clone(), Vec::new())]));
+        assert!(state.coins().is_empty());
+        state.update(&mut signer);
+        assert!(signer.list_coins().is_empty());
+    }
+}
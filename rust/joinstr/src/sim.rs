@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use miniscript::bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, Network, OutPoint, Transaction, TxIn, TxOut,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use simple_nostr_client::nostr::{nips::nip13, Event, EventBuilder, Keys, Kind, PublicKey, Tag};
+use tokio::{
+    sync::mpsc::{self, error::TryRecvError},
+    time::Instant,
+};
+
+use crate::{
+    backend::memory::MemoryChain,
+    joinstr::{
+        self,
+        r#async::{Joinstr, NostrTransport},
+        Connector,
+    },
+    nostr::{error::Error, sync::Connection, Pool, PoolMessage},
+    signer::{self, Coin, CoinPath, WpkhHotSigner},
+    utils::Clock,
+};
+
+/// The bitcoin network of the simulations.
+pub const NETWORK: Network = Network::Regtest;
+
+/// An async [`Joinstr`] instance running over a [`SimRelay`].
+pub type SimJoinstr = Joinstr<SimClient, MemoryChain>;
+
+// unix time at which a [`SimClock`] starts
+const SIM_EPOCH: u64 = 1_700_000_000;
+// delay between two checks of the relay events in [`SimRelay::wait_pool()`]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// the [`SimRelay`]s of the process by url, [`SimClient::connect()`] only
+// receive the url of the relay.
+static RELAYS: OnceLock<Mutex<HashMap<String, Weak<Mutex<RelayState>>>>> = OnceLock::new();
+static RELAY_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+fn relays() -> &'static Mutex<HashMap<String, Weak<Mutex<RelayState>>>> {
+    RELAYS.get_or_init(Default::default)
+}
+
+#[derive(Debug, Default)]
+struct RelayState {
+    // every event received w/ its recipient if it's a DM, the DMs are
+    // replayed to the clients that connect later, as a nostr relay does
+    events: Vec<(Option<PublicKey>, Event)>,
+    clients: Vec<(PublicKey, mpsc::UnboundedSender<Event>)>,
+}
+
+/// An in-process nostr relay, the [`SimClient`]s connect to it by its url.
+///
+/// The relay stores every event it receives, DMs are routed to every
+///   client connected w/ the recipient keys.
+#[derive(Debug, Clone)]
+pub struct SimRelay {
+    url: String,
+    state: Arc<Mutex<RelayState>>,
+}
+
+impl Default for SimRelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimRelay {
+    /// Create a new relay, registered under an unique url.
+    pub fn new() -> Self {
+        let url = format!(
+            "sim://relay_{}",
+            RELAY_INDEX.fetch_add(1, Ordering::Relaxed)
+        );
+        let state = Arc::new(Mutex::new(RelayState::default()));
+        let mut relays = relays().lock().expect("poisoned");
+        relays.retain(|_, relay| relay.strong_count() > 0);
+        relays.insert(url.clone(), Arc::downgrade(&state));
+        SimRelay { url, state }
+    }
+
+    /// Returns the url of the relay.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Returns the events received by the relay.
+    pub fn events(&self) -> Vec<Event> {
+        let state = self.state.lock().expect("poisoned");
+        state.events.iter().map(|(_, e)| e.clone()).collect()
+    }
+
+    /// Returns the pools posted on the relay.
+    pub fn pools(&self) -> Vec<Pool> {
+        self.events()
+            .into_iter()
+            .filter_map(|e| Pool::try_from(e).ok())
+            .collect()
+    }
+
+    /// Wait for a pool to be posted on the relay, returns the last one.
+    pub async fn wait_pool(&self) -> Pool {
+        loop {
+            if let Some(pool) = self.pools().pop() {
+                return pool;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Blocking version of [`SimRelay::wait_pool()`], for the sync
+    ///   [`joinstr::Joinstr`].
+    pub fn wait_pool_blocking(&self) -> Pool {
+        loop {
+            if let Some(pool) = self.pools().pop() {
+                return pool;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Returns the relay registered under `url`.
+    fn find(url: &str) -> Result<Self, Error> {
+        let state = relays()
+            .lock()
+            .expect("poisoned")
+            .get(url)
+            .and_then(Weak::upgrade)
+            .ok_or(Error::NotConnected)?;
+        Ok(SimRelay {
+            url: url.into(),
+            state,
+        })
+    }
+
+    fn publish(&self, recipient: Option<PublicKey>, event: Event) {
+        let mut state = self.state.lock().expect("poisoned");
+        if let Some(recipient) = recipient {
+            // the clients that have been dropped are removed
+            state
+                .clients
+                .retain(|(pk, sender)| *pk != recipient || sender.send(event.clone()).is_ok());
+        }
+        state.events.push((recipient, event));
+    }
+
+    fn subscribe(&self, public_key: PublicKey) -> mpsc::UnboundedReceiver<Event> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut state = self.state.lock().expect("poisoned");
+        for (_, event) in state
+            .events
+            .iter()
+            .filter(|(recipient, _)| *recipient == Some(public_key))
+        {
+            let _ = sender.send(event.clone());
+        }
+        state.clients.push((public_key, sender));
+        receiver
+    }
+}
+
+/// A client of a [`SimRelay`], DMs are sent in clear text.
+#[derive(Debug)]
+pub struct SimClient {
+    name: String,
+    keys: Keys,
+    relay: SimRelay,
+    receiver: mpsc::UnboundedReceiver<Event>,
+}
+
+impl SimClient {
+    /// Connect to the relay, subscribed to the DMs sent to `keys`.
+    pub fn new(relay: &SimRelay, keys: Keys, name: &str) -> Self {
+        SimClient {
+            name: name.into(),
+            receiver: relay.subscribe(keys.public_key()),
+            keys,
+            relay: relay.clone(),
+        }
+    }
+
+    /// Returns the public key of the client.
+    pub fn public_key(&self) -> PublicKey {
+        self.keys.public_key()
+    }
+
+    /// Send a DM w/ a raw content, that is not necessarily a valid
+    ///   [`PoolMessage`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing the event fails.
+    pub fn send_raw(&self, npub: &PublicKey, content: &str, pow: Option<u8>) -> Result<(), Error> {
+        log::debug!("SimClient({}).send_raw(): {content}", self.name);
+        let mut dm = EventBuilder::new(
+            Kind::EncryptedDirectMessage,
+            content,
+            vec![Tag::public_key(*npub)],
+        );
+        if let Some(difficulty) = pow {
+            dm = dm.pow(difficulty);
+        }
+        self.relay.publish(Some(*npub), dm.to_event(&self.keys)?);
+        Ok(())
+    }
+}
+
+impl NostrTransport for SimClient {
+    async fn connect(relay: &str, keys: Keys, name: &str) -> Result<Self, Error> {
+        Ok(Self::new(&SimRelay::find(relay)?, keys, name))
+    }
+
+    async fn post_event(&mut self, event: EventBuilder) -> Result<(), Error> {
+        self.relay.publish(None, event.to_event(&self.keys)?);
+        Ok(())
+    }
+
+    async fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> Result<(), Error> {
+        self.send_raw(npub, &msg.to_string()?, pow)
+    }
+
+    async fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let event = match tokio::time::timeout_at(deadline, self.receiver.recv()).await {
+                Err(_) => return Ok(None),
+                Ok(None) => return Err(Error::Disconnected),
+                Ok(Some(event)) => event,
+            };
+            if let Some(msg) = parse(event) {
+                return Ok(Some(msg));
+            }
+        }
+    }
+}
+
+impl joinstr::NostrTransport for SimClient {
+    fn post_event(&mut self, event: EventBuilder) -> Result<(), Error> {
+        self.relay.publish(None, event.to_event(&self.keys)?);
+        Ok(())
+    }
+
+    fn send_pool_message(
+        &mut self,
+        npub: &PublicKey,
+        msg: PoolMessage,
+        pow: Option<u8>,
+    ) -> Result<(), Error> {
+        self.send_raw(npub, &msg.to_string()?, pow)
+    }
+
+    fn receive_pool_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(PoolMessage, u8)>, Error> {
+        // NOTE: the sync driver runs on OS threads, it then waits on the
+        // real clock
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => {
+                    if let Some(msg) = parse(event) {
+                        return Ok(Some(msg));
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return Err(Error::Disconnected),
+                Err(TryRecvError::Empty) => {
+                    if std::time::Instant::now() >= deadline {
+                        return Ok(None);
+                    }
+                    thread::sleep(Duration::from_millis(joinstr::WAIT));
+                }
+            }
+        }
+    }
+}
+
+// Parse a DM received from the relay, None if it's not a pool message.
+fn parse(event: Event) -> Option<(PoolMessage, u8)> {
+    let pow = nip13::get_leading_zero_bits(event.id.as_bytes());
+    let msg = PoolMessage::from_str(&event.content).ok()?;
+    // if the join request does not contain a pubkey to respond to, we respond to
+    // sender
+    let msg = match msg {
+        PoolMessage::Join(None) => PoolMessage::Join(Some(event.pubkey)),
+        m => m,
+    };
+    Some((msg, pow))
+}
+
+/// A [`Connector`] that opens [`SimClient`]s, for the sync
+///   [`joinstr::Joinstr`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SimConnector;
+
+impl Connector for SimConnector {
+    fn connect(
+        &self,
+        connection: Connection,
+        keys: Keys,
+        name: &str,
+    ) -> Result<Box<dyn joinstr::NostrTransport>, Error> {
+        let relay = SimRelay::find(&connection.relay())?;
+        Ok(Box::new(SimClient::new(&relay, keys, name)))
+    }
+}
+
+/// A [`Clock`] that follows the tokio clock, when the tokio clock is paused
+///   (see `tokio::time::pause()`) the time only advance when every task is
+///   waiting, so the timelines of a pool do not depend on the speed of the
+///   machine. Random delays are drawn from a seeded RNG.
+///
+/// The sync [`joinstr::Joinstr`] blocks OS threads, that do not let the
+///   paused tokio clock advance: it runs on an unpaused clock sped up by
+///   [`SimClock::speed()`] instead.
+#[derive(Debug)]
+pub struct SimClock {
+    start: Instant,
+    speed: u32,
+    rng: Mutex<StdRng>,
+}
+
+impl SimClock {
+    /// Create a new clock, that starts at the current instant of the tokio
+    ///   clock.
+    ///
+    /// # Arguments
+    /// * `seed` - The seed of the random delays
+    pub fn new(seed: u64) -> Self {
+        SimClock {
+            start: Instant::now(),
+            speed: 1,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Make the clock run `speed` times faster than the tokio clock, the
+    ///   delays slept w/ [`Clock::sleep()`] are shortened accordingly.
+    pub fn speed(mut self, speed: u32) -> Self {
+        self.speed = speed.max(1);
+        self
+    }
+}
+
+impl Clock for SimClock {
+    fn unix_time(&self) -> Duration {
+        Duration::from_secs(SIM_EPOCH) + self.start.elapsed() * self.speed
+    }
+
+    fn rand_delay(&self) -> Duration {
+        let millis: u64 = self.rng.lock().expect("poisoned").random_range(200..5000);
+        Duration::from_millis(millis)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration / self.speed);
+    }
+}
+
+/// Create a new signer w/ a confirmed coin of `amount` on the chain.
+///
+/// # Errors
+///
+/// This function will return an error if the signer cannot be created or
+///   cannot find its coin on the chain.
+pub fn funded_signer(
+    chain: &MemoryChain,
+    amount: Amount,
+) -> Result<(WpkhHotSigner, Coin), signer::Error> {
+    let mut signer = WpkhHotSigner::new(NETWORK)?;
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: amount,
+            script_pubkey: signer.recv_addr_at(0).script_pubkey(),
+        }],
+    };
+    chain.add_tx(tx, Some(1));
+    signer.set_client(chain.clone());
+    signer.get_coins_at(CoinPath::new(0, 0))?;
+    let coin = signer
+        .list_coins()
+        .into_iter()
+        .map(|(_, coin)| coin)
+        .next()
+        .ok_or(signer::Error::CoinMissing)?;
+    Ok((signer, coin))
+}
@@ -0,0 +1,35 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The header do not commit to the previous header of the chain
+    BrokenChain(u32),
+    /// The header PoW is invalid or its target too easy
+    InvalidPow(u32),
+    /// The header difficulty do not match the expected one
+    UnexpectedDifficulty(u32),
+    /// The header is below the checkpoint of the chain
+    BelowCheckpoint(u32),
+    /// We do not know the header at this height yet
+    UnknownHeight(u32),
+    /// The merkle proof is malformed
+    MerkleParsing,
+    /// The merkle proof do not match the block header
+    InvalidMerkleProof,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BrokenChain(h) => write!(f, "Header at height {h} do not extend the chain"),
+            Error::InvalidPow(h) => write!(f, "Header at height {h} have an invalid PoW"),
+            Error::UnexpectedDifficulty(h) => {
+                write!(f, "Header at height {h} have an unexpected difficulty")
+            }
+            Error::BelowCheckpoint(h) => write!(f, "Height {h} is below the checkpoint"),
+            Error::UnknownHeight(h) => write!(f, "Header at height {h} not yet synced"),
+            Error::MerkleParsing => write!(f, "Fail to parse the merkle proof"),
+            Error::InvalidMerkleProof => write!(f, "The merkle proof is invalid"),
+        }
+    }
+}
@@ -0,0 +1,323 @@
+mod error;
+
+use std::str::FromStr;
+
+pub use error::Error;
+use miniscript::bitcoin::{
+    block,
+    consensus::Params,
+    constants::genesis_block,
+    hashes::{sha256d, Hash, HashEngine},
+    pow::Work,
+    BlockHash, CompactTarget, Network, TxMerkleNode, Txid,
+};
+
+/// A chain of validated block headers, starting either at the genesis block
+///   or at a trusted checkpoint.
+///
+/// Each header added to the chain is checked to:
+///   - commit to the previous header
+///   - have a valid PoW for its target, and a target not easier than the
+///     network limit
+///   - keep the same difficulty than the previous header between two
+///     retargets, and have the expected difficulty at retarget heights (on
+///     networks that do not allow min difficulty blocks)
+///
+/// When two branches compete, the one w/ the most cumulative work is kept.
+///
+/// Note: the difficulty at a retarget height can only be checked if the
+///   first header of the previous period is known, i.e. not for the first
+///   retarget following the checkpoint.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: Params,
+    start: u32,
+    headers: Vec<block::Header>,
+}
+
+impl HeaderChain {
+    /// Create a new chain starting at the genesis block of the network.
+    pub fn new(network: Network) -> Self {
+        Self::from_checkpoint(network, 0, genesis_block(network).header)
+    }
+
+    /// Create a new chain starting at a trusted checkpoint, avoid to sync
+    ///   the full header chain.
+    ///
+    /// # Arguments
+    /// * `network` - The network of the chain
+    /// * `height` - The height of the checkpoint
+    /// * `header` - The header of the block at `height`
+    pub fn from_checkpoint(network: Network, height: u32, header: block::Header) -> Self {
+        HeaderChain {
+            params: Params::new(network),
+            start: height,
+            headers: vec![header],
+        }
+    }
+
+    /// Returns the height of the first header of the chain.
+    pub fn checkpoint_height(&self) -> u32 {
+        self.start
+    }
+
+    /// Returns the height of the tip of the chain.
+    pub fn tip_height(&self) -> u32 {
+        self.start + self.headers.len() as u32 - 1
+    }
+
+    /// Returns the hash of the tip of the chain.
+    pub fn tip_hash(&self) -> BlockHash {
+        self.headers.last().expect("never empty").block_hash()
+    }
+
+    /// Returns the header at the given height if known.
+    pub fn header(&self, height: u32) -> Option<&block::Header> {
+        height
+            .checked_sub(self.start)
+            .and_then(|i| self.headers.get(i as usize))
+    }
+
+    /// Add headers to the chain, `headers[0]` is at height `start`.
+    ///
+    /// If `start` is below the tip and headers differ from the known ones,
+    ///   the chain is reorged if the new branch have more cumulative work:
+    ///   known headers from the fork point are replaced.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - `start` is below the checkpoint or above the tip + 1
+    ///   - a header do not extend the chain
+    ///   - a header have an invalid PoW or difficulty
+    ///
+    /// Note: the chain is left unchanged on error.
+    pub fn extend(&mut self, start: u32, headers: &[block::Header]) -> Result<(), Error> {
+        if start <= self.start {
+            return Err(Error::BelowCheckpoint(start));
+        }
+        if start > self.tip_height() + 1 {
+            return Err(Error::UnknownHeight(start - 1));
+        }
+        // validate against a copy to not leave the chain half updated
+        let keep = (start - self.start) as usize;
+        let mut chain = self.headers[..keep].to_vec();
+        for (i, header) in headers.iter().enumerate() {
+            let height = start + i as u32;
+            self.validate(height, &chain, header)?;
+            chain.push(*header);
+        }
+        // do not replace a branch by one w/ less work
+        if chain_work(&chain[keep..]) > chain_work(&self.headers[keep..]) {
+            if keep < self.headers.len() {
+                log::warn!("HeaderChain::extend() reorg at height {start}");
+            }
+            self.headers = chain;
+        }
+        Ok(())
+    }
+
+    /// Validate `header` at `height` against `chain`, the headers from the
+    ///   checkpoint to `height - 1`.
+    fn validate(
+        &self,
+        height: u32,
+        chain: &[block::Header],
+        header: &block::Header,
+    ) -> Result<(), Error> {
+        let prev = chain.last().expect("never empty");
+        if header.prev_blockhash != prev.block_hash() {
+            return Err(Error::BrokenChain(height));
+        }
+        let target = header.target();
+        if target > self.params.max_attainable_target || header.validate_pow(target).is_err() {
+            return Err(Error::InvalidPow(height));
+        }
+        if self.params.allow_min_difficulty_blocks || self.params.no_pow_retargeting {
+            return Ok(());
+        }
+        let interval = self.params.difficulty_adjustment_interval() as u32;
+        let expected = if height % interval == 0 {
+            // the first header of the previous period
+            let first = (height - interval)
+                .checked_sub(self.start)
+                .and_then(|i| chain.get(i as usize));
+            match first {
+                Some(first) => {
+                    let timespan = prev.time.saturating_sub(first.time) as u64;
+                    CompactTarget::from_next_work_required(prev.bits, timespan, &self.params)
+                }
+                // cannot be checked w/o the previous period
+                None => return Ok(()),
+            }
+        } else {
+            prev.bits
+        };
+        if header.bits != expected {
+            return Err(Error::UnexpectedDifficulty(height));
+        }
+        Ok(())
+    }
+
+    /// Verify that the transaction is included in the block at `height`.
+    ///
+    /// # Arguments
+    /// * `txid` - The txid of the transaction
+    /// * `height` - The height of the block including the transaction
+    /// * `pos` - The position of the transaction in the block
+    /// * `merkle` - The merkle branch as returned by electrum
+    ///   `blockchain.transaction.get_merkle`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the header at `height` is not yet synced
+    ///   - the merkle branch is malformed or do not match the header
+    pub fn verify_tx(
+        &self,
+        txid: Txid,
+        height: u32,
+        pos: usize,
+        merkle: &[String],
+    ) -> Result<(), Error> {
+        let header = self.header(height).ok_or(Error::UnknownHeight(height))?;
+        if merkle_root(txid, pos, merkle)? == header.merkle_root {
+            Ok(())
+        } else {
+            Err(Error::InvalidMerkleProof)
+        }
+    }
+}
+
+/// Returns the cumulative work of the headers, None if empty.
+fn chain_work(headers: &[block::Header]) -> Option<Work> {
+    headers.iter().map(block::Header::work).reduce(|a, b| a + b)
+}
+
+/// Compute the merkle root of a block from a transaction and its merkle
+///   branch.
+///
+/// # Errors
+///
+/// This function will return an error if:
+///   - the merkle branch is malformed
+///   - the branch is deeper than `usize::BITS` or `pos` does not fit in it
+pub fn merkle_root(txid: Txid, pos: usize, merkle: &[String]) -> Result<TxMerkleNode, Error> {
+    if merkle.len() >= usize::BITS as usize || pos >= 1 << merkle.len() {
+        return Err(Error::MerkleParsing);
+    }
+    let mut node = txid.to_raw_hash();
+    for (i, sibling) in merkle.iter().enumerate() {
+        let sibling = TxMerkleNode::from_str(sibling)
+            .map_err(|_| Error::MerkleParsing)?
+            .to_raw_hash();
+        let mut engine = sha256d::Hash::engine();
+        if (pos >> i) & 1 == 1 {
+            engine.input(sibling.as_byte_array());
+            engine.input(node.as_byte_array());
+        } else {
+            engine.input(node.as_byte_array());
+            engine.input(sibling.as_byte_array());
+        }
+        node = sha256d::Hash::from_engine(engine);
+    }
+    Ok(TxMerkleNode::from_raw_hash(node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_merkle_branch() {
+        let txid = genesis_block(Network::Bitcoin).txdata[0].compute_txid();
+        let sibling = txid.to_string();
+        let merkle = vec![sibling.clone(); usize::BITS as usize];
+        assert!(matches!(
+            merkle_root(txid, 0, &merkle),
+            Err(Error::MerkleParsing)
+        ));
+        assert!(matches!(
+            merkle_root(txid, 4, &merkle[..2]),
+            Err(Error::MerkleParsing)
+        ));
+        assert!(merkle_root(txid, 3, &merkle[..2]).is_ok());
+    }
+
+    #[test]
+    fn genesis_merkle_root() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let txid = genesis.txdata[0].compute_txid();
+        // a block w/ a single tx have its txid as merkle root
+        assert_eq!(
+            merkle_root(txid, 0, &[]).unwrap(),
+            genesis.header.merkle_root
+        );
+        // pos does not fit in an empty branch
+        assert!(matches!(
+            merkle_root(txid, 1, &[]),
+            Err(Error::MerkleParsing)
+        ));
+        let chain = HeaderChain::new(Network::Bitcoin);
+        chain.verify_tx(txid, 0, 0, &[]).unwrap();
+        assert_eq!(
+            chain.verify_tx(txid, 1, 0, &[]),
+            Err(Error::UnknownHeight(1))
+        );
+        let sibling = txid.to_string();
+        assert_eq!(
+            chain.verify_tx(txid, 0, 0, &[sibling]),
+            Err(Error::InvalidMerkleProof)
+        );
+    }
+
+    #[test]
+    fn broken_chain() {
+        let mut chain = HeaderChain::new(Network::Regtest);
+        let mut header = chain.header(0).copied().unwrap();
+        // do not commit to the genesis block
+        header.prev_blockhash = BlockHash::all_zeros();
+        assert_eq!(chain.extend(1, &[header]), Err(Error::BrokenChain(1)));
+        assert_eq!(chain.extend(2, &[header]), Err(Error::UnknownHeight(1)));
+        assert_eq!(chain.tip_height(), 0);
+    }
+
+    // mine a regtest header on top of `prev`
+    fn mine(prev: &block::Header, bits: CompactTarget) -> block::Header {
+        let mut header = block::Header {
+            prev_blockhash: prev.block_hash(),
+            time: prev.time + 600,
+            bits,
+            nonce: 0,
+            ..*prev
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn most_work_branch() {
+        let mut chain = HeaderChain::new(Network::Regtest);
+        let genesis = chain.header(0).copied().unwrap();
+        let easy = genesis.bits;
+        let hard = CompactTarget::from_consensus(0x1f00ffff);
+
+        // a longer branch w/ the minimal difficulty
+        let a1 = mine(&genesis, easy);
+        let a2 = mine(&a1, easy);
+        chain.extend(1, &[a1, a2]).unwrap();
+        assert_eq!(chain.tip_height(), 2);
+
+        // a shorter branch w/ more work replace it
+        let b1 = mine(&genesis, hard);
+        chain.extend(1, &[b1]).unwrap();
+        assert_eq!(chain.tip_height(), 1);
+        assert_eq!(chain.tip_hash(), b1.block_hash());
+
+        // a longer branch w/ less work do not
+        chain.extend(1, &[a1, a2]).unwrap();
+        assert_eq!(chain.tip_hash(), b1.block_hash());
+    }
+}
@@ -1,5 +1,6 @@
 use rand::{rng, Rng};
 use std::{
+    fmt::Debug,
     thread,
     time::{Duration, SystemTime},
 };
@@ -12,10 +13,48 @@ pub fn now() -> u64 {
         .as_secs()
 }
 
-/// wait for a random delay (200ms-5sec.)
-pub fn rand_delay() {
+// a random delay between 200ms & 5sec.
+fn random_delay() -> Duration {
     let mut rng = rng();
     let millis: u64 = rng.random_range(200..5000);
-    let delay = Duration::from_millis(millis);
-    thread::sleep(delay);
+    Duration::from_millis(millis)
+}
+
+/// wait for a random delay (200ms-5sec.)
+pub fn rand_delay() {
+    thread::sleep(random_delay());
+}
+
+/// A source of time for the coinjoin timelines & the random delays between
+///   the messages sent to a pool.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time, as a duration since the unix epoch.
+    fn unix_time(&self) -> Duration;
+
+    /// Returns the current timestamp, see [`now()`].
+    fn now(&self) -> u64 {
+        self.unix_time().as_secs()
+    }
+
+    /// Returns a random delay (200ms-5sec.), see [`rand_delay()`].
+    fn rand_delay(&self) -> Duration {
+        random_delay()
+    }
+
+    /// Block the current thread for `duration` of this clock.
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// The [`Clock`] of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn unix_time(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("unix timestamp must not fail")
+    }
 }
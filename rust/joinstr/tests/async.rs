@@ -0,0 +1,114 @@
+#![cfg(feature = "async")]
+
+pub mod utils;
+use std::time::Duration;
+
+use crate::utils::{bootstrap_electrs, funded_wallet_with_bitcoind};
+use electrsd::bitcoind::bitcoincore_rpc::RpcApi;
+use joinstr::{
+    electrum::Client,
+    joinstr::{r#async::Joinstr, Step},
+    nostr::r#async::NostrClient,
+    signer::{CoinPath, WpkhHotSigner},
+    simple_electrum_client::async_client::AsyncClient,
+    utils::now,
+};
+use miniscript::bitcoin::Network;
+use nostrd::NostrD;
+use simple_nostr_client::nostr::Keys;
+
+type AsyncJoinstr = Joinstr<NostrClient, AsyncClient>;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_coinjoin() {
+    let nostrd = NostrD::new().unwrap();
+    let relay = nostrd.url();
+    let (url, port, _electrsd, bitcoind) = bootstrap_electrs();
+
+    let mut pool_listener = NostrClient::new("pool_listener")
+        .relay(relay.clone())
+        .unwrap()
+        .keys(Keys::generate())
+        .unwrap();
+    pool_listener.connect_nostr().await.unwrap();
+    pool_listener.subscribe_pools(24 * 60 * 60).await.unwrap();
+
+    let (backend, _) = AsyncClient::connect(&url, port, false, false)
+        .await
+        .unwrap();
+    let mut coordinator = AsyncJoinstr::new_initiator(
+        Keys::generate(),
+        &relay,
+        backend,
+        Network::Regtest,
+        "initiator",
+    )
+    .await
+    .unwrap()
+    .denomination(0.01)
+    .unwrap()
+    .fee(10)
+    .unwrap()
+    .simple_timeout(now() + 60)
+    .unwrap()
+    .min_peers(2)
+    .unwrap();
+    let coordinator_handle = tokio::spawn(async move {
+        coordinator
+            .start_coinjoin(None, Option::<WpkhHotSigner>::None)
+            .await
+            .unwrap();
+        coordinator
+    });
+
+    // wait for the 2022 event to be broadcast
+    let pool = loop {
+        if let Some(pool) = pool_listener.receive_pool_notification().unwrap() {
+            break pool;
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+    };
+
+    let mut signer = funded_wallet_with_bitcoind(&[0.011, 0.011], &bitcoind);
+    signer.set_client(Client::new(&url, port).unwrap());
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(signer.get_coins_at(CoinPath::new(0, 0)).unwrap(), 1);
+    assert_eq!(signer.get_coins_at(CoinPath::new(0, 1)).unwrap(), 1);
+    let coins = signer.list_coins();
+    assert_eq!(coins.len(), 2);
+
+    let mut peers = vec![];
+    for (i, (_, coin)) in coins.into_iter().enumerate() {
+        let address = signer
+            .address_at(&CoinPath::new(0, 100 + i as u32))
+            .unwrap()
+            .as_unchecked()
+            .clone();
+        let mut peer = AsyncJoinstr::new_peer(
+            &relay,
+            &pool,
+            coin,
+            address,
+            Network::Regtest,
+            &format!("peer_{i}"),
+        )
+        .await
+        .unwrap();
+        let pool = pool.clone();
+        let signer = signer.clone();
+        peers.push(tokio::spawn(async move {
+            peer.start_coinjoin(Some(pool), Some(signer)).await
+        }));
+    }
+
+    let coordinator = coordinator_handle.await.unwrap();
+    assert_eq!(coordinator.status().step(), Step::Broadcast);
+    let final_tx = coordinator.final_tx().unwrap();
+    let _tx = bitcoind
+        .client
+        .get_raw_transaction(&final_tx.compute_txid(), None)
+        .unwrap();
+    for peer in peers {
+        peer.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+pub mod utils;
+use crate::utils::{bootstrap_electrs, generate};
+
+use electrsd::bitcoind::bitcoincore_rpc::RpcApi;
+use joinstr::{
+    backend::ChainSource,
+    bitcoind::{Auth, BitcoindBackend},
+    coinjoin::BitcoinBackend,
+    signer::WpkhHotSigner,
+};
+use miniscript::bitcoin::{Amount, Network, OutPoint};
+
+#[test]
+fn bitcoind_backend() {
+    let (_, _, _electrsd, bitcoind) = bootstrap_electrs();
+    let mut backend = BitcoindBackend::new(
+        &bitcoind.rpc_url(),
+        Auth::Cookie(bitcoind.params.cookie_file.clone()),
+    );
+    assert_eq!(backend.ping().unwrap(), 101);
+
+    let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+    let addr = signer.recv_addr_at(0);
+    assert!(!backend.address_already_used(&addr).unwrap());
+
+    let amount = Amount::from_btc(0.1).unwrap();
+    let txid = bitcoind
+        .client
+        .send_to_address(&addr, amount, None, None, None, None, None, None)
+        .unwrap();
+    // NOTE: bitcoind runs w/o txindex, we can only fetch mempool txs
+    let tx = backend.get_tx(txid).unwrap();
+    assert_eq!(tx.compute_txid(), txid);
+    let vout = tx
+        .output
+        .iter()
+        .position(|o| o.script_pubkey == addr.script_pubkey())
+        .unwrap() as u32;
+    generate(&bitcoind, 1);
+
+    assert!(backend.address_already_used(&addr).unwrap());
+    assert_eq!(
+        backend
+            .get_outpoint_value(OutPoint::new(txid, vout))
+            .unwrap(),
+        Some(amount)
+    );
+    assert_eq!(
+        backend
+            .get_outpoint_value(OutPoint::new(txid, 1000))
+            .unwrap(),
+        None
+    );
+    assert!(matches!(
+        backend.broadcast(&tx),
+        Err(joinstr::bitcoind::Error::Rpc(..))
+    ));
+
+    let (txout, spent_by) = ChainSource::get_txout(&mut backend, OutPoint::new(txid, vout))
+        .unwrap()
+        .unwrap();
+    assert_eq!(txout.script_pubkey, addr.script_pubkey());
+    assert!(spent_by.is_none());
+
+    // spend the whole wallet balance, change of `tx` included
+    let change = OutPoint::new(txid, 1 - vout);
+    assert!(ChainSource::get_txout(&mut backend, change)
+        .unwrap()
+        .is_some());
+    let balance = bitcoind.client.get_balance(None, None).unwrap();
+    let other = signer.recv_addr_at(1);
+    bitcoind
+        .client
+        .send_to_address(&other, balance, None, None, Some(true), None, None, None)
+        .unwrap();
+    assert!(ChainSource::get_txout(&mut backend, change)
+        .unwrap()
+        .is_none());
+
+    let mut wrong_auth = BitcoindBackend::new(
+        &bitcoind.rpc_url(),
+        Auth::UserPass("user".into(), "wrong".into()),
+    );
+    assert!(matches!(
+        wrong_auth.ping(),
+        Err(joinstr::bitcoind::Error::Status(401, _))
+    ));
+}
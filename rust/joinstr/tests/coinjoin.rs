@@ -12,12 +12,7 @@ fn simple_tx() {
 
     signer.set_client(client.clone());
 
-    let coin = signer
-        .get_coins_at(CoinPath {
-            depth: 0,
-            index: Some(0),
-        })
-        .unwrap();
+    let coin = signer.get_coins_at(CoinPath::new(0, 0)).unwrap();
     assert_eq!(coin, 1);
 
     // generate output addresses
@@ -53,12 +48,7 @@ fn simple_coinjoin() {
 
     // fetch input data for each coins
     (0..5).for_each(|i| {
-        let coin = signer
-            .get_coins_at(CoinPath {
-                depth: 0,
-                index: Some(i),
-            })
-            .unwrap();
+        let coin = signer.get_coins_at(CoinPath::new(0, i)).unwrap();
         assert_eq!(coin, 1);
     });
 
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use joinstr::{
+    coinjoin::BitcoinBackend,
+    esplora::{Error, EsploraClient},
+    signer::{CoinPath, WpkhHotSigner},
+    simple_electrum_client::electrum::types::ScriptHash,
+};
+use miniscript::bitcoin::{
+    absolute::LockTime, consensus::encode::serialize_hex, transaction::Version, Amount, FeeRate,
+    Network, OutPoint, Transaction, TxIn, TxOut,
+};
+
+type Routes = HashMap<String /* "METHOD /path" */, (u16, String)>;
+
+/// Spawn a mock HTTP server answering the given routes, returns its url and
+///   the list of (request line, body) it received.
+fn mock_server(routes: Routes) -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let rcvd = received.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(l) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = l.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let route = request_line
+                .rsplit_once(' ')
+                .map(|(r, _)| r.to_string())
+                .unwrap();
+            rcvd.lock()
+                .unwrap()
+                .push((route.clone(), String::from_utf8(body).unwrap()));
+            let (status, body) = routes
+                .get(&route)
+                .cloned()
+                .unwrap_or((404, "Not found".into()));
+            let response = format!(
+                "HTTP/1.1 {status} OK\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (url, received)
+}
+
+#[test]
+fn esplora_backend() {
+    let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+    let coin_path = CoinPath::new(0, 0);
+    let addr = signer.address_at(&coin_path).unwrap();
+    let unused = signer.address_at(&CoinPath::new(0, 1)).unwrap();
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: addr.script_pubkey(),
+        }],
+    };
+    let txid = tx.compute_txid();
+    let sh = ScriptHash::new(&addr.script_pubkey());
+    let unused_sh = ScriptHash::new(&unused.script_pubkey());
+
+    let mut routes = Routes::new();
+    routes.insert(
+        format!("GET /api/scripthash/{sh}/txs"),
+        (
+            200,
+            format!(r#"[{{"txid":"{txid}","status":{{"confirmed":true,"block_height":102}}}}]"#),
+        ),
+    );
+    routes.insert(
+        format!("GET /api/scripthash/{unused_sh}/txs"),
+        (200, "[]".into()),
+    );
+    routes.insert(format!("GET /api/tx/{txid}/hex"), (200, serialize_hex(&tx)));
+    routes.insert("POST /api/tx".into(), (200, txid.to_string()));
+    routes.insert(
+        "GET /api/fee-estimates".into(),
+        (200, r#"{"1":20.0,"6":10.0,"144":1.0}"#.into()),
+    );
+    routes.insert(
+        "GET /api/mempool".into(),
+        (
+            200,
+            r#"{"count":3,"vsize":600,"total_fee":4000,"fee_histogram":[[12.5,100],[2.0,500]]}"#
+                .into(),
+        ),
+    );
+    let (url, received) = mock_server(routes);
+    let mut client = EsploraClient::new(&format!("{url}/api/"));
+
+    // BitcoinBackend
+    assert!(client.address_already_used(&addr).unwrap());
+    assert!(!client.address_already_used(&unused).unwrap());
+    assert_eq!(
+        client.get_outpoint_value(OutPoint::new(txid, 0)).unwrap(),
+        Some(Amount::from_sat(100_000))
+    );
+    let missing = OutPoint::new(tx.input[0].previous_output.txid, 0);
+    assert_eq!(client.get_outpoint_value(missing).unwrap(), None);
+    assert!(matches!(
+        client.get_outpoint_value(OutPoint::new(txid, 1)),
+        Err(Error::WrongOutPoint)
+    ));
+
+    // tx fetch, broadcast & fee estimation
+    assert_eq!(client.get_tx(txid).unwrap(), tx);
+    assert_eq!(client.broadcast(&tx).unwrap(), txid);
+    assert!(received
+        .lock()
+        .unwrap()
+        .contains(&("POST /api/tx".into(), serialize_hex(&tx))));
+    assert_eq!(
+        client.estimate_fee(10).unwrap(),
+        Some(FeeRate::from_sat_per_vb_unchecked(10))
+    );
+    assert_eq!(client.estimate_fee(0).unwrap(), None);
+    assert_eq!(
+        client.fee_histogram().unwrap(),
+        vec![
+            (FeeRate::from_sat_per_kwu(3125), 100),
+            (FeeRate::from_sat_per_vb_unchecked(2), 500)
+        ]
+    );
+
+    // coin listing from the signer
+    let mut signer = signer.client(client);
+    assert_eq!(signer.get_coins_at(coin_path).unwrap(), 1);
+    let coins = signer.list_coins();
+    assert_eq!(coins.len(), 1);
+    assert_eq!(coins[0].1.outpoint, OutPoint::new(txid, 0));
+    // coins are not added twice
+    assert_eq!(signer.get_coins_at(coin_path).unwrap(), 0);
+}
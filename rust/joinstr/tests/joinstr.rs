@@ -166,20 +166,10 @@ fn simple_coinjoin() {
     sleep(Duration::from_secs(2));
 
     // fetch coins on electrum server
-    let coin = signer
-        .get_coins_at(CoinPath {
-            depth: 0,
-            index: Some(0),
-        })
-        .unwrap();
+    let coin = signer.get_coins_at(CoinPath::new(0, 0)).unwrap();
     assert_eq!(coin, 1);
 
-    let coin = signer
-        .get_coins_at(CoinPath {
-            depth: 0,
-            index: Some(1),
-        })
-        .unwrap();
+    let coin = signer.get_coins_at(CoinPath::new(0, 1)).unwrap();
     assert_eq!(coin, 1);
 
     // get list of fetched coins
@@ -187,18 +177,12 @@ fn simple_coinjoin() {
     assert_eq!(coins.len(), 2);
 
     let address_a = signer
-        .address_at(&CoinPath {
-            depth: 0,
-            index: Some(100),
-        })
+        .address_at(&CoinPath::new(0, 100))
         .unwrap()
         .as_unchecked()
         .clone();
     let address_b = signer
-        .address_at(&CoinPath {
-            depth: 0,
-            index: Some(101),
-        })
+        .address_at(&CoinPath::new(0, 101))
         .unwrap()
         .as_unchecked()
         .clone();
@@ -239,3 +223,138 @@ fn simple_coinjoin() {
         .get_raw_transaction(&final_tx.compute_txid(), None)
         .unwrap();
 }
+
+#[test]
+fn multi_input_coinjoin() {
+    let mut relay = Relay::new();
+    let relays = relay.url();
+    let keys = Keys::generate();
+    let (url, port, _electrsd, bitcoind) = bootstrap_electrs();
+
+    let mut pool_listener = NostrClient::new("pool_listener")
+        .relay(relays.clone())
+        .unwrap()
+        .keys(Keys::generate())
+        .unwrap();
+    pool_listener.connect_nostr().unwrap();
+    // subscribe to 2020 event up to 1 day back in time
+    pool_listener.subscribe_pools(24 * 60 * 60).unwrap();
+
+    // start a separate coordinator
+    let mut coordinator = Joinstr::new_initiator(
+        keys.clone(),
+        relays.clone(),
+        (&url, port),
+        Network::Regtest,
+        "initiator",
+    )
+    .unwrap()
+    .denomination(0.01)
+    .unwrap()
+    .fee(10)
+    .unwrap()
+    .simple_timeout(now() + 60)
+    .unwrap()
+    .min_peers(3)
+    .unwrap();
+
+    let coordinator_handle = thread::spawn(move || {
+        coordinator
+            .start_coinjoin_blocking(None, Option::<WpkhHotSigner>::None, || {})
+            .unwrap();
+        coordinator.final_tx()
+    });
+
+    clear_nostr_log(&mut relay);
+
+    // wait for the 2022 event to be broadcast
+    let pool;
+    loop {
+        if let Some(notif) = pool_listener.receive_pool_notification().unwrap() {
+            pool = notif;
+            break;
+        }
+        sleep(Duration::from_millis(300));
+        clear_nostr_log(&mut relay);
+    }
+
+    log::info!("Received pool notification.");
+
+    let mut signer = funded_wallet_with_bitcoind(&[0.011, 0.011, 0.011], &bitcoind);
+    let client = Client::new(&url, port).unwrap();
+    signer.set_client(client);
+
+    sleep(Duration::from_secs(2));
+
+    // fetch coins on electrum server
+    let coin = signer.get_coins_at(CoinPath::new(0, 0)).unwrap();
+    assert_eq!(coin, 1);
+
+    let coin = signer.get_coins_at(CoinPath::new(0, 1)).unwrap();
+    assert_eq!(coin, 1);
+
+    let coin = signer.get_coins_at(CoinPath::new(0, 2)).unwrap();
+    assert_eq!(coin, 1);
+
+    // get list of fetched coins
+    let coins = signer.list_coins();
+    assert_eq!(coins.len(), 3);
+
+    let address_a = signer
+        .address_at(&CoinPath::new(0, 100))
+        .unwrap()
+        .as_unchecked()
+        .clone();
+    let address_b = signer
+        .address_at(&CoinPath::new(0, 101))
+        .unwrap()
+        .as_unchecked()
+        .clone();
+    let address_c = signer
+        .address_at(&CoinPath::new(0, 102))
+        .unwrap()
+        .as_unchecked()
+        .clone();
+
+    let mut peer_a = Joinstr::new_peer(
+        relays.clone(),
+        &pool,
+        coins[0].1.clone(),
+        address_a,
+        Network::Regtest,
+        "peer_a",
+    )
+    .unwrap();
+
+    // peer_a register 2 inputs & 2 outputs
+    peer_a.add_coin(coins[2].1.clone()).unwrap();
+    peer_a.add_address(address_c).unwrap();
+
+    let mut peer_b = Joinstr::new_peer(
+        relays.clone(),
+        &pool,
+        coins[1].1.clone(),
+        address_b,
+        Network::Regtest,
+        "peer_b",
+    )
+    .unwrap();
+
+    let signer_a = signer.clone();
+    let pool_a = pool.clone();
+    let _peer_a = thread::spawn(move || {
+        let _ = peer_a.start_coinjoin_blocking(Some(pool_a), Some(signer_a), || {});
+    });
+
+    let _peer_b = thread::spawn(move || {
+        let _ = peer_b.start_coinjoin_blocking(Some(pool), Some(signer), || {});
+    });
+
+    let final_tx = coordinator_handle.join().unwrap().unwrap();
+    let _tx = bitcoind
+        .client
+        .get_raw_transaction(&final_tx.compute_txid(), None)
+        .unwrap();
+    assert_eq!(final_tx.input.len(), 3);
+    assert_eq!(final_tx.output.len(), 3);
+}
@@ -0,0 +1,568 @@
+#![cfg(feature = "sim")]
+
+use std::{
+    str::FromStr,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use joinstr::{
+    backend::{self, memory::MemoryChain},
+    joinstr::{Driver, Error, Joinstr, Step},
+    nostr::{InputDataSigned, Pool, PoolMessage},
+    signer::WpkhHotSigner,
+    sim::{funded_signer, SimClient, SimClock, SimConnector, SimJoinstr, SimRelay, NETWORK},
+    utils::Clock,
+};
+use miniscript::bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, Transaction, TxIn, TxOut,
+};
+use simple_nostr_client::nostr::Keys;
+
+const DENOMINATION: f64 = 0.01;
+// denomination + fee share
+const COIN: Amount = Amount::from_sat(1_010_000);
+// speed of the clock of the sync simulations, that run on the real clock
+const SYNC_SPEED: u32 = 50;
+// min proof of work of the join requests of the pools w/ a ban list
+const POW: u8 = 8;
+// delay between two checks of the relay events by mallory
+const POLL: Duration = Duration::from_millis(1);
+
+struct Sim {
+    relay: SimRelay,
+    chain: MemoryChain,
+    clock: Arc<SimClock>,
+}
+
+impl Sim {
+    fn new(seed: u64) -> Self {
+        Sim {
+            relay: SimRelay::new(),
+            chain: MemoryChain::new(),
+            clock: Arc::new(SimClock::new(seed)),
+        }
+    }
+
+    async fn initiator(&self, peers: usize, timeout: u64) -> SimJoinstr {
+        SimJoinstr::new_initiator(
+            Keys::generate(),
+            &self.relay.url(),
+            self.chain.clone(),
+            NETWORK,
+            "initiator",
+        )
+        .await
+        .unwrap()
+        .clock(self.clock.clone())
+        .denomination(DENOMINATION)
+        .unwrap()
+        .fee(10)
+        .unwrap()
+        .simple_timeout(self.clock.now() + timeout)
+        .unwrap()
+        .min_peers(peers)
+        .unwrap()
+    }
+
+    fn sync(seed: u64) -> Self {
+        Sim {
+            relay: SimRelay::new(),
+            chain: MemoryChain::new(),
+            clock: Arc::new(SimClock::new(seed).speed(SYNC_SPEED)),
+        }
+    }
+
+    fn sync_initiator(&self, peers: usize, timeout: u64) -> Joinstr<'static> {
+        Joinstr::new_initiator_with_chain_source(
+            Keys::generate(),
+            self.relay.url(),
+            backend::shared(self.chain.clone()),
+            NETWORK,
+            "initiator",
+        )
+        .unwrap()
+        .connector(Arc::new(SimConnector))
+        .clock(self.clock.clone())
+        .denomination(DENOMINATION)
+        .unwrap()
+        .fee(10)
+        .unwrap()
+        .simple_timeout(self.clock.now() + timeout)
+        .unwrap()
+        .min_peers(peers)
+        .unwrap()
+    }
+
+    fn sync_peer(&self, pool: &Pool, name: &str) -> (Joinstr<'static>, WpkhHotSigner) {
+        let (signer, coin) = funded_signer(&self.chain, COIN).unwrap();
+        let address = signer.recv_addr_at(100).as_unchecked().clone();
+        let peer = Joinstr::new_peer(self.relay.url(), pool, coin, address, NETWORK, name)
+            .unwrap()
+            .connector(Arc::new(SimConnector))
+            .clock(self.clock.clone())
+            .chain_source(backend::shared(self.chain.clone()));
+        (peer, signer)
+    }
+
+    // Inputs that must be dropped by the pool: a copy of an input already
+    // registered, an input w/ a wrong amount & an input whose coin has been
+    // spent by another transaction.
+    fn bad_inputs(&self, registered: InputDataSigned) -> Vec<String> {
+        let input = |txin: TxIn, amount: Amount| {
+            PoolMessage::Input(InputDataSigned {
+                txin,
+                amount: Some(amount),
+            })
+            .to_string()
+            .unwrap()
+        };
+        let (_, lying) = funded_signer(&self.chain, COIN).unwrap();
+        let (_, spent) = funded_signer(&self.chain, COIN).unwrap();
+        let spend = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: spent.outpoint,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000_000),
+                script_pubkey: spent.txout.script_pubkey.clone(),
+            }],
+        };
+        self.chain.add_tx(spend, Some(2));
+        let txin = |outpoint| TxIn {
+            previous_output: outpoint,
+            ..Default::default()
+        };
+        vec![
+            input(registered.txin, COIN),
+            input(txin(lying.outpoint), Amount::from_sat(5_000_000)),
+            input(txin(spent.outpoint), COIN),
+        ]
+    }
+
+    // Returns the first input registered to a pool on the relay, if any.
+    fn registered_input(&self) -> Option<InputDataSigned> {
+        self.relay.events().into_iter().find_map(|e| {
+            match PoolMessage::from_str(&e.content).ok()? {
+                PoolMessage::Input(input) => Some(input),
+                PoolMessage::Psbt(psbt) => psbt.try_into().ok(),
+                _ => None,
+            }
+        })
+    }
+
+    async fn peer(&self, pool: &Pool, name: &str) -> (SimJoinstr, WpkhHotSigner) {
+        let (signer, coin) = funded_signer(&self.chain, COIN).unwrap();
+        let address = signer.recv_addr_at(100).as_unchecked().clone();
+        let peer = SimJoinstr::new_peer(&self.relay.url(), pool, coin, address, NETWORK, name)
+            .await
+            .unwrap()
+            .clock(self.clock.clone())
+            .backend(self.chain.clone());
+        (peer, signer)
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_round() {
+    let sim = Sim::new(1);
+    let mut initiator = sim.initiator(3, 600).await;
+
+    let peers = async {
+        let pool = sim.relay.wait_pool().await;
+        let (mut a, signer_a) = sim.peer(&pool, "peer_a").await;
+        let (mut b, signer_b) = sim.peer(&pool, "peer_b").await;
+        let (mut c, signer_c) = sim.peer(&pool, "peer_c").await;
+        let (ra, rb, rc) = tokio::join!(
+            a.start_coinjoin(Some(pool.clone()), Some(signer_a)),
+            b.start_coinjoin(Some(pool.clone()), Some(signer_b)),
+            c.start_coinjoin(Some(pool), Some(signer_c)),
+        );
+        ra.unwrap();
+        rb.unwrap();
+        rc.unwrap();
+        [a, b, c]
+    };
+    let (result, peers) = tokio::join!(
+        initiator.start_coinjoin(None, Option::<WpkhHotSigner>::None),
+        peers
+    );
+    result.unwrap();
+
+    let status = initiator.status();
+    assert_eq!(status.step(), Step::Broadcast);
+    assert_eq!(status.registered_peers(), 3);
+    assert_eq!(status.registered_inputs(), 3);
+    let tx = initiator.final_tx().unwrap().clone();
+    assert_eq!(tx.input.len(), 3);
+    assert_eq!(tx.output.len(), 3);
+    for peer in peers {
+        assert_eq!(peer.final_tx(), Some(&tx));
+    }
+    assert!(sim.chain.broadcasted().contains(&tx));
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_not_enough_peers() {
+    let sim = Sim::new(2);
+    let mut initiator = sim.initiator(3, 60).await;
+    let start = sim.clock.now();
+
+    let peers = async {
+        let pool = sim.relay.wait_pool().await;
+        let (mut a, signer_a) = sim.peer(&pool, "peer_a").await;
+        let (mut b, signer_b) = sim.peer(&pool, "peer_b").await;
+        let (ra, rb) = tokio::join!(
+            a.start_coinjoin(Some(pool.clone()), Some(signer_a)),
+            b.start_coinjoin(Some(pool), Some(signer_b)),
+        );
+        assert!(matches!(ra, Err(Error::NotEnoughPeers(2, 3))));
+        assert!(matches!(rb, Err(Error::NotEnoughPeers(2, 3))));
+    };
+    let (result, _) = tokio::join!(
+        initiator.start_coinjoin(None, Option::<WpkhHotSigner>::None),
+        peers
+    );
+    assert!(matches!(result, Err(Error::NotEnoughPeers(2, 3))));
+    assert_eq!(initiator.status().step(), Step::Failed);
+    // the round ends at the pool timeout
+    assert!(sim.clock.now() >= start + 60);
+    assert!(sim.chain.broadcasted().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_peer_dropout() {
+    let sim = Sim::new(3);
+    let mut initiator = sim.initiator(3, 60).await;
+
+    let peers = async {
+        let pool = sim.relay.wait_pool().await;
+        // a peer that join the pool then never register its output
+        let lurker = SimClient::new(&sim.relay, Keys::generate(), "lurker");
+        let join = PoolMessage::Join(Some(lurker.public_key()))
+            .to_string()
+            .unwrap();
+        lurker.send_raw(&pool.public_key, &join, None).unwrap();
+
+        let (mut a, signer_a) = sim.peer(&pool, "peer_a").await;
+        let (mut b, signer_b) = sim.peer(&pool, "peer_b").await;
+        let (ra, rb) = tokio::join!(
+            a.start_coinjoin(Some(pool.clone()), Some(signer_a)),
+            b.start_coinjoin(Some(pool), Some(signer_b)),
+        );
+        assert!(matches!(ra, Err(Error::PeerCountNotMatch(2, 3))));
+        assert!(matches!(rb, Err(Error::PeerCountNotMatch(2, 3))));
+    };
+    let (result, _) = tokio::join!(
+        initiator.start_coinjoin(None, Option::<WpkhHotSigner>::None),
+        peers
+    );
+    assert!(matches!(result, Err(Error::PeerCountNotMatch(2, 3))));
+    assert!(sim.chain.broadcasted().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_malicious_messages() {
+    let sim = Sim::new(4);
+    let mallory = SimClient::new(&sim.relay, Keys::generate(), "mallory");
+    let mut initiator = sim
+        .initiator(2, 600)
+        .await
+        .pow(POW)
+        .unwrap()
+        .ban_peers([mallory.public_key()])
+        .unwrap();
+
+    let peers = async {
+        let pool = sim.relay.wait_pool().await;
+        let npub = pool.public_key;
+        // garbage is dropped
+        mallory.send_raw(&npub, "", None).unwrap();
+        mallory.send_raw(&npub, "{not json", None).unwrap();
+        mallory
+            .send_raw(&npub, r#"{"type": "join_pool", "npub": "xx"}"#, None)
+            .unwrap();
+        mallory
+            .send_raw(&npub, r#"{"type": "input", "psbt": 42}"#, None)
+            .unwrap();
+        // the join requests answered to the banned key are ignored, even w/
+        // enough proof of work
+        let join = PoolMessage::Join(Some(mallory.public_key()))
+            .to_string()
+            .unwrap();
+        mallory.send_raw(&npub, &join, Some(POW)).unwrap();
+        mallory
+            .send_raw(&npub, r#"{"type": "join_pool"}"#, Some(POW))
+            .unwrap();
+        // the ban is evaded w/ a fresh key, but a join request w/o enough
+        // proof of work is ignored
+        let join = PoolMessage::Join(Some(Keys::generate().public_key()))
+            .to_string()
+            .unwrap();
+        mallory.send_raw(&npub, &join, None).unwrap();
+
+        let (mut a, signer_a) = sim.peer(&pool, "peer_a").await;
+        let (mut b, signer_b) = sim.peer(&pool, "peer_b").await;
+        let (ra, rb) = tokio::join!(
+            a.start_coinjoin(Some(pool.clone()), Some(signer_a)),
+            b.start_coinjoin(Some(pool), Some(signer_b)),
+        );
+        ra.unwrap();
+        rb.unwrap();
+    };
+    let (result, _) = tokio::join!(
+        initiator.start_coinjoin(None, Option::<WpkhHotSigner>::None),
+        peers
+    );
+    result.unwrap();
+    assert_eq!(initiator.status().registered_peers(), 2);
+    assert_eq!(initiator.final_tx().unwrap().input.len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn sim_malicious_inputs() {
+    let sim = Sim::new(5);
+    let mut initiator = sim.initiator(2, 600).await;
+
+    let peers = async {
+        let pool = sim.relay.wait_pool().await;
+        let (mut a, signer_a) = sim.peer(&pool, "peer_a").await;
+        let (mut b, signer_b) = sim.peer(&pool, "peer_b").await;
+        let (ra, rb) = tokio::join!(
+            a.start_coinjoin(Some(pool.clone()), Some(signer_a)),
+            b.start_coinjoin(Some(pool), Some(signer_b)),
+        );
+        ra.unwrap();
+        rb.unwrap();
+    };
+    // once a first input is registered, any of these inputs would complete
+    // the coinjoin if it were accepted
+    let mallory = async {
+        let pool = sim.relay.wait_pool().await;
+        let registered = loop {
+            if let Some(input) = sim.registered_input() {
+                break input;
+            }
+            tokio::time::sleep(POLL).await;
+        };
+        let client = SimClient::new(&sim.relay, Keys::generate(), "mallory");
+        for input in sim.bad_inputs(registered) {
+            client.send_raw(&pool.public_key, &input, None).unwrap();
+        }
+    };
+    let (result, _, _) = tokio::join!(
+        initiator.start_coinjoin(None, Option::<WpkhHotSigner>::None),
+        peers,
+        mallory,
+    );
+    result.unwrap();
+    assert_eq!(initiator.status().registered_inputs(), 2);
+    let tx = initiator.final_tx().unwrap().clone();
+    assert_eq!(tx.input.len(), 2);
+    assert!(sim.chain.broadcasted().contains(&tx));
+}
+
+// Start the coinjoin of a sync initiator on its own thread.
+fn spawn_initiator(initiator: &Joinstr<'static>) -> JoinHandle<Result<(), Error>> {
+    let mut initiator = initiator.clone();
+    thread::spawn(move || {
+        initiator.start_coinjoin_blocking(None, Option::<WpkhHotSigner>::None, || {})
+    })
+}
+
+// Drive the coinjoins of several initiators from a single thread, until they
+// all end.
+fn spawn_driver(
+    mut initiators: Vec<(Joinstr<'static>, Driver<'static>)>,
+) -> JoinHandle<Vec<Result<(), Error>>> {
+    thread::spawn(move || {
+        let mut results: Vec<_> = initiators.iter().map(|_| None).collect();
+        while results.iter().any(Option::is_none) {
+            for ((initiator, driver), result) in initiators.iter_mut().zip(results.iter_mut()) {
+                if result.is_none() {
+                    match initiator.poll(driver, || {}) {
+                        Ok(false) => {}
+                        Ok(true) => *result = Some(Ok(())),
+                        Err(e) => *result = Some(Err(e)),
+                    }
+                }
+            }
+            thread::sleep(POLL);
+        }
+        results.into_iter().flatten().collect()
+    })
+}
+
+// Run the coinjoin of sync peers, each on its own thread.
+fn run_peers(peers: &[(Joinstr<'static>, WpkhHotSigner)], pool: &Pool) -> Vec<Result<(), Error>> {
+    let handles: Vec<_> = peers
+        .iter()
+        .map(|(peer, signer)| {
+            let (mut peer, signer, pool) = (peer.clone(), signer.clone(), pool.clone());
+            thread::spawn(move || peer.start_coinjoin_blocking(Some(pool), Some(signer), || {}))
+        })
+        .collect();
+    handles
+        .into_iter()
+        .map(|h| h.join().expect("peer panicked"))
+        .collect()
+}
+
+#[test]
+fn sync_sim_round() {
+    let sim = Sim::sync(1);
+    let initiator = sim.sync_initiator(3, 600);
+    let handle = spawn_initiator(&initiator);
+
+    let pool = sim.relay.wait_pool_blocking();
+    let peers: Vec<_> = ["peer_a", "peer_b", "peer_c"]
+        .into_iter()
+        .map(|name| sim.sync_peer(&pool, name))
+        .collect();
+    for result in run_peers(&peers, &pool) {
+        result.unwrap();
+    }
+    handle.join().expect("initiator panicked").unwrap();
+
+    let status = initiator.status();
+    assert_eq!(status.step(), Step::Broadcast);
+    assert_eq!(status.registered_peers(), 3);
+    assert_eq!(status.registered_inputs(), 3);
+    let tx = initiator.final_tx().unwrap();
+    assert_eq!(tx.input.len(), 3);
+    assert_eq!(tx.output.len(), 3);
+    for (peer, _) in peers {
+        assert_eq!(peer.final_tx(), Some(tx.clone()));
+    }
+    assert!(sim.chain.broadcasted().contains(&tx));
+}
+
+#[test]
+fn sync_sim_not_enough_peers() {
+    let sim = Sim::sync(2);
+    let initiator = sim.sync_initiator(3, 60);
+    let start = sim.clock.now();
+    let handle = spawn_initiator(&initiator);
+
+    let pool = sim.relay.wait_pool_blocking();
+    let peers: Vec<_> = ["peer_a", "peer_b"]
+        .into_iter()
+        .map(|name| sim.sync_peer(&pool, name))
+        .collect();
+    for result in run_peers(&peers, &pool) {
+        assert!(matches!(result, Err(Error::NotEnoughPeers(2, 3))));
+    }
+    let result = handle.join().expect("initiator panicked");
+    assert!(matches!(result, Err(Error::NotEnoughPeers(2, 3))));
+    // the round ends at the pool timeout
+    assert!(sim.clock.now() >= start + 60);
+    assert!(sim.chain.broadcasted().is_empty());
+}
+
+#[test]
+fn sync_sim_peer_dropout() {
+    let sim = Sim::sync(3);
+    let initiator = sim.sync_initiator(3, 60);
+    let handle = spawn_initiator(&initiator);
+
+    let pool = sim.relay.wait_pool_blocking();
+    // a peer that join the pool then never register its output
+    let lurker = SimClient::new(&sim.relay, Keys::generate(), "lurker");
+    let join = PoolMessage::Join(Some(lurker.public_key()))
+        .to_string()
+        .unwrap();
+    lurker.send_raw(&pool.public_key, &join, None).unwrap();
+
+    let peers: Vec<_> = ["peer_a", "peer_b"]
+        .into_iter()
+        .map(|name| sim.sync_peer(&pool, name))
+        .collect();
+    for result in run_peers(&peers, &pool) {
+        assert!(matches!(result, Err(Error::PeerCountNotMatch(2, 3))));
+    }
+    let result = handle.join().expect("initiator panicked");
+    assert!(matches!(result, Err(Error::PeerCountNotMatch(2, 3))));
+    assert!(sim.chain.broadcasted().is_empty());
+}
+
+#[test]
+fn sync_sim_malicious_inputs() {
+    let sim = Sim::sync(5);
+    let initiator = sim.sync_initiator(2, 600);
+    let handle = spawn_initiator(&initiator);
+
+    let pool = sim.relay.wait_pool_blocking();
+    let peers: Vec<_> = ["peer_a", "peer_b"]
+        .into_iter()
+        .map(|name| sim.sync_peer(&pool, name))
+        .collect();
+    let mallory = thread::scope(|scope| {
+        let mallory = scope.spawn(|| {
+            // once a first input is registered, any of these inputs would
+            // complete the coinjoin if it were accepted
+            let registered = loop {
+                if let Some(input) = sim.registered_input() {
+                    break input;
+                }
+                thread::sleep(POLL);
+            };
+            let client = SimClient::new(&sim.relay, Keys::generate(), "mallory");
+            for input in sim.bad_inputs(registered) {
+                client.send_raw(&pool.public_key, &input, None).unwrap();
+            }
+        });
+        for result in run_peers(&peers, &pool) {
+            result.unwrap();
+        }
+        mallory.join()
+    });
+    mallory.expect("mallory panicked");
+    handle.join().expect("initiator panicked").unwrap();
+
+    assert_eq!(initiator.status().registered_inputs(), 2);
+    let tx = initiator.final_tx().unwrap();
+    assert_eq!(tx.input.len(), 2);
+    assert!(sim.chain.broadcasted().contains(&tx));
+}
+
+#[test]
+fn sync_sim_driven_rounds() {
+    let sim = Sim::sync(6);
+    // both rounds are driven from a single thread, the first one never get
+    // any peer
+    let mut lonely = sim.sync_initiator(3, 60);
+    let lonely_driver = lonely.drive().unwrap();
+    let mut initiator = sim.sync_initiator(3, 600);
+    let driver = initiator.drive().unwrap();
+    let handle = spawn_driver(vec![
+        (lonely.clone(), lonely_driver),
+        (initiator.clone(), driver),
+    ]);
+
+    // the last pool posted
+    let pool = sim.relay.wait_pool_blocking();
+    let peers: Vec<_> = ["peer_a", "peer_b", "peer_c"]
+        .into_iter()
+        .map(|name| sim.sync_peer(&pool, name))
+        .collect();
+    for result in run_peers(&peers, &pool) {
+        result.unwrap();
+    }
+    let results = handle.join().expect("driver panicked");
+    assert!(matches!(results[0], Err(Error::NotEnoughPeers(0, 3))));
+    assert_eq!(lonely.status().step(), Step::Failed);
+    assert!(results[1].is_ok());
+
+    let status = initiator.status();
+    assert_eq!(status.step(), Step::Broadcast);
+    assert_eq!(status.registered_inputs(), 3);
+    let tx = initiator.final_tx().unwrap();
+    for (peer, _) in peers {
+        assert_eq!(peer.final_tx(), Some(tx.clone()));
+    }
+    assert!(sim.chain.broadcasted().contains(&tx));
+}
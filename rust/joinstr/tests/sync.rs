@@ -0,0 +1,73 @@
+pub mod utils;
+use std::{thread::sleep, time::Duration};
+
+use crate::utils::{funded_wallet, generate, send_to_address};
+
+use electrsd::bitcoind::bitcoincore_rpc::RpcApi;
+use joinstr::{
+    electrum::CoinStatus,
+    signer::{sync::WalletSync, Account, CoinPath, WpkhHotSigner},
+};
+use miniscript::bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, Network, Sequence, Transaction, TxIn, TxOut,
+};
+
+#[test]
+fn wallet_sync() {
+    let (mut signer, client, _electrsd, bitcoind) = funded_wallet(&[0.1, 0.2]);
+    sleep(Duration::from_secs(2));
+
+    let mut sync = WalletSync::new(client);
+    sync.sync(&mut signer).unwrap();
+    assert_eq!(signer.list_coins().len(), 2);
+    assert!(sync
+        .coins()
+        .iter()
+        .all(|(_, status)| *status == CoinStatus::Confirmed));
+
+    // a payment inside the gap limit is detected, unconfirmed
+    let addr = signer.recv_addr_at(15);
+    send_to_address(&bitcoind, &addr, Amount::from_btc(0.3).unwrap());
+    sleep(Duration::from_secs(2));
+    sync.sync(&mut signer).unwrap();
+    assert_eq!(signer.list_coins().len(), 3);
+    let (coin, status) = sync
+        .coins()
+        .into_iter()
+        .find(|(c, _)| c.coin_path == CoinPath::new(0, 15))
+        .unwrap();
+    assert_eq!(status, CoinStatus::Unconfirmed);
+    // the gap limit moved forward
+    assert_eq!(
+        signer.next_address(Account::Deposit),
+        signer.recv_addr_at(16)
+    );
+
+    // spent coins are removed from the signer
+    let other = WpkhHotSigner::new(Network::Regtest)
+        .unwrap()
+        .recv_addr_at(0);
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: coin.outpoint,
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: coin.txout.value - Amount::from_sat(1_000),
+            script_pubkey: other.script_pubkey(),
+        }],
+    };
+    let tx = signer.sign_tx(tx, &[coin.clone()]).unwrap();
+    bitcoind.client.send_raw_transaction(&tx).unwrap();
+    generate(&bitcoind, 1);
+    sleep(Duration::from_secs(2));
+    sync.sync(&mut signer).unwrap();
+    assert_eq!(signer.list_coins().len(), 2);
+    assert!(sync
+        .coins()
+        .iter()
+        .any(|(c, status)| c.outpoint == coin.outpoint && *status == CoinStatus::Spend));
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::Error;
+
+/// Options that do not expect a value.
+const FLAGS: [&str; 6] = ["json", "follow", "all", "force", "self-signed", "mnemonic"];
+
+/// Parsed command line: `joinstr <command> [positional..] [--option value..] [--flag..]`
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: String,
+    pub positional: Vec<String>,
+    // None for flags
+    options: HashMap<String, Option<String>>,
+}
+
+impl Args {
+    /// Parse the command line arguments, the program name excluded.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, Error> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                if name.is_empty() {
+                    return Err(Error::Usage("empty option name".into()));
+                }
+                let value = match args.peek() {
+                    Some(next) if !FLAGS.contains(&name) && !next.starts_with("--") => args.next(),
+                    _ => None,
+                };
+                parsed.options.insert(name.into(), value);
+            } else if parsed.command.is_empty() {
+                parsed.command = arg;
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Returns true if the flag is set.
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    /// Returns the value of an option, None if missing.
+    pub fn get(&self, name: &str) -> Result<Option<&str>, Error> {
+        match self.options.get(name) {
+            None => Ok(None),
+            Some(Some(value)) => Ok(Some(value)),
+            Some(None) => Err(Error::Usage(format!("--{name} expects a value"))),
+        }
+    }
+
+    /// Returns the value of a mandatory option.
+    pub fn require(&self, name: &str) -> Result<&str, Error> {
+        self.get(name)?
+            .ok_or_else(|| Error::Usage(format!("--{name} is missing")))
+    }
+
+    /// Parse the value of an option, `default` if missing.
+    pub fn parse_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, Error> {
+        match self.get(name)? {
+            Some(value) => Self::parse_value(name, value),
+            None => Ok(default),
+        }
+    }
+
+    /// Parse the value of a mandatory option.
+    pub fn parse_required<T: FromStr>(&self, name: &str) -> Result<T, Error> {
+        Self::parse_value(name, self.require(name)?)
+    }
+
+    fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, Error> {
+        T::from_str(value).map_err(|_| Error::Usage(format!("invalid value for --{name}: {value}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Args {
+        Args::parse(s.split_whitespace().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let a = args("join abcd --json --input txid:0 --fee 2");
+        assert_eq!(a.command, "join");
+        assert_eq!(a.positional, vec!["abcd".to_string()]);
+        assert!(a.flag("json"));
+        assert!(!a.flag("follow"));
+        assert_eq!(a.require("input").unwrap(), "txid:0");
+        assert_eq!(a.parse_required::<u32>("fee").unwrap(), 2);
+        assert_eq!(a.parse_or::<u32>("peers", 5).unwrap(), 5);
+        assert!(a.require("output").is_err());
+        assert!(a.get("json").is_err());
+        assert!(a.parse_required::<u32>("input").is_err());
+
+        let a = args("--json coins");
+        assert_eq!(a.command, "coins");
+        assert!(a.flag("json"));
+    }
+}
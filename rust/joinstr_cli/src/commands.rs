@@ -0,0 +1,337 @@
+use std::{collections::HashSet, env, io, str::FromStr};
+
+use joinstr::{
+    bip39::Mnemonic,
+    electrum::CoinStatus,
+    interface::{self, PeerConfig, PoolConfig},
+    miniscript::bitcoin::{address::NetworkUnchecked, Address, Network, OutPoint},
+    nostr::{Fee, Pool},
+    serde_json::{self, json},
+    signer::{Account, Coin, CoinPath, WpkhHotSigner},
+};
+use joinstr_wallet::{Database, ElectrumServer, Settings};
+use url::Url;
+
+use crate::{args::Args, round, Error, Printer};
+
+const DEFAULT_GAP_LIMIT: u32 = 20;
+/// Default for `--back`: how far back (secs) we look for pool announcements
+pub const DEFAULT_BACK: u64 = 3600;
+/// Default for `--timeout`: how long (secs) we listen for pool announcements
+const DEFAULT_LISTEN: u64 = 10;
+const DEFAULT_DURATION: u64 = 3600;
+/// The environment variable holding the mnemonic to import on `init`
+const MNEMONIC_ENV: &str = "JOINSTR_MNEMONIC";
+
+pub fn load_settings() -> Result<Settings, Error> {
+    Settings::load().ok_or(Error::Settings(
+        "no settings found, run `joinstr init` first".into(),
+    ))
+}
+
+pub fn relay(settings: &Settings) -> Result<String, Error> {
+    settings
+        .relays
+        .first()
+        .cloned()
+        .ok_or(Error::Settings("no relay configured".into()))
+}
+
+pub fn electrum(settings: &Settings) -> Result<&ElectrumServer, Error> {
+    settings
+        .electrum
+        .first()
+        .ok_or(Error::Settings("no electrum server configured".into()))
+}
+
+// Sync the wallet & store the result in the wallet database
+fn synced_wallet(settings: &Settings, gap_limit: u32) -> Result<(WpkhHotSigner, Database), Error> {
+    let mut signer = WpkhHotSigner::new_from_mnemonics(settings.network, &settings.mnemonics)?;
+    for account in Account::all() {
+        signer.set_gap_limit(account, gap_limit);
+    }
+    let client = electrum(settings)?.connect()?;
+    let mut db = Database::open(&settings.datadir())?;
+    db.sync(&mut signer, client)?;
+    db.flush()?;
+    Ok((signer, db))
+}
+
+fn find_coin(db: &Database, outpoint: &str) -> Result<Coin, Error> {
+    let outpoint = OutPoint::from_str(outpoint)
+        .map_err(|_| Error::Usage(format!("invalid outpoint: {outpoint}")))?;
+    db.unspent_coins()
+        .into_iter()
+        .find(|c| c.outpoint == outpoint)
+        .ok_or(Error::Usage(format!("no unspent coin at {outpoint}")))
+}
+
+fn peer_config(settings: &Settings, args: &Args, input: Coin) -> Result<PeerConfig, Error> {
+    let output = match args.get("output")? {
+        Some(addr) => Some(
+            Address::<NetworkUnchecked>::from_str(addr)
+                .map_err(|_| Error::Usage(format!("invalid address: {addr}")))?,
+        ),
+        None => None,
+    };
+    let server = electrum(settings)?;
+    Ok(PeerConfig {
+        mnemonics: Mnemonic::from_str(&settings.mnemonics)
+            .map_err(|e| Error::Settings(e.to_string()))?,
+        electrum_address: server.address(),
+        electrum_port: server.port,
+        esplora_url: None,
+        input,
+        output,
+        relay: relay(settings)?,
+    })
+}
+
+fn path(coin_path: &CoinPath) -> String {
+    match coin_path.index {
+        Some(index) => format!("{:?}/{}/{}", coin_path.account, coin_path.depth, index),
+        None => format!("{:?}/{}/*", coin_path.account, coin_path.depth),
+    }
+}
+
+fn pool_line(pool: &Pool) -> String {
+    match &pool.payload {
+        Some(payload) => {
+            let fee = match &payload.fee {
+                Fee::Fixed(fee) => format!("{fee} sats/vb"),
+                Fee::Provider(_) => "fee provider".into(),
+            };
+            format!(
+                "{} {} {} peers {}",
+                pool.id, payload.denomination, payload.peers, fee
+            )
+        }
+        None => pool.id.clone(),
+    }
+}
+
+// The mnemonic to import is read from `MNEMONIC_ENV` or from stdin, never
+//   from argv: the command line is visible to the other users of the host
+//   and is kept in the shell history.
+fn read_mnemonic(args: &Args) -> Result<Option<String>, Error> {
+    if let Ok(words) = env::var(MNEMONIC_ENV) {
+        return Ok(Some(words));
+    }
+    if !args.flag("mnemonic") {
+        return Ok(None);
+    }
+    let mut words = String::new();
+    io::stdin().read_line(&mut words)?;
+    Ok(Some(words))
+}
+
+pub fn init(args: &Args, printer: Printer) -> Result<(), Error> {
+    if Settings::load().is_some() && !args.flag("force") {
+        return Err(Error::Settings(
+            "settings already exist, use --force to overwrite them".into(),
+        ));
+    }
+    let (mnemonic, generated) = match read_mnemonic(args)? {
+        Some(words) => (
+            Mnemonic::from_str(words.trim()).map_err(|e| Error::Usage(e.to_string()))?,
+            false,
+        ),
+        None => (
+            Mnemonic::generate(12).map_err(|e| Error::Settings(e.to_string()))?,
+            true,
+        ),
+    };
+    let network: Network = args.parse_or("network", Network::Bitcoin)?;
+    let mut server: ElectrumServer = args.parse_required("electrum")?;
+    server.self_signed = args.flag("self-signed");
+    let relay = args.require("relay")?;
+    Url::parse(relay).map_err(|_| Error::Usage(format!("invalid relay url: {relay}")))?;
+
+    let mut settings = Settings::new(&mnemonic.to_string(), network);
+    settings.electrum.push(server);
+    settings.relays.push(relay.into());
+    if settings.save() != 0 {
+        return Err(Error::Settings("fail to write the settings file".into()));
+    }
+
+    let text = match generated {
+        true => format!("Wallet created, back up your mnemonic:\n{mnemonic}"),
+        false => "Wallet imported".into(),
+    };
+    printer.print(
+        text,
+        json!({
+            "network": network,
+            "mnemonic": generated.then(|| mnemonic.to_string()),
+        }),
+    );
+    Ok(())
+}
+
+pub fn coins(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let (_, db) = synced_wallet(&settings, args.parse_or("gap-limit", DEFAULT_GAP_LIMIT)?)?;
+    let coins: Vec<_> = db
+        .coins()
+        .iter()
+        .filter(|c| args.flag("all") || c.status != CoinStatus::Spend)
+        .collect();
+
+    let text = coins
+        .iter()
+        .map(|c| {
+            let label = db.label(&c.coin.outpoint.to_string()).unwrap_or_default();
+            format!(
+                "{} {} {} {:?} {}",
+                c.coin.outpoint,
+                c.coin.txout.value,
+                path(&c.coin.coin_path),
+                c.status,
+                label
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let json = coins
+        .iter()
+        .map(|c| {
+            json!({
+                "coin": c.coin,
+                "status": c.status,
+                "label": db.label(&c.coin.outpoint.to_string()),
+            })
+        })
+        .collect::<Vec<_>>();
+    printer.print(text, json.into());
+    Ok(())
+}
+
+pub fn address(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let (mut signer, _) = synced_wallet(&settings, args.parse_or("gap-limit", DEFAULT_GAP_LIMIT)?)?;
+    let address = signer.next_address(Account::Deposit);
+    printer.print(&address, json!({ "address": address }));
+    Ok(())
+}
+
+pub fn pools(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let relay = relay(&settings)?;
+    let back = args.parse_or("back", DEFAULT_BACK)?;
+    let timeout = args.parse_or("timeout", DEFAULT_LISTEN)?;
+
+    if !args.flag("follow") {
+        let pools = interface::list_pools(back, timeout, relay)?;
+        let text = pools.iter().map(pool_line).collect::<Vec<_>>().join("\n");
+        printer.print(text, serde_json::to_value(&pools)?);
+        return Ok(());
+    }
+
+    // print the pools as they are announced
+    let mut seen = HashSet::new();
+    loop {
+        for pool in interface::list_pools(back, timeout, relay.clone())? {
+            if seen.insert(pool.id.clone()) {
+                printer.print(pool_line(&pool), serde_json::to_value(&pool)?);
+            }
+        }
+    }
+}
+
+pub fn create_pool(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let config = PoolConfig {
+        denomination: args.parse_required("denomination")?,
+        fee: args.parse_required("fee")?,
+        max_duration: args.parse_or("duration", DEFAULT_DURATION)?,
+        peers: args.parse_required("peers")?,
+        network: settings.network,
+    };
+    let (_, db) = synced_wallet(&settings, DEFAULT_GAP_LIMIT)?;
+    let input = find_coin(&db, args.require("input")?)?;
+    let (initiator, signer) = interface::initiator(config, peer_config(&settings, args, input)?)?;
+    round::run(initiator, signer, None, &settings, printer)
+}
+
+pub fn join(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let id = args
+        .positional
+        .first()
+        .ok_or(Error::Usage("the pool id is missing".into()))?;
+    let back = args.parse_or("back", DEFAULT_BACK)?;
+    let timeout = args.parse_or("timeout", DEFAULT_LISTEN)?;
+    let pool = interface::list_pools(back, timeout, relay(&settings)?)?
+        .into_iter()
+        .find(|p| p.id == *id)
+        .ok_or(Error::PoolNotFound(id.clone()))?;
+
+    let (_, db) = synced_wallet(&settings, DEFAULT_GAP_LIMIT)?;
+    let input = find_coin(&db, args.require("input")?)?;
+    let (peer, signer) = interface::peer(&pool, peer_config(&settings, args, input)?)?;
+    round::run(peer, signer, Some(pool), &settings, printer)
+}
+
+pub fn status(printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let states = round::load_all(&settings)?;
+    let text = states
+        .iter()
+        .map(|s| {
+            let txid = s.final_tx.as_ref().map(|tx| tx.compute_txid().to_string());
+            format!(
+                "{} {:?} {:?} {}",
+                s.pool.id,
+                s.role,
+                s.step,
+                txid.unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let json = states
+        .iter()
+        .map(|s| {
+            json!({
+                "pool_id": s.pool.id,
+                "role": s.role,
+                "step": s.step,
+                "txid": s.final_tx.as_ref().map(|tx| tx.compute_txid()),
+            })
+        })
+        .collect::<Vec<_>>();
+    printer.print(text, json.into());
+    Ok(())
+}
+
+pub fn resume(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    round::resume(
+        args.positional.first().map(String::as_str),
+        &settings,
+        printer,
+    )
+}
+
+pub fn history(printer: Printer) -> Result<(), Error> {
+    let settings = load_settings()?;
+    let db = Database::open(&settings.datadir())?;
+    let text = db
+        .history()
+        .iter()
+        .map(|r| {
+            format!(
+                "{} {:?} {} {} {:?} {}",
+                r.started_at,
+                r.role,
+                r.pool_id.as_deref().unwrap_or("-"),
+                r.denomination.map(|d| d.to_string()).unwrap_or_default(),
+                r.outcome,
+                r.txid.map(|t| t.to_string()).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    printer.print(text, serde_json::to_value(db.history())?);
+    Ok(())
+}
@@ -0,0 +1,197 @@
+mod args;
+mod commands;
+mod remix;
+mod round;
+
+use std::{fmt::Display, process::ExitCode};
+
+use args::Args;
+use joinstr::serde_json::{self, json, Value};
+
+const USAGE: &str = "Usage: joinstr <command> [options] [--json]
+
+Commands:
+  init          Create the wallet settings
+                  [--mnemonic] [--network <network>]
+                  --electrum <[ssl://]host:port> [--self-signed]
+                  --relay <url> [--force]
+                  --mnemonic reads the words to import from stdin,
+                  JOINSTR_MNEMONIC is used instead if set
+  coins         Sync the wallet and list its coins
+                  [--gap-limit <n>] [--all]
+  address       Sync the wallet and show the next deposit address
+                  [--gap-limit <n>]
+  pools         List the pools announced on the relay
+                  [--back <secs>] [--timeout <secs>] [--follow]
+  create-pool   Initiate a pool and take part in it
+                  --denomination <btc> --fee <sats/vb> --peers <n>
+                  --input <txid:vout> [--output <address>] [--duration <secs>]
+  join <id>     Join an announced pool
+                  --input <txid:vout> [--output <address>] [--back <secs>]
+                  [--timeout <secs>]
+  status        Show the state of the rounds we took part in
+  resume [<id>] Resume an interrupted round
+  history       Show the coinjoin history of the wallet
+  remix         Remix the postmix coins until they reach the target
+                  anonymity set, the remix counters are kept across runs
+                  [--target <n>] [--interval <secs>] [--scan <n>]
+                  [--back <secs>]
+
+Options:
+  --json        Output JSON instead of text";
+
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    Settings(String),
+    Interface(joinstr::interface::Error),
+    Joinstr(joinstr::joinstr::Error),
+    Signer(joinstr::signer::Error),
+    Electrum(joinstr::electrum::Error),
+    Remixer(joinstr::remixer::Error),
+    Database(joinstr_wallet::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Round(String),
+    PoolNotFound(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Usage(e) => write!(f, "{}", e),
+            Error::Settings(e) => write!(f, "Settings error: {}", e),
+            Error::Interface(e) => write!(f, "{}", e),
+            Error::Joinstr(e) => write!(f, "Joinstr error: {:?}", e),
+            Error::Signer(e) => write!(f, "Signer error: {}", e),
+            Error::Electrum(e) => write!(f, "Electrum error: {:?}", e),
+            Error::Remixer(e) => write!(f, "Remixer error: {}", e),
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Round(e) => write!(f, "Coinjoin failed: {}", e),
+            Error::PoolNotFound(id) => write!(f, "Pool not found: {}", id),
+        }
+    }
+}
+
+impl From<joinstr::interface::Error> for Error {
+    fn from(value: joinstr::interface::Error) -> Self {
+        Error::Interface(value)
+    }
+}
+
+impl From<joinstr::joinstr::Error> for Error {
+    fn from(value: joinstr::joinstr::Error) -> Self {
+        Error::Joinstr(value)
+    }
+}
+
+impl From<joinstr::signer::Error> for Error {
+    fn from(value: joinstr::signer::Error) -> Self {
+        Error::Signer(value)
+    }
+}
+
+impl From<joinstr::electrum::Error> for Error {
+    fn from(value: joinstr::electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
+
+impl From<joinstr::remixer::Error> for Error {
+    fn from(value: joinstr::remixer::Error) -> Self {
+        Error::Remixer(value)
+    }
+}
+
+impl From<joinstr_wallet::Error> for Error {
+    fn from(value: joinstr_wallet::Error) -> Self {
+        Error::Database(value)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+/// Print the output of the commands, either as text or as JSON (one JSON
+///   value per line).
+#[derive(Debug, Clone, Copy)]
+pub struct Printer {
+    json: bool,
+}
+
+impl Printer {
+    pub fn print(&self, text: impl Display, json: Value) {
+        if self.json {
+            println!("{}", json);
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    fn error(&self, error: &Error) {
+        if self.json {
+            println!("{}", json!({ "error": error.to_string() }));
+        } else {
+            eprintln!("{}", error);
+        }
+    }
+}
+
+fn run(args: &Args, printer: Printer) -> Result<(), Error> {
+    match args.command.as_str() {
+        "init" => commands::init(args, printer),
+        "coins" => commands::coins(args, printer),
+        "address" => commands::address(args, printer),
+        "pools" => commands::pools(args, printer),
+        "create-pool" => commands::create_pool(args, printer),
+        "join" => commands::join(args, printer),
+        "status" => commands::status(printer),
+        "resume" => commands::resume(args, printer),
+        "history" => commands::history(printer),
+        "remix" => remix::run(args, printer),
+        "" | "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        c => Err(Error::Usage(format!("unknown command: {c}"))),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let printer = Printer {
+        json: args.flag("json"),
+    };
+    match run(&args, printer) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            printer.error(&e);
+            match e {
+                Error::Usage(_) => {
+                    if !printer.json {
+                        eprintln!("\n{}", USAGE);
+                    }
+                    ExitCode::from(2)
+                }
+                _ => ExitCode::FAILURE,
+            }
+        }
+    }
+}
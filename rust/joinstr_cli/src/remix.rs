@@ -0,0 +1,119 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, ErrorKind},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use joinstr::{
+    remixer::{Remixer, RemixerState},
+    serde_json::{self, json},
+    signer::WpkhHotSigner,
+};
+use joinstr_wallet::Settings;
+
+use crate::{args::Args, commands, Error, Printer};
+
+/// Default for `--target`: the anonymity set each coin should reach
+const DEFAULT_TARGET: usize = 5;
+/// Default for `--interval`: how long (secs) we wait before looking for
+///   pools again
+const DEFAULT_INTERVAL: u64 = 60;
+/// Default for `--scan`: how many postmix addresses we scan for coins
+const DEFAULT_SCAN: u32 = 20;
+
+// The state of the remixer is saved in `<datadir>/remixer.json`, next to
+//   the rounds states
+fn state_path(settings: &Settings) -> PathBuf {
+    let mut path = settings.datadir();
+    path.push("remixer.json");
+    path
+}
+
+fn save(settings: &Settings, state: &RemixerState) -> Result<(), Error> {
+    let path = state_path(settings);
+    let mut tmp = path.clone();
+    tmp.set_extension("json.tmp");
+    serde_json::to_writer(File::create(&tmp)?, state)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn load(settings: &Settings) -> Result<Option<RemixerState>, Error> {
+    match File::open(state_path(settings)) {
+        Ok(file) => Ok(Some(serde_json::from_reader(BufReader::new(file))?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remix the postmix coins of the wallet until each of them reach the target
+///   anonymity set, the remix counters are saved after each state change and
+///   restored on the next run.
+///
+/// # Arguments
+/// * `args` - the command line arguments
+/// * `printer` - where to print the remixed coins
+pub fn run(args: &Args, printer: Printer) -> Result<(), Error> {
+    let settings = commands::load_settings()?;
+    let relay = commands::relay(&settings)?;
+    let server = commands::electrum(&settings)?;
+    let electrum = (server.address(), server.port);
+    let mut signer = WpkhHotSigner::new_from_mnemonics(settings.network, &settings.mnemonics)?;
+    signer.set_client(server.connect()?);
+
+    let remixer = match load(&settings)? {
+        Some(state) => Remixer::restore(
+            state,
+            signer,
+            relay,
+            (&electrum.0, electrum.1),
+            settings.network,
+        )?,
+        None => Remixer::new(signer, relay, (&electrum.0, electrum.1), settings.network),
+    };
+    let mut remixer = remixer
+        .target(args.parse_or("target", DEFAULT_TARGET)?)
+        .pools_lookback(args.parse_or("back", commands::DEFAULT_BACK)?);
+    let interval = Duration::from_secs(args.parse_or("interval", DEFAULT_INTERVAL)?);
+    let found = remixer.scan((0, args.parse_or("scan", DEFAULT_SCAN)?))?;
+    printer.print(
+        format!("{found} new postmix coins found"),
+        json!({ "found": found }),
+    );
+    save(&settings, &remixer.state())?;
+
+    while !remixer.pending_coins().is_empty() {
+        let watched = remixer.clone();
+        let result = remixer.remix_once(|| {
+            if let Err(e) = save(&settings, &watched.state()) {
+                printer.print(
+                    format!("Fail to save the remixer state: {e}"),
+                    json!({ "error": e.to_string() }),
+                );
+            }
+        });
+        save(&settings, &remixer.state())?;
+        match result {
+            Ok(Some(txid)) => printer.print(txid, json!({ "txid": txid })),
+            Ok(None) => thread::sleep(interval),
+            Err(e) => {
+                printer.print(
+                    format!("Remix failed: {e}"),
+                    json!({ "error": e.to_string() }),
+                );
+                thread::sleep(interval);
+            }
+        }
+    }
+
+    let coins = remixer.coins();
+    let text = coins
+        .iter()
+        .map(|c| format!("{} {} {}", c.coin.outpoint, c.remixes, c.anonset))
+        .collect::<Vec<_>>()
+        .join("\n");
+    printer.print(text, serde_json::to_value(&coins)?);
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use joinstr::{
+    joinstr::{Joinstr, State, Step},
+    nostr::Pool,
+    serde_json::{self, json},
+    signer::WpkhHotSigner,
+};
+use joinstr_wallet::{coinjoin_of, start_coinjoin, update_coinjoin, Settings};
+
+use crate::{commands, Error, Printer};
+
+/// Some state changes (e.g. confirmations) do not trigger a notification,
+///   the status is also polled at this interval.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// The state of each round is saved in `<datadir>/rounds/<pool id>.json`
+fn rounds_dir(settings: &Settings) -> Result<PathBuf, Error> {
+    let mut dir = settings.datadir();
+    dir.push("rounds");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn save(settings: &Settings, state: &State) -> Result<(), Error> {
+    let mut path = rounds_dir(settings)?;
+    path.push(format!("{}.json", state.pool.id));
+    let mut tmp = path.clone();
+    tmp.set_extension("json.tmp");
+    serde_json::to_writer(File::create(&tmp)?, state)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Load the saved state of all the rounds we took part in.
+pub fn load_all(settings: &Settings) -> Result<Vec<State>, Error> {
+    let mut states = Vec::new();
+    for entry in fs::read_dir(rounds_dir(settings)?)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            states.push(serde_json::from_reader(BufReader::new(File::open(path)?))?);
+        }
+    }
+    Ok(states)
+}
+
+fn is_finished(step: Step) -> bool {
+    matches!(step, Step::Broadcast | Step::Mined | Step::Failed)
+}
+
+/// Run the coinjoin of `joinstr` until the transaction is broadcast or the
+///   round fails, the round is recorded in the wallet history.
+///
+/// # Arguments
+/// * `joinstr` - the configured [`Joinstr`] instance
+/// * `signer` - the signer owning our input
+/// * `pool` - the pool to join, None if we are the initiator
+/// * `settings` - the wallet settings
+/// * `printer` - where to print the status updates
+pub fn run(
+    joinstr: Joinstr<'static>,
+    signer: WpkhHotSigner,
+    pool: Option<Pool>,
+    settings: &Settings,
+    printer: Printer,
+) -> Result<(), Error> {
+    let record = start_coinjoin(&joinstr);
+    let (sender, receiver) = mpsc::channel();
+    // the sender is dropped when the coinjoin thread ends
+    joinstr
+        .clone()
+        .start_coinjoin_with_notif(pool, Some(signer), move || {
+            let _ = sender.send(());
+        });
+    watch(&joinstr, receiver, record, settings, printer)
+}
+
+/// Resume an interrupted round.
+///
+/// # Arguments
+/// * `id` - the id of the pool, can be omitted if a single round is
+///   unfinished
+/// * `settings` - the wallet settings
+/// * `printer` - where to print the status updates
+pub fn resume(id: Option<&str>, settings: &Settings, printer: Printer) -> Result<(), Error> {
+    let mut states: Vec<_> = load_all(settings)?
+        .into_iter()
+        .filter(|s| !is_finished(s.step) && id.is_none_or(|id| s.pool.id == id))
+        .collect();
+    let state = match (states.len(), id) {
+        (0, Some(id)) => return Err(Error::PoolNotFound(id.into())),
+        (0, None) => return Err(Error::Round("no interrupted round".into())),
+        (1, _) => states.remove(0),
+        _ => {
+            return Err(Error::Usage(
+                "several rounds are interrupted, the pool id is missing".into(),
+            ))
+        }
+    };
+
+    let mut signer = WpkhHotSigner::new_from_mnemonics(settings.network, &settings.mnemonics)?;
+    signer.set_client(commands::electrum(settings)?.connect()?);
+
+    let record = coinjoin_of(&state.pool.id);
+    let (sender, receiver) = mpsc::channel();
+    let joinstr = Joinstr::restart(state, "resume", signer, move || {
+        let _ = sender.send(());
+    })?;
+    watch(&joinstr, receiver, record, settings, printer)
+}
+
+// Save the state & print the status each time it changes, until the
+//   coinjoin thread ends.
+fn watch(
+    joinstr: &Joinstr<'static>,
+    receiver: mpsc::Receiver<()>,
+    record: Option<usize>,
+    settings: &Settings,
+    printer: Printer,
+) -> Result<(), Error> {
+    let mut last = None;
+    loop {
+        let done = matches!(
+            receiver.recv_timeout(POLL_INTERVAL),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        let status = joinstr.status();
+        let json = serde_json::to_value(&status)?;
+        if last.as_ref() != Some(&json) {
+            if let Some(state) = joinstr.state() {
+                save(settings, &state)?;
+            }
+            if let Some(id) = record {
+                update_coinjoin(id, joinstr);
+            }
+            printer.print(format!("{:?}", status.step()), json.clone());
+            last = Some(json);
+        }
+        if done {
+            break;
+        }
+    }
+
+    let status = joinstr.status();
+    if status.step() == Step::Failed {
+        return Err(Error::Round(status.error().unwrap_or("unknown").into()));
+    }
+    let txid = joinstr.final_tx().map(|tx| tx.compute_txid());
+    printer.print(
+        txid.map(|t| t.to_string()).unwrap_or_default(),
+        json!({ "txid": txid }),
+    );
+    Ok(())
+}
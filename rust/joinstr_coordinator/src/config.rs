@@ -0,0 +1,191 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use joinstr::{
+    fee::FeePolicy,
+    miniscript::bitcoin::{Amount, Network},
+    serde_json,
+    simple_nostr_client::nostr::PublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+fn default_max_rounds() -> usize {
+    10
+}
+
+fn default_concurrent() -> usize {
+    1
+}
+
+/// Configuration of the coordinator, loaded from a JSON file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub network: Network,
+    pub relay: String,
+    /// Address of the electrum server, prefixed w/ `ssl://` if TLS is used
+    pub electrum_address: String,
+    pub electrum_port: u16,
+    /// Where the state of the running rounds is persisted
+    pub datadir: PathBuf,
+    /// Max number of rounds running at the same time
+    #[serde(default = "default_max_rounds")]
+    pub max_rounds: usize,
+    /// The pools published by the coordinator
+    pub pools: Vec<PoolTemplate>,
+    #[serde(default)]
+    pub policy: Policy,
+    /// Address the admin RPC listens on, disabled if None. It is not
+    ///   authenticated and should only listen on localhost.
+    #[serde(default)]
+    pub admin: Option<SocketAddr>,
+}
+
+/// Parameters of the pools published for a denomination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolTemplate {
+    /// Denomination in BTC
+    pub denomination: f64,
+    pub fee: FeePolicy,
+    /// Min number of peers
+    pub peers: usize,
+    /// Time (secs) a pool waits for its peers
+    pub timeout: u64,
+    /// Number of pools of this template running at the same time
+    #[serde(default = "default_concurrent")]
+    pub concurrent: usize,
+}
+
+/// The rules applied to the published pools.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Policy {
+    /// Min fee rate (sats/vb) of a pool, a fee rate resolved below it is
+    ///   raised to it
+    #[serde(default)]
+    pub min_fee: u32,
+    /// Min NIP-13 proof of work of the join requests
+    #[serde(default)]
+    pub pow: Option<u8>,
+    /// The join requests of these keys are ignored, best-effort as a peer can
+    ///   join again from a fresh key: `pow` is what makes join requests costly
+    #[serde(default)]
+    pub banned_peers: Vec<PublicKey>,
+}
+
+impl Config {
+    /// Load & validate the configuration.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the file cannot be read or parsed
+    ///   - the configuration is not valid, see [`Config::validate()`]
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let config: Config = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the configuration.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - there is no pool template
+    ///   - `max_rounds` is 0
+    ///   - a template have an invalid denomination, less than 2 peers, a
+    ///     null timeout or a null concurrency
+    ///   - a template have a fixed fee rate below `policy.min_fee`
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.pools.is_empty() {
+            return Err(Error::Config("no pool template".into()));
+        }
+        if self.max_rounds == 0 {
+            return Err(Error::Config("max_rounds must not be 0".into()));
+        }
+        for (i, pool) in self.pools.iter().enumerate() {
+            if Amount::from_btc(pool.denomination).is_err() || pool.denomination <= 0.0 {
+                return Err(Error::Config(format!("pools[{i}]: invalid denomination")));
+            }
+            if pool.peers < 2 {
+                return Err(Error::Config(format!("pools[{i}]: min 2 peers")));
+            }
+            if pool.timeout == 0 || pool.concurrent == 0 {
+                return Err(Error::Config(format!(
+                    "pools[{i}]: timeout & concurrent must not be 0"
+                )));
+            }
+            if let FeePolicy::Fixed(fee) = pool.fee {
+                if fee < self.policy.min_fee {
+                    return Err(Error::Config(format!(
+                        "pools[{i}]: fee rate below policy.min_fee"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        {
+          "network": "regtest",
+          "relay": "ws://127.0.0.1:7777",
+          "electrum_address": "127.0.0.1",
+          "electrum_port": 50001,
+          "datadir": "/tmp/joinstr_coordinator",
+          "pools": [
+            { "denomination": 0.01, "fee": { "Fixed": 10 }, "peers": 5, "timeout": 600 },
+            { "denomination": 0.1, "fee": { "TargetBlocks": 6 }, "peers": 3, "timeout": 600,
+              "concurrent": 2 }
+          ],
+          "policy": { "min_fee": 2, "pow": 8 },
+          "admin": "127.0.0.1:7878"
+        }
+    "#;
+
+    #[test]
+    fn parse() {
+        let config: Config = serde_json::from_str(CONFIG).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.max_rounds, 10);
+        assert_eq!(config.pools[0].concurrent, 1);
+        assert_eq!(config.pools[1].fee, FeePolicy::TargetBlocks(6));
+        assert_eq!(config.policy.pow, Some(8));
+        assert!(config.policy.banned_peers.is_empty());
+    }
+
+    #[test]
+    fn validate() {
+        let config: Config = serde_json::from_str(CONFIG).unwrap();
+
+        let mut c = config.clone();
+        c.pools.clear();
+        assert!(c.validate().is_err());
+
+        let mut c = config.clone();
+        c.pools[0].peers = 1;
+        assert!(c.validate().is_err());
+
+        let mut c = config.clone();
+        c.pools[0].denomination = -1.0;
+        assert!(c.validate().is_err());
+
+        let mut c = config.clone();
+        c.policy.min_fee = 11;
+        assert!(c.validate().is_err());
+
+        let mut c = config;
+        c.max_rounds = 0;
+        assert!(c.validate().is_err());
+    }
+}
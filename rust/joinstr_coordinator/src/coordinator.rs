@@ -0,0 +1,435 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use joinstr::{
+    joinstr::{Driver, Joinstr, State, Status, Step},
+    log,
+    serde_json::{self, json, Value},
+    simple_nostr_client::{nostr::Keys, SharedConnection, Waiter},
+    utils::now,
+};
+
+use crate::{
+    config::{Config, Policy},
+    metrics::Metrics,
+    rpc, Error,
+};
+
+/// Interval between two checks of the number of running rounds.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(1);
+/// The rounds are polled at this interval even if the relay connection does
+///   not receive any message, for their timeouts to be handled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A round run by the coordinator.
+#[derive(Debug)]
+struct Round {
+    id: u64,
+    /// Index of the template of the pool, None for a resumed round
+    template: Option<usize>,
+    joinstr: Joinstr<'static>,
+    driver: Driver<'static>,
+    /// The last status handled
+    last: Option<Status>,
+    pool_id: Option<String>,
+}
+
+impl Round {
+    fn new(
+        id: u64,
+        template: Option<usize>,
+        joinstr: Joinstr<'static>,
+        driver: Driver<'static>,
+    ) -> Self {
+        Round {
+            id,
+            template,
+            joinstr,
+            driver,
+            last: None,
+            pool_id: None,
+        }
+    }
+
+    fn current_pool_id(&self) -> Option<String> {
+        let inner = self.joinstr.inner.lock().expect("poisoned");
+        inner.pool.as_ref().map(|p| p.id.clone())
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "template": self.template,
+            "pool_id": self.current_pool_id(),
+            "status": self.joinstr.status(),
+        })
+    }
+}
+
+/// Continuously publish the pools of its configuration & coordinate their
+///   rounds, the state of each round is persisted in the data directory
+///   and the interrupted rounds are resumed at startup.
+///
+/// All the rounds share a single relay connection & are driven by a single
+///   thread, woken up each time the connection receives a message.
+#[derive(Debug)]
+pub struct Coordinator {
+    config: Config,
+    connection: Mutex<Option<SharedConnection>>,
+    /// Whether the thread driving the rounds is started
+    dispatching: AtomicBool,
+    /// The policy applied to the next pools, can be updated from the admin
+    ///   RPC
+    policy: Mutex<Policy>,
+    rounds: Mutex<Vec<Round>>,
+    /// Stop publishing new pools, the running rounds are not affected
+    paused: AtomicBool,
+    next_id: AtomicU64,
+    pub metrics: Metrics,
+}
+
+impl Coordinator {
+    pub fn new(config: Config) -> Self {
+        Coordinator {
+            connection: Mutex::new(None),
+            dispatching: AtomicBool::new(false),
+            policy: Mutex::new(config.policy.clone()),
+            config,
+            rounds: Mutex::new(Vec::new()),
+            paused: AtomicBool::new(false),
+            next_id: AtomicU64::new(0),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Resume the interrupted rounds, start the admin RPC if configured,
+    ///   then publish pools forever.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the data directory cannot be read
+    ///   - the admin RPC cannot listen on its address
+    pub fn run(self: Arc<Self>) -> Result<(), Error> {
+        if !self.dispatching.swap(true, Ordering::SeqCst) {
+            let coordinator = self.clone();
+            thread::spawn(move || coordinator.dispatch());
+        }
+        self.resume_rounds()?;
+        if let Some(addr) = self.config.admin {
+            rpc::serve(self.clone(), addr)?;
+        }
+        loop {
+            if !self.paused.load(Ordering::SeqCst) {
+                self.schedule();
+            }
+            thread::sleep(SCHEDULE_INTERVAL);
+        }
+    }
+
+    // Start a round for each template running less rounds than expected,
+    // within the limit of `max_rounds`.
+    fn schedule(self: &Arc<Self>) {
+        for (index, template) in self.config.pools.iter().enumerate() {
+            loop {
+                let rounds = self.rounds.lock().expect("poisoned");
+                let running = rounds.iter().filter(|r| r.template == Some(index)).count();
+                if running >= template.concurrent || rounds.len() >= self.config.max_rounds {
+                    break;
+                }
+                drop(rounds);
+                if let Err(e) = self.start_round(index) {
+                    log::error!("Coordinator::schedule() fail to start pools[{index}]: {e}");
+                    Metrics::incr(&self.metrics.start_errors);
+                    // retry at next schedule
+                    break;
+                }
+            }
+        }
+    }
+
+    // Returns the relay connection shared by the rounds, reconnect if the
+    // relay closed it.
+    fn connection(&self) -> Result<SharedConnection, Error> {
+        let mut connection = self.connection.lock().expect("poisoned");
+        match connection.as_ref() {
+            Some(c) if !c.is_closed() => Ok(c.clone()),
+            _ => {
+                let c = SharedConnection::connect(self.config.relay.clone())?;
+                *connection = Some(c.clone());
+                Ok(c)
+            }
+        }
+    }
+
+    fn start_round(self: &Arc<Self>, index: usize) -> Result<(), Error> {
+        let template = &self.config.pools[index];
+        let policy = self.policy.lock().expect("poisoned").clone();
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        let joinstr = Joinstr::new_initiator(
+            Keys::generate(),
+            self.connection()?,
+            (&self.config.electrum_address, self.config.electrum_port),
+            self.config.network,
+            &format!("round_{id}"),
+        )?;
+        let backend = joinstr
+            .inner
+            .lock()
+            .expect("poisoned")
+            .backend
+            .clone()
+            .expect("initiator have a backend");
+        let fee = template
+            .fee
+            .resolve(&mut *backend.lock().expect("poisoned"))
+            .map_err(joinstr::joinstr::Error::from)?
+            .max(policy.min_fee);
+        let mut joinstr = joinstr
+            .denomination(template.denomination)?
+            .fee(fee)?
+            .simple_timeout(now() + template.timeout)?
+            .min_peers(template.peers)?
+            .ban_peers(policy.banned_peers)?;
+        if let Some(pow) = policy.pow {
+            joinstr = joinstr.pow(pow)?;
+        }
+
+        log::info!(
+            "Coordinator: start round {id} ({} BTC, {fee} sats/vb, {} peers)",
+            template.denomination,
+            template.peers
+        );
+        let driver = joinstr.drive()?;
+        let mut rounds = self.rounds.lock().expect("poisoned");
+        rounds.push(Round::new(id, Some(index), joinstr, driver));
+        Ok(())
+    }
+
+    fn rounds_dir(&self) -> Result<PathBuf, Error> {
+        let mut dir = self.config.datadir.clone();
+        dir.push("rounds");
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn state_path(&self, pool_id: &str) -> Result<PathBuf, Error> {
+        let mut path = self.rounds_dir()?;
+        path.push(format!("{pool_id}.json"));
+        Ok(path)
+    }
+
+    fn save_state(&self, state: &State) -> Result<(), Error> {
+        let path = self.state_path(&state.pool.id)?;
+        let mut tmp = path.clone();
+        tmp.set_extension("json.tmp");
+        serde_json::to_writer(File::create(&tmp)?, state)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    // Restart the rounds whose state have been persisted, a round that
+    // cannot be restarted is dropped.
+    fn resume_rounds(self: &Arc<Self>) -> Result<(), Error> {
+        for entry in fs::read_dir(self.rounds_dir()?)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let state: State = match File::open(&path)
+                .map_err(Error::from)
+                .and_then(|f| Ok(serde_json::from_reader(BufReader::new(f))?))
+            {
+                Ok(state) => state,
+                Err(e) => {
+                    log::error!("Coordinator: fail to load {}: {e}", path.display());
+                    continue;
+                }
+            };
+            let pool_id = state.pool.id.clone();
+            if let Err(e) = self.resume_round(state) {
+                log::error!("Coordinator: fail to resume pool {pool_id}: {e}");
+                Metrics::incr(&self.metrics.rounds_failed);
+                let _ = fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+
+    fn resume_round(self: &Arc<Self>, state: State) -> Result<(), Error> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        log::info!("Coordinator: resume pool {} as round {id}", state.pool.id);
+        // NOTE: a round published on another relay is only polled at
+        // `POLL_INTERVAL`, the dispatcher is not woken by its connection
+        let connection = if state.relay == self.config.relay {
+            self.connection()?
+        } else {
+            SharedConnection::connect(state.relay.clone())?
+        };
+        let (joinstr, driver) =
+            Joinstr::restart_driven(state, &connection, &format!("round_{id}"))?;
+        Metrics::incr(&self.metrics.rounds_resumed);
+        let mut rounds = self.rounds.lock().expect("poisoned");
+        rounds.push(Round::new(id, None, joinstr, driver));
+        Ok(())
+    }
+
+    // Drive all the rounds: they are polled each time the relay connection
+    // receives a message & at least every `POLL_INTERVAL`.
+    fn dispatch(&self) {
+        let mut waiter: Option<(SharedConnection, Waiter)> = None;
+        loop {
+            if let Some((_, w)) = waiter.as_mut().filter(|(c, _)| !c.is_closed()) {
+                w.wait(POLL_INTERVAL);
+            } else {
+                // NOTE: the waiter is created before polling in order to not
+                // miss a message received meanwhile
+                waiter = self.waiter();
+            }
+            let ids: Vec<_> = self
+                .rounds
+                .lock()
+                .expect("poisoned")
+                .iter()
+                .map(|r| r.id)
+                .collect();
+            for id in ids {
+                self.poll(id);
+            }
+        }
+    }
+
+    // Returns a waiter on the relay connection shared by the rounds, None
+    // after `POLL_INTERVAL` if the relay cannot be reached.
+    fn waiter(&self) -> Option<(SharedConnection, Waiter)> {
+        match self.connection() {
+            Ok(connection) => {
+                let waiter = connection.waiter();
+                Some((connection, waiter))
+            }
+            Err(e) => {
+                log::error!("Coordinator::dispatch() fail to connect the relay: {e}");
+                thread::sleep(POLL_INTERVAL);
+                None
+            }
+        }
+    }
+
+    // Drive the round, persist its state & update the metrics if its status
+    // changed, drop the round if it ended.
+    fn poll(&self, id: u64) {
+        let mut rounds = self.rounds.lock().expect("poisoned");
+        let Some(round) = rounds.iter_mut().find(|r| r.id == id) else {
+            return;
+        };
+        let ended = match round.joinstr.poll(&mut round.driver, || {}) {
+            Ok(ended) => ended,
+            // NOTE: the round is marked failed, the error is logged below
+            Err(_) => true,
+        };
+        let status = round.joinstr.status();
+        let changed = round.last.as_ref().is_none_or(|l| {
+            l.step() != status.step() || l.registered_peers() != status.registered_peers()
+        });
+        if changed {
+            if let Some(state) = round.joinstr.state() {
+                if round.pool_id.is_none() && round.template.is_some() {
+                    Metrics::incr(&self.metrics.pools_posted);
+                }
+                round.pool_id = Some(state.pool.id.clone());
+                if let Err(e) = self.save_state(&state) {
+                    log::error!("Coordinator: fail to persist round {id}: {e}");
+                }
+            }
+            let last_peers = round
+                .last
+                .as_ref()
+                .map(|l| l.registered_peers())
+                .unwrap_or(0);
+            Metrics::add(
+                &self.metrics.peers_joined,
+                status.registered_peers().saturating_sub(last_peers) as u64,
+            );
+            log::debug!("Coordinator: round {id} status: {status:?}");
+            round.last = Some(status);
+        }
+        if !ended {
+            return;
+        }
+
+        let status = round.joinstr.status();
+        match status.step() {
+            Step::Failed => {
+                log::warn!(
+                    "Coordinator: round {id} failed: {}",
+                    status.error().unwrap_or("unknown")
+                );
+                Metrics::incr(&self.metrics.rounds_failed);
+            }
+            _ => {
+                let txid = round.joinstr.final_tx().map(|tx| tx.compute_txid());
+                log::info!("Coordinator: round {id} broadcast {txid:?}");
+                Metrics::incr(&self.metrics.rounds_broadcast);
+            }
+        }
+        if let Some(pool_id) = &round.pool_id {
+            if let Ok(path) = self.state_path(pool_id) {
+                let _ = fs::remove_file(path);
+            }
+        }
+        rounds.retain(|r| r.id != id);
+    }
+
+    /// Returns the running rounds.
+    pub fn rounds(&self) -> Vec<Value> {
+        let rounds = self.rounds.lock().expect("poisoned");
+        rounds.iter().map(Round::to_json).collect()
+    }
+
+    /// Cancel a running round, returns false if there is no such round.
+    pub fn cancel(&self, id: u64) -> bool {
+        let rounds = self.rounds.lock().expect("poisoned");
+        match rounds.iter().find(|r| r.id == id) {
+            Some(round) => {
+                round.joinstr.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop or restart publishing new pools.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Returns the policy applied to the next pools.
+    pub fn policy(&self) -> Policy {
+        self.policy.lock().expect("poisoned").clone()
+    }
+
+    /// Update the policy applied to the next pools, the running rounds keep
+    ///   the policy they have been published with.
+    pub fn update_policy(&self, f: impl FnOnce(&mut Policy)) {
+        f(&mut self.policy.lock().expect("poisoned"));
+    }
+
+    /// Returns the configuration of the coordinator.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
@@ -0,0 +1,95 @@
+mod config;
+mod coordinator;
+mod metrics;
+mod rpc;
+
+use std::{fmt::Display, path::PathBuf, process::ExitCode, sync::Arc};
+
+use config::Config;
+use coordinator::Coordinator;
+use joinstr::serde_json;
+
+const USAGE: &str = "Usage: joinstr-coordinator <config.json>
+
+Publish the pools described in the config file & coordinate their rounds,
+  the log level is set w/ the RUST_LOG environment variable.";
+
+#[derive(Debug)]
+pub enum Error {
+    Config(String),
+    Joinstr(joinstr::joinstr::Error),
+    Relay(joinstr::simple_nostr_client::Error),
+    Signer(joinstr::signer::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "Invalid config: {}", e),
+            Error::Joinstr(e) => write!(f, "Joinstr error: {:?}", e),
+            Error::Relay(e) => write!(f, "Relay error: {:?}", e),
+            Error::Signer(e) => write!(f, "Signer error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+        }
+    }
+}
+
+impl From<joinstr::joinstr::Error> for Error {
+    fn from(value: joinstr::joinstr::Error) -> Self {
+        Error::Joinstr(value)
+    }
+}
+
+impl From<joinstr::simple_nostr_client::Error> for Error {
+    fn from(value: joinstr::simple_nostr_client::Error) -> Self {
+        Error::Relay(value)
+    }
+}
+
+impl From<joinstr::signer::Error> for Error {
+    fn from(value: joinstr::signer::Error) -> Self {
+        Error::Signer(value)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [path] if path != "--help" && path != "-h" => PathBuf::from(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    env_logger::init();
+
+    let config = match Config::from_file(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match Arc::new(Coordinator::new(config)).run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use joinstr::serde_json::{json, Value};
+
+/// Counters of the coordinator activity since it started.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Pools announced on the relay
+    pub pools_posted: AtomicU64,
+    /// Rounds restarted from a persisted state
+    pub rounds_resumed: AtomicU64,
+    /// Rounds whose transaction has been broadcast
+    pub rounds_broadcast: AtomicU64,
+    /// Rounds that failed (timeout, not enough peers, canceled, ...)
+    pub rounds_failed: AtomicU64,
+    /// Rounds that could not be started
+    pub start_errors: AtomicU64,
+    /// Peers registered in the rounds
+    pub peers_joined: AtomicU64,
+}
+
+impl Metrics {
+    pub fn incr(counter: &AtomicU64) {
+        Self::add(counter, 1);
+    }
+
+    pub fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Returns the counters as a JSON object.
+    pub fn to_json(&self) -> Value {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        json!({
+            "pools_posted": get(&self.pools_posted),
+            "rounds_resumed": get(&self.rounds_resumed),
+            "rounds_broadcast": get(&self.rounds_broadcast),
+            "rounds_failed": get(&self.rounds_failed),
+            "start_errors": get(&self.start_errors),
+            "peers_joined": get(&self.peers_joined),
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+};
+
+use joinstr::{
+    log,
+    serde_json::{self, json, Value},
+    simple_nostr_client::nostr::PublicKey,
+};
+use serde::Deserialize;
+
+use crate::{coordinator::Coordinator, Error};
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Listen for admin connections on `addr` in a dedicated thread. Each
+///   request is a JSON line `{"method": <method>, "params": <params>}`
+///   answered by a JSON line `{"result": <value>}` or `{"error": <message>}`.
+///
+/// Methods:
+///   - `status`: whether new pools are published & the number of rounds
+///   - `rounds`: the running rounds
+///   - `metrics`: the counters of [`crate::metrics::Metrics`]
+///   - `pause` / `resume`: stop / restart publishing new pools
+///   - `cancel`: cancel the round `{"id": <round id>}`
+///   - `policy`: the policy applied to the next pools
+///   - `ban` / `unban`: add / remove `{"peer": <npub hex>}` to the banned
+///     peers of the next pools, the change is not persisted
+///
+/// # Errors
+///
+/// This function will return an error if it cannot listen on `addr`.
+pub fn serve(coordinator: Arc<Coordinator>, addr: SocketAddr) -> Result<(), Error> {
+    let listener = TcpListener::bind(addr)?;
+    log::info!("Admin RPC listening on {addr}");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let coordinator = coordinator.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(&coordinator, stream) {
+                            log::debug!("Admin RPC connection closed: {e}");
+                        }
+                    });
+                }
+                Err(e) => log::error!("Admin RPC fail to accept connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(coordinator: &Coordinator, stream: TcpStream) -> Result<(), Error> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => match handle(coordinator, request) {
+                Ok(result) => json!({ "result": result }),
+                Err(e) => json!({ "error": e }),
+            },
+            Err(e) => json!({ "error": format!("invalid request: {e}") }),
+        };
+        writeln!(writer, "{response}")?;
+    }
+    Ok(())
+}
+
+fn param<'a>(params: &'a Value, name: &str) -> Result<&'a Value, String> {
+    params
+        .get(name)
+        .ok_or_else(|| format!("missing param: {name}"))
+}
+
+fn peer_param(params: &Value) -> Result<PublicKey, String> {
+    param(params, "peer")?
+        .as_str()
+        .and_then(|p| PublicKey::parse(p).ok())
+        .ok_or_else(|| "invalid param: peer".into())
+}
+
+fn handle(coordinator: &Coordinator, request: Request) -> Result<Value, String> {
+    let params = &request.params;
+    Ok(match request.method.as_str() {
+        "status" => json!({
+            "paused": coordinator.is_paused(),
+            "rounds": coordinator.rounds().len(),
+            "max_rounds": coordinator.config().max_rounds,
+        }),
+        "rounds" => coordinator.rounds().into(),
+        "metrics" => coordinator.metrics.to_json(),
+        "pause" => {
+            coordinator.set_paused(true);
+            Value::Null
+        }
+        "resume" => {
+            coordinator.set_paused(false);
+            Value::Null
+        }
+        "cancel" => {
+            let id = param(params, "id")?.as_u64().ok_or("invalid param: id")?;
+            if !coordinator.cancel(id) {
+                return Err(format!("no running round {id}"));
+            }
+            Value::Null
+        }
+        "policy" => serde_json::to_value(coordinator.policy()).map_err(|e| e.to_string())?,
+        "ban" => {
+            let peer = peer_param(params)?;
+            coordinator.update_policy(|p| {
+                if !p.banned_peers.contains(&peer) {
+                    p.banned_peers.push(peer);
+                }
+            });
+            Value::Null
+        }
+        "unban" => {
+            let peer = peer_param(params)?;
+            coordinator.update_policy(|p| p.banned_peers.retain(|b| *b != peer));
+            Value::Null
+        }
+        m => return Err(format!("unknown method: {m}")),
+    })
+}
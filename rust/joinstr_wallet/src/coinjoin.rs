@@ -0,0 +1,459 @@
+use std::{
+    ffi::{c_char, c_int, CStr},
+    str::FromStr,
+};
+
+use joinstr::{
+    bip39::Mnemonic,
+    interface::{self, PeerConfig, PoolConfig},
+    joinstr::Joinstr,
+    miniscript::bitcoin::{address::NetworkUnchecked, Address, Network},
+    nostr::Pool,
+    serde_json,
+    signer::{Coin, WpkhHotSigner},
+};
+use serde::Deserialize;
+
+use crate::{
+    database,
+    settings::{write_string, ConstStr, MutStrPtr},
+};
+
+/// An opaque handle on a coinjoin, created w/ [`initiator_new()`] or
+///   [`peer_new()`] and released w/ [`coinjoin_free()`].
+pub struct Coinjoin {
+    pub(crate) joinstr: Joinstr<'static>,
+    pub(crate) signer: WpkhHotSigner,
+    // the pool to join, None if we are the initiator
+    pub(crate) pool: Option<Pool>,
+}
+
+/// JSON form of [`PoolConfig`]
+#[derive(Debug, Deserialize)]
+struct PoolConfigJson {
+    denomination: f64,
+    fee: u32,
+    max_duration: u64,
+    peers: usize,
+    network: Network,
+}
+
+impl From<PoolConfigJson> for PoolConfig {
+    fn from(value: PoolConfigJson) -> Self {
+        PoolConfig {
+            denomination: value.denomination,
+            fee: value.fee,
+            max_duration: value.max_duration,
+            peers: value.peers,
+            network: value.network,
+        }
+    }
+}
+
+/// JSON form of [`PeerConfig`]
+#[derive(Debug, Deserialize)]
+struct PeerConfigJson {
+    mnemonics: String,
+    electrum_address: String,
+    electrum_port: u16,
+    #[serde(default)]
+    esplora_url: Option<String>,
+    input: Coin,
+    #[serde(default)]
+    output: Option<Address<NetworkUnchecked>>,
+    relay: String,
+}
+
+impl PeerConfigJson {
+    fn into_config(self) -> Option<PeerConfig> {
+        Some(PeerConfig {
+            mnemonics: Mnemonic::from_str(&self.mnemonics).ok()?,
+            electrum_address: self.electrum_address,
+            electrum_port: self.electrum_port,
+            esplora_url: self.esplora_url,
+            input: self.input,
+            output: self.output,
+            relay: self.relay,
+        })
+    }
+}
+
+// Returns None if `src` is null or not valid UTF-8
+unsafe fn read_string(src: ConstStr) -> Option<String> {
+    if src.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(src) }
+        .to_str()
+        .ok()
+        .map(String::from)
+}
+
+/// List the coins of a wallet, written as a JSON array of `Coin` in
+///   `coins`, to be released w/ [`free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null or not valid UTF-8
+///   - -2 if `network` is not a valid network
+///   - -3 if the wallet sync fails
+///   - -4 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn list_coins(
+    mnemonics: ConstStr,
+    electrum_address: ConstStr,
+    electrum_port: u16,
+    gap_limit: u32,
+    network: ConstStr,
+    coins: MutStrPtr,
+) -> c_int {
+    if coins.is_null() {
+        return -1;
+    }
+    let (mnemonics, electrum_address, network) = match (
+        read_string(mnemonics),
+        read_string(electrum_address),
+        read_string(network),
+    ) {
+        (Some(m), Some(e), Some(n)) => (m, e, n),
+        _ => return -1,
+    };
+    let network = match Network::from_str(&network) {
+        Ok(n) => n,
+        Err(_) => return -2,
+    };
+    let list = match interface::list_coins(
+        mnemonics,
+        electrum_address,
+        electrum_port,
+        gap_limit,
+        network,
+    ) {
+        Ok(l) => l,
+        Err(_) => return -3,
+    };
+    match serde_json::to_string(&list) {
+        Ok(json) if write_string(&json, coins) == 0 => 0,
+        _ => -4,
+    }
+}
+
+/// List the pools announced on `relay`, written as a JSON array of `Pool`
+///   in `pools`, to be released w/ [`free_string()`].
+///
+/// # Arguments
+/// * `back` - how far back in time (seconds) to look for pool announcements
+/// * `timeout` - how long (seconds) to listen for pool announcements
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null or not valid UTF-8
+///   - -2 if fetching the pools fails
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn list_pools(
+    back: u64,
+    timeout: u64,
+    relay: ConstStr,
+    pools: MutStrPtr,
+) -> c_int {
+    if pools.is_null() {
+        return -1;
+    }
+    let relay = match read_string(relay) {
+        Some(r) => r,
+        None => return -1,
+    };
+    let list = match interface::list_pools(back, timeout, relay) {
+        Ok(l) => l,
+        Err(_) => return -2,
+    };
+    match serde_json::to_string(&list) {
+        Ok(json) if write_string(&json, pools) == 0 => 0,
+        _ => -3,
+    }
+}
+
+/// Prepare a coinjoin initiating a new pool, the handle written in
+///   `coinjoin` must be released w/ [`coinjoin_free()`].
+///
+/// # Arguments
+/// * `pool_config` - JSON object w/ `denomination` (btc), `fee` (sats/vb),
+///   `max_duration` (seconds), `peers` and `network` fields
+/// * `peer_config` - JSON object w/ `mnemonics`, `electrum_address`,
+///   `electrum_port`, `esplora_url` (optional), `input` (a `Coin` as
+///   returned by [`list_coins()`]), `output` (optional) and `relay` fields
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null or not valid UTF-8
+///   - -2 if `pool_config` is not valid
+///   - -3 if `peer_config` is not valid
+///   - -4 if the connection to the relay or the bitcoin backend fails
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn initiator_new(
+    pool_config: ConstStr,
+    peer_config: ConstStr,
+    coinjoin: *mut *mut Coinjoin,
+) -> c_int {
+    if coinjoin.is_null() {
+        return -1;
+    }
+    let (pool_config, peer_config) = match (read_string(pool_config), read_string(peer_config)) {
+        (Some(pool), Some(peer)) => (pool, peer),
+        _ => return -1,
+    };
+    let pool_config = match serde_json::from_str::<PoolConfigJson>(&pool_config) {
+        Ok(c) => c.into(),
+        Err(_) => return -2,
+    };
+    let peer_config = match serde_json::from_str::<PeerConfigJson>(&peer_config)
+        .ok()
+        .and_then(PeerConfigJson::into_config)
+    {
+        Some(c) => c,
+        None => return -3,
+    };
+    match interface::initiator(pool_config, peer_config) {
+        Ok((joinstr, signer)) => {
+            *coinjoin = Box::into_raw(Box::new(Coinjoin {
+                joinstr,
+                signer,
+                pool: None,
+            }));
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// Prepare a coinjoin joining an already initiated pool, the handle written
+///   in `coinjoin` must be released w/ [`coinjoin_free()`].
+///
+/// # Arguments
+/// * `pool` - JSON `Pool` as returned by [`list_pools()`]
+/// * `peer_config` - see [`initiator_new()`]
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null or not valid UTF-8
+///   - -2 if `pool` is not valid
+///   - -3 if `peer_config` is not valid
+///   - -4 if the connection to the relay or the bitcoin backend fails
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn peer_new(
+    pool: ConstStr,
+    peer_config: ConstStr,
+    coinjoin: *mut *mut Coinjoin,
+) -> c_int {
+    if coinjoin.is_null() {
+        return -1;
+    }
+    let (pool, peer_config) = match (read_string(pool), read_string(peer_config)) {
+        (Some(pool), Some(peer)) => (pool, peer),
+        _ => return -1,
+    };
+    let pool = match serde_json::from_str::<Pool>(&pool) {
+        Ok(p) => p,
+        Err(_) => return -2,
+    };
+    let peer_config = match serde_json::from_str::<PeerConfigJson>(&peer_config)
+        .ok()
+        .and_then(PeerConfigJson::into_config)
+    {
+        Some(c) => c,
+        None => return -3,
+    };
+    match interface::peer(&pool, peer_config) {
+        Ok((joinstr, signer)) => {
+            *coinjoin = Box::into_raw(Box::new(Coinjoin {
+                joinstr,
+                signer,
+                pool: Some(pool),
+            }));
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// Run the coinjoin, blocking until the transaction is broadcasted, its
+///   txid is then written in `txid`, to be released w/ [`free_string()`].
+///   The coinjoin is recorded in the wallet history.
+///
+/// [`coinjoin_status()`] and [`coinjoin_cancel()`] can be called on the
+///   same handle from another thread while this function runs.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null
+///   - -2 if the coinjoin fails or is canceled
+///   - -3 if the txid cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn coinjoin_run(coinjoin: *const Coinjoin, txid: MutStrPtr) -> c_int {
+    if coinjoin.is_null() || txid.is_null() {
+        return -1;
+    }
+    let coinjoin = unsafe { &*coinjoin };
+    let mut joinstr = coinjoin.joinstr.clone();
+    let record = database::start_coinjoin(&joinstr);
+    let result = joinstr.start_coinjoin_blocking(
+        coinjoin.pool.clone(),
+        Some(coinjoin.signer.clone()),
+        || {},
+    );
+    if let Err(e) = &result {
+        joinstr.fail(e);
+    }
+    if let Some(id) = record {
+        database::update_coinjoin(id, &joinstr);
+    }
+    if result.is_err() {
+        return -2;
+    }
+    let tx = match joinstr.final_tx() {
+        Some(tx) => tx,
+        None => return -2,
+    };
+    match write_string(&tx.compute_txid().to_string(), txid) {
+        0 => 0,
+        _ => -3,
+    }
+}
+
+/// Write the current status of the coinjoin as a JSON `Status` in
+///   `status`, to be released w/ [`free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null
+///   - -2 if the status cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn coinjoin_status(coinjoin: *const Coinjoin, status: MutStrPtr) -> c_int {
+    if coinjoin.is_null() || status.is_null() {
+        return -1;
+    }
+    let coinjoin = unsafe { &*coinjoin };
+    match serde_json::to_string(&coinjoin.joinstr.status()) {
+        Ok(json) if write_string(&json, status) == 0 => 0,
+        _ => -2,
+    }
+}
+
+/// Cancel the coinjoin, a running [`coinjoin_run()`] returns at its next
+///   round.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `coinjoin` is null
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn coinjoin_cancel(coinjoin: *const Coinjoin) -> c_int {
+    if coinjoin.is_null() {
+        return -1;
+    }
+    unsafe { &*coinjoin }.joinstr.cancel();
+    0
+}
+
+/// Release a handle created w/ [`initiator_new()`] or [`peer_new()`], the
+///   coinjoin must not be running.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn coinjoin_free(coinjoin: *mut Coinjoin) {
+    if !coinjoin.is_null() {
+        drop(unsafe { Box::from_raw(coinjoin) });
+    }
+}
+
+/// Release a string returned by this library.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_string(s: *mut c_char) {
+    if !s.is_null() {
+        libc::free(s as *mut libc::c_void);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, ptr};
+
+    use super::*;
+
+    const POOL_CONFIG: &str = r#"{"denomination": 0.001, "fee": 1, "max_duration": 60, "peers": 2, "network": "regtest"}"#;
+
+    fn cstr(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn strings() {
+        let mut s: *mut c_char = ptr::null_mut();
+        unsafe {
+            assert_eq!(write_string(POOL_CONFIG, &mut s), 0);
+            assert_eq!(CStr::from_ptr(s).to_str().unwrap(), POOL_CONFIG);
+            assert_eq!(read_string(s), Some(POOL_CONFIG.into()));
+            free_string(s);
+            // interior nul cannot be written
+            s = ptr::null_mut();
+            assert_eq!(write_string("a\0b", &mut s), -1);
+            assert!(s.is_null());
+            assert_eq!(read_string(ptr::null()), None);
+            free_string(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        let pool_config = cstr(POOL_CONFIG);
+        let empty = cstr("{}");
+        let mut s: *mut c_char = ptr::null_mut();
+        let mut coinjoin: *mut Coinjoin = ptr::null_mut();
+        unsafe {
+            assert_eq!(list_pools(0, 0, ptr::null(), &mut s), -1);
+            assert_eq!(
+                list_pools(0, 0, cstr("wss://relay").as_ptr(), ptr::null_mut()),
+                -1
+            );
+            assert_eq!(
+                list_coins(
+                    cstr("").as_ptr(),
+                    cstr("127.0.0.1").as_ptr(),
+                    1,
+                    20,
+                    cstr("not a network").as_ptr(),
+                    &mut s
+                ),
+                -2
+            );
+
+            assert_eq!(
+                initiator_new(pool_config.as_ptr(), ptr::null(), &mut coinjoin),
+                -1
+            );
+            assert_eq!(
+                initiator_new(empty.as_ptr(), empty.as_ptr(), &mut coinjoin),
+                -2
+            );
+            assert_eq!(
+                initiator_new(pool_config.as_ptr(), empty.as_ptr(), &mut coinjoin),
+                -3
+            );
+            assert_eq!(peer_new(empty.as_ptr(), empty.as_ptr(), &mut coinjoin), -2);
+            assert!(coinjoin.is_null());
+
+            assert_eq!(coinjoin_run(ptr::null(), &mut s), -1);
+            assert_eq!(coinjoin_status(ptr::null(), &mut s), -1);
+            assert_eq!(coinjoin_cancel(ptr::null()), -1);
+            coinjoin_free(ptr::null_mut());
+        }
+        assert!(s.is_null());
+    }
+}
@@ -0,0 +1,632 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{c_int, CStr},
+    fmt::Display,
+    fs::{self, File},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use joinstr::{
+    electrum::{self, CoinStatus},
+    joinstr::{Joinstr, Role, Step},
+    log,
+    miniscript::bitcoin::{Amount, Transaction, Txid},
+    serde_json,
+    signer::{sync::WalletSync, Account, Coin, WpkhHotSigner},
+    utils::now,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{datadir, write_string, ConstStr, MutStrPtr, Settings};
+
+/// Version of the database schema, bumped on each breaking change.
+pub const DATABASE_VERSION: u32 = 1;
+
+const DATABASE_FILE: &str = "wallet.json";
+
+// Serialize the accesses to the database file between FFI calls & sessions
+static DATABASE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Signer(joinstr::signer::Error),
+    Electrum(electrum::Error),
+    UnsupportedVersion(u32),
+    UnknownCoinjoin(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Signer(e) => write!(f, "Signer error: {}", e),
+            Error::Electrum(e) => write!(f, "Electrum error: {:?}", e),
+            Error::UnsupportedVersion(v) => write!(f, "Unsupported database version: {}", v),
+            Error::UnknownCoinjoin(id) => write!(f, "Unknown coinjoin: {}", id),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+impl From<joinstr::signer::Error> for Error {
+    fn from(value: joinstr::signer::Error) -> Self {
+        Error::Signer(value)
+    }
+}
+
+impl From<electrum::Error> for Error {
+    fn from(value: electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
+
+/// A coin discovered by a wallet sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCoin {
+    pub coin: Coin,
+    pub status: CoinStatus,
+}
+
+/// The outcome of a coinjoin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Running,
+    Broadcast,
+    Failed(String),
+}
+
+/// A coinjoin we took part in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinjoinRecord {
+    /// None until the pool is posted if we are the initiator
+    pub pool_id: Option<String>,
+    pub denomination: Option<Amount>,
+    pub role: Role,
+    pub txid: Option<Txid>,
+    pub started_at: u64,
+    pub ended_at: Option<u64>,
+    pub outcome: Outcome,
+}
+
+impl CoinjoinRecord {
+    /// Create a record for a starting coinjoin.
+    pub fn new(joinstr: &Joinstr) -> Self {
+        let mut record = CoinjoinRecord {
+            pool_id: None,
+            denomination: None,
+            role: joinstr.status().role(),
+            txid: None,
+            started_at: now(),
+            ended_at: None,
+            outcome: Outcome::Running,
+        };
+        record.update(joinstr);
+        record
+    }
+
+    /// Update the record from the current state of the coinjoin.
+    pub fn update(&mut self, joinstr: &Joinstr) {
+        let status = joinstr.status();
+        {
+            let inner = joinstr.inner.lock().expect("poisoned");
+            if let Some(pool) = &inner.pool {
+                self.pool_id = Some(pool.id.clone());
+            }
+            self.denomination = inner.denomination.or(self.denomination);
+        }
+        self.txid = joinstr.final_tx().map(|tx| tx.compute_txid());
+        let outcome = match (status.step(), status.error()) {
+            (Step::Failed, e) => Outcome::Failed(e.unwrap_or_default().to_string()),
+            (Step::Broadcast | Step::Mined, _) => Outcome::Broadcast,
+            _ => Outcome::Running,
+        };
+        if outcome != Outcome::Running && self.ended_at.is_none() {
+            self.ended_at = Some(now());
+        }
+        self.outcome = outcome;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Data {
+    version: u32,
+    coins: Vec<StoredCoin>,
+    txs: Vec<Transaction>,
+    /// Labels by reference: an outpoint, a txid or an address
+    labels: BTreeMap<String, String>,
+    history: Vec<CoinjoinRecord>,
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Data {
+            version: DATABASE_VERSION,
+            coins: Vec::new(),
+            txs: Vec::new(),
+            labels: BTreeMap::new(),
+            history: Vec::new(),
+        }
+    }
+}
+
+/// The wallet database: coins & transactions discovered by the wallet sync,
+///   user labels and the history of our coinjoins, stored as JSON in the
+///   data directory.
+#[derive(Debug)]
+pub struct Database {
+    path: PathBuf,
+    data: Data,
+}
+
+impl Database {
+    /// Open the database of the given data directory, an empty one is
+    ///   created if missing.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the database file cannot be read or parsed
+    ///   - the database file is from a newer version
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let mut path = dir.to_path_buf();
+        path.push(DATABASE_FILE);
+        let data = if path.is_file() {
+            let data: Data = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+            if data.version != DATABASE_VERSION {
+                return Err(Error::UnsupportedVersion(data.version));
+            }
+            data
+        } else {
+            Data::default()
+        };
+        Ok(Database { path, data })
+    }
+
+    /// Write the database to its file, the previous file is replaced only
+    ///   once the new one is fully written.
+    pub fn flush(&self) -> Result<(), Error> {
+        let tmp = self.path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, &self.data)?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Sync the wallet of `signer`, only the transactions that are not
+    ///   cached in the database are fetched, then store the discovered coins
+    ///   and transactions.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sync fails.
+    pub fn sync(
+        &mut self,
+        signer: &mut WpkhHotSigner,
+        client: electrum::Client,
+    ) -> Result<(), Error> {
+        let mut sync = WalletSync::new(client).with_txs(self.data.txs.clone());
+        sync.sync(signer)?;
+        self.store_sync(&sync);
+        Ok(())
+    }
+
+    /// Store the coins and transactions discovered by a [`WalletSync`].
+    pub fn store_sync(&mut self, sync: &WalletSync) {
+        self.data.coins = sync
+            .coins()
+            .into_iter()
+            .map(|(coin, status)| StoredCoin { coin, status })
+            .collect();
+        self.data.txs = sync.txs().cloned().collect();
+    }
+
+    /// Returns the stored coins, spent ones included.
+    pub fn coins(&self) -> &[StoredCoin] {
+        &self.data.coins
+    }
+
+    /// Returns the stored coins that are not spent.
+    pub fn unspent_coins(&self) -> Vec<Coin> {
+        self.data
+            .coins
+            .iter()
+            .filter(|c| c.status != CoinStatus::Spend)
+            .map(|c| c.coin.clone())
+            .collect()
+    }
+
+    /// Returns the stored transactions by txid.
+    pub fn txs(&self) -> HashMap<Txid, Transaction> {
+        self.data
+            .txs
+            .iter()
+            .map(|tx| (tx.compute_txid(), tx.clone()))
+            .collect()
+    }
+
+    /// Returns the label of an outpoint, a txid or an address.
+    pub fn label(&self, reference: &str) -> Option<&str> {
+        self.data.labels.get(reference).map(String::as_str)
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.data.labels
+    }
+
+    /// Set the label of an outpoint, a txid or an address, an empty label
+    ///   removes it.
+    pub fn set_label(&mut self, reference: &str, label: &str) {
+        if label.is_empty() {
+            self.data.labels.remove(reference);
+        } else {
+            self.data.labels.insert(reference.into(), label.into());
+        }
+    }
+
+    /// Returns our coinjoins, the oldest first.
+    pub fn history(&self) -> &[CoinjoinRecord] {
+        &self.data.history
+    }
+
+    /// Record a coinjoin, returns its id.
+    pub fn add_coinjoin(&mut self, record: CoinjoinRecord) -> usize {
+        self.data.history.push(record);
+        self.data.history.len() - 1
+    }
+
+    /// Returns the coinjoin of the given id.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the coinjoin does not exist.
+    pub fn coinjoin_mut(&mut self, id: usize) -> Result<&mut CoinjoinRecord, Error> {
+        self.data
+            .history
+            .get_mut(id)
+            .ok_or(Error::UnknownCoinjoin(id))
+    }
+}
+
+// Returns the data directory selected in the settings, the default one if
+//   there is no settings.
+fn data_dir() -> PathBuf {
+    Settings::load()
+        .map(|s| s.datadir())
+        .unwrap_or_else(datadir)
+}
+
+// Open the database, apply `f`, then write the database.
+fn update<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut Database) -> Result<T, Error>,
+{
+    let _lock = DATABASE_LOCK.lock().expect("poisoned");
+    let mut db = Database::open(&data_dir())?;
+    let result = f(&mut db)?;
+    db.flush()?;
+    Ok(result)
+}
+
+// Open the database and apply `f`.
+fn read<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce(&Database) -> T,
+{
+    let _lock = DATABASE_LOCK.lock().expect("poisoned");
+    let db = Database::open(&data_dir())?;
+    Ok(f(&db))
+}
+
+/// Record a starting coinjoin in the database, returns its id or None if
+///   the database cannot be written.
+pub fn start_coinjoin(joinstr: &Joinstr) -> Option<usize> {
+    let record = CoinjoinRecord::new(joinstr);
+    match update(|db| Ok(db.add_coinjoin(record))) {
+        Ok(id) => Some(id),
+        Err(e) => {
+            log::error!("database::start_coinjoin() fail to record coinjoin: {e}");
+            None
+        }
+    }
+}
+
+/// Update the record of a coinjoin from its current state.
+pub fn update_coinjoin(id: usize, joinstr: &Joinstr) {
+    if let Err(e) = update(|db| {
+        db.coinjoin_mut(id)?.update(joinstr);
+        Ok(())
+    }) {
+        log::error!("database::update_coinjoin() fail to update coinjoin: {e}");
+    }
+}
+
+/// Returns the id of the last coinjoin recorded for the pool, None if there
+///   is none or if the database cannot be read.
+pub fn coinjoin_of(pool_id: &str) -> Option<usize> {
+    let found = read(|db| {
+        db.history()
+            .iter()
+            .rposition(|r| r.pool_id.as_deref() == Some(pool_id))
+    });
+    match found {
+        Ok(id) => id,
+        Err(e) => {
+            log::error!("database::coinjoin_of() fail to read the history: {e}");
+            None
+        }
+    }
+}
+
+/// Sync the wallet described in the settings against its preferred electrum
+///   server, store the result in the database and write the unspent coins as
+///   a JSON array of `Coin` in `coins`, to be released w/
+///   [`crate::free_string()`].
+///
+/// # Arguments
+/// * `gap_limit` - addresses are derived until `gap_limit` consecutive
+///   addresses are unused on every account
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `coins` is null
+///   - -2 if there is no settings file or no electrum server
+///   - -3 if the connection to the electrum server fails
+///   - -4 if the sync fails
+///   - -5 if the database cannot be read or written
+///   - -6 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wallet_sync(gap_limit: u32, coins: MutStrPtr) -> c_int {
+    if coins.is_null() {
+        return -1;
+    }
+    let settings = match Settings::load() {
+        Some(s) => s,
+        None => return -2,
+    };
+    let client = match settings.electrum.first().map(|s| s.connect()) {
+        Some(Ok(c)) => c,
+        Some(Err(_)) => return -3,
+        None => return -2,
+    };
+    let mut signer = match WpkhHotSigner::new_from_mnemonics(settings.network, &settings.mnemonics)
+    {
+        Ok(s) => s,
+        Err(_) => return -2,
+    };
+    for account in Account::all() {
+        signer.set_gap_limit(account, gap_limit);
+    }
+    let txs = match read(|db| db.txs().into_values().collect::<Vec<_>>()) {
+        Ok(txs) => txs,
+        Err(_) => return -5,
+    };
+    // NOTE: the database is not locked during the sync, in order to not block
+    // the sessions updating their coinjoin record behind a slow server
+    let mut sync = WalletSync::new(client).with_txs(txs);
+    if sync.sync(&mut signer).is_err() {
+        return -4;
+    }
+    let unspent = match update(|db| {
+        db.store_sync(&sync);
+        Ok(db.unspent_coins())
+    }) {
+        Ok(u) => u,
+        Err(_) => return -5,
+    };
+    match serde_json::to_string(&unspent) {
+        Ok(json) if write_string(&json, coins) == 0 => 0,
+        _ => -6,
+    }
+}
+
+/// Write the unspent coins stored by the last [`wallet_sync()`] as a JSON
+///   array of `Coin` in `coins`, to be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `coins` is null
+///   - -2 if the database cannot be read
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wallet_coins(coins: MutStrPtr) -> c_int {
+    if coins.is_null() {
+        return -1;
+    }
+    let json = match read(|db| serde_json::to_string(&db.unspent_coins())) {
+        Ok(Ok(json)) => json,
+        Ok(Err(_)) => return -3,
+        Err(_) => return -2,
+    };
+    match write_string(&json, coins) {
+        0 => 0,
+        _ => -3,
+    }
+}
+
+/// Set the label of an outpoint, a txid or an address, an empty label
+///   removes it.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null or not valid UTF-8
+///   - -2 if the database cannot be read or written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wallet_set_label(reference: ConstStr, label: ConstStr) -> c_int {
+    if reference.is_null() || label.is_null() {
+        return -1;
+    }
+    let (reference, label) = match (
+        unsafe { CStr::from_ptr(reference) }.to_str(),
+        unsafe { CStr::from_ptr(label) }.to_str(),
+    ) {
+        (Ok(r), Ok(l)) => (r, l),
+        _ => return -1,
+    };
+    match update(|db| {
+        db.set_label(reference, label);
+        Ok(())
+    }) {
+        Ok(_) => 0,
+        Err(_) => -2,
+    }
+}
+
+/// Write the labels as a JSON object (reference => label) in `labels`, to
+///   be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `labels` is null
+///   - -2 if the database cannot be read
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wallet_labels(labels: MutStrPtr) -> c_int {
+    if labels.is_null() {
+        return -1;
+    }
+    let json = match read(|db| serde_json::to_string(db.labels())) {
+        Ok(Ok(json)) => json,
+        Ok(Err(_)) => return -3,
+        Err(_) => return -2,
+    };
+    match write_string(&json, labels) {
+        0 => 0,
+        _ => -3,
+    }
+}
+
+/// Write our coinjoins as a JSON array of [`CoinjoinRecord`] in `history`,
+///   the oldest first, to be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `history` is null
+///   - -2 if the database cannot be read
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wallet_history(history: MutStrPtr) -> c_int {
+    if history.is_null() {
+        return -1;
+    }
+    let json = match read(|db| serde_json::to_string(db.history())) {
+        Ok(Ok(json)) => json,
+        Ok(Err(_)) => return -3,
+        Err(_) => return -2,
+    };
+    match write_string(&json, history) {
+        0 => 0,
+        _ => -3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use joinstr::miniscript::bitcoin::{
+        absolute::LockTime, transaction::Version, Network, OutPoint, Sequence, TxOut,
+    };
+    use joinstr::signer::CoinPath;
+
+    use super::*;
+
+    #[test]
+    fn persistence() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("joinstr_wallet_db_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let signer = WpkhHotSigner::new(Network::Regtest).unwrap();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: signer.recv_addr_at(0).script_pubkey(),
+            }],
+        };
+        let txid = tx.compute_txid();
+
+        let mut db = Database::open(&dir).unwrap();
+        assert!(db.coins().is_empty());
+        db.data.txs.push(tx.clone());
+        db.data.coins.push(StoredCoin {
+            coin: Coin {
+                txout: tx.output[0].clone(),
+                outpoint: OutPoint::new(txid, 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                coin_path: CoinPath::new(0, 0),
+            },
+            status: CoinStatus::Confirmed,
+        });
+        db.set_label(&txid.to_string(), "deposit");
+        let id = db.add_coinjoin(CoinjoinRecord {
+            pool_id: Some("pool".into()),
+            denomination: Some(Amount::from_sat(100_000)),
+            role: Role::Peer,
+            txid: None,
+            started_at: 0,
+            ended_at: None,
+            outcome: Outcome::Running,
+        });
+        db.coinjoin_mut(id).unwrap().outcome = Outcome::Failed("Canceled".into());
+        assert!(db.coinjoin_mut(id + 1).is_err());
+        db.flush().unwrap();
+
+        let mut db = Database::open(&dir).unwrap();
+        assert_eq!(db.unspent_coins().len(), 1);
+        assert_eq!(db.unspent_coins()[0].coin_path, CoinPath::new(0, 0));
+        assert_eq!(db.txs().get(&txid), Some(&tx));
+        assert_eq!(db.label(&txid.to_string()), Some("deposit"));
+        assert_eq!(db.history()[0].outcome, Outcome::Failed("Canceled".into()));
+
+        db.set_label(&txid.to_string(), "");
+        assert!(db.labels().is_empty());
+        db.data.coins[0].status = CoinStatus::Spend;
+        assert!(db.unspent_coins().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn version() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("joinstr_wallet_db_version_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut path = dir.clone();
+        path.push(DATABASE_FILE);
+        let json = serde_json::to_string(&Data {
+            version: DATABASE_VERSION + 1,
+            ..Default::default()
+        })
+        .unwrap();
+        fs::write(&path, json).unwrap();
+        assert!(matches!(
+            Database::open(&dir),
+            Err(Error::UnsupportedVersion(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,2 +1,8 @@
+mod coinjoin;
+mod database;
+mod session;
 mod settings;
+pub use coinjoin::*;
+pub use database::*;
+pub use session::*;
 pub use settings::*;
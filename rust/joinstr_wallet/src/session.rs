@@ -0,0 +1,282 @@
+use std::{
+    ffi::{c_int, c_void, CString},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, ThreadId},
+    time::Duration,
+};
+
+use joinstr::{joinstr::Joinstr, serde_json};
+
+use crate::{
+    coinjoin::Coinjoin,
+    database,
+    settings::{write_string, ConstStr, MutStrPtr},
+};
+
+/// The status is also polled at this interval, as some state changes
+///   (e.g. confirmations) do not trigger a notification.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Called w/ the JSON `Status` of the session and the `user_data` pointer
+///   registered at [`session_start()`], the status string is only valid
+///   during the call.
+pub type StatusCallback = extern "C" fn(status: ConstStr, user_data: *mut c_void);
+
+struct Callback {
+    func: StatusCallback,
+    user_data: *mut c_void,
+    // set when the session is freed, the callback is not called anymore
+    stopped: AtomicBool,
+    // held while the callback runs
+    lock: Mutex<()>,
+}
+
+// SAFETY: the user data pointer is only passed back to the callback, the
+//   caller is responsible for it to be usable from the notifier thread.
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Callback {
+    fn call(&self, status: &str) {
+        let _guard = self.lock.lock().expect("poisoned");
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(status) = CString::new(status) {
+            (self.func)(status.as_ptr(), self.user_data);
+        }
+    }
+}
+
+/// A coinjoin running in the background, created w/ [`session_start()`]
+///   and released w/ [`session_free()`].
+pub struct Session {
+    joinstr: Joinstr<'static>,
+    callback: Arc<Callback>,
+    notifier: ThreadId,
+}
+
+// Call the callback & update the coinjoin record each time the status of
+//   the coinjoin changes, until the coinjoin thread ends.
+fn notify(
+    joinstr: Joinstr<'static>,
+    callback: Arc<Callback>,
+    record: Option<usize>,
+    receiver: Receiver<()>,
+) {
+    let mut last = None;
+    loop {
+        let done = matches!(
+            receiver.recv_timeout(POLL_INTERVAL),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        if let Ok(status) = serde_json::to_string(&joinstr.status()) {
+            if last.as_ref() != Some(&status) {
+                if let Some(id) = record {
+                    database::update_coinjoin(id, &joinstr);
+                }
+                callback.call(&status);
+                last = Some(status);
+            }
+        }
+        if done {
+            break;
+        }
+    }
+}
+
+/// Start the coinjoin of `coinjoin` in the background, `callback` is then
+///   called from a dedicated thread each time the status of the coinjoin
+///   changes, and the coinjoin is recorded in the wallet history. The
+///   handle written in `session` must be released w/ [`session_free()`],
+///   `coinjoin` can be released independently.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `coinjoin`, `callback` or `session` is null
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn session_start(
+    coinjoin: *const Coinjoin,
+    callback: Option<StatusCallback>,
+    user_data: *mut c_void,
+    session: *mut *mut Session,
+) -> c_int {
+    let func = match callback {
+        Some(f) => f,
+        None => return -1,
+    };
+    if coinjoin.is_null() || session.is_null() {
+        return -1;
+    }
+    let coinjoin = unsafe { &*coinjoin };
+    let callback = Arc::new(Callback {
+        func,
+        user_data,
+        stopped: AtomicBool::new(false),
+        lock: Mutex::new(()),
+    });
+
+    let record = database::start_coinjoin(&coinjoin.joinstr);
+
+    let (sender, receiver) = mpsc::channel();
+    let notifier = {
+        let joinstr = coinjoin.joinstr.clone();
+        let callback = callback.clone();
+        thread::spawn(move || notify(joinstr, callback, record, receiver))
+    };
+
+    // the sender is dropped when the coinjoin thread ends, it stops the notifier
+    let mut joinstr = coinjoin.joinstr.clone();
+    joinstr.start_coinjoin_with_notif(
+        coinjoin.pool.clone(),
+        Some(coinjoin.signer.clone()),
+        move || {
+            let _ = sender.send(());
+        },
+    );
+
+    *session = Box::into_raw(Box::new(Session {
+        joinstr,
+        callback,
+        notifier: notifier.thread().id(),
+    }));
+    0
+}
+
+/// Write the current status of the session as a JSON `Status` in `status`,
+///   to be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null
+///   - -2 if the status cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn session_status(session: *const Session, status: MutStrPtr) -> c_int {
+    if session.is_null() || status.is_null() {
+        return -1;
+    }
+    let session = unsafe { &*session };
+    match serde_json::to_string(&session.joinstr.status()) {
+        Ok(json) if write_string(&json, status) == 0 => 0,
+        _ => -2,
+    }
+}
+
+/// Write the txid of the coinjoin transaction in `txid`, to be released w/
+///   [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if an argument is null
+///   - -2 if the transaction has not been broadcasted yet
+///   - -3 if the txid cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn session_txid(session: *const Session, txid: MutStrPtr) -> c_int {
+    if session.is_null() || txid.is_null() {
+        return -1;
+    }
+    let session = unsafe { &*session };
+    let tx = match session.joinstr.final_tx() {
+        Some(tx) => tx,
+        None => return -2,
+    };
+    match write_string(&tx.compute_txid().to_string(), txid) {
+        0 => 0,
+        _ => -3,
+    }
+}
+
+/// Cancel the session, the coinjoin fails at its next round and the
+///   callback is called w/ a `Failed` status.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `session` is null
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn session_cancel(session: *const Session) -> c_int {
+    if session.is_null() {
+        return -1;
+    }
+    unsafe { &*session }.joinstr.cancel();
+    0
+}
+
+/// Cancel and release a session, the callback is never called once this
+///   function returns. It can be called from the callback itself.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn session_free(session: *mut Session) {
+    if session.is_null() {
+        return;
+    }
+    let session = unsafe { Box::from_raw(session) };
+    session.joinstr.cancel();
+    session.callback.stopped.store(true, Ordering::SeqCst);
+    // wait for a running callback to return, unless we are called from it
+    if thread::current().id() != session.notifier {
+        drop(session.callback.lock.lock().expect("poisoned"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CStr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use joinstr::{miniscript::bitcoin::Network, simple_nostr_client::nostr::Keys};
+
+    use super::*;
+
+    extern "C" fn count(status: ConstStr, user_data: *mut c_void) {
+        let status = unsafe { CStr::from_ptr(status) }.to_str().unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(status).is_ok());
+        unsafe { &*(user_data as *const AtomicUsize) }.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn callback(calls: &AtomicUsize) -> Arc<Callback> {
+        Arc::new(Callback {
+            func: count,
+            user_data: calls as *const AtomicUsize as *mut c_void,
+            stopped: AtomicBool::new(false),
+            lock: Mutex::new(()),
+        })
+    }
+
+    #[test]
+    fn callback_fires() {
+        // nothing is sent to the relay or the esplora server
+        let joinstr = Joinstr::new_initiator_with_esplora(
+            Keys::generate(),
+            "ws://127.0.0.1:1",
+            "http://127.0.0.1:1",
+            Network::Regtest,
+            "callback_fires",
+        )
+        .unwrap();
+        let calls = AtomicUsize::new(0);
+
+        // the coinjoin thread already ended: the status is notified once
+        let (sender, receiver) = mpsc::channel();
+        drop(sender);
+        notify(joinstr.clone(), callback(&calls), None, receiver);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // a stopped callback is not called anymore
+        let stopped = callback(&calls);
+        stopped.stopped.store(true, Ordering::SeqCst);
+        let (sender, receiver) = mpsc::channel();
+        drop(sender);
+        notify(joinstr, stopped, None, receiver);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
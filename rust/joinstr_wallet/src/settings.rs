@@ -1,5 +1,6 @@
 use std::{
     ffi::{c_char, c_int, CStr, CString},
+    fmt::Display,
     fs::File,
     io::Read,
     path::{Path, PathBuf},
@@ -9,23 +10,27 @@ use std::{
 
 use libc::malloc;
 
-use joinstr::{bip39::Mnemonic, serde_json};
+use joinstr::{bip39::Mnemonic, electrum, miniscript::bitcoin::Network, serde_json};
 use serde::{Deserialize, Serialize};
-use url::Url;
+use url::{Host, Url};
 
-type MutStrPtr = *mut *mut c_char;
-type ConstStr = *const c_char;
+pub(crate) type MutStrPtr = *mut *mut c_char;
+pub(crate) type ConstStr = *const c_char;
 
-fn datadir() -> PathBuf {
+/// Version of the [`Settings`] schema, bumped on each breaking change.
+pub const SETTINGS_VERSION: u32 = 1;
+
+// Returns the default data directory, created if missing.
+pub(crate) fn datadir() -> PathBuf {
     #[cfg(target_os = "linux")]
-    let mut dir = {
+    let dir = {
         let mut dir = dirs::home_dir().unwrap();
         dir.push(".joinstr");
         dir
     };
 
     #[cfg(not(target_os = "linux"))]
-    let mut dir = {
+    let dir = {
         let mut dir = dirs::config_dir().unwrap();
         dir.push("Joinstr");
         dir
@@ -33,11 +38,17 @@ fn datadir() -> PathBuf {
 
     maybe_create_dir(&dir);
 
-    dir.push("joinstr.conf");
-
     dir
 }
 
+// The settings file always lives in the default data directory, as it
+//   holds the data directory override.
+fn settings_path() -> PathBuf {
+    let mut path = datadir();
+    path.push("joinstr.conf");
+    path
+}
+
 fn maybe_create_dir(dir: &PathBuf) {
     if !dir.exists() {
         #[cfg(unix)]
@@ -68,6 +79,14 @@ pub unsafe extern "C" fn is_mnemonic_valid(mnemonic: ConstStr) -> c_int {
     }
 }
 
+/// Check an electrum server address of the form `[ssl://|tcp://]<host>:<port>`.
+///
+/// # Returns
+///   - 0 if the address is valid
+///   - -1 if `addr` is not valid UTF-8
+///   - -2 if the port is missing
+///   - -3 if the host is not valid
+///   - -4 if the port is not valid
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn is_electrum_valid(addr: ConstStr) -> c_int {
@@ -76,19 +95,9 @@ pub unsafe extern "C" fn is_electrum_valid(addr: ConstStr) -> c_int {
         Ok(r) => r,
         Err(_) => return -1,
     };
-    let separators = electrum.chars().filter(|c| *c == ':').count();
-    if separators != 1 {
-        return -2;
-    }
-    let (url, port) = electrum.split_once(':').expect("checked");
-    let port = u16::from_str(port).is_ok();
-    let url = Url::parse(url).is_ok();
-    if !url {
-        -3
-    } else if !port {
-        -4
-    } else {
-        0
+    match ElectrumServer::from_str(electrum) {
+        Ok(_) => 0,
+        Err(e) => e.code(),
     }
 }
 
@@ -106,6 +115,9 @@ pub unsafe extern "C" fn is_relay_valid(addr: ConstStr) -> c_int {
     }
 }
 
+/// Save the mnemonics, electrum server and relay: the electrum server and
+///   the relay become the preferred ones, other settings are kept if a
+///   settings file already exists, network defaults to mainnet otherwise.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn save_settings(
@@ -127,7 +139,17 @@ pub unsafe extern "C" fn save_settings(
     let relay = unsafe { CStr::from_ptr(relay) }.to_str();
 
     if let (Ok(mnemonics), Ok(electrum), Ok(relay)) = (mnemonics, electrum, relay) {
-        if Settings::new(mnemonics, electrum, relay).to_file(&datadir()) != 0 {
+        let electrum = ElectrumServer::from_str(electrum).expect("checked");
+        let mut settings = match Settings::load() {
+            Some(mut s) => {
+                s.mnemonics = mnemonics.into();
+                s
+            }
+            None => Settings::new(mnemonics, Network::Bitcoin),
+        };
+        settings.prefer_electrum(electrum);
+        settings.prefer_relay(relay);
+        if settings.to_file(&settings_path()) != 0 {
             -4
         } else {
             0
@@ -137,7 +159,7 @@ pub unsafe extern "C" fn save_settings(
     }
 }
 
-unsafe fn write_string(src: &str, dst: *mut *mut c_char) -> c_int {
+pub(crate) unsafe fn write_string(src: &str, dst: *mut *mut c_char) -> c_int {
     let c_str = match CString::new(src) {
         Ok(r) => r,
         Err(_) => return -1,
@@ -152,6 +174,9 @@ unsafe fn write_string(src: &str, dst: *mut *mut c_char) -> c_int {
     0
 }
 
+/// Load the mnemonics, the preferred electrum server (in the form accepted
+///   by [`is_electrum_valid()`]) and the preferred relay, an empty string is
+///   written if there is no electrum server or relay.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn load_settings(
@@ -162,7 +187,7 @@ pub unsafe extern "C" fn load_settings(
     if mnemonics.is_null() || electrum.is_null() || relay.is_null() {
         return -1;
     }
-    let settings = match Settings::from_file(&datadir()) {
+    let settings = match Settings::load() {
         Some(s) => s,
         None => return -2,
     };
@@ -170,32 +195,489 @@ pub unsafe extern "C" fn load_settings(
     if write_string(&settings.mnemonics, mnemonics) != 0 {
         return -3;
     }
-    if write_string(&settings.electrum, electrum) != 0 {
+    let server = settings
+        .electrum
+        .first()
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+    if write_string(&server, electrum) != 0 {
         return -4;
     }
-    if write_string(&settings.relay, relay) != 0 {
+    let first_relay = settings.relays.first().cloned().unwrap_or_default();
+    if write_string(&first_relay, relay) != 0 {
         return -5;
     }
 
     0
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Settings {
+// Load the settings, apply `f` and save them.
+fn update_settings<F>(f: F) -> c_int
+where
+    F: FnOnce(&mut Settings),
+{
+    let path = settings_path();
+    let mut settings = match Settings::from_file(&path) {
+        Some(s) => s,
+        None => return -2,
+    };
+    f(&mut settings);
+    if settings.to_file(&path) != 0 {
+        return -4;
+    }
+    0
+}
+
+// Load the settings and write `f(settings)` in `dst`.
+unsafe fn read_settings<F>(dst: MutStrPtr, f: F) -> c_int
+where
+    F: FnOnce(&Settings) -> Option<String>,
+{
+    if dst.is_null() {
+        return -1;
+    }
+    let settings = match Settings::load() {
+        Some(s) => s,
+        None => return -2,
+    };
+    match f(&settings) {
+        Some(value) if write_string(&value, dst) == 0 => 0,
+        _ => -3,
+    }
+}
+
+// Parse a JSON string argument.
+unsafe fn read_json<T: for<'a> Deserialize<'a>>(src: ConstStr) -> Option<T> {
+    if src.is_null() {
+        return None;
+    }
+    let json = unsafe { CStr::from_ptr(src) }.to_str().ok()?;
+    serde_json::from_str(json).ok()
+}
+
+/// Write the whole settings as JSON in `settings`, to be released w/
+///   [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `settings` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_settings(settings: MutStrPtr) -> c_int {
+    read_settings(settings, |s| serde_json::to_string(s).ok())
+}
+
+/// Replace the whole settings by the JSON `settings`, see [`Settings`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `settings` is null or not a valid JSON settings object
+///   - -3 if the settings are not valid
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_settings(settings: ConstStr) -> c_int {
+    let settings: Settings = match read_json(settings) {
+        Some(s) => s,
+        None => return -1,
+    };
+    if !settings.is_valid() {
+        return -3;
+    }
+    if settings.to_file(&settings_path()) != 0 {
+        return -4;
+    }
+    0
+}
+
+/// Write the network (`bitcoin`, `testnet`, `signet` or `regtest`) in
+///   `network`, to be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `network` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_network(network: MutStrPtr) -> c_int {
+    read_settings(network, |s| Some(s.network.to_string()))
+}
+
+/// Set the network (`bitcoin`, `testnet`, `signet` or `regtest`).
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `network` is null or not a valid network
+///   - -2 if there is no settings file
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_network(network: ConstStr) -> c_int {
+    if network.is_null() {
+        return -1;
+    }
+    let network = match unsafe { CStr::from_ptr(network) }
+        .to_str()
+        .ok()
+        .and_then(|n| Network::from_str(n).ok())
+    {
+        Some(n) => n,
+        None => return -1,
+    };
+    update_settings(|s| s.network = network)
+}
+
+/// Write the relays as a JSON array of urls in `relays`, to be released w/
+///   [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `relays` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_relays(relays: MutStrPtr) -> c_int {
+    read_settings(relays, |s| serde_json::to_string(&s.relays).ok())
+}
+
+/// Set the relays from a JSON array of urls, the first one is preferred.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `relays` is null or not a JSON array of strings
+///   - -2 if there is no settings file
+///   - -3 if a relay url is not valid
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_relays(relays: ConstStr) -> c_int {
+    let relays: Vec<String> = match read_json(relays) {
+        Some(r) => r,
+        None => return -1,
+    };
+    if relays.iter().any(|r| Url::parse(r).is_err()) {
+        return -3;
+    }
+    update_settings(|s| s.relays = relays)
+}
+
+/// Write the electrum servers as a JSON array of [`ElectrumServer`] in
+///   `servers`, to be released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `servers` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_electrum_servers(servers: MutStrPtr) -> c_int {
+    read_settings(servers, |s| serde_json::to_string(&s.electrum).ok())
+}
+
+/// Set the electrum servers from a JSON array of [`ElectrumServer`], the
+///   first one is preferred.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `servers` is null or not a JSON array of servers
+///   - -2 if there is no settings file
+///   - -3 if a server host is not valid
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_electrum_servers(servers: ConstStr) -> c_int {
+    let servers: Vec<ElectrumServer> = match read_json(servers) {
+        Some(s) => s,
+        None => return -1,
+    };
+    if servers.iter().any(|s| !s.is_valid()) {
+        return -3;
+    }
+    update_settings(|s| s.electrum = servers)
+}
+
+/// Write the default pool preferences as a JSON object w/ optional
+///   `denomination` (btc) and `fee` (sats/vb) fields in `preferences`, to be
+///   released w/ [`crate::free_string()`].
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `preferences` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_preferences(preferences: MutStrPtr) -> c_int {
+    read_settings(preferences, |s| serde_json::to_string(&s.preferences).ok())
+}
+
+/// Set the default pool preferences from a JSON object w/ optional
+///   `denomination` (btc) and `fee` (sats/vb) fields.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `preferences` is null or not a valid JSON object
+///   - -2 if there is no settings file
+///   - -3 if the denomination is not positive
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_preferences(preferences: ConstStr) -> c_int {
+    let preferences: Preferences = match read_json(preferences) {
+        Some(p) => p,
+        None => return -1,
+    };
+    if !preferences.is_valid() {
+        return -3;
+    }
+    update_settings(|s| s.preferences = preferences)
+}
+
+/// Write the data directory in `datadir`, to be released w/
+///   [`crate::free_string()`]: the override if any, the default one otherwise.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `datadir` is null
+///   - -2 if there is no settings file
+///   - -3 if the result cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn get_datadir(datadir: MutStrPtr) -> c_int {
+    read_settings(datadir, |s| Some(s.datadir().to_string_lossy().to_string()))
+}
+
+/// Override the data directory, an empty string restores the default one.
+///
+/// # Returns
+///   - 0 on success
+///   - -1 if `datadir` is null or not valid UTF-8
+///   - -2 if there is no settings file
+///   - -3 if `datadir` is not an absolute path
+///   - -4 if the settings file cannot be written
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn set_datadir(datadir: ConstStr) -> c_int {
+    if datadir.is_null() {
+        return -1;
+    }
+    let datadir = match unsafe { CStr::from_ptr(datadir) }.to_str() {
+        Ok(d) => d,
+        Err(_) => return -1,
+    };
+    let datadir = match datadir.is_empty() {
+        true => None,
+        false => Some(PathBuf::from(datadir)),
+    };
+    if datadir.as_ref().is_some_and(|d| !d.is_absolute()) {
+        return -3;
+    }
+    update_settings(|s| s.datadir = datadir)
+}
+
+/// Reasons an electrum server address is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElectrumError {
+    MissingPort,
+    InvalidHost,
+    InvalidPort,
+}
+
+impl ElectrumError {
+    fn code(&self) -> c_int {
+        match self {
+            ElectrumError::MissingPort => -2,
+            ElectrumError::InvalidHost => -3,
+            ElectrumError::InvalidPort => -4,
+        }
+    }
+}
+
+/// An electrum server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElectrumServer {
+    pub host: String,
+    pub port: u16,
+    /// Connect using TLS
+    #[serde(default)]
+    pub tls: bool,
+    /// Accept a self-signed TLS certificate
+    #[serde(default)]
+    pub self_signed: bool,
+}
+
+impl ElectrumServer {
+    fn is_valid(&self) -> bool {
+        Host::parse(&self.host).is_ok()
+    }
+
+    /// Returns the address expected by `joinstr::electrum::Client::new()`:
+    ///   the host, prefixed w/ `ssl://` if TLS is used.
+    pub fn address(&self) -> String {
+        match self.tls {
+            true => format!("ssl://{}", self.host),
+            false => self.host.clone(),
+        }
+    }
+
+    /// Connect to the server, the TLS certificate is not verified if
+    ///   `self_signed` is set.
+    pub fn connect(&self) -> Result<electrum::Client, electrum::Error> {
+        match self.self_signed {
+            true => electrum::Client::new_local(&self.address(), self.port),
+            false => electrum::Client::new(&self.address(), self.port),
+        }
+    }
+}
+
+impl FromStr for ElectrumServer {
+    type Err = ElectrumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tls, s) = if let Some(s) = s.strip_prefix("ssl://") {
+            (true, s)
+        } else {
+            (false, s.strip_prefix("tcp://").unwrap_or(s))
+        };
+        let (host, port) = s.rsplit_once(':').ok_or(ElectrumError::MissingPort)?;
+        let server = ElectrumServer {
+            host: host.into(),
+            port: u16::from_str(port).map_err(|_| ElectrumError::InvalidPort)?,
+            tls,
+            self_signed: false,
+        };
+        match server.is_valid() {
+            true => Ok(server),
+            false => Err(ElectrumError::InvalidHost),
+        }
+    }
+}
+
+impl Display for ElectrumServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.address(), self.port)
+    }
+}
+
+/// Default values proposed when initiating a pool.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preferences {
+    /// Denomination in btc
+    #[serde(default)]
+    pub denomination: Option<f64>,
+    /// Fee rate in sats/vb
+    #[serde(default)]
+    pub fee: Option<u32>,
+}
+
+impl Preferences {
+    fn is_valid(&self) -> bool {
+        self.denomination.is_none_or(|d| d > 0.0)
+    }
+}
+
+/// The wallet settings, persisted as JSON in `joinstr.conf`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Version of the schema, see [`SETTINGS_VERSION`]
+    pub version: u32,
+    pub mnemonics: String,
+    pub network: Network,
+    /// Nostr relays, the first one is preferred
+    pub relays: Vec<String>,
+    /// Electrum servers, the first one is preferred
+    pub electrum: Vec<ElectrumServer>,
+    #[serde(default)]
+    pub preferences: Preferences,
+    /// Overrides the default data directory
+    #[serde(default)]
+    pub datadir: Option<PathBuf>,
+}
+
+/// The settings before versioning, the network was not persisted.
+#[derive(Debug, Deserialize)]
+struct SettingsV0 {
     pub mnemonics: String,
     pub electrum: String,
     pub relay: String,
 }
 
+impl From<SettingsV0> for Settings {
+    fn from(value: SettingsV0) -> Self {
+        let mut settings = Settings::new(&value.mnemonics, Network::Bitcoin);
+        if let Ok(server) = ElectrumServer::from_str(&value.electrum) {
+            settings.electrum.push(server);
+        }
+        if !value.relay.is_empty() {
+            settings.relays.push(value.relay);
+        }
+        settings
+    }
+}
+
 impl Settings {
-    pub fn new(mnemonics: &str, electrum: &str, relay: &str) -> Self {
+    pub fn new(mnemonics: &str, network: Network) -> Self {
         Settings {
+            version: SETTINGS_VERSION,
             mnemonics: mnemonics.into(),
-            electrum: electrum.into(),
-            relay: relay.into(),
+            network,
+            relays: Vec::new(),
+            electrum: Vec::new(),
+            preferences: Preferences::default(),
+            datadir: None,
+        }
+    }
+
+    /// Returns the data directory, created if missing: the override if any,
+    ///   the default one otherwise.
+    pub fn datadir(&self) -> PathBuf {
+        match &self.datadir {
+            Some(dir) => {
+                maybe_create_dir(dir);
+                dir.clone()
+            }
+            None => datadir(),
         }
     }
 
+    /// Load the settings from the settings file of the default data
+    ///   directory.
+    pub fn load() -> Option<Self> {
+        Self::from_file(&settings_path())
+    }
+
+    /// Save the settings in the settings file of the default data directory.
+    pub fn save(&self) -> c_int {
+        self.to_file(&settings_path())
+    }
+
+    fn is_valid(&self) -> bool {
+        self.version == SETTINGS_VERSION
+            && Mnemonic::from_str(&self.mnemonics).is_ok()
+            && self.relays.iter().all(|r| Url::parse(r).is_ok())
+            && self.electrum.iter().all(|s| s.is_valid())
+            && self.preferences.is_valid()
+            && self.datadir.as_ref().is_none_or(|d| d.is_absolute())
+    }
+
+    // Move `server` in first position
+    fn prefer_electrum(&mut self, server: ElectrumServer) {
+        self.electrum
+            .retain(|s| (&s.host, s.port) != (&server.host, server.port));
+        self.electrum.insert(0, server);
+    }
+
+    // Move `relay` in first position
+    fn prefer_relay(&mut self, relay: &str) {
+        self.relays.retain(|r| r != relay);
+        self.relays.insert(0, relay.into());
+    }
+
     pub fn to_file(&self, path: &Path) -> c_int {
         let path: &str = &path.to_string_lossy();
         let file = match File::create(path) {
@@ -209,6 +691,8 @@ impl Settings {
         }
     }
 
+    /// Load the settings, migrating them from a previous schema if needed,
+    ///   returns None if the file is missing, invalid or from a newer schema.
     pub fn from_file(path: &Path) -> Option<Self> {
         if !path.exists() || !path.is_file() {
             return None;
@@ -217,7 +701,88 @@ impl Settings {
         let mut file = File::open(path).ok()?;
         let mut settings_str = String::new();
         let _conf_size = file.read_to_string(&mut settings_str).ok()?;
-        let conf: Self = serde_json::from_str(&settings_str).ok()?;
-        Some(conf)
+        Self::from_json(&settings_str)
+    }
+
+    fn from_json(json: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).ok()?;
+        match value.get("version").map(|v| v.as_u64()) {
+            None => serde_json::from_value::<SettingsV0>(value)
+                .ok()
+                .map(Settings::from),
+            Some(Some(v)) if v == SETTINGS_VERSION as u64 => serde_json::from_value(value).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MNEMONICS: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn electrum_server() {
+        let server = ElectrumServer::from_str("ssl://electrum.blockstream.info:50002").unwrap();
+        assert!(server.tls);
+        assert_eq!(server.address(), "ssl://electrum.blockstream.info");
+        assert_eq!(server.to_string(), "ssl://electrum.blockstream.info:50002");
+        let server = ElectrumServer::from_str("127.0.0.1:50001").unwrap();
+        assert!(!server.tls);
+        assert_eq!(server.to_string(), "127.0.0.1:50001");
+        assert_eq!(
+            ElectrumServer::from_str("tcp://localhost:50001").unwrap(),
+            ElectrumServer::from_str("localhost:50001").unwrap()
+        );
+        assert_eq!(
+            ElectrumServer::from_str("localhost"),
+            Err(ElectrumError::MissingPort)
+        );
+        assert_eq!(
+            ElectrumServer::from_str("localhost:port"),
+            Err(ElectrumError::InvalidPort)
+        );
+        assert_eq!(
+            ElectrumServer::from_str("local host:50001"),
+            Err(ElectrumError::InvalidHost)
+        );
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let v0 = format!(
+            r#"{{"mnemonics": "{MNEMONICS}", "electrum": "ssl://127.0.0.1:50002", "relay": "wss://relay.example"}}"#
+        );
+        let settings = Settings::from_json(&v0).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.network, Network::Bitcoin);
+        assert_eq!(settings.relays, vec!["wss://relay.example".to_string()]);
+        assert_eq!(settings.electrum[0].address(), "ssl://127.0.0.1");
+        assert!(settings.is_valid());
+
+        // round trip
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(Settings::from_json(&json), Some(settings.clone()));
+
+        // newer schema are refused
+        let mut value = serde_json::to_value(&settings).unwrap();
+        value["version"] = (SETTINGS_VERSION + 1).into();
+        assert_eq!(Settings::from_json(&value.to_string()), None);
+    }
+
+    #[test]
+    fn prefer() {
+        let mut settings = Settings::new(MNEMONICS, Network::Regtest);
+        settings.prefer_relay("wss://a");
+        settings.prefer_relay("wss://b");
+        settings.prefer_relay("wss://a");
+        assert_eq!(settings.relays, vec!["wss://a", "wss://b"]);
+        let server = |s| ElectrumServer::from_str(s).unwrap();
+        settings.prefer_electrum(server("a:1"));
+        settings.prefer_electrum(server("b:1"));
+        settings.prefer_electrum(server("ssl://a:1"));
+        assert_eq!(settings.electrum, vec![server("ssl://a:1"), server("b:1")]);
     }
 }
@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    time,
+};
+use tokio_openssl::SslStream;
+
+use crate::electrum::{
+    self,
+    request::Request,
+    response::{parse_str_response, Response},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Ssl(String),
+    Electrum(electrum::Error),
+    SerializeRequest,
+    /// The connection to the server have been closed
+    Disconnected,
+    /// The response to the request cannot be parsed
+    InvalidResponse(String),
+    /// The server did not answer in time
+    Timeout,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Ssl(e) => write!(f, "SSL error: {}", e),
+            Error::Electrum(e) => write!(f, "{:?}", e),
+            Error::SerializeRequest => write!(f, "Fail to serialize the request"),
+            Error::Disconnected => write!(f, "Connection closed"),
+            Error::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+            Error::Timeout => write!(f, "Request timed out"),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<electrum::Error> for Error {
+    fn from(value: electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
+
+/// How long we wait for the response to a request by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Stream of the notifications (headers & scripthash status) sent by the
+///   server.
+pub type Notifications = mpsc::UnboundedReceiver<Response>;
+
+#[derive(Debug, Default)]
+struct InFlight {
+    index: HashMap<usize, Request>,
+    waiters: HashMap<usize, oneshot::Sender<Result<Response, Error>>>,
+    // set when the read loop ends, no response can be received anymore
+    closed: bool,
+}
+
+impl InFlight {
+    // Fail the requests answered by an unparsable `line`, all the pending
+    //   requests if we cannot tell which ones it answers.
+    fn fail(&mut self, line: &str, error: String) {
+        let mut ids = response_ids(line);
+        // NOTE: a late answer to a timed out request is ignored
+        if ids.is_empty() {
+            ids = self.waiters.keys().copied().collect();
+        }
+        for id in ids {
+            self.index.remove(&id);
+            if let Some(waiter) = self.waiters.remove(&id) {
+                // NOTE: the caller may have dropped the future
+                let _ = waiter.send(Err(Error::InvalidResponse(error.clone())));
+            }
+        }
+    }
+}
+
+// Returns the ids of the responses in `line`, a single response or a batch.
+fn response_ids(line: &str) -> Vec<usize> {
+    let id = |v: &serde_json::Value| v.get("id").and_then(|id| id.as_u64());
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(serde_json::Value::Array(batch)) => {
+            batch.iter().filter_map(id).map(|id| id as usize).collect()
+        }
+        Ok(value) => id(&value).map(|id| id as usize).into_iter().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Async electrum client over TCP or TLS.
+///
+/// Several requests can be in flight at the same time, from clones of the
+///   same client, responses are matched to their request by id. The
+///   connection is driven by a background task that route notifications to
+///   the [`Notifications`] stream returned on connection.
+///
+/// Note: some server implementations (electrs for instance) answer w/ an
+///   empty response if a request is received while the previous one is
+///   still processed, [`AsyncClient::batch_call()`] should be preferred
+///   against those servers.
+///
+/// Requests fail w/ [`Error::Timeout`] if the server does not answer within
+///   [`DEFAULT_TIMEOUT`], see [`AsyncClient::timeout()`].
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    writer: mpsc::UnboundedSender<String>,
+    in_flight: Arc<Mutex<InFlight>>,
+    last_id: Arc<AtomicUsize>,
+    timeout: Option<Duration>,
+}
+
+impl AsyncClient {
+    /// Connect to an electrum server.
+    ///
+    /// # Arguments
+    /// * `url` - url/ip of the electrum server
+    /// * `port` - port of the electrum server
+    /// * `ssl` - whether the connection should use TLS
+    /// * `verif_certificate` - verify the server certificate, should be
+    ///   false for self-signed certificates
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the TCP connection fails
+    ///   - the TLS handshake fails
+    pub async fn connect(
+        url: &str,
+        port: u16,
+        ssl: bool,
+        verif_certificate: bool,
+    ) -> Result<(Self, Notifications), Error> {
+        let tcp = TcpStream::connect((url, port)).await?;
+        if !ssl {
+            return Ok(Self::from_stream(tcp));
+        }
+        let mut builder =
+            SslConnector::builder(SslMethod::tls()).map_err(|e| Error::Ssl(e.to_string()))?;
+        // do not verify for self-signed certs
+        if !verif_certificate {
+            builder.set_verify(SslVerifyMode::NONE);
+        }
+        let ssl = builder
+            .build()
+            .configure()
+            .and_then(|c| c.into_ssl(url))
+            .map_err(|e| Error::Ssl(e.to_string()))?;
+        let mut stream = SslStream::new(ssl, tcp).map_err(|e| Error::Ssl(e.to_string()))?;
+        Pin::new(&mut stream)
+            .connect()
+            .await
+            .map_err(|e| Error::Ssl(e.to_string()))?;
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Create a client from an already connected stream, spawn the tasks
+    ///   driving the connection.
+    ///
+    /// Note: must be called from a tokio runtime.
+    pub fn from_stream<S>(stream: S) -> (Self, Notifications)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read, write) = tokio::io::split(stream);
+        let (writer, to_write) = mpsc::unbounded_channel();
+        let (notif_sender, notifications) = mpsc::unbounded_channel();
+        let in_flight = Arc::new(Mutex::new(InFlight::default()));
+
+        tokio::spawn(write_loop(write, to_write));
+        tokio::spawn(read_loop(read, in_flight.clone(), notif_sender));
+
+        let client = AsyncClient {
+            writer,
+            in_flight,
+            last_id: Arc::new(AtomicUsize::new(0)),
+            timeout: Some(DEFAULT_TIMEOUT),
+        };
+        (client, notifications)
+    }
+
+    /// Set how long we wait for the response to a request (or to a whole
+    ///   batch), None to wait until the connection is closed.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Register requests & returns the receivers of their responses.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection is closed.
+    #[allow(clippy::type_complexity)]
+    fn register(
+        &self,
+        requests: Vec<Request>,
+    ) -> Result<
+        (
+            Vec<Request>,
+            Vec<oneshot::Receiver<Result<Response, Error>>>,
+        ),
+        Error,
+    > {
+        let mut in_flight = self.in_flight.lock().expect("poisoned");
+        if in_flight.closed {
+            return Err(Error::Disconnected);
+        }
+        let mut registered = Vec::with_capacity(requests.len());
+        let mut receivers = Vec::with_capacity(requests.len());
+        for request in requests {
+            let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let request = request.id(id);
+            let (sender, receiver) = oneshot::channel();
+            in_flight.index.insert(id, request.clone());
+            in_flight.waiters.insert(id, sender);
+            registered.push(request);
+            receivers.push(receiver);
+        }
+        Ok((registered, receivers))
+    }
+
+    fn unregister(&self, requests: &[Request]) {
+        let mut in_flight = self.in_flight.lock().expect("poisoned");
+        for r in requests {
+            in_flight.index.remove(&r.id);
+            in_flight.waiters.remove(&r.id);
+        }
+    }
+
+    fn send(&self, raw: String, requests: &[Request]) -> Result<(), Error> {
+        self.writer.send(raw).map_err(|_| {
+            self.unregister(requests);
+            Error::Disconnected
+        })
+    }
+
+    /// Send a request & wait for its response.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the request cannot be serialized
+    ///   - the connection is closed before the response is received
+    ///   - the response cannot be parsed
+    ///   - the response is not received before the timeout
+    pub async fn call(&self, request: Request) -> Result<Response, Error> {
+        let (mut requests, mut receivers) = self.register(vec![request])?;
+        let (request, receiver) = (
+            requests.pop().expect("registered"),
+            receivers.pop().expect("registered"),
+        );
+        let raw = serde_json::to_string(&request).map_err(|_| {
+            self.unregister(&[request.clone()]);
+            Error::SerializeRequest
+        })?;
+        self.send(raw, &[request.clone()])?;
+        let response = async { receiver.await.unwrap_or(Err(Error::Disconnected)) };
+        self.wait(&[request], response).await
+    }
+
+    /// Send several requests in a single batch & wait for all responses,
+    ///   responses are returned in the same order than requests.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - the requests cannot be serialized
+    ///   - the connection is closed before all responses are received
+    ///   - a response cannot be parsed
+    ///   - the responses are not received before the timeout
+    pub async fn batch_call(&self, requests: Vec<Request>) -> Result<Vec<Response>, Error> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (requests, receivers) = self.register(requests)?;
+        let raw = serde_json::to_string(&requests).map_err(|_| {
+            self.unregister(&requests);
+            Error::SerializeRequest
+        })?;
+        self.send(raw, &requests)?;
+        self.wait(&requests, async {
+            let mut responses = Vec::with_capacity(receivers.len());
+            for receiver in receivers {
+                responses.push(receiver.await.unwrap_or(Err(Error::Disconnected))?);
+            }
+            Ok::<_, Error>(responses)
+        })
+        .await
+    }
+
+    // Wait for `responses` until the timeout, the requests are unregistered
+    //   if it expires.
+    async fn wait<T>(
+        &self,
+        requests: &[Request],
+        responses: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let Some(timeout) = self.timeout else {
+            return responses.await;
+        };
+        match time::timeout(timeout, responses).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.unregister(requests);
+                Err(Error::Timeout)
+            }
+        }
+    }
+}
+
+async fn write_loop<W>(mut write: W, mut to_write: mpsc::UnboundedReceiver<String>)
+where
+    W: AsyncWrite + Unpin,
+{
+    while let Some(mut raw) = to_write.recv().await {
+        raw.push('\n');
+        if let Err(e) = write.write_all(raw.as_bytes()).await {
+            log::error!("AsyncClient: fail to write: {e}");
+            break;
+        }
+        if write.flush().await.is_err() {
+            break;
+        }
+    }
+    let _ = write.shutdown().await;
+}
+
+async fn read_loop<R>(
+    read: R,
+    in_flight: Arc<Mutex<InFlight>>,
+    notifications: mpsc::UnboundedSender<Response>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut lines = BufReader::new(read).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(l)) => l,
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("AsyncClient: fail to read: {e}");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut in_flight = in_flight.lock().expect("poisoned");
+        let responses = match parse_str_response(&line, &in_flight.index) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("AsyncClient: fail to parse {line}: {e:?}");
+                in_flight.fail(&line, format!("{e:?}"));
+                continue;
+            }
+        };
+        for response in responses {
+            let waiter = response.id().and_then(|id| {
+                in_flight.index.remove(&id);
+                in_flight.waiters.remove(&id)
+            });
+            match waiter {
+                Some(waiter) => {
+                    // NOTE: the caller may have dropped the future
+                    let _ = waiter.send(Ok(response));
+                }
+                None => {
+                    // NOTE: the stream may have been dropped by the consumer
+                    let _ = notifications.send(response);
+                }
+            }
+        }
+    }
+    // dropping the waiters will notify callers the connection is closed
+    let mut in_flight = in_flight.lock().expect("poisoned");
+    in_flight.closed = true;
+    in_flight.waiters.clear();
+    in_flight.index.clear();
+}
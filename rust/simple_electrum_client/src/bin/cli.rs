@@ -1 +1,277 @@
-fn main() {}
+use std::{collections::HashMap, env, process, str::FromStr};
+
+use miniscript::bitcoin::{Address, ScriptBuf, Txid};
+use serde_json::Value;
+use simple_electrum_client::{
+    daemon::DEFAULT_DAEMON_PORT,
+    electrum::{
+        request::Request,
+        response::{parse_str_response, OptionalFee, Response, TxGetResult},
+    },
+    raw_client::Client,
+};
+
+const USAGE: &str = "\
+Usage: electrumsc-cli [options] <command> [args...]
+
+Send a request to an electrumsc daemon (default) or directly to an electrum
+server.
+
+Options:
+  --daemon <host:port>   Daemon to connect to (default 127.0.0.1:50101)
+  --server <host:port>   Connect directly to an electrum server
+  --ssl                  Connect to the electrum server over SSL
+  --no-verif             Do not verify the server certificate
+  --json                 Print the raw JSON response
+  --follow               Keep printing notifications after a subscription
+  -h, --help             Print this help
+
+Commands:
+  ping
+  version <client_name> <version>
+  version_range <client_name> <min> <max>
+  banner
+  donation
+  features
+  subscribe_peers
+  relay_fee
+  get_fee_histogram
+  header <height>
+  headers <start> <count>
+  estimate_fee <block_target>
+  subscribe_headers
+  sh_get_balance <address|script_hex>
+  sh_get_history <address|script_hex>
+  sh_list_unspent <address|script_hex>
+  subscribe_sh <address|script_hex>
+  unsubscribe_sh <address|script_hex>
+  tx_broadcast <tx_hex>
+  tx_get <txid>
+  tx_get_verbose <txid>
+  tx_get_merkle <txid> <height>
+  tx_from_pos <height> <tx_pos> [merkle]";
+
+fn exit_usage(msg: &str) -> ! {
+    eprintln!("{msg}\n\n{USAGE}");
+    process::exit(1);
+}
+
+fn exit_error(msg: &str) -> ! {
+    eprintln!("{msg}");
+    process::exit(1);
+}
+
+fn split_host_port(s: &str) -> Option<(String, u16)> {
+    let (host, port) = s.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+struct Args {
+    host: String,
+    port: u16,
+    ssl: bool,
+    verif_certificate: bool,
+    json: bool,
+    follow: bool,
+    command: String,
+    params: Vec<String>,
+}
+
+fn parse_args() -> Args {
+    let mut target = None;
+    let mut ssl = false;
+    let mut verif_certificate = true;
+    let mut json = false;
+    let mut follow = false;
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--daemon" | "--server" => {
+                let value = args
+                    .next()
+                    .unwrap_or_else(|| exit_usage(&format!("{arg} expect a value")));
+                target = Some(
+                    split_host_port(&value)
+                        .unwrap_or_else(|| exit_usage(&format!("{arg} expect <host:port>"))),
+                );
+            }
+            "--ssl" => ssl = true,
+            "--no-verif" => verif_certificate = false,
+            "--json" => json = true,
+            "--follow" => follow = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.is_empty() {
+        exit_usage("Command missing");
+    }
+    let command = positional.remove(0);
+    let (host, port) = target.unwrap_or(("127.0.0.1".into(), DEFAULT_DAEMON_PORT));
+
+    Args {
+        host,
+        port,
+        ssl,
+        verif_certificate,
+        json,
+        follow,
+        command,
+        params: positional,
+    }
+}
+
+fn param<T: FromStr>(params: &[String], index: usize, name: &str) -> T {
+    let raw = params
+        .get(index)
+        .unwrap_or_else(|| exit_usage(&format!("<{name}> missing")));
+    raw.parse()
+        .unwrap_or_else(|_| exit_usage(&format!("Invalid <{name}>: {raw}")))
+}
+
+/// Parse a script from an address or an hex encoded script
+fn script(params: &[String]) -> ScriptBuf {
+    let raw: String = param(params, 0, "address|script_hex");
+    if let Ok(script) = ScriptBuf::from_hex(&raw) {
+        return script;
+    }
+    match Address::from_str(&raw) {
+        Ok(addr) => addr.assume_checked().script_pubkey(),
+        Err(_) => exit_usage(&format!("Invalid address or script: {raw}")),
+    }
+}
+
+fn build_request(command: &str, params: &[String]) -> Request {
+    match command {
+        "ping" => Request::ping(),
+        "version" => Request::version(param(params, 0, "client_name"), param(params, 1, "version")),
+        "version_range" => Request::version_range(
+            param(params, 0, "client_name"),
+            param(params, 1, "min"),
+            param(params, 2, "max"),
+        ),
+        "banner" => Request::banner(),
+        "donation" => Request::donation(),
+        "features" => Request::features(),
+        "subscribe_peers" => Request::subscribe_peers(),
+        "relay_fee" => Request::relay_fee(),
+        "get_fee_histogram" => Request::get_fee_histogram(),
+        "header" => Request::header(param(params, 0, "height")),
+        "headers" => Request::headers(param(params, 0, "start"), param(params, 1, "count")),
+        "estimate_fee" => Request::estimate_fee(param(params, 0, "block_target")),
+        "subscribe_headers" => Request::subscribe_headers(),
+        "sh_get_balance" => Request::sh_get_balance(&script(params)),
+        "sh_get_history" => Request::sh_get_history(&script(params)),
+        "sh_list_unspent" => Request::sh_list_unspent(&script(params)),
+        "subscribe_sh" => Request::subscribe_sh(&script(params)),
+        "unsubscribe_sh" => Request::unsubscribe_sh(&script(params)),
+        "tx_broadcast" => Request::tx_broadcast(param(params, 0, "tx_hex")),
+        "tx_get" => Request::tx_get(param::<Txid>(params, 0, "txid")),
+        "tx_get_verbose" => Request::tx_get_verbose(param::<Txid>(params, 0, "txid")),
+        "tx_get_merkle" => {
+            Request::tx_get_merkle(param::<Txid>(params, 0, "txid"), param(params, 1, "height"))
+        }
+        "tx_from_pos" => Request::tx_from_pos(
+            param(params, 0, "height"),
+            param(params, 1, "tx_pos"),
+            params.get(2).map(|m| m == "merkle").unwrap_or(false),
+        ),
+        c => exit_usage(&format!("Unknown command: {c}")),
+    }
+}
+
+fn fee(fee: &OptionalFee) -> String {
+    match fee {
+        OptionalFee::Fee(f) => format!("{f} BTC/kvB"),
+        OptionalFee::None(_) => "unavailable".into(),
+    }
+}
+
+/// Print a response in a human readable way
+fn print_response(response: &Response) {
+    match response {
+        Response::Error(e) => eprintln!("{e}"),
+        Response::Ping(_) => println!("pong"),
+        Response::Banner(r) => println!("{}", r.result),
+        Response::Header(r) => println!("{}", r.raw_header),
+        Response::Donation(r) => println!("{}", r.address.as_deref().unwrap_or("none")),
+        Response::EstimateFee(r) => println!("{}", fee(&r.fee)),
+        Response::RelayFee(r) => println!("{}", fee(&r.fee)),
+        Response::TxBroadcast(r) => println!("{}", r.txid),
+        Response::TxGet(r) => match &r.result {
+            TxGetResult::Raw(raw) => println!("{raw}"),
+            TxGetResult::Verbose(tx) => println!("{tx:#?}"),
+        },
+        Response::SHSubscribe(r) => println!("{}", r.result.as_deref().unwrap_or("no history")),
+        Response::SHUnsubscribe(r) => println!("{}", r.result),
+        Response::SHGetBalance(r) => {
+            println!("confirmed:   {} sats", r.balance.confirmed);
+            println!("unconfirmed: {} sats", r.balance.unconfirmed);
+        }
+        Response::SHGetHistory(r) => {
+            for h in &r.history {
+                println!("{} height={}", h.txid, h.height);
+            }
+        }
+        Response::SHListUnspent(r) => {
+            for u in &r.unspent {
+                println!("{}:{} {} sats height={}", u.txid, u.vout, u.value, u.height);
+            }
+        }
+        Response::SHNotification(n) => println!(
+            "{} => {}",
+            n.status.0,
+            n.status.1.as_deref().unwrap_or("no history")
+        ),
+        r => println!("{r:#?}"),
+    }
+}
+
+fn main() {
+    let args = parse_args();
+    let request = build_request(&args.command, &args.params).id(1);
+    let subscription = args.command.starts_with("subscribe_");
+
+    let mut client = Client::new_ssl_maybe(&args.host, args.port, args.ssl)
+        .verif_certificate(args.verif_certificate);
+    if let Err(e) = client.try_connect() {
+        exit_error(&format!(
+            "Fail to connect to {}:{}: {e}",
+            args.host, args.port
+        ));
+    }
+    if let Err(e) = client.try_send(&request) {
+        exit_error(&format!("Fail to send request: {e}"));
+    }
+
+    let mut index = HashMap::new();
+    index.insert(request.id, request);
+
+    loop {
+        let raw = match client.recv_str() {
+            Ok(r) if r.is_empty() => exit_error("Connection closed"),
+            Ok(r) => r,
+            Err(e) => exit_error(&format!("Fail to receive response: {e}")),
+        };
+        if args.json {
+            match serde_json::from_str::<Value>(&raw) {
+                Ok(v) => println!("{}", serde_json::to_string_pretty(&v).expect("valid json")),
+                Err(_) => println!("{}", raw.trim()),
+            }
+        } else {
+            match parse_str_response(&raw, &index) {
+                Ok(responses) => responses.iter().for_each(print_response),
+                Err(e) => exit_error(&format!("Fail to parse response: {e:?}")),
+            }
+        }
+        if !(subscription && args.follow) {
+            break;
+        }
+    }
+}
@@ -1 +1,85 @@
-fn main() {}
+use std::{env, process};
+
+use simple_electrum_client::daemon::{Config, Daemon, DEFAULT_DAEMON_PORT};
+
+const USAGE: &str = "\
+Usage: electrumsc --server <host:port> [--ssl] [--no-verif] [--listen <host:port>]
+
+Keep a persistent connection to an electrum server & serve electrum JSON-RPC
+to local clients.
+
+Options:
+  --server <host:port>   Electrum server to connect to
+  --ssl                  Connect to the electrum server over SSL
+  --no-verif             Do not verify the server certificate
+  --listen <host:port>   Local address to listen on (default 127.0.0.1:50101)
+  -h, --help             Print this help";
+
+fn exit_usage(msg: &str) -> ! {
+    eprintln!("{msg}\n\n{USAGE}");
+    process::exit(1);
+}
+
+fn split_host_port(s: &str) -> Option<(String, u16)> {
+    let (host, port) = s.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+fn parse_args() -> Config {
+    let mut server = None;
+    let mut ssl = false;
+    let mut verif_certificate = true;
+    let mut listen = format!("127.0.0.1:{DEFAULT_DAEMON_PORT}");
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => {
+                let value = args
+                    .next()
+                    .unwrap_or_else(|| exit_usage("--server expect a value"));
+                server = Some(
+                    split_host_port(&value)
+                        .unwrap_or_else(|| exit_usage("--server expect <host:port>")),
+                );
+            }
+            "--ssl" => ssl = true,
+            "--no-verif" => verif_certificate = false,
+            "--listen" => {
+                listen = args
+                    .next()
+                    .unwrap_or_else(|| exit_usage("--listen expect a value"));
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            a => exit_usage(&format!("Unknown argument: {a}")),
+        }
+    }
+
+    let (server, port) = server.unwrap_or_else(|| exit_usage("--server is missing"));
+    Config {
+        server,
+        port,
+        ssl,
+        verif_certificate,
+        listen,
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = parse_args();
+    let daemon = match Daemon::new(config) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    if let Err(e) = daemon.run() {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
@@ -1 +1,528 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::mpsc::{self, Receiver, Sender},
+};
 
+use miniscript::bitcoin::{
+    block,
+    consensus::encode::{deserialize, deserialize_hex, serialize_hex},
+    hex::FromHex,
+    FeeRate, Script, Transaction, Txid,
+};
+
+use crate::{
+    electrum::{
+        self,
+        request::Request,
+        response::{
+            parse_str_response, BalanceResult, ErrorResult, FeaturesResult, GetMerkleResult,
+            Header, HeaderNotification, HistoryResult, OptionalFee, Peer, Response,
+            SingleHeaderNotif, TxGetResult, TxfromPosResult, UtxoResult,
+        },
+        types::ScriptHash,
+    },
+    raw_client::{self, Client as RawClient},
+};
+
+// size of a serialized block header
+const HEADER_SIZE: usize = 80;
+
+#[derive(Debug)]
+pub enum Error {
+    RawClient(raw_client::Error),
+    Electrum(electrum::Error),
+    /// The server answered w/ an error
+    Server(ErrorResult),
+    /// The server closed the connection
+    Disconnected,
+    /// The response does not match the request
+    WrongResponse,
+    TxParsing,
+    HeaderParsing,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::RawClient(e) => write!(f, "{}", e),
+            Error::Electrum(e) => write!(f, "{:?}", e),
+            Error::Server(e) => write!(f, "Electrum error {}: {}", e.code, e.message),
+            Error::Disconnected => write!(f, "Connection closed by the server"),
+            Error::WrongResponse => write!(f, "Wrong response from electrum server"),
+            Error::TxParsing => write!(f, "Fail to parse the transaction"),
+            Error::HeaderParsing => write!(f, "Fail to parse the block header"),
+        }
+    }
+}
+
+impl From<raw_client::Error> for Error {
+    fn from(value: raw_client::Error) -> Self {
+        Error::RawClient(value)
+    }
+}
+
+impl From<electrum::Error> for Error {
+    fn from(value: electrum::Error) -> Self {
+        Error::Electrum(value)
+    }
+}
+
+/// Extract the expected variant of a [`Response`].
+macro_rules! expect {
+    ($response:expr, $variant:ident) => {
+        match $response {
+            Response::$variant(r) => Ok(r),
+            Response::Error(e) => Err(Error::Server(e.error)),
+            _ => Err(Error::WrongResponse),
+        }
+    };
+}
+
+/// Typed blocking electrum client.
+///
+/// Request ids are managed by the client, responses are matched to their
+///   request & converted to their concrete type. Notifications received
+///   while waiting for a response are routed to the [`Receiver`]s returned
+///   by [`Client::subscribe_headers()`] & [`Client::subscribe_sh()`].
+///
+/// Note: notifications are only read from the socket while a call is
+///   running or when [`Client::poll()`] is called.
+#[derive(Debug)]
+pub struct Client {
+    inner: RawClient,
+    index: HashMap<usize, Request>,
+    last_id: usize,
+    headers_subscribers: Vec<Sender<Header>>,
+    sh_subscribers: HashMap<ScriptHash, Vec<Sender<Option<String>>>>,
+}
+
+impl Client {
+    /// Create a new [`Client`] and connect to the electrum server.
+    ///
+    /// # Arguments
+    /// * `url` - url/ip of the electrum server
+    /// * `port` - port of the electrum server
+    /// * `ssl` - whether the connection should use SSL
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to connect.
+    pub fn new(url: &str, port: u16, ssl: bool) -> Result<Self, Error> {
+        let mut inner = RawClient::new_ssl_maybe(url, port, ssl);
+        inner.try_connect()?;
+        Ok(Self::from_raw(inner))
+    }
+
+    /// Create a new [`Client`] from an already connected [`RawClient`].
+    pub fn from_raw(inner: RawClient) -> Self {
+        Client {
+            inner,
+            index: HashMap::new(),
+            last_id: 0,
+            headers_subscribers: Vec::new(),
+            sh_subscribers: HashMap::new(),
+        }
+    }
+
+    /// Close the connection to the electrum server.
+    pub fn close(&mut self) -> Result<(), Error> {
+        Ok(self.inner.close()?)
+    }
+
+    /// Generate a new request id
+    fn id(&mut self) -> usize {
+        self.last_id = self.last_id.wrapping_add(1);
+        self.last_id
+    }
+
+    /// Send a single request & wait for its response.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - it fails to send the request
+    ///   - it fails to receive or parse the response
+    pub fn call(&mut self, request: Request) -> Result<Response, Error> {
+        Ok(self
+            .batch_call(vec![request])?
+            .pop()
+            .expect("one response by request"))
+    }
+
+    /// Send several requests in a single round trip & wait for all responses,
+    ///   responses are returned in the same order than requests.
+    ///
+    /// Note: error responses from the server are returned as
+    ///   [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - it fails to send the requests
+    ///   - it fails to receive or parse a response
+    pub fn batch_call(&mut self, requests: Vec<Request>) -> Result<Vec<Response>, Error> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let requests: Vec<_> = requests
+            .into_iter()
+            .map(|r| {
+                let id = self.id();
+                r.id(id)
+            })
+            .collect();
+        let ids: Vec<_> = requests.iter().map(|r| r.id).collect();
+        for r in &requests {
+            self.index.insert(r.id, r.clone());
+        }
+
+        let sent = if requests.len() == 1 {
+            self.inner.try_send(&requests[0])
+        } else {
+            self.inner.try_send_batch(requests.iter().collect())
+        };
+        if let Err(e) = sent {
+            self.forget(&ids);
+            return Err(e.into());
+        }
+
+        let mut responses = HashMap::new();
+        while responses.len() < ids.len() {
+            let received = match self.recv() {
+                Ok(r) => r,
+                Err(e) => {
+                    self.forget(&ids);
+                    return Err(e);
+                }
+            };
+            for response in received {
+                if let Some(id) = response.id() {
+                    if ids.contains(&id) {
+                        responses.insert(id, response);
+                    }
+                }
+            }
+        }
+        self.forget(&ids);
+
+        Ok(ids
+            .iter()
+            .map(|id| responses.remove(id).expect("all responses received"))
+            .collect())
+    }
+
+    fn forget(&mut self, ids: &[usize]) {
+        for id in ids {
+            self.index.remove(id);
+        }
+    }
+
+    /// Block until a message is received, notifications are routed to their
+    ///   subscribers & the other responses are returned.
+    fn recv(&mut self) -> Result<Vec<Response>, Error> {
+        let raw = self.inner.recv_str()?;
+        if raw.is_empty() {
+            return Err(Error::Disconnected);
+        }
+        self.parse(&raw)
+    }
+
+    fn parse(&mut self, raw: &str) -> Result<Vec<Response>, Error> {
+        let responses = parse_str_response(raw, &self.index)?;
+        Ok(responses
+            .into_iter()
+            .filter_map(|r| self.route(r))
+            .collect())
+    }
+
+    /// Route a notification to its subscribers, returns the response if it's
+    ///   not a notification.
+    fn route(&mut self, response: Response) -> Option<Response> {
+        match response {
+            Response::BatchHeaderNotif(n) | Response::HeaderNotif(HeaderNotification::Batch(n)) => {
+                for header in n.headers {
+                    self.headers_subscribers
+                        .retain(|s| s.send(header.clone()).is_ok());
+                }
+                None
+            }
+            Response::SHNotification(n) => {
+                let (sh, status) = n.status;
+                if let Some(subscribers) = self.sh_subscribers.get_mut(&sh) {
+                    subscribers.retain(|s| s.send(status.clone()).is_ok());
+                    if subscribers.is_empty() {
+                        self.sh_subscribers.remove(&sh);
+                    }
+                }
+                None
+            }
+            r => Some(r),
+        }
+    }
+
+    /// Read all the messages already received w/o blocking and route
+    ///   notifications to their subscribers.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to receive or parse
+    ///   a message.
+    pub fn poll(&mut self) -> Result<(), Error> {
+        while let Some(raw) = self.inner.try_recv_str()? {
+            if raw.is_empty() {
+                return Err(Error::Disconnected);
+            }
+            let unexpected = self.parse(&raw)?;
+            if !unexpected.is_empty() {
+                log::debug!("Client::poll() drop unexpected responses: {unexpected:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Ping the server.
+    pub fn ping(&mut self) -> Result<(), Error> {
+        expect!(self.call(Request::ping())?, Ping).map(|_| ())
+    }
+
+    /// Returns the server banner.
+    pub fn banner(&mut self) -> Result<String, Error> {
+        expect!(self.call(Request::banner())?, Banner).map(|r| r.result)
+    }
+
+    /// Returns the donation address of the server.
+    pub fn donation(&mut self) -> Result<Option<String>, Error> {
+        expect!(self.call(Request::donation())?, Donation).map(|r| r.address)
+    }
+
+    /// Returns the features supported by the server.
+    pub fn features(&mut self) -> Result<FeaturesResult, Error> {
+        expect!(self.call(Request::features())?, Features).map(|r| r.features)
+    }
+
+    /// Returns the peers known by the server.
+    pub fn peers(&mut self) -> Result<Vec<Peer>, Error> {
+        expect!(self.call(Request::subscribe_peers())?, ListPeers).map(|r| r.peers)
+    }
+
+    /// Returns the minimum fee rate accepted by the server mempool.
+    pub fn relay_fee(&mut self) -> Result<Option<FeeRate>, Error> {
+        expect!(self.call(Request::relay_fee())?, RelayFee).map(|r| fee_rate(&r.fee))
+    }
+
+    /// Returns the fee histogram of the server mempool as a list of
+    ///   (fee rate in sat/vb, vsize) pairs.
+    pub fn fee_histogram(&mut self) -> Result<Vec<(usize, usize)>, Error> {
+        expect!(self.call(Request::get_fee_histogram())?, FeeHistogram).map(|r| r.histogram)
+    }
+
+    /// Estimate the fee rate needed for a transaction to confirm within
+    ///   `block_target` blocks, returns None if the server cannot estimate.
+    pub fn estimate_fee(&mut self, block_target: u16) -> Result<Option<FeeRate>, Error> {
+        let response = self.call(Request::estimate_fee(block_target))?;
+        expect!(response, EstimateFee).map(|r| fee_rate(&r.fee))
+    }
+
+    /// Returns the block header at the given height.
+    pub fn block_header(&mut self, height: usize) -> Result<block::Header, Error> {
+        let response = self.call(Request::header(height))?;
+        let raw = expect!(response, Header)?.raw_header;
+        deserialize_hex(&raw).map_err(|_| Error::HeaderParsing)
+    }
+
+    /// Returns `count` block headers starting at height `start`, the server
+    ///   can return less headers than requested.
+    pub fn block_headers(
+        &mut self,
+        start: usize,
+        count: usize,
+    ) -> Result<Vec<block::Header>, Error> {
+        let response = self.call(Request::headers(start, count))?;
+        let raw = expect!(response, Headers)?.headers.raw_headers;
+        let raw = Vec::<u8>::from_hex(&raw).map_err(|_| Error::HeaderParsing)?;
+        raw.chunks(HEADER_SIZE)
+            .map(|h| deserialize(h).map_err(|_| Error::HeaderParsing))
+            .collect()
+    }
+
+    /// Subscribe to new blocks, returns the current tip & a [`Receiver`]
+    ///   that will receive the next headers.
+    pub fn subscribe_headers(&mut self) -> Result<(Header, Receiver<Header>), Error> {
+        let response = self.call(Request::subscribe_headers())?;
+        let tip = match expect!(response, HeaderNotif)? {
+            HeaderNotification::Single(SingleHeaderNotif { header, .. }) => header,
+            HeaderNotification::Batch(_) => return Err(Error::WrongResponse),
+        };
+        let (sender, receiver) = mpsc::channel();
+        self.headers_subscribers.push(sender);
+        Ok((tip, receiver))
+    }
+
+    /// Returns the balance of a script.
+    pub fn sh_get_balance(&mut self, script: &Script) -> Result<BalanceResult, Error> {
+        expect!(self.call(Request::sh_get_balance(script))?, SHGetBalance).map(|r| r.balance)
+    }
+
+    /// Returns the history of a script.
+    pub fn sh_get_history(&mut self, script: &Script) -> Result<Vec<HistoryResult>, Error> {
+        expect!(self.call(Request::sh_get_history(script))?, SHGetHistory).map(|r| r.history)
+    }
+
+    /// Returns the unspent outputs of a script.
+    pub fn sh_list_unspent(&mut self, script: &Script) -> Result<Vec<UtxoResult>, Error> {
+        expect!(self.call(Request::sh_list_unspent(script))?, SHListUnspent).map(|r| r.unspent)
+    }
+
+    /// Subscribe to a script, returns the current status & a [`Receiver`]
+    ///   that will receive the next status changes.
+    pub fn subscribe_sh(
+        &mut self,
+        script: &Script,
+    ) -> Result<(Option<String>, Receiver<Option<String>>), Error> {
+        let status = expect!(self.call(Request::subscribe_sh(script))?, SHSubscribe)?.result;
+        let (sender, receiver) = mpsc::channel();
+        self.sh_subscribers
+            .entry(ScriptHash::new(script))
+            .or_default()
+            .push(sender);
+        Ok((status, receiver))
+    }
+
+    /// Unsubscribe from a script.
+    pub fn unsubscribe_sh(&mut self, script: &Script) -> Result<bool, Error> {
+        self.sh_subscribers.remove(&ScriptHash::new(script));
+        expect!(self.call(Request::unsubscribe_sh(script))?, SHUnsubscribe).map(|r| r.result)
+    }
+
+    /// Broadcast a transaction.
+    pub fn tx_broadcast(&mut self, tx: &Transaction) -> Result<Txid, Error> {
+        let response = self.call(Request::tx_broadcast(serialize_hex(tx)))?;
+        expect!(response, TxBroadcast).map(|r| r.txid)
+    }
+
+    /// Returns the transaction w/ the given txid.
+    pub fn tx_get(&mut self, txid: Txid) -> Result<Transaction, Error> {
+        parse_tx(self.call(Request::tx_get(txid))?)
+    }
+
+    /// Returns the merkle proof of a confirmed transaction.
+    pub fn tx_get_merkle(&mut self, txid: Txid, height: usize) -> Result<GetMerkleResult, Error> {
+        let response = self.call(Request::tx_get_merkle(txid, height))?;
+        expect!(response, TxGetMerkle).map(|r| r.result)
+    }
+
+    /// Returns the txid of the transaction at position `tx_pos` in the block
+    ///   at `height`, along w/ its merkle proof if `merkle` is true.
+    pub fn tx_from_pos(
+        &mut self,
+        height: usize,
+        tx_pos: usize,
+        merkle: bool,
+    ) -> Result<TxfromPosResult, Error> {
+        let response = self.call(Request::tx_from_pos(height, tx_pos, merkle))?;
+        expect!(response, TxFromposition).map(|r| r.tx)
+    }
+
+    /// Get several transactions in a single round trip.
+    pub fn batch_tx_get(&mut self, txids: &[Txid]) -> Result<Vec<Transaction>, Error> {
+        let requests = txids.iter().map(|txid| Request::tx_get(*txid)).collect();
+        self.batch_call(requests)?
+            .into_iter()
+            .map(parse_tx)
+            .collect()
+    }
+
+    /// Get the history of several scripts in a single round trip.
+    pub fn batch_sh_get_history(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<Vec<HistoryResult>>, Error> {
+        let requests = scripts.iter().map(|s| Request::sh_get_history(s)).collect();
+        self.batch_call(requests)?
+            .into_iter()
+            .map(|r| expect!(r, SHGetHistory).map(|r| r.history))
+            .collect()
+    }
+
+    /// Get the unspent outputs of several scripts in a single round trip.
+    pub fn batch_sh_list_unspent(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<Vec<UtxoResult>>, Error> {
+        let requests = scripts
+            .iter()
+            .map(|s| Request::sh_list_unspent(s))
+            .collect();
+        self.batch_call(requests)?
+            .into_iter()
+            .map(|r| expect!(r, SHListUnspent).map(|r| r.unspent))
+            .collect()
+    }
+
+    /// Get the balance of several scripts in a single round trip.
+    pub fn batch_sh_get_balance(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<BalanceResult>, Error> {
+        let requests = scripts.iter().map(|s| Request::sh_get_balance(s)).collect();
+        self.batch_call(requests)?
+            .into_iter()
+            .map(|r| expect!(r, SHGetBalance).map(|r| r.balance))
+            .collect()
+    }
+
+    /// Estimate fees for several block targets in a single round trip.
+    pub fn batch_estimate_fee(
+        &mut self,
+        block_targets: &[u16],
+    ) -> Result<Vec<Option<FeeRate>>, Error> {
+        let requests = block_targets
+            .iter()
+            .map(|t| Request::estimate_fee(*t))
+            .collect();
+        self.batch_call(requests)?
+            .into_iter()
+            .map(|r| expect!(r, EstimateFee).map(|r| fee_rate(&r.fee)))
+            .collect()
+    }
+}
+
+fn parse_tx(response: Response) -> Result<Transaction, Error> {
+    match expect!(response, TxGet)?.result {
+        TxGetResult::Raw(raw) => deserialize_hex(&raw).map_err(|_| Error::TxParsing),
+        TxGetResult::Verbose(tx) => deserialize_hex(&tx.raw_tx).map_err(|_| Error::TxParsing),
+    }
+}
+
+/// Convert a fee in BTC/kvB as returned by electrum into a [`FeeRate`],
+///   returns None if the server cannot estimate the fee.
+fn fee_rate(fee: &OptionalFee) -> Option<FeeRate> {
+    match fee {
+        OptionalFee::Fee(btc_per_kvb) if *btc_per_kvb >= 0.0 => {
+            // 1 vb == 4 wu
+            let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round() as u64;
+            Some(FeeRate::from_sat_per_kwu(sat_per_kvb / 4))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_fee_rate() {
+        assert_eq!(
+            fee_rate(&OptionalFee::Fee(0.00001)),
+            Some(FeeRate::from_sat_per_vb_unchecked(1))
+        );
+        assert_eq!(
+            fee_rate(&OptionalFee::Fee(0.0002)),
+            Some(FeeRate::from_sat_per_vb_unchecked(20))
+        );
+        assert_eq!(fee_rate(&OptionalFee::Fee(-1.0)), None);
+        assert_eq!(fee_rate(&OptionalFee::None(-1)), None);
+    }
+}
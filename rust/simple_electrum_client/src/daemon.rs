@@ -0,0 +1,572 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use backoff::Backoff;
+use serde_json::{json, Value};
+
+use crate::raw_client::{self, Client};
+
+/// Default port the daemon listen on for local clients
+pub const DEFAULT_DAEMON_PORT: u16 = 50101;
+// delay we wait between (non-blocking) polls
+const WAIT: u64 = 50;
+// interval we ping the electrum server to keep the connection alive
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+// max number of entries of the response cache
+const MAX_CACHE_SIZE: usize = 10_000;
+const RETRY: usize = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Methods whose result never change and can be cached
+// NOTE: the methods looking up a block by height (block.header,
+// transaction.id_from_pos) or a tx in a block (transaction.get_merkle) are
+// not cached, their result changes on a reorg. Verbose transaction.get
+// results carry the number of confirmations and are not cached either.
+const CACHEABLE: [&str; 1] = ["blockchain.transaction.get"];
+
+const HEADERS_SUBSCRIBE: &str = "blockchain.headers.subscribe";
+const SH_SUBSCRIBE: &str = "blockchain.scripthash.subscribe";
+const SH_UNSUBSCRIBE: &str = "blockchain.scripthash.unsubscribe";
+
+#[derive(Debug)]
+pub enum Error {
+    Client(raw_client::Error),
+    Listen(std::io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Client(e) => write!(f, "Electrum client error: {}", e),
+            Error::Listen(e) => write!(f, "Fail to listen local socket: {}", e),
+        }
+    }
+}
+
+impl From<raw_client::Error> for Error {
+    fn from(value: raw_client::Error) -> Self {
+        Error::Client(value)
+    }
+}
+
+/// Configuration of the [`Daemon`]
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address of the electrum server
+    pub server: String,
+    /// Port of the electrum server
+    pub port: u16,
+    /// Connect to the electrum server over SSL
+    pub ssl: bool,
+    /// Verify the certificate of the electrum server
+    pub verif_certificate: bool,
+    /// Local address the daemon listen on (<address>:<port>)
+    pub listen: String,
+}
+
+#[derive(Debug)]
+enum Event {
+    Connected(usize, Sender<String>),
+    Request(usize, Value),
+    Disconnected(usize),
+}
+
+#[derive(Debug)]
+struct Pending {
+    // None if the request have been sent by the daemon itself
+    conn: Option<(usize, Value)>,
+    cache_key: Option<String>,
+    subscription: Option<String>,
+}
+
+#[derive(Debug)]
+struct Subscription {
+    method: String,
+    params: Value,
+    subscribers: HashSet<usize>,
+    last: Option<Value>,
+}
+
+/// Keep a persistent connection to an electrum server and serve electrum
+///   JSON-RPC to local clients, subscriptions are shared between clients
+///   and results of immutable calls are cached.
+///
+/// Note: batch requests from local clients are split and answered one
+///   response by request.
+#[derive(Debug)]
+pub struct Daemon {
+    config: Config,
+    client: Client,
+    next_id: usize,
+    conns: HashMap<usize, Sender<String>>,
+    pending: HashMap<usize, Pending>,
+    cache: HashMap<String, Value>,
+    subscriptions: HashMap<String, Subscription>,
+    last_ping: Instant,
+}
+
+impl Daemon {
+    /// Create a new [`Daemon`] and connect to the electrum server.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to connect the
+    ///   electrum server.
+    pub fn new(config: Config) -> Result<Self, Error> {
+        let client = Self::connect(&config)?;
+        Ok(Daemon {
+            config,
+            client,
+            next_id: 0,
+            conns: HashMap::new(),
+            pending: HashMap::new(),
+            cache: HashMap::new(),
+            subscriptions: HashMap::new(),
+            last_ping: Instant::now(),
+        })
+    }
+
+    fn connect(config: &Config) -> Result<Client, Error> {
+        let mut client = Client::new_ssl_maybe(&config.server, config.port, config.ssl)
+            .verif_certificate(config.verif_certificate);
+        client.try_connect_retry(RETRY, RETRY_DELAY)?;
+        log::info!(
+            "Daemon::connect() connected to {}:{}",
+            config.server,
+            config.port
+        );
+        Ok(client)
+    }
+
+    /// Close the connection to the electrum server, reconnect and subscribe
+    ///   again.
+    fn reconnect(&mut self) -> Result<(), Error> {
+        log::error!("Daemon::reconnect() connection to electrum server lost, reconnecting...");
+        let _ = self.client.close();
+        self.client = Self::connect(&self.config)?;
+        // responses of in flight requests will never come
+        for (_, pending) in self.pending.drain() {
+            if let Some((conn, id)) = pending.conn {
+                if let Some(sender) = self.conns.get(&conn) {
+                    let _ = sender.send(error_response(&id, "connection to server lost"));
+                }
+            }
+        }
+        let subscriptions: Vec<_> = self
+            .subscriptions
+            .iter()
+            .map(|(k, s)| (k.clone(), s.method.clone(), s.params.clone()))
+            .collect();
+        for (key, method, params) in subscriptions {
+            let pending = Pending {
+                conn: None,
+                cache_key: None,
+                subscription: Some(key),
+            };
+            self.forward(&method, params, pending)?;
+        }
+        Ok(())
+    }
+
+    /// Listen for local clients & serve them, this function block forever
+    ///   or until an unrecoverable error occur.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///   - it fails to listen on the local address
+    ///   - the connection to the electrum server is lost and cannot be restored
+    pub fn run(mut self) -> Result<(), Error> {
+        let listener = TcpListener::bind(&self.config.listen).map_err(Error::Listen)?;
+        log::info!("Daemon::run() listening on {}", self.config.listen);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || accept(listener, sender));
+        self.serve(receiver)
+    }
+
+    fn serve(&mut self, receiver: Receiver<Event>) -> Result<(), Error> {
+        let mut backoff = Backoff::new_us(WAIT);
+        loop {
+            let mut idle = true;
+
+            while let Ok(event) = receiver.try_recv() {
+                idle = false;
+                if let Err(e) = self.handle_event(event) {
+                    log::error!("Daemon::serve() fail to handle request: {}", e);
+                    self.reconnect()?;
+                }
+            }
+
+            match self.client.try_recv_str() {
+                Ok(Some(raw)) => {
+                    idle = false;
+                    if raw.is_empty() {
+                        // the server closed the connection
+                        self.reconnect()?;
+                    } else {
+                        self.handle_server_message(&raw);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::error!("Daemon::serve() fail to receive: {}", e);
+                    self.reconnect()?;
+                }
+            }
+
+            if self.last_ping.elapsed() > PING_INTERVAL {
+                self.last_ping = Instant::now();
+                let pending = Pending {
+                    conn: None,
+                    cache_key: None,
+                    subscription: None,
+                };
+                if self.forward("server.ping", json!([]), pending).is_err() {
+                    self.reconnect()?;
+                }
+            }
+
+            if idle {
+                backoff.snooze();
+            } else {
+                backoff.reset();
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<(), Error> {
+        match event {
+            Event::Connected(conn, sender) => {
+                log::debug!("Daemon: client {conn} connected");
+                self.conns.insert(conn, sender);
+            }
+            Event::Disconnected(conn) => {
+                log::debug!("Daemon: client {conn} disconnected");
+                self.conns.remove(&conn);
+                for sub in self.subscriptions.values_mut() {
+                    sub.subscribers.remove(&conn);
+                }
+            }
+            Event::Request(conn, Value::Array(batch)) => {
+                for request in batch {
+                    self.handle_request(conn, request)?;
+                }
+            }
+            Event::Request(conn, request) => self.handle_request(conn, request)?,
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, conn: usize, request: Value) -> Result<(), Error> {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match request.get("method").and_then(|m| m.as_str()) {
+            Some(m) => m.to_string(),
+            None => {
+                self.reply(conn, error_response(&id, "method missing"));
+                return Ok(());
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(json!([]));
+
+        let cache_key = cache_key(&method, &params);
+        if let Some(result) = cache_key.as_ref().and_then(|k| self.cache.get(k)) {
+            log::debug!("Daemon: serve {method} from cache");
+            let resp = response(&id, result);
+            self.reply(conn, resp);
+            return Ok(());
+        }
+
+        if method == SH_UNSUBSCRIBE {
+            if let Some(key) = subscription_key(SH_SUBSCRIBE, &params) {
+                let last_subscriber = match self.subscriptions.get_mut(&key) {
+                    Some(sub) => {
+                        sub.subscribers.remove(&conn);
+                        sub.subscribers.is_empty()
+                    }
+                    None => false,
+                };
+                if !last_subscriber {
+                    self.reply(conn, response(&id, &Value::Bool(true)));
+                    return Ok(());
+                }
+                self.subscriptions.remove(&key);
+            }
+        }
+
+        let subscription = subscription_key(&method, &params);
+        if let Some(key) = &subscription {
+            let sub = self
+                .subscriptions
+                .entry(key.clone())
+                .or_insert_with(|| Subscription {
+                    method: method.clone(),
+                    params: params.clone(),
+                    subscribers: HashSet::new(),
+                    last: None,
+                });
+            sub.subscribers.insert(conn);
+            if let Some(last) = sub.last.clone() {
+                self.reply(conn, response(&id, &last));
+                return Ok(());
+            }
+        }
+
+        let pending = Pending {
+            conn: Some((conn, id)),
+            cache_key,
+            subscription,
+        };
+        self.forward(&method, params, pending)
+    }
+
+    /// Send a request to the electrum server
+    fn forward(&mut self, method: &str, params: Value, pending: Pending) -> Result<(), Error> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        self.pending.insert(id, pending);
+        self.client.try_send_str(&request.to_string())?;
+        Ok(())
+    }
+
+    fn reply(&self, conn: usize, msg: String) {
+        if let Some(sender) = self.conns.get(&conn) {
+            let _ = sender.send(msg);
+        }
+    }
+
+    fn handle_server_message(&mut self, raw: &str) {
+        let value: Value = match serde_json::from_str(raw) {
+            Ok(v) => v,
+            Err(_) => {
+                log::error!("Daemon: fail to parse server message: {raw}");
+                return;
+            }
+        };
+        match value {
+            Value::Array(batch) => batch.into_iter().for_each(|m| self.handle_server_value(m)),
+            v => self.handle_server_value(v),
+        }
+    }
+
+    fn handle_server_value(&mut self, value: Value) {
+        // response to a request
+        if let Some(id) = value.get("id").and_then(|id| id.as_u64()) {
+            let pending = match self.pending.remove(&(id as usize)) {
+                Some(p) => p,
+                None => {
+                    log::error!("Daemon: receive response w/ unknown id {id}");
+                    return;
+                }
+            };
+            let result = value.get("result").cloned();
+            if let Some(result) = &result {
+                if let Some(key) = pending.cache_key {
+                    if self.cache.len() >= MAX_CACHE_SIZE {
+                        self.cache.clear();
+                    }
+                    self.cache.insert(key, result.clone());
+                }
+                if let Some(key) = pending.subscription {
+                    if let Some(sub) = self.subscriptions.get_mut(&key) {
+                        sub.last = Some(result.clone());
+                    }
+                }
+            }
+            if let Some((conn, local_id)) = pending.conn {
+                let mut value = value;
+                value["id"] = local_id;
+                self.reply(conn, value.to_string());
+            }
+        // notification
+        } else if let Some(method) = value.get("method").and_then(|m| m.as_str()) {
+            let params = value.get("params").cloned().unwrap_or(json!([]));
+            let (key, last) = match notification(method, &params) {
+                Some(n) => n,
+                None => {
+                    log::error!("Daemon: unexpected notification: {value}");
+                    return;
+                }
+            };
+            if let Some(sub) = self.subscriptions.get_mut(&key) {
+                sub.last = Some(last);
+                let msg = value.to_string();
+                for conn in &sub.subscribers {
+                    if let Some(sender) = self.conns.get(conn) {
+                        let _ = sender.send(msg.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Accept local clients connections
+fn accept(listener: TcpListener, sender: Sender<Event>) {
+    let mut conn_id = 0;
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                conn_id += 1;
+                let sender = sender.clone();
+                let (resp_sender, resp_receiver) = mpsc::channel();
+                if sender.send(Event::Connected(conn_id, resp_sender)).is_err() {
+                    // daemon stopped
+                    return;
+                }
+                if let Ok(writer) = stream.try_clone() {
+                    thread::spawn(move || write_client(writer, resp_receiver));
+                }
+                let id = conn_id;
+                thread::spawn(move || read_client(id, stream, sender));
+            }
+            Err(e) => log::error!("Daemon: fail to accept connection: {e}"),
+        }
+    }
+}
+
+fn read_client(conn: usize, stream: TcpStream, sender: Sender<Event>) {
+    let reader = BufReader::new(stream);
+    for line in reader.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                if sender.send(Event::Request(conn, request)).is_err() {
+                    return;
+                }
+            }
+            Err(_) => log::error!("Daemon: client {conn} sent an invalid request: {line}"),
+        }
+    }
+    let _ = sender.send(Event::Disconnected(conn));
+}
+
+fn write_client(mut stream: TcpStream, receiver: Receiver<String>) {
+    while let Ok(msg) = receiver.recv() {
+        if stream.write_all(msg.as_bytes()).is_err()
+            || stream.write_all(b"\n").is_err()
+            || stream.flush().is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Returns the cache key of a request if the method result can be cached
+fn cache_key(method: &str, params: &Value) -> Option<String> {
+    if !CACHEABLE.contains(&method) {
+        return None;
+    }
+    // transaction.get params are [txid, verbose], verbose defaults to false
+    if params.get(1).and_then(Value::as_bool).unwrap_or(false) {
+        return None;
+    }
+    Some(format!("{method}:{}", params.get(0)?))
+}
+
+/// Returns the key of a subscription if the method is a subscription
+fn subscription_key(method: &str, params: &Value) -> Option<String> {
+    match method {
+        HEADERS_SUBSCRIBE => Some("headers".into()),
+        SH_SUBSCRIBE => params
+            .get(0)
+            .and_then(|sh| sh.as_str())
+            .map(|sh| format!("sh:{sh}")),
+        _ => None,
+    }
+}
+
+/// Returns the subscription key & the new subscription status of a
+///   notification
+fn notification(method: &str, params: &Value) -> Option<(String, Value)> {
+    match method {
+        HEADERS_SUBSCRIBE => Some(("headers".into(), params.get(0)?.clone())),
+        SH_SUBSCRIBE => {
+            let key = subscription_key(method, params)?;
+            Some((key, params.get(1).cloned().unwrap_or(Value::Null)))
+        }
+        _ => None,
+    }
+}
+
+fn response(id: &Value, result: &Value) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string()
+}
+
+fn error_response(id: &Value, message: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": 1, "message": message},
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let txid = "000000000000000000032aea06ce8a8dd70127e86382b5ea68c7d810e8dbfc9b";
+        assert_eq!(
+            cache_key("blockchain.transaction.get", &json!([txid, false])),
+            Some(format!("blockchain.transaction.get:\"{txid}\""))
+        );
+        assert_eq!(cache_key("blockchain.estimatefee", &json!([6])), None);
+        assert_eq!(
+            cache_key("blockchain.transaction.get", &json!([txid])),
+            Some(format!("blockchain.transaction.get:\"{txid}\""))
+        );
+        assert_eq!(
+            cache_key("blockchain.transaction.get", &json!([txid, true])),
+            None
+        );
+        assert_eq!(
+            cache_key("blockchain.transaction.get_merkle", &json!([txid, 1])),
+            None
+        );
+        assert_eq!(cache_key("blockchain.block.header", &json!([1])), None);
+        assert_eq!(
+            cache_key("blockchain.transaction.id_from_pos", &json!([1, 0])),
+            None
+        );
+
+        assert_eq!(
+            subscription_key(HEADERS_SUBSCRIBE, &json!([])),
+            Some("headers".into())
+        );
+        assert_eq!(
+            subscription_key(SH_SUBSCRIBE, &json!(["abcd"])),
+            Some("sh:abcd".into())
+        );
+        assert_eq!(subscription_key("server.ping", &json!([])), None);
+
+        assert_eq!(
+            notification(SH_SUBSCRIBE, &json!(["abcd", "status"])),
+            Some(("sh:abcd".into(), json!("status")))
+        );
+        assert_eq!(
+            notification(HEADERS_SUBSCRIBE, &json!([{"height": 1, "hex": "00"}])),
+            Some(("headers".into(), json!({"height": 1, "hex": "00"})))
+        );
+    }
+}
@@ -65,6 +65,9 @@ impl Response {
             Response::TxGetMerkle(TxGetMerkleResponse { id, .. }) => Some(*id),
             Response::TxFromposition(TxFromPositionResponse { id, .. }) => Some(*id),
             Response::ListPeers(ListPeersResponse { id, .. }) => Some(*id),
+            Response::HeaderNotif(HeaderNotification::Single(SingleHeaderNotif { id, .. })) => {
+                Some(*id)
+            }
             _ => None,
         }
     }
@@ -164,7 +167,7 @@ impl Response {
             Method::TransactionGetMerkle => parse!(TxGetMerkle, TxGetMerkleResponse, raw),
             Method::TransactionFromPosition => parse!(TxFromposition, TxFromPositionResponse, raw),
             Method::TransactionBroadcast => parse!(TxBroadcast, TxBroadcastResponse, raw),
-            Method::ListPeers => todo!(),
+            Method::ListPeers => parse!(ListPeers, ListPeersResponse, raw),
         }
     }
 }
@@ -464,10 +467,10 @@ pub struct TxGetResponse {
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct GetMerkleResult {
-    merkle: Vec<String>,
-    block_height: usize,
+    pub merkle: Vec<String>,
+    pub block_height: usize,
     #[serde(rename = "pos")]
-    tx_pos: usize,
+    pub tx_pos: usize,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -503,6 +506,41 @@ pub struct Peer(
     ),
 );
 
+impl Peer {
+    /// Returns the IP address of the peer.
+    pub fn ip(&self) -> &str {
+        &self.0 .0
+    }
+
+    /// Returns the domain of the peer.
+    pub fn host(&self) -> &str {
+        &self.0 .1
+    }
+
+    /// Returns the features advertised by the peer (`v1.4`, `s50002`, ...).
+    pub fn features(&self) -> &[String] {
+        &self.0 .2
+    }
+
+    // NOTE: a feature w/o port (`t` or `s`) means the default port
+    fn port(&self, prefix: char, default: u16) -> Option<u16> {
+        self.features()
+            .iter()
+            .find(|f| f.starts_with(prefix))
+            .map(|f| f[1..].parse().unwrap_or(default))
+    }
+
+    /// Returns the TCP port of the peer if it accept TCP connections.
+    pub fn tcp_port(&self) -> Option<u16> {
+        self.port('t', 50001)
+    }
+
+    /// Returns the SSL port of the peer if it accept SSL connections.
+    pub fn ssl_port(&self) -> Option<u16> {
+        self.port('s', 50002)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct ListPeersResponse {
     pub id: usize,
@@ -893,4 +931,21 @@ mod tests {
         };
         assert_eq!(response, expected);
     }
+
+    #[test]
+    fn list_peers() {
+        let response = r#"{"jsonrpc": "2.0", "result": [["107.150.45.210", "e.anonyhost.org", ["v1.0", "p10000", "t", "s995"]], ["94.23.216.80", "electrum.example.org", ["v1.4", "s"]]], "id": 1}"#;
+        let response: ListPeersResponse = serde_json::from_str(response).unwrap();
+        assert_eq!(response.peers.len(), 2);
+
+        let peer = &response.peers[0];
+        assert_eq!(peer.ip(), "107.150.45.210");
+        assert_eq!(peer.host(), "e.anonyhost.org");
+        assert_eq!(peer.tcp_port(), Some(50001));
+        assert_eq!(peer.ssl_port(), Some(995));
+
+        let peer = &response.peers[1];
+        assert_eq!(peer.tcp_port(), None);
+        assert_eq!(peer.ssl_port(), Some(50002));
+    }
 }
@@ -1,4 +1,7 @@
 #![allow(dead_code)]
+#[cfg(feature = "async")]
+pub mod async_client;
 pub mod client;
+pub mod daemon;
 pub mod electrum;
 pub mod raw_client;
@@ -0,0 +1,154 @@
+#![cfg(feature = "async")]
+
+use std::{env, path::PathBuf, time::Duration};
+
+use electrsd::{
+    bitcoind::{bitcoincore_rpc::RpcApi, BitcoinD, P2P},
+    ElectrsD,
+};
+use serde_json::Value;
+use simple_electrum_client::{
+    async_client::{AsyncClient, Error},
+    electrum::{request::Request, response::*},
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+fn bootstrap_electrs() -> (String, u16, ElectrsD, BitcoinD) {
+    let mut cwd: PathBuf = env::current_dir().expect("Failed to get current directory");
+    cwd.push("tests");
+
+    let mut electrs_path = cwd.clone();
+    electrs_path.push("bin");
+    electrs_path.push("electrs_0_9_11");
+
+    let mut bitcoind_path = cwd.clone();
+    bitcoind_path.push("bin");
+    bitcoind_path.push("bitcoind_25_2");
+
+    let mut conf = electrsd::bitcoind::Conf::default();
+    conf.p2p = P2P::Yes;
+    let bitcoind = BitcoinD::with_conf(bitcoind_path, &conf).unwrap();
+
+    let electrsd_conf = electrsd::Conf::default();
+    let electrsd = ElectrsD::with_conf(electrs_path, &bitcoind, &electrsd_conf).unwrap();
+    let (url, port) = electrsd.electrum_url.split_once(':').unwrap();
+    let port = port.parse::<u16>().unwrap();
+    (url.into(), port, electrsd, bitcoind)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batch() {
+    let (url, port, _e, _b) = bootstrap_electrs();
+    let (client, _) = AsyncClient::connect(&url, port, false, false)
+        .await
+        .unwrap();
+
+    let responses = client
+        .batch_call(vec![
+            Request::ping(),
+            Request::banner(),
+            Request::features(),
+        ])
+        .await
+        .unwrap();
+    assert!(matches!(responses[0], Response::Ping(_)));
+    assert!(matches!(responses[1], Response::Banner(_)));
+    assert!(matches!(responses[2], Response::Features(_)));
+
+    // a clone share the same connection
+    let clone = client.clone();
+    let response = clone.call(Request::ping()).await.unwrap();
+    assert!(matches!(response, Response::Ping(_)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn headers_notification() {
+    let (url, port, _e, bitcoind) = bootstrap_electrs();
+    let (client, mut notifications) = AsyncClient::connect(&url, port, false, false)
+        .await
+        .unwrap();
+
+    let tip = match client.call(Request::subscribe_headers()).await.unwrap() {
+        Response::HeaderNotif(HeaderNotification::Single(SingleHeaderNotif { header, .. })) => {
+            header
+        }
+        r => panic!("wrong response: {r:?}"),
+    };
+
+    let node_address = bitcoind.client.call::<Value>("getnewaddress", &[]).unwrap();
+    bitcoind
+        .client
+        .call::<Value>("generatetoaddress", &[1.into(), node_address])
+        .unwrap();
+
+    let notif = tokio::time::timeout(Duration::from_secs(10), notifications.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    if let Response::BatchHeaderNotif(BatchHeaderNotif { headers, .. }) = notif {
+        assert_eq!(headers[0].height, tip.height + 1);
+    } else {
+        panic!("wrong notification: {notif:?}");
+    }
+}
+
+// A fake server answering each request w/ `answer(id)`, if any.
+fn fake_server(answer: fn(u64) -> Option<String>) -> AsyncClient {
+    let (client, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let (read, mut write) = tokio::io::split(server);
+        let mut lines = BufReader::new(read).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let request: Value = serde_json::from_str(&line).unwrap();
+            if let Some(mut raw) = answer(request["id"].as_u64().unwrap()) {
+                raw.push('\n');
+                write.write_all(raw.as_bytes()).await.unwrap();
+            }
+        }
+    });
+    AsyncClient::from_stream(client).0
+}
+
+#[tokio::test]
+async fn invalid_response() {
+    // the banner is not a string
+    let client = fake_server(|id| Some(format!(r#"{{"jsonrpc":"2.0","id":{id},"result":42}}"#)));
+    let result = client.call(Request::banner()).await;
+    assert!(matches!(result, Err(Error::InvalidResponse(_))));
+
+    // the waiters are failed even if we cannot tell which request is answered
+    let client = fake_server(|_| Some("not json".into()));
+    let result = client
+        .batch_call(vec![Request::ping(), Request::banner()])
+        .await;
+    assert!(matches!(result, Err(Error::InvalidResponse(_))));
+}
+
+#[tokio::test]
+async fn request_timeout() {
+    let client = fake_server(|_| None).timeout(Some(Duration::from_millis(100)));
+    let result = client.call(Request::ping()).await;
+    assert!(matches!(result, Err(Error::Timeout)));
+}
+
+#[tokio::test]
+async fn closed_connection() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (read, mut write) = tokio::io::split(server);
+    // the server stops answering but keeps reading
+    write.shutdown().await.unwrap();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(read).lines();
+        while let Ok(Some(_)) = lines.next_line().await {}
+    });
+    let client = AsyncClient::from_stream(client).0.timeout(None);
+    let limit = Duration::from_secs(5);
+
+    let result = tokio::time::timeout(limit, client.call(Request::ping())).await;
+    assert!(matches!(result, Ok(Err(Error::Disconnected))));
+    // later requests fail right away instead of waiting forever
+    let result = tokio::time::timeout(limit, client.batch_call(vec![Request::ping()])).await;
+    assert!(matches!(result, Ok(Err(Error::Disconnected))));
+    let result = tokio::time::timeout(limit, client.call(Request::banner())).await;
+    assert!(matches!(result, Ok(Err(Error::Disconnected))));
+}
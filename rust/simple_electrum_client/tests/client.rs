@@ -0,0 +1,130 @@
+use std::{env, path::PathBuf, thread, time::Duration};
+
+use electrsd::{
+    bitcoind::{bitcoincore_rpc::RpcApi, BitcoinD, P2P},
+    ElectrsD,
+};
+use miniscript::bitcoin::{Address, Amount};
+use serde_json::Value;
+use simple_electrum_client::client::Client;
+
+fn bootstrap_electrs() -> (String, u16, ElectrsD, BitcoinD) {
+    let mut cwd: PathBuf = env::current_dir().expect("Failed to get current directory");
+    cwd.push("tests");
+
+    let mut electrs_path = cwd.clone();
+    electrs_path.push("bin");
+    electrs_path.push("electrs_0_9_11");
+
+    let mut bitcoind_path = cwd.clone();
+    bitcoind_path.push("bin");
+    bitcoind_path.push("bitcoind_25_2");
+
+    let mut conf = electrsd::bitcoind::Conf::default();
+    conf.p2p = P2P::Yes;
+    let bitcoind = BitcoinD::with_conf(bitcoind_path, &conf).unwrap();
+
+    let electrsd_conf = electrsd::Conf::default();
+    let electrsd = ElectrsD::with_conf(electrs_path, &bitcoind, &electrsd_conf).unwrap();
+    let (url, port) = electrsd.electrum_url.split_once(':').unwrap();
+    let port = port.parse::<u16>().unwrap();
+    (url.into(), port, electrsd, bitcoind)
+}
+
+fn client() -> (Client, ElectrsD, BitcoinD) {
+    let (url, port, electrs, bitcoind) = bootstrap_electrs();
+    let client = Client::new(&url, port, false).unwrap();
+    (client, electrs, bitcoind)
+}
+
+fn generate(bitcoind: &BitcoinD, blocks: u64) -> Address {
+    let address = bitcoind.client.call::<Value>("getnewaddress", &[]).unwrap();
+    bitcoind
+        .client
+        .call::<Value>("generatetoaddress", &[blocks.into(), address.clone()])
+        .unwrap();
+    // wait for electrs to update
+    thread::sleep(Duration::from_millis(1500));
+    address
+        .as_str()
+        .unwrap()
+        .parse::<Address<_>>()
+        .unwrap()
+        .assume_checked()
+}
+
+#[test]
+fn ping_banner() {
+    let (mut client, _e, _b) = client();
+    client.ping().unwrap();
+    assert!(!client.banner().unwrap().is_empty());
+    client.features().unwrap();
+}
+
+#[test]
+fn headers() {
+    let (mut client, _e, bitcoind) = client();
+    generate(&bitcoind, 20);
+
+    let (tip, _) = client.subscribe_headers().unwrap();
+    assert_eq!(tip.height, 20);
+
+    let header = client.block_header(10).unwrap();
+    let headers = client.block_headers(5, 10).unwrap();
+    assert_eq!(headers.len(), 10);
+    assert_eq!(headers[5], header);
+    assert_eq!(headers[6].prev_blockhash, header.block_hash());
+}
+
+#[test]
+fn headers_notification() {
+    let (mut client, _e, bitcoind) = client();
+    let (tip, notifications) = client.subscribe_headers().unwrap();
+
+    generate(&bitcoind, 1);
+    client.poll().unwrap();
+
+    let header = notifications.try_recv().unwrap();
+    assert_eq!(header.height, tip.height + 1);
+}
+
+#[test]
+fn coins() {
+    let (mut client, _e, bitcoind) = client();
+    let address = generate(&bitcoind, 101);
+    let spk = address.script_pubkey();
+
+    let unspent = client.sh_list_unspent(&spk).unwrap();
+    assert_eq!(unspent.len(), 101);
+    let history = client.sh_get_history(&spk).unwrap();
+    assert_eq!(history.len(), 101);
+    let balance = client.sh_get_balance(&spk).unwrap();
+    assert_eq!(
+        balance.confirmed as u64,
+        unspent.iter().map(|u| u.value as u64).sum::<u64>()
+    );
+
+    // fetch all the txs in a single round trip
+    let txids: Vec<_> = unspent.iter().map(|u| u.txid).collect();
+    let txs = client.batch_tx_get(&txids).unwrap();
+    assert_eq!(txs.len(), txids.len());
+    for (tx, utxo) in txs.iter().zip(&unspent) {
+        assert_eq!(tx.compute_txid(), utxo.txid);
+        assert_eq!(
+            tx.output[utxo.vout].value,
+            Amount::from_sat(utxo.value as u64)
+        );
+    }
+    assert_eq!(client.tx_get(txids[0]).unwrap(), txs[0]);
+
+    let histories = client.batch_sh_get_history(&[&spk, &spk]).unwrap();
+    assert_eq!(histories[0], histories[1]);
+}
+
+#[test]
+fn estimate_fee() {
+    let (mut client, _e, _b) = client();
+    let fees = client.batch_estimate_fee(&[1, 6, 12]).unwrap();
+    assert_eq!(fees.len(), 3);
+    client.relay_fee().unwrap();
+}
@@ -1,7 +1,11 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     io::ErrorKind,
-    sync::mpsc::{self, Receiver, Sender},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
     time::{Duration, SystemTime},
 };
 
@@ -70,6 +74,48 @@ pub enum RecvMsg {
     Msg(Message),
 }
 
+/// Signal the threads waiting for messages that a message have been
+///   received.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier(Arc<(Mutex<u64>, Condvar)>);
+
+impl Notifier {
+    fn notify(&self) {
+        let (count, cvar) = &*self.0;
+        *count.lock().expect("poisoned") += 1;
+        cvar.notify_all();
+    }
+
+    fn count(&self) -> u64 {
+        *self.0 .0.lock().expect("poisoned")
+    }
+}
+
+/// Allow a thread to block until a [`WsClient`] receive a message, see
+///   [`WsClient::waiter()`].
+#[derive(Debug, Clone)]
+pub struct Waiter {
+    notifier: Notifier,
+    seen: u64,
+}
+
+impl Waiter {
+    /// Block until a message have been received since the last call (or
+    ///   since the waiter have been created) or `timeout` elapsed.
+    ///
+    /// Returns false if the timeout elapsed.
+    pub fn wait(&mut self, timeout: Duration) -> bool {
+        let seen = self.seen;
+        let (count, cvar) = &*self.notifier.0;
+        let count = count.lock().expect("poisoned");
+        let (count, _) = cvar
+            .wait_timeout_while(count, timeout, |c| *c == seen)
+            .expect("poisoned");
+        self.seen = *count;
+        *count != seen
+    }
+}
+
 pub struct WsClient {
     client: Option<Client<Box<dyn NetworkStream + Send>>>,
     sender: Sender<SendMsg>,
@@ -79,6 +125,10 @@ pub struct WsClient {
     connected: bool,
     relay: String,
     keys: Keys,
+    notifier: Notifier,
+    // (connection, client id) if the client use a connection shared w/
+    // other clients
+    shared: Option<(Arc<SharedInner>, u64)>,
 }
 
 impl Debug for WsClient {
@@ -130,10 +180,7 @@ impl WsClientBuilder {
         } else {
             return Err(Error::ArgMissing);
         };
-        let client = ClientBuilder::new(&url)?.connect(None)?;
-        client
-            .set_nonblocking(true)
-            .map_err(|_| Error::NonBlocking)?;
+        let client = connect(&url)?;
         let (sender, ws_receiver) = mpsc::channel();
         let (ws_sender, receiver) = mpsc::channel();
         let mut client = WsClient {
@@ -145,6 +192,8 @@ impl WsClientBuilder {
             connected: false,
             relay: url,
             keys,
+            notifier: Notifier::default(),
+            shared: None,
         };
         client.listen()?;
         Ok(client)
@@ -167,7 +216,8 @@ impl WsClient {
             self.ws_sender.take(),
             self.ws_receiver.take(),
         ) {
-            std::thread::spawn(|| listen(client, sender, receiver));
+            let notifier = self.notifier.clone();
+            std::thread::spawn(|| listen_and_notify(client, sender, receiver, notifier));
             self.connected = true;
             Ok(())
         } else {
@@ -175,6 +225,24 @@ impl WsClient {
         }
     }
 
+    /// Returns a [`Waiter`] that allow to block until this client receive a
+    ///   message, instead of polling [`WsClient::try_receive()`].
+    pub fn waiter(&self) -> Waiter {
+        Waiter {
+            seen: self.notifier.count(),
+            notifier: self.notifier.clone(),
+        }
+    }
+
+    // Route the events of the subscription to this client if it use a
+    // shared connection.
+    fn route(&self, id: &SubscriptionId) {
+        if let Some((shared, client)) = &self.shared {
+            let mut routes = shared.routes.lock().expect("poisoned");
+            routes.subscriptions.insert(id.clone(), *client);
+        }
+    }
+
     pub fn encrypt<T>(&mut self, receiver: &PublicKey, content: T) -> Result<String, Error>
     where
         T: AsRef<[u8]>,
@@ -202,7 +270,9 @@ impl WsClient {
         let filter = Filter::new()
             .kind(Kind::EncryptedDirectMessage)
             .pubkey(self.get_keys().public_key());
-        let msg = nostr::ClientMessage::req(SubscriptionId::generate(), vec![filter]);
+        let id = SubscriptionId::generate();
+        self.route(&id);
+        let msg = nostr::ClientMessage::req(id, vec![filter]);
         self.send_raw(msg.as_json())?;
         Ok(())
     }
@@ -211,22 +281,40 @@ impl WsClient {
         self.is_connected()?;
         let since = Timestamp::now() - Timestamp::from_secs(back);
         let filter = Filter::new().kind(Kind::Custom(2022)).since(since);
-        let msg = nostr::ClientMessage::req(SubscriptionId::generate(), vec![filter]);
+        let id = SubscriptionId::generate();
+        self.route(&id);
+        let msg = nostr::ClientMessage::req(id, vec![filter]);
         self.send_raw(msg.as_json())?;
         Ok(())
     }
 
+    fn dm(&mut self, content: String, receiver: &PublicKey) -> Result<EventBuilder, Error> {
+        let content = self.encrypt(receiver, content)?;
+        Ok(EventBuilder::new(
+            Kind::EncryptedDirectMessage,
+            content,
+            vec![Tag::public_key(*receiver)],
+        ))
+    }
+
     pub fn send_dm<T: Into<String>>(
         &mut self,
         content: T,
         receiver: &PublicKey,
     ) -> Result<(), Error> {
-        let content = self.encrypt(receiver, content.into())?;
-        let dm = EventBuilder::new(
-            Kind::EncryptedDirectMessage,
-            content,
-            vec![Tag::public_key(*receiver)],
-        );
+        let dm = self.dm(content.into(), receiver)?;
+        self.post_event(dm)
+    }
+
+    /// Send a DM w/ a NIP-13 proof of work of (at least) `difficulty`
+    ///   leading zero bits, the event is mined before being sent.
+    pub fn send_dm_with_pow<T: Into<String>>(
+        &mut self,
+        content: T,
+        receiver: &PublicKey,
+        difficulty: u8,
+    ) -> Result<(), Error> {
+        let dm = self.dm(content.into(), receiver)?.pow(difficulty);
         self.post_event(dm)
     }
 
@@ -282,8 +370,27 @@ impl WsClient {
     }
 
     pub fn stop(&mut self) {
-        if self.connected {
-            self.connected = false;
+        if !self.connected {
+            return;
+        }
+        self.connected = false;
+        if let Some((shared, client)) = self.shared.take() {
+            // the connection is kept open for the other clients, we only
+            // close our subscriptions
+            let mut routes = shared.routes.lock().expect("poisoned");
+            routes.clients.remove(&client);
+            let subscriptions: Vec<_> = routes
+                .subscriptions
+                .iter()
+                .filter(|(_, c)| **c == client)
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in subscriptions {
+                routes.subscriptions.remove(&id);
+                let msg = ClientMessage::close(id);
+                _ = self.sender.send(SendMsg::Msg(msg.as_json()));
+            }
+        } else {
             _ = self.sender.send(SendMsg::Stop);
         }
     }
@@ -321,10 +428,28 @@ impl Drop for WsClient {
     }
 }
 
+// Open a non blocking websocket to the relay.
+fn connect(url: &str) -> Result<Client<Box<dyn NetworkStream + Send>>, Error> {
+    let client = ClientBuilder::new(url)?.connect(None)?;
+    client
+        .set_nonblocking(true)
+        .map_err(|_| Error::NonBlocking)?;
+    Ok(client)
+}
+
 pub fn listen(
+    client: Client<Box<dyn NetworkStream + Send>>,
+    sender: Sender<RecvMsg>,
+    receiver: Receiver<SendMsg>,
+) {
+    listen_and_notify(client, sender, receiver, Notifier::default())
+}
+
+fn listen_and_notify(
     mut client: Client<Box<dyn NetworkStream + Send>>,
     sender: Sender<RecvMsg>,
     receiver: Receiver<SendMsg>,
+    notifier: Notifier,
 ) {
     let mut backoff = Backoff::new_us(50);
 
@@ -354,6 +479,7 @@ pub fn listen(
                     OwnedMessage::Text(m) => {
                         log::debug!("recv text: {:?}", m);
                         let _ = sender.send(RecvMsg::Msg(m));
+                        notifier.notify();
                     }
                     OwnedMessage::Binary(m) => {
                         log::error!("listen() unexpected binary message {:?}", m);
@@ -361,6 +487,7 @@ pub fn listen(
                     OwnedMessage::Close(_) => {
                         log::debug!("recv: Close ");
                         sender.send(RecvMsg::Close).expect("main thread panicked");
+                        notifier.notify();
                     }
                     OwnedMessage::Ping(nonce) => {
                         _ = client.send_message(&OwnedMessage::Pong(nonce));
@@ -411,6 +538,7 @@ pub fn listen(
             > Duration::from_secs(3 * PING_INTERVAL)
         {
             _ = sender.send(RecvMsg::Close);
+            notifier.notify();
             return;
         }
 
@@ -419,3 +547,167 @@ pub fn listen(
         }
     }
 }
+
+struct Route {
+    sender: Sender<RecvMsg>,
+    notifier: Notifier,
+}
+
+#[derive(Default)]
+struct Routes {
+    next_id: u64,
+    clients: HashMap<u64, Route>,
+    // the client each subscription belongs to
+    subscriptions: HashMap<SubscriptionId, u64>,
+    closed: bool,
+}
+
+struct SharedInner {
+    relay: String,
+    sender: Mutex<Sender<SendMsg>>,
+    routes: Arc<Mutex<Routes>>,
+    // notified each time a message is routed to one of the clients
+    notifier: Notifier,
+}
+
+impl Drop for SharedInner {
+    fn drop(&mut self) {
+        // no more client use the connection
+        _ = self.sender.lock().expect("poisoned").send(SendMsg::Stop);
+    }
+}
+
+/// A relay connection shared by several [`WsClient`]s, each having its own
+///   keys: the events received on a subscription are routed to the client
+///   that opened it. A single websocket & listener thread serve all the
+///   clients, the connection is closed when the last client is dropped.
+#[derive(Clone)]
+pub struct SharedConnection {
+    inner: Arc<SharedInner>,
+}
+
+impl Debug for SharedConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedConnection")
+            .field("relay", &self.inner.relay)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl SharedConnection {
+    /// Connect to the relay.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the url is invalid or the
+    ///   connection fails.
+    pub fn connect<T: Into<String>>(relay: T) -> Result<Self, Error> {
+        let relay = relay.into();
+        let client = connect(&relay)?;
+        let (sender, ws_receiver) = mpsc::channel();
+        let (ws_sender, receiver) = mpsc::channel();
+        std::thread::spawn(|| listen(client, ws_sender, ws_receiver));
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let notifier = Notifier::default();
+        let (r, n) = (routes.clone(), notifier.clone());
+        std::thread::spawn(move || route(receiver, r, n));
+        Ok(SharedConnection {
+            inner: Arc::new(SharedInner {
+                relay,
+                sender: Mutex::new(sender),
+                routes,
+                notifier,
+            }),
+        })
+    }
+
+    /// Create a new client using this connection.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection is closed.
+    pub fn client(&self, keys: Keys) -> Result<WsClient, Error> {
+        let (sender, receiver) = mpsc::channel();
+        let notifier = Notifier::default();
+        let mut routes = self.inner.routes.lock().expect("poisoned");
+        if routes.closed {
+            return Err(Error::ConnectionClosed);
+        }
+        let id = routes.next_id;
+        routes.next_id += 1;
+        routes.clients.insert(
+            id,
+            Route {
+                sender,
+                notifier: notifier.clone(),
+            },
+        );
+        drop(routes);
+        Ok(WsClient {
+            client: None,
+            sender: self.inner.sender.lock().expect("poisoned").clone(),
+            ws_receiver: None,
+            receiver,
+            ws_sender: None,
+            connected: true,
+            relay: self.inner.relay.clone(),
+            keys,
+            notifier,
+            shared: Some((self.inner.clone(), id)),
+        })
+    }
+
+    pub fn get_relay(&self) -> String {
+        self.inner.relay.clone()
+    }
+
+    /// Returns a [`Waiter`] that allow to block until any client of this
+    ///   connection receive a message, a single thread can then serve all
+    ///   the clients.
+    pub fn waiter(&self) -> Waiter {
+        Waiter {
+            seen: self.inner.notifier.count(),
+            notifier: self.inner.notifier.clone(),
+        }
+    }
+
+    /// Returns true if the relay closed the connection.
+    pub fn is_closed(&self) -> bool {
+        self.inner.routes.lock().expect("poisoned").closed
+    }
+}
+
+// Dispatch the messages received on a shared connection to the client
+// owning the subscription, until the connection is closed.
+fn route(receiver: Receiver<RecvMsg>, routes: Arc<Mutex<Routes>>, notifier: Notifier) {
+    while let Ok(RecvMsg::Msg(msg)) = receiver.recv() {
+        let id = match RawRelayMessage::from_json(&msg).map(RelayMessage::try_from) {
+            Ok(Ok(RelayMessage::Event {
+                subscription_id, ..
+            })) => subscription_id,
+            // other messages are not related to a subscription we track
+            _ => continue,
+        };
+        let routes = routes.lock().expect("poisoned");
+        match routes
+            .subscriptions
+            .get(&id)
+            .and_then(|c| routes.clients.get(c))
+        {
+            Some(route) => {
+                _ = route.sender.send(RecvMsg::Msg(msg));
+                route.notifier.notify();
+                notifier.notify();
+            }
+            None => log::debug!("route(): no client for subscription {:?}", id),
+        }
+    }
+    let mut routes = routes.lock().expect("poisoned");
+    routes.closed = true;
+    for route in routes.clients.values() {
+        _ = route.sender.send(RecvMsg::Close);
+        route.notifier.notify();
+    }
+    notifier.notify();
+}
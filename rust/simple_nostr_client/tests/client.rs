@@ -7,7 +7,8 @@ use joinstr::{
     nostr::{Fee, Timeline, Transport, Vpn},
 };
 use nostr::event::EventBuilder;
-use nostr::key::PublicKey;
+use nostr::key::{Keys, PublicKey};
+use simple_nostr_client::WsClient;
 use utils::{clear_nostr_log, Relay};
 
 use crate::utils::dump_nostr_log;
@@ -94,6 +95,8 @@ fn test_pool_notif() {
                 }),
                 tor: None,
             },
+            pow: None,
+            banned: Vec::new(),
         }),
     };
 
@@ -114,3 +117,49 @@ fn test_pool_notif() {
         assert!(counter < 10);
     }
 }
+
+fn received(client: &mut WsClient) -> String {
+    loop {
+        match client.try_receive() {
+            Ok(Some(event)) => return event.content,
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_shared_connection() {
+    init_logger();
+
+    let relay = Relay::new();
+    let connection = relay.shared_connection();
+    let mut client_a = connection.client(Keys::generate()).unwrap();
+    let mut client_b = connection.client(Keys::generate()).unwrap();
+    let mut sender = relay.new_client();
+
+    client_a.subscribe_dm().unwrap();
+    client_b.subscribe_dm().unwrap();
+    std::thread::sleep(Duration::from_secs(1));
+
+    let mut waiter_a = client_a.waiter();
+    let mut waiter_all = connection.waiter();
+    sender.send_dm("to a", &client_a.pubkey()).unwrap();
+    sender.send_dm("to b", &client_b.pubkey()).unwrap();
+
+    // every client only receive its own DMs
+    assert!(waiter_a.wait(Duration::from_secs(5)));
+    // the connection waiter is woken by the messages of any client
+    assert!(waiter_all.wait(Duration::from_secs(5)));
+    assert_eq!(received(&mut client_a), "to a");
+    assert_eq!(received(&mut client_b), "to b");
+    std::thread::sleep(Duration::from_secs(1));
+    assert!(client_a.try_receive().unwrap().is_none());
+
+    // the connection is kept open for the remaining clients
+    drop(client_a);
+    sender.send_dm("to b again", &client_b.pubkey()).unwrap();
+    assert_eq!(received(&mut client_b), "to b again");
+    assert!(waiter_all.wait(Duration::from_secs(5)));
+    assert!(!connection.is_closed());
+}
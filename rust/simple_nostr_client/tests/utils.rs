@@ -1,6 +1,6 @@
 use nostr::key::Keys;
 use nostrd::NostrD;
-use simple_nostr_client::WsClient;
+use simple_nostr_client::{SharedConnection, WsClient};
 
 pub struct Relay {
     nostrd: NostrD,
@@ -19,6 +19,11 @@ impl Relay {
         self.new_client_with_keys(keys)
     }
 
+    #[allow(dead_code)]
+    pub fn shared_connection(&self) -> SharedConnection {
+        SharedConnection::connect(self.nostrd.url()).unwrap()
+    }
+
     pub fn new_client_with_keys(&self, keys: Keys) -> WsClient {
         WsClient::new()
             .relay(self.nostrd.url())